@@ -1,6 +1,12 @@
 #!/bin/bash
 # Build WASM package and include prompt-spec.json
+#   --minimal: size-optimized build (wasm-minimal feature and profile); the
+#              page must pass pkg/prompt-spec.json to loadSpec() first
 set -e
-wasm-pack build --target web --features wasm
+if [ "$1" = "--minimal" ]; then
+    wasm-pack build --target web --profile wasm-minimal --features wasm-minimal
+else
+    wasm-pack build --target web --features wasm
+fi
 cp prompt-spec.json pkg/prompt-spec.json
 echo "prompt-spec.json copied to pkg/"
@@ -0,0 +1,576 @@
+//! `AiBackend` combinators
+//!
+//! Wrappers that compose concrete backends (Gemini CLI, GenAI SDK, ...) without
+//! the pipeline having to know about them.
+
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use crate::i18n::{self, language};
+use crate::input::InputImage;
+use crate::pipeline::{AiBackend, BackendCapabilities, BackendOptions, PipelineError};
+
+/// Tries the primary backend first, then each fallback in order.
+///
+/// Any error (including rate limits) moves on to the next backend. The name of
+/// the backend that finally answered is reported through `send_prompt_traced`
+/// and ends up in the run logs.
+pub struct FallbackBackend {
+    backends: Vec<Box<dyn AiBackend>>,
+}
+
+impl FallbackBackend {
+    pub fn new(primary: Box<dyn AiBackend>) -> Self {
+        Self {
+            backends: vec![primary],
+        }
+    }
+
+    /// Append a fallback tried after all previously added backends
+    pub fn with_fallback(mut self, backend: Box<dyn AiBackend>) -> Self {
+        self.backends.push(backend);
+        self
+    }
+}
+
+impl AiBackend for FallbackBackend {
+    fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+        self.send_prompt_traced(prompt, images).map(|(r, _)| r)
+    }
+
+    fn name(&self) -> &str {
+        self.backends[0].name()
+    }
+
+    /// Any backend may answer, so only what all of them accept
+    fn capabilities(&self) -> BackendCapabilities {
+        self.backends[1..]
+            .iter()
+            .fold(self.backends[0].capabilities(), |caps, b| caps.intersect(&b.capabilities()))
+    }
+
+    fn send_prompt_traced(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+    ) -> Result<(String, String), PipelineError> {
+        self.send_prompt_within(prompt, images, None, &BackendOptions::default())
+    }
+
+    fn send_prompt_with_options(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        self.send_prompt_within(prompt, images, None, options)
+    }
+
+    /// Each backend gets the full limit; a timed-out backend falls through to the next
+    fn send_prompt_within(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        limit: Option<Duration>,
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        let mut failures = Vec::new();
+        for backend in &self.backends {
+            match backend.send_prompt_within(prompt, images, limit, options) {
+                Ok(reply) => return Ok(reply),
+                Err(e) => failures.push(format!("{}: {}", backend.name(), e)),
+            }
+        }
+        Err(PipelineError::AiError(
+            i18n::ALL_BACKENDS_FAILED.format(language(), &[&failures.join("; ")]),
+        ))
+    }
+}
+
+/// Backend that keeps state between calls (a subprocess pipe, a conversation,
+/// a refreshed access token) and so sends with `&mut self`.
+///
+/// Wrap it in a `Session` to use it as an `AiBackend`.
+pub trait StatefulBackend {
+    fn send_prompt(&mut self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError>;
+
+    /// Backend name recorded in run logs (read once, by `Session::new`)
+    fn name(&self) -> &str {
+        "default"
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::default()
+    }
+
+    /// Send with generation options; the default ignores them
+    fn send_prompt_with_options(
+        &mut self,
+        prompt: &str,
+        images: &[InputImage],
+        options: &BackendOptions,
+    ) -> Result<String, PipelineError> {
+        let _ = options;
+        self.send_prompt(prompt, images)
+    }
+}
+
+/// Session handle adapting a `StatefulBackend` to `AiBackend`.
+///
+/// Calls are serialized through a mutex, so a session is `Send + Sync`
+/// whenever the backend is `Send` and can serve the concurrent and batch
+/// entry points (one call at a time).
+pub struct Session<B> {
+    name: String,
+    inner: std::sync::Mutex<B>,
+}
+
+impl<B: StatefulBackend> Session<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            name: backend.name().to_string(),
+            inner: std::sync::Mutex::new(backend),
+        }
+    }
+
+    /// Run `f` with exclusive access to the backend (inspect or reset its state)
+    pub fn with<R>(&self, f: impl FnOnce(&mut B) -> R) -> R {
+        f(&mut self.inner.lock().unwrap_or_else(|p| p.into_inner()))
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner.into_inner().unwrap_or_else(|p| p.into_inner())
+    }
+}
+
+impl<B: StatefulBackend> AiBackend for Session<B> {
+    fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+        self.with(|b| b.send_prompt(prompt, images))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.with(|b| b.capabilities())
+    }
+
+    fn send_prompt_with_options(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        let reply = self.with(|b| b.send_prompt_with_options(prompt, images, options))?;
+        Ok((reply, self.name.clone()))
+    }
+}
+
+/// Enforces call time limits by running the wrapped backend on a helper thread.
+///
+/// When the limit passes, `send_prompt_within` returns `CallTimeout` right away
+/// so a hung subprocess no longer blocks the analysis. The helper thread is
+/// abandoned and its late reply discarded. Not available on wasm32.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Watchdog<B> {
+    inner: std::sync::Arc<B>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B> Watchdog<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner: std::sync::Arc::new(inner),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B: AiBackend + Send + Sync + 'static> AiBackend for Watchdog<B> {
+    fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+        self.inner.send_prompt(prompt, images)
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn send_prompt_traced(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+    ) -> Result<(String, String), PipelineError> {
+        self.inner.send_prompt_traced(prompt, images)
+    }
+
+    fn send_prompt_with_options(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        self.inner.send_prompt_with_options(prompt, images, options)
+    }
+
+    fn send_prompt_within(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        limit: Option<Duration>,
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        use std::sync::mpsc::{self, RecvTimeoutError};
+
+        let Some(limit) = limit else {
+            return self.inner.send_prompt_with_options(prompt, images, options);
+        };
+        let (tx, rx) = mpsc::channel();
+        let inner = std::sync::Arc::clone(&self.inner);
+        let prompt = prompt.to_string();
+        let images = images.to_vec();
+        let options = options.clone();
+        std::thread::spawn(move || {
+            // The receiver is gone if the call already timed out
+            let _ = tx.send(inner.send_prompt_with_options(&prompt, &images, &options));
+        });
+        match rx.recv_timeout(limit) {
+            Ok(reply) => reply,
+            Err(RecvTimeoutError::Timeout) => Err(PipelineError::CallTimeout(limit)),
+            Err(RecvTimeoutError::Disconnected) => Err(PipelineError::AiError(
+                i18n::BACKEND_THREAD_EXITED.get(language()).to_string(),
+            )),
+        }
+    }
+}
+
+/// Stops calling a backend after repeated failures.
+///
+/// After `threshold` consecutive failed calls the circuit opens and every
+/// call fails at once with `CircuitOpen` until `cooldown` has passed. The
+/// next call is then let through as a probe (half-open): success closes the
+/// circuit, failure reopens it for another cooldown. Meant for batch jobs,
+/// so a dead provider costs `threshold` calls instead of one timeout per
+/// photo; behind a `FallbackBackend` the open circuit moves straight on to
+/// the next backend. Not available on wasm32.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct CircuitBreaker<B> {
+    inner: B,
+    threshold: u32,
+    cooldown: Duration,
+    state: std::sync::Mutex<BreakerState>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<std::time::Instant>,
+    probing: bool,
+}
+
+/// Observable state of a `CircuitBreaker`
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    /// Cooldown over; the next call (or the one in flight) is a probe
+    HalfOpen,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B: AiBackend> CircuitBreaker<B> {
+    /// Open after `threshold` consecutive failures (at least 1) for `cooldown`
+    pub fn new(inner: B, threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            inner,
+            threshold: threshold.max(1),
+            cooldown,
+            state: Default::default(),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let state = self.lock();
+        match state.opened_at {
+            None => CircuitState::Closed,
+            Some(opened) if opened.elapsed() < self.cooldown => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.state.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// Run the call unless the circuit is open, and record its outcome
+    fn guard<T>(&self, call: impl FnOnce() -> Result<T, PipelineError>) -> Result<T, PipelineError> {
+        {
+            let mut state = self.lock();
+            if let Some(opened) = state.opened_at {
+                let elapsed = opened.elapsed();
+                if elapsed < self.cooldown || state.probing {
+                    return Err(PipelineError::CircuitOpen {
+                        backend: self.inner.name().to_string(),
+                        retry_after: self.cooldown.saturating_sub(elapsed),
+                    });
+                }
+                state.probing = true;
+            }
+        }
+        let reply = call();
+        let mut state = self.lock();
+        state.probing = false;
+        match reply {
+            Ok(_) => *state = BreakerState::default(),
+            Err(_) => {
+                state.consecutive_failures += 1;
+                if state.opened_at.is_some() || state.consecutive_failures >= self.threshold {
+                    state.opened_at = Some(std::time::Instant::now());
+                }
+            }
+        }
+        reply
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<B: AiBackend> AiBackend for CircuitBreaker<B> {
+    fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+        self.guard(|| self.inner.send_prompt(prompt, images))
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn send_prompt_traced(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+    ) -> Result<(String, String), PipelineError> {
+        self.guard(|| self.inner.send_prompt_traced(prompt, images))
+    }
+
+    fn send_prompt_streaming(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        on_chunk: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<String, PipelineError> {
+        self.guard(|| self.inner.send_prompt_streaming(prompt, images, on_chunk))
+    }
+
+    fn send_prompt_with_options(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        self.guard(|| self.inner.send_prompt_with_options(prompt, images, options))
+    }
+
+    fn send_prompt_within(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        limit: Option<Duration>,
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        self.guard(|| self.inner.send_prompt_within(prompt, images, limit, options))
+    }
+}
+
+// The wrappers stay shareable across threads when the wrapped backend is
+#[cfg(not(target_arch = "wasm32"))]
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    fn wrappers<B: AiBackend + Send + Sync + 'static>() {
+        assert_send_sync::<Watchdog<B>>();
+        assert_send_sync::<CircuitBreaker<B>>();
+    }
+    wrappers::<crate::offline::OfflineBackend>();
+    // A session only needs `Send` state
+    assert_send_sync::<Session<std::cell::RefCell<String>>>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    struct Named {
+        name: &'static str,
+        fail: bool,
+    }
+
+    impl AiBackend for Named {
+        fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            if self.fail {
+                Err(PipelineError::AiError("rate limited".into()))
+            } else {
+                Ok(format!("reply from {}", self.name))
+            }
+        }
+
+        fn name(&self) -> &str {
+            self.name
+        }
+    }
+
+    fn named(name: &'static str, fail: bool) -> Box<dyn AiBackend> {
+        Box::new(Named { name, fail })
+    }
+
+    #[test]
+    fn test_primary_answers() {
+        let fb = FallbackBackend::new(named("gemini", false)).with_fallback(named("claude", false));
+        let (reply, used) = fb.send_prompt_traced("p", &[]).unwrap();
+        assert_eq!(reply, "reply from gemini");
+        assert_eq!(used, "gemini");
+    }
+
+    #[test]
+    fn test_falls_back_in_order() {
+        let fb = FallbackBackend::new(named("gemini", true))
+            .with_fallback(named("openai", true))
+            .with_fallback(named("claude", false));
+        let (_, used) = fb.send_prompt_traced("p", &[]).unwrap();
+        assert_eq!(used, "claude");
+    }
+
+    #[test]
+    fn test_all_fail_reports_every_backend() {
+        let fb = FallbackBackend::new(named("gemini", true)).with_fallback(named("claude", true));
+        let err = fb.send_prompt("p", &[]).unwrap_err().to_string();
+        assert!(err.contains("gemini") && err.contains("claude"));
+    }
+
+    struct Hung;
+
+    impl AiBackend for Hung {
+        fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            std::thread::sleep(Duration::from_secs(5));
+            Ok("too late".into())
+        }
+    }
+
+    #[test]
+    fn test_watchdog_times_out_hung_backend() {
+        let started = std::time::Instant::now();
+        let err = Watchdog::new(Hung)
+            .send_prompt_within("p", &[], Some(Duration::from_millis(20)), &BackendOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, PipelineError::CallTimeout(_)));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_watchdog_passes_reply_within_limit() {
+        let wd = Watchdog::new(Named { name: "gemini", fail: false });
+        let (reply, used) = wd
+            .send_prompt_within("p", &[], Some(Duration::from_secs(5)), &BackendOptions::default())
+            .unwrap();
+        assert_eq!(reply, "reply from gemini");
+        assert_eq!(used, "gemini");
+    }
+
+    /// Fails while `down` is set, counting the calls that reached it
+    #[derive(Default)]
+    struct Flaky {
+        down: std::sync::atomic::AtomicBool,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl AiBackend for Arc<Flaky> {
+        fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            use std::sync::atomic::Ordering;
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                Err(PipelineError::AiError("503".into()))
+            } else {
+                Ok("ok".into())
+            }
+        }
+    }
+
+    #[test]
+    fn test_circuit_breaker_opens_and_probes() {
+        use std::sync::atomic::Ordering;
+
+        let flaky = Arc::new(Flaky::default());
+        flaky.down.store(true, Ordering::SeqCst);
+        let breaker = CircuitBreaker::new(flaky.clone(), 3, Duration::from_millis(50));
+        for _ in 0..3 {
+            assert!(matches!(breaker.send_prompt("p", &[]), Err(PipelineError::AiError(_))));
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+        for _ in 0..10 {
+            assert!(matches!(breaker.send_prompt("p", &[]), Err(PipelineError::CircuitOpen { .. })));
+        }
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 3, "open circuit makes no calls");
+
+        // Failed probe reopens at once
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(matches!(breaker.send_prompt("p", &[]), Err(PipelineError::AiError(_))));
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // Successful probe closes
+        flaky.down.store(false, Ordering::SeqCst);
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.send_prompt("p", &[]).unwrap(), "ok");
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 5);
+
+        // Behind a fallback the open circuit skips straight to the next backend
+        flaky.down.store(true, Ordering::SeqCst);
+        let chain = FallbackBackend::new(Box::new(CircuitBreaker::new(flaky.clone(), 1, Duration::from_secs(60))))
+            .with_fallback(named("claude", false));
+        assert_eq!(chain.send_prompt_traced("p", &[]).unwrap().1, "claude");
+        assert_eq!(chain.send_prompt_traced("p", &[]).unwrap().1, "claude");
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 6);
+    }
+
+    /// Keeps a conversation: each reply carries the turn number
+    struct Conversation {
+        turns: usize,
+    }
+
+    impl StatefulBackend for Conversation {
+        fn send_prompt(&mut self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            self.turns += 1;
+            Ok(format!("{}:{}", self.turns, prompt))
+        }
+
+        fn name(&self) -> &str {
+            "conversation"
+        }
+    }
+
+    #[test]
+    fn test_session_threads_state_through_calls() {
+        let session = Session::new(Conversation { turns: 0 });
+        assert_eq!(session.send_prompt("a", &[]).unwrap(), "1:a");
+        let (reply, name) = session.send_prompt_with_options("b", &[], &BackendOptions::default()).unwrap();
+        assert_eq!((reply.as_str(), name.as_str()), ("2:b", "conversation"));
+
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| session.send_prompt("c", &[]).unwrap());
+            }
+        });
+        session.with(|c| c.turns = 10);
+        assert_eq!(session.send_prompt("d", &[]).unwrap(), "11:d");
+        assert_eq!(session.into_inner().turns, 11);
+    }
+}
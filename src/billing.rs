@@ -0,0 +1,292 @@
+//! Billing helpers
+//!
+//! Invoices are issued on a rounded quantity. Rounding happens here, once,
+//! and the result carries both the raw estimate and the billed value so
+//! clients do not re-round the raw figure their own way. `line_item` and
+//! `invoice` turn results into priced lines for a billing export.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::i18n::{self, language, Language, Localize};
+use crate::metadata::LoadMetadata;
+use crate::pipeline::BoxOverlayResult;
+use crate::report::ReportingMode;
+
+/// Direction of invoice rounding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RoundingMode {
+    /// Always to the next increment (切り上げ)
+    Up,
+    /// Always to the previous increment (切り捨て)
+    Down,
+    /// To the nearest increment, halves up (四捨五入)
+    #[default]
+    HalfUp,
+}
+
+/// Rounding of billed tonnage to a fixed increment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvoiceRounding {
+    /// Billing unit in tonnes (e.g. 0.1 or 0.5)
+    pub increment: f64,
+    pub mode: RoundingMode,
+}
+
+impl Default for InvoiceRounding {
+    fn default() -> Self {
+        Self {
+            increment: 0.1,
+            mode: RoundingMode::HalfUp,
+        }
+    }
+}
+
+impl InvoiceRounding {
+    pub fn new(increment: f64, mode: RoundingMode) -> Self {
+        Self { increment, mode }
+    }
+
+    /// `value` rounded to a multiple of `increment` (unchanged when the
+    /// increment is not positive)
+    pub fn round(&self, value: f64) -> f64 {
+        if self.increment <= 0.0 || !self.increment.is_finite() {
+            return value;
+        }
+        let steps = value / self.increment;
+        // 3.3 / 0.1 = 32.999999999999996: snap float noise to the whole step
+        let nearest = steps.round();
+        let steps = if (steps - nearest).abs() < 1e-9 { nearest } else { steps };
+        let steps = match self.mode {
+            RoundingMode::Up => steps.ceil(),
+            RoundingMode::Down => steps.floor(),
+            RoundingMode::HalfUp => (steps + 0.5).floor(),
+        };
+        // Drop representation noise (e.g. 3 * 0.1)
+        (steps * self.increment * 1e9).round() / 1e9
+    }
+}
+
+// ─── Line items ──────────────────────────────────────────────────────
+
+/// Unit prices in yen per unit of the billed quantity (t, or m³ for results
+/// reported by volume)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceTable {
+    /// Unit price keyed by material name
+    pub prices: HashMap<String, u64>,
+    /// Price for materials not in `prices` (None = `NoPrice` error)
+    pub default_price: Option<u64>,
+    /// Consumption tax rate (0.1 = 10%)
+    pub tax_rate: f64,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        Self {
+            prices: HashMap::new(),
+            default_price: None,
+            tax_rate: 0.1,
+        }
+    }
+}
+
+impl PriceTable {
+    /// Unit price of a material
+    pub fn price(&self, material: &str) -> Option<u64> {
+        self.prices.get(material).copied().or(self.default_price)
+    }
+}
+
+/// Result that cannot be priced
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum BillingError {
+    NoPrice(String),
+}
+
+impl BillingError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoPrice(_) => "NO_PRICE",
+        }
+    }
+}
+
+impl Localize for BillingError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::NoPrice(material) => i18n::NO_PRICE.format(lang, &[material]),
+        }
+    }
+}
+
+impl fmt::Display for BillingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// One priced load
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineItem {
+    pub material: String,
+    /// Billed quantity (`billed_tonnage` when set, else the raw value)
+    pub quantity: f64,
+    /// "t" or "m³"
+    pub unit: &'static str,
+    /// Yen per unit
+    pub unit_price: u64,
+    /// quantity × unit price, yen fractions dropped
+    pub amount: u64,
+    /// Tax on this line, yen fractions dropped
+    pub tax: u64,
+    /// Dispatch identifiers of the load (omitted when empty)
+    #[serde(skip_serializing_if = "LoadMetadata::is_empty")]
+    pub metadata: LoadMetadata,
+}
+
+/// Price one analysis result
+pub fn line_item(result: &BoxOverlayResult, prices: &PriceTable) -> Result<LineItem, BillingError> {
+    let unit_price = prices
+        .price(&result.material_type)
+        .ok_or_else(|| BillingError::NoPrice(result.material_type.clone()))?;
+    let (quantity, unit) = match result.reporting {
+        ReportingMode::Weight => (result.billed_tonnage.unwrap_or(result.tonnage), "t"),
+        ReportingMode::Volume => result.primary_quantity(),
+    };
+    let amount = yen(quantity * unit_price as f64);
+    Ok(LineItem {
+        material: result.material_type.clone(),
+        quantity,
+        unit,
+        unit_price,
+        amount,
+        tax: yen(amount as f64 * prices.tax_rate),
+        metadata: result.metadata.clone(),
+    })
+}
+
+/// Priced batch of loads
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invoice {
+    pub items: Vec<LineItem>,
+    /// Sum of the line amounts
+    pub subtotal: u64,
+    /// Tax on the subtotal, rounded once per invoice (may differ from the sum
+    /// of the line taxes by a few yen)
+    pub tax: u64,
+    pub total: u64,
+}
+
+/// Price every result; fails on the first result without a price
+pub fn invoice<'a>(
+    results: impl IntoIterator<Item = &'a BoxOverlayResult>,
+    prices: &PriceTable,
+) -> Result<Invoice, BillingError> {
+    let items = results
+        .into_iter()
+        .map(|r| line_item(r, prices))
+        .collect::<Result<Vec<_>, _>>()?;
+    let subtotal = items.iter().map(|i| i.amount).sum();
+    let tax = yen(subtotal as f64 * prices.tax_rate);
+    Ok(Invoice {
+        items,
+        subtotal,
+        tax,
+        total: subtotal + tax,
+    })
+}
+
+/// Whole yen, fractions dropped (円未満切り捨て)
+fn yen(value: f64) -> u64 {
+    // 3.3 × 1000 = 3299.9999999999995
+    (value + 1e-6).floor().max(0.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    fn billed(tonnage: f64) -> BoxOverlayResult {
+        let config = BoxOverlayConfig::builder()
+            .invoicing(InvoiceRounding::new(0.5, RoundingMode::HalfUp))
+            .build()
+            .unwrap();
+        let mut r = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        r.tonnage = tonnage;
+        r.billed_tonnage = config.invoicing.map(|i| i.round(tonnage));
+        r
+    }
+
+    fn prices() -> PriceTable {
+        PriceTable {
+            prices: HashMap::from([("As殻".to_string(), 1_250)]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_line_item_uses_billed_tonnage() {
+        let item = line_item(&billed(4.3), &prices()).unwrap();
+        assert_eq!(item.material, "As殻");
+        assert_eq!((item.quantity, item.unit), (4.5, "t"));
+        assert_eq!(item.amount, 5_625);
+        assert_eq!(item.tax, 562);
+
+        let mut soil = billed(4.3);
+        soil.material_type = "土砂".into();
+        let err = line_item(&soil, &prices()).unwrap_err();
+        assert_eq!(err.code(), "NO_PRICE");
+    }
+
+    #[test]
+    fn test_invoice_totals() {
+        let loads = [billed(4.3), billed(3.1)];
+        let inv = invoice(&loads, &prices()).unwrap();
+        assert_eq!(inv.items.len(), 2);
+        // 4.5t + 3.0t at 1,250 yen
+        assert_eq!(inv.subtotal, 9_375);
+        assert_eq!(inv.tax, 937);
+        assert_eq!(inv.total, 10_312);
+        let json = serde_json::to_value(&inv).unwrap();
+        assert_eq!(json["items"][0]["unitPrice"], 1_250);
+    }
+
+    #[test]
+    fn test_round_modes() {
+        let half = InvoiceRounding::new(0.5, RoundingMode::HalfUp);
+        assert_eq!(half.round(3.24), 3.0);
+        assert_eq!(half.round(3.25), 3.5);
+        assert_eq!(InvoiceRounding::new(0.5, RoundingMode::Up).round(3.01), 3.5);
+        assert_eq!(InvoiceRounding::new(0.5, RoundingMode::Down).round(3.49), 3.0);
+
+        // Exact multiples stay put despite float noise
+        assert_eq!(InvoiceRounding::new(0.1, RoundingMode::Down).round(3.3), 3.3);
+        assert_eq!(InvoiceRounding::new(0.1, RoundingMode::Up).round(3.3), 3.3);
+        assert_eq!(InvoiceRounding::default().round(4.27), 4.3);
+        assert_eq!(InvoiceRounding::new(0.0, RoundingMode::Up).round(4.27), 4.27);
+    }
+
+    #[test]
+    fn test_metadata_carried_to_line_item() {
+        let config = BoxOverlayConfig::builder()
+            .metadata(LoadMetadata::new().job_number("J-1").with("trip", 2))
+            .build()
+            .unwrap();
+        let result = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        assert_eq!(result.metadata.job_number.as_deref(), Some("J-1"));
+        let json = serde_json::to_value(line_item(&result, &prices()).unwrap()).unwrap();
+        assert_eq!(json["metadata"], serde_json::json!({"jobNumber": "J-1", "trip": 2}));
+        // Omitted when empty
+        let json = serde_json::to_value(line_item(&billed(4.3), &prices()).unwrap()).unwrap();
+        assert!(json.get("metadata").is_none());
+    }
+}
@@ -7,8 +7,61 @@
 //!   compressionFactor = 1.0 + 0.15 * (volume - 2.0)
 //!   effectivePacking = clamp(packing * compressionFactor, 0.7, 0.95)
 //!   tonnage = volume * density * effectivePacking
+//!
+//! v2.0 is the same without the compression correction (compressionFactor
+//! = 1.0). `CoreParams::formula` selects the version, so results stored
+//! under v2.0 recompute exactly and a candidate formula can be compared with
+//! the current one on the same inputs.
+
+use std::fmt;
+
+use crate::i18n::{self, language, Language, Localize};
+use crate::spec::{
+    back_panel_height, default_bed_area, get_material_density, get_material_density_range, get_truck_spec,
+    hinge_height, plate_height_m, Range, SPEC,
+};
+use crate::validation::{validate_params, EstimationParams, ValidationError};
+
+/// Version of the box-overlay formula
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub enum FormulaVersion {
+    /// Packing density used as estimated, without the compression correction
+    #[serde(rename = "2.0")]
+    V2_0,
+    /// Current: packing corrected for compression under the load's own weight
+    #[default]
+    #[serde(rename = "2.1")]
+    V2_1,
+}
+
+impl FormulaVersion {
+    /// Every version, oldest first
+    pub const ALL: [Self; 2] = [Self::V2_0, Self::V2_1];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::V2_0 => "2.0",
+            Self::V2_1 => "2.1",
+        }
+    }
+
+    pub fn is_current(&self) -> bool {
+        *self == Self::default()
+    }
+}
 
-use crate::spec::{get_material_density, get_truck_spec, default_bed_area, SPEC};
+impl std::str::FromStr for FormulaVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let s = s.strip_prefix('v').unwrap_or(s);
+        Self::ALL
+            .into_iter()
+            .find(|v| v.as_str() == s)
+            .ok_or_else(|| format!("unknown formula version: {}", s))
+    }
+}
 
 /// Input parameters for box-overlay tonnage calculation
 #[derive(Debug, Clone)]
@@ -19,6 +72,26 @@ pub struct CoreParams {
     pub taper_ratio: f64,
     pub packing_density: f64,
     pub material_type: String,
+    /// Formula the tonnage is calculated with
+    pub formula: FormulaVersion,
+}
+
+impl CoreParams {
+    /// Check every parameter against the spec ranges before calculating
+    pub fn validated(self) -> Result<Self, Vec<ValidationError>> {
+        let errors = validate_params(&EstimationParams {
+            height: Some(self.height),
+            fill_ratio_l: Some(self.fill_ratio_l),
+            fill_ratio_w: Some(self.fill_ratio_w),
+            taper_ratio: Some(self.taper_ratio),
+            packing_density: Some(self.packing_density),
+        });
+        if errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 /// Calculation result
@@ -28,44 +101,304 @@ pub struct TonnageResult {
     pub volume: f64,
     /// Estimated tonnage
     pub tonnage: f64,
+    /// `tonnage` before rounding, for rounding to a billing unit
+    pub raw_tonnage: f64,
+    /// Tonnage at the low end of the material's density range
+    pub tonnage_min: f64,
+    /// Tonnage at the high end of the material's density range
+    pub tonnage_max: f64,
     /// Effective packing density after compression correction
     pub effective_packing: f64,
     /// Material density used
     pub density: f64,
+    /// Intermediate values of the formula
+    pub breakdown: TonnageBreakdown,
+}
+
+/// Intermediate values of the box-overlay formula (unrounded), so UIs can
+/// show the calculation basis without recomputing it
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TonnageBreakdown {
+    /// Bed length used (m)
+    pub bed_length: f64,
+    /// Bed width used (m)
+    pub bed_width: f64,
+    /// fillRatioL * taperRatio
+    pub effective_l: f64,
+    /// (BOTTOM_FILL + fillRatioW) / 2
+    pub effective_w: f64,
+    /// Compression correction applied to the packing density
+    pub compression_factor: f64,
 }
 
 /// Calculate tonnage using box-overlay formula
 pub fn calculate_tonnage(params: &CoreParams, truck_class: Option<&str>) -> TonnageResult {
+    calculate_tonnage_with(
+        params,
+        bed_dimensions(truck_class),
+        get_material_density(&params.material_type),
+        &get_material_density_range(&params.material_type),
+    )
+}
+
+/// `calculate_tonnage` with the bed (length, width) and material density
+/// (nominal and range) given instead of looked up in the spec
+pub fn calculate_tonnage_with(
+    params: &CoreParams,
+    bed: (f64, f64),
+    density: f64,
+    density_range: &Range,
+) -> TonnageResult {
     let c = &SPEC.constants;
 
-    let (bed_l, bed_w) = truck_class
-        .and_then(|cls| get_truck_spec(cls))
-        .map(|s| (s.bed_length, s.bed_width))
-        .unwrap_or_else(|| {
-            let area = default_bed_area();
-            // Approximate: assume 4t proportions
-            (3.4, area / 3.4)
-        });
+    let (bed_l, bed_w) = bed;
 
     let effective_l = params.fill_ratio_l * params.taper_ratio;
     let effective_w = (c.bottom_fill + params.fill_ratio_w) / 2.0;
     let volume = bed_l * bed_w * params.height * effective_l * effective_w;
 
-    let compression_factor = 1.0 + c.compression_factor * (volume - c.compression_ref_volume);
+    let compression_factor = match params.formula {
+        FormulaVersion::V2_0 => 1.0,
+        FormulaVersion::V2_1 => 1.0 + c.compression_factor * (volume - c.compression_ref_volume),
+    };
     let effective_packing = (params.packing_density * compression_factor)
         .clamp(c.effective_packing_min, c.effective_packing_max);
 
-    let density = get_material_density(&params.material_type);
     let tonnage = volume * density * effective_packing;
 
     TonnageResult {
         volume: round3(volume),
         tonnage: round2(tonnage),
+        raw_tonnage: tonnage,
+        tonnage_min: round2(volume * density_range.min * effective_packing),
+        tonnage_max: round2(volume * density_range.max * effective_packing),
         effective_packing: round3(effective_packing),
         density,
+        breakdown: TonnageBreakdown {
+            bed_length: bed_l,
+            bed_width: bed_w,
+            effective_l,
+            effective_w,
+            compression_factor,
+        },
     }
 }
 
+/// Input parameters for the legacy multi-param calculation
+#[derive(Debug, Clone)]
+pub struct MultiParams {
+    pub height: f64,
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub fill_ratio_z: f64,
+    pub packing_density: f64,
+    pub material_type: String,
+}
+
+/// Legacy multi-param formula:
+///   volume = bedL * bedW * height * fillRatioL * fillRatioW * fillRatioZ
+///   tonnage = volume * density * packingDensity
+pub fn calculate_multi_param(params: &MultiParams, truck_class: Option<&str>) -> TonnageResult {
+    calculate_multi_param_with(
+        params,
+        bed_dimensions(truck_class),
+        get_material_density(&params.material_type),
+        &get_material_density_range(&params.material_type),
+    )
+}
+
+/// `calculate_multi_param` with the bed and material density given, like
+/// `calculate_tonnage_with`. The breakdown carries fillRatioL/W as the
+/// effective length/width and no compression (factor 1.0).
+pub fn calculate_multi_param_with(
+    params: &MultiParams,
+    bed: (f64, f64),
+    density: f64,
+    density_range: &Range,
+) -> TonnageResult {
+    let (bed_l, bed_w) = bed;
+    let volume = bed_l * bed_w * params.height * params.fill_ratio_l * params.fill_ratio_w * params.fill_ratio_z;
+    let packing = params.packing_density;
+
+    let tonnage = volume * density * packing;
+
+    TonnageResult {
+        volume: round3(volume),
+        tonnage: round2(tonnage),
+        raw_tonnage: tonnage,
+        tonnage_min: round2(volume * density_range.min * packing),
+        tonnage_max: round2(volume * density_range.max * packing),
+        effective_packing: round3(packing),
+        density,
+        breakdown: TonnageBreakdown {
+            bed_length: bed_l,
+            bed_width: bed_w,
+            effective_l: params.fill_ratio_l,
+            effective_w: params.fill_ratio_w,
+            compression_factor: 1.0,
+        },
+    }
+}
+
+/// `calculate_tonnage` that rejects parameters outside the spec ranges
+pub fn calculate_tonnage_checked(
+    params: &CoreParams,
+    truck_class: Option<&str>,
+) -> Result<TonnageResult, Vec<ValidationError>> {
+    let params = params.clone().validated()?;
+    Ok(calculate_tonnage(&params, truck_class))
+}
+
+/// Bed (length, width) for a truck class, approximating 4t proportions when unknown
+pub fn bed_dimensions(truck_class: Option<&str>) -> (f64, f64) {
+    truck_class
+        .and_then(get_truck_spec)
+        .map(|s| (s.bed_length, s.bed_width))
+        .unwrap_or_else(|| {
+            let area = default_bed_area();
+            // Approximate: assume 4t proportions
+            (3.4, area / 3.4)
+        })
+}
+
+/// Relative difference between the tailgate and plate scales above which
+/// a fused estimate is flagged as inconsistent
+pub const SCALE_DISAGREEMENT_TOLERANCE: f64 = 0.25;
+
+/// Width / height of Japanese license plates (330×165 mm and 440×220 mm)
+pub const PLATE_ASPECT_RATIO: f64 = 2.0;
+
+/// Allowed relative deviation from `PLATE_ASPECT_RATIO` (perspective, box slop)
+pub const PLATE_ASPECT_TOLERANCE: f64 = 0.35;
+
+/// How the scale reference is chosen in `estimate_height`
+#[derive(Debug, Clone)]
+pub struct ScaleOptions {
+    /// Combine tailgate and plate scales when both are detected
+    /// (default: tailgate only, plate as fallback)
+    pub fuse_plate: bool,
+    /// Reject plate boxes whose pixel aspect ratio is implausible for a plate
+    pub check_plate_aspect: bool,
+    /// Image width / height, needed to turn the normalized plate box into a
+    /// pixel aspect ratio (None = aspect check skipped)
+    pub image_aspect: Option<f64>,
+    /// Plate class (中板 / 大板) selecting the physical plate height
+    /// (None = `PLATE_HEIGHT_M`)
+    pub plate_class: Option<String>,
+    /// Scale of an earlier analysis of the same truck from the same camera
+    /// position; used instead of the detected references (None = detect)
+    pub calibration: Option<ScaleCalibration>,
+}
+
+impl Default for ScaleOptions {
+    fn default() -> Self {
+        Self {
+            fuse_plate: false,
+            check_plate_aspect: true,
+            image_aspect: None,
+            plate_class: None,
+            calibration: None,
+        }
+    }
+}
+
+/// Image scale an analysis measured the load with, for later photos of the
+/// same truck from the same camera position (`analyze_delta`)
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScaleCalibration {
+    /// Scale reference it was measured from ("tailgate", "plate" or "fused")
+    pub method: &'static str,
+    /// Meters per normalized image unit
+    pub m_per_norm: f64,
+}
+
+impl ScaleCalibration {
+    /// Load height from the tailgate top alone, which is `bed_height` above
+    /// the bed floor
+    pub fn height(&self, tg_top: f64, cargo_top: f64, bed_height: f64) -> f64 {
+        (bed_height + (tg_top - cargo_top) * self.m_per_norm).clamp(0.0, MAX_CARGO_HEIGHT_M)
+    }
+}
+
+impl ScaleOptions {
+    /// Whether a normalized plate box has a plausible plate aspect ratio.
+    /// Always true when the check is disabled or the image aspect is unknown.
+    pub fn plate_aspect_ok(&self, plate_box: [f64; 4]) -> bool {
+        let Some(image_aspect) = self.image_aspect.filter(|_| self.check_plate_aspect) else {
+            return true;
+        };
+        let height = plate_box[3] - plate_box[1];
+        if height <= 0.0 {
+            return false;
+        }
+        let aspect = (plate_box[2] - plate_box[0]) * image_aspect / height;
+        (aspect / PLATE_ASPECT_RATIO - 1.0).abs() <= PLATE_ASPECT_TOLERANCE
+    }
+}
+
+/// Height estimate with the scale references it was derived from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScaleEstimate {
+    pub height_m: f64,
+    /// "tailgate", "plate", "fused" or "none"
+    pub method: &'static str,
+    /// Meters per normalized unit from the tailgate
+    pub tailgate_scale: Option<f64>,
+    /// Meters per normalized unit from the license plate
+    pub plate_scale: Option<f64>,
+    /// |tailgate / plate - 1| when both references were available
+    pub scale_disagreement: Option<f64>,
+    /// A plate box was detected but failed the aspect-ratio check
+    pub plate_rejected: bool,
+    /// Meters per normalized unit the height was scaled with (None = "none")
+    pub m_per_norm: Option<f64>,
+}
+
+impl ScaleEstimate {
+    /// Both references were found but disagree beyond `SCALE_DISAGREEMENT_TOLERANCE`
+    pub fn is_inconsistent(&self) -> bool {
+        self.scale_disagreement
+            .is_some_and(|d| d > SCALE_DISAGREEMENT_TOLERANCE)
+    }
+}
+
+/// Cargo peak relative to the spec's calibration landmarks, as answered to
+/// the landmark prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Landmark {
+    #[serde(rename = "後板未満")]
+    BelowBackPanel,
+    #[serde(rename = "後板と同じ")]
+    AtBackPanel,
+    #[serde(rename = "後板とヒンジの間")]
+    BetweenBackPanelAndHinge,
+    #[serde(rename = "ヒンジと同じ")]
+    AtHinge,
+    #[serde(rename = "ヒンジ超え")]
+    AboveHinge,
+}
+
+/// Deterministic height for a landmark answer.
+///
+/// The two calibration heights (後板 0.30 m, ヒンジ 0.60 m) map directly;
+/// the open-ended answers sit half the landmark spacing beyond them, and
+/// the result is clamped to the spec height range.
+pub fn landmark_height(landmark: Landmark) -> f64 {
+    let (back_panel, hinge) = (back_panel_height(), hinge_height());
+    let half = (hinge - back_panel) / 2.0;
+    let height = match landmark {
+        Landmark::BelowBackPanel => back_panel - half,
+        Landmark::AtBackPanel => back_panel,
+        Landmark::BetweenBackPanelAndHinge => back_panel + half,
+        Landmark::AtHinge => hinge,
+        Landmark::AboveHinge => hinge + half,
+    };
+    let range = &SPEC.ranges.height;
+    height.clamp(range.min, range.max)
+}
+
 /// Geometry-based height calculation from normalized image coordinates
 ///
 /// Returns (height_m, scale_method)
@@ -79,31 +412,74 @@ pub fn height_from_geometry(
     plate_box: Option<[f64; 4]>,
     bed_height: f64,
 ) -> (f64, &'static str) {
+    let est = estimate_height(tg_top, tg_bot, cargo_top, plate_box, bed_height, &ScaleOptions::default());
+    (est.height_m, est.method)
+}
+
+/// `height_from_geometry` with both scale references reported.
+///
+/// With `fuse_plate`, a detected plate is combined with the tailgate as a
+/// weighted average of the two meter-per-normalized factors, each weighted
+/// by the normalized size of its reference ("fused"). A `calibration` in the
+/// options replaces both references; only the tailgate top is needed then.
+pub fn estimate_height(
+    tg_top: f64,
+    tg_bot: f64,
+    cargo_top: f64,
+    plate_box: Option<[f64; 4]>,
+    bed_height: f64,
+    options: &ScaleOptions,
+) -> ScaleEstimate {
     let c = &SPEC.constants;
 
+    if let Some(calibration) = options.calibration.as_ref().filter(|_| tg_top > 0.0) {
+        return ScaleEstimate {
+            height_m: calibration.height(tg_top, cargo_top, bed_height),
+            method: calibration.method,
+            tailgate_scale: None,
+            plate_scale: None,
+            scale_disagreement: None,
+            plate_rejected: false,
+            m_per_norm: Some(calibration.m_per_norm),
+        };
+    }
+
     let has_tailgate = tg_bot > 0.0 && tg_bot > tg_top;
+    let tg_height_norm = tg_bot - tg_top;
 
     let plate_height_norm = plate_box
         .map(|pb| pb[3] - pb[1])
         .unwrap_or(0.0);
-    let has_plate = plate_height_norm > c.plate_min_norm;
+    let plate_rejected = plate_height_norm > c.plate_min_norm
+        && plate_box.is_some_and(|pb| !options.plate_aspect_ok(pb));
+    let has_plate = plate_height_norm > c.plate_min_norm && !plate_rejected;
 
-    if !has_plate && !has_tailgate {
-        return (0.0, "none");
-    }
+    let tailgate_scale = has_tailgate.then(|| bed_height / tg_height_norm);
+    let plate_scale = has_plate.then(|| plate_height_m(options.plate_class.as_deref()) / plate_height_norm);
+    let scale_disagreement = match (tailgate_scale, plate_scale) {
+        (Some(t), Some(p)) => Some((t / p - 1.0).abs()),
+        _ => None,
+    };
 
-    let (cargo_height_m, method) = if has_tailgate {
-        let tg_height_norm = tg_bot - tg_top;
-        let m_per_norm = bed_height / tg_height_norm;
-        let h = (tg_bot - cargo_top) * m_per_norm;
-        (h, "tailgate")
-    } else {
-        let m_per_norm = c.plate_height_m / plate_height_norm;
-        let h = bed_height + (tg_top - cargo_top) * m_per_norm;
-        (h, "plate")
+    let (cargo_height_m, method, m_per_norm) = match (tailgate_scale, plate_scale) {
+        (Some(t), Some(p)) if options.fuse_plate => {
+            let m_per_norm = (t * tg_height_norm + p * plate_height_norm) / (tg_height_norm + plate_height_norm);
+            ((tg_bot - cargo_top) * m_per_norm, "fused", Some(m_per_norm))
+        }
+        (Some(t), _) => ((tg_bot - cargo_top) * t, "tailgate", Some(t)),
+        (None, Some(p)) => (bed_height + (tg_top - cargo_top) * p, "plate", Some(p)),
+        (None, None) => (0.0, "none", None),
     };
 
-    (cargo_height_m.clamp(0.0, 0.8), method)
+    ScaleEstimate {
+        height_m: if method == "none" { 0.0 } else { cargo_height_m.clamp(0.0, MAX_CARGO_HEIGHT_M) },
+        method,
+        tailgate_scale,
+        plate_scale,
+        scale_disagreement,
+        plate_rejected,
+        m_per_norm,
+    }
 }
 
 fn round2(v: f64) -> f64 {
@@ -114,9 +490,266 @@ fn round3(v: f64) -> f64 {
     (v * 1000.0).round() / 1000.0
 }
 
+// ─── Invariant checks ────────────────────────────────────────────────
+
+/// Highest load above the bed that `estimate_height` reports (m)
+pub const MAX_CARGO_HEIGHT_M: f64 = 0.8;
+
+/// Height step of the monotonicity check in `debug_checks` (m)
+const MONOTONIC_STEP_M: f64 = 0.05;
+
+/// Slack for the rounding of `TonnageResult` values
+const ROUNDING_SLACK: f64 = 0.005;
+
+/// Physically impossible outcome of a calculation, found by `debug_checks`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum InvariantViolation {
+    NonFinite { field: &'static str, value: f64 },
+    /// A higher load weighed less
+    NotMonotonic {
+        height: f64,
+        tonnage: f64,
+        higher_height: f64,
+        higher_tonnage: f64,
+    },
+    /// Volume above bed length × width × `MAX_CARGO_HEIGHT_M`
+    VolumeExceedsBed { volume: f64, limit: f64 },
+    PackingOutOfRange { value: f64, min: f64, max: f64 },
+}
+
+impl InvariantViolation {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NonFinite { .. } => "INVARIANT_NON_FINITE",
+            Self::NotMonotonic { .. } => "INVARIANT_NOT_MONOTONIC",
+            Self::VolumeExceedsBed { .. } => "INVARIANT_VOLUME_EXCEEDS_BED",
+            Self::PackingOutOfRange { .. } => "INVARIANT_PACKING_OUT_OF_RANGE",
+        }
+    }
+}
+
+impl Localize for InvariantViolation {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::NonFinite { field, value } => i18n::INVARIANT_NON_FINITE.format(lang, &[field, value]),
+            Self::NotMonotonic {
+                height,
+                tonnage,
+                higher_height,
+                higher_tonnage,
+            } => i18n::INVARIANT_NOT_MONOTONIC.format(lang, &[higher_height, higher_tonnage, height, tonnage]),
+            Self::VolumeExceedsBed { volume, limit } => {
+                i18n::INVARIANT_VOLUME_EXCEEDS_BED.format(lang, &[volume, limit])
+            }
+            Self::PackingOutOfRange { value, min, max } => {
+                i18n::INVARIANT_PACKING_OUT_OF_RANGE.format(lang, &[value, min, max])
+            }
+        }
+    }
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// Check a calculation against physical invariants (opt-in through
+/// `BoxOverlayConfig::debug_checks`): every value finite, tonnage not
+/// decreasing with height, volume within bed length × width ×
+/// `MAX_CARGO_HEIGHT_M`, effective packing within its clamp.
+///
+/// `result` must come from `params`; the bed and density are taken from it.
+pub fn debug_checks(params: &CoreParams, result: &TonnageResult) -> Vec<InvariantViolation> {
+    let c = &SPEC.constants;
+    let mut violations: Vec<InvariantViolation> = [
+        ("volume", result.volume),
+        ("tonnage", result.tonnage),
+        ("tonnageMin", result.tonnage_min),
+        ("tonnageMax", result.tonnage_max),
+        ("effectivePacking", result.effective_packing),
+    ]
+    .into_iter()
+    .filter(|(_, value)| !value.is_finite())
+    .map(|(field, value)| InvariantViolation::NonFinite { field, value })
+    .collect();
+    if !violations.is_empty() {
+        return violations;
+    }
+
+    let bed = (result.breakdown.bed_length, result.breakdown.bed_width);
+    let higher_params = CoreParams {
+        height: params.height + MONOTONIC_STEP_M,
+        ..params.clone()
+    };
+    let density = Range {
+        min: result.density,
+        max: result.density,
+    };
+    let higher = calculate_tonnage_with(&higher_params, bed, result.density, &density);
+    if higher.tonnage + ROUNDING_SLACK < result.tonnage {
+        violations.push(InvariantViolation::NotMonotonic {
+            height: params.height,
+            tonnage: result.tonnage,
+            higher_height: higher_params.height,
+            higher_tonnage: higher.tonnage,
+        });
+    }
+
+    let limit = bed.0 * bed.1 * MAX_CARGO_HEIGHT_M;
+    if result.volume > limit + ROUNDING_SLACK {
+        violations.push(InvariantViolation::VolumeExceedsBed {
+            volume: result.volume,
+            limit: round3(limit),
+        });
+    }
+
+    let (min, max) = (c.effective_packing_min, c.effective_packing_max);
+    if result.effective_packing < min - ROUNDING_SLACK || result.effective_packing > max + ROUNDING_SLACK {
+        violations.push(InvariantViolation::PackingOutOfRange {
+            value: result.effective_packing,
+            min,
+            max,
+        });
+    }
+    violations
+}
+
+// ─── Calculation tree ────────────────────────────────────────────────
+
+/// One term of the tonnage formula with the terms it is derived from
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalcTree {
+    /// Spec field name (`tonnage`, `volume`, `fillRatioL`, ...)
+    pub key: String,
+    /// Display label
+    pub label: String,
+    pub value: f64,
+    /// Unit ("" for dimensionless ratios)
+    pub unit: String,
+    /// How the value is derived from the children (None for inputs/constants)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub formula: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<CalcTree>,
+}
+
+impl CalcTree {
+    fn leaf(key: &str, label: &str, value: f64, unit: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            label: label.to_string(),
+            value,
+            unit: unit.to_string(),
+            formula: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn node(key: &str, label: &str, value: f64, unit: &str, formula: &str, children: Vec<CalcTree>) -> Self {
+        Self {
+            formula: Some(formula.to_string()),
+            children,
+            ..Self::leaf(key, label, value, unit)
+        }
+    }
+
+    /// Find a term anywhere in the tree by key
+    pub fn find(&self, key: &str) -> Option<&CalcTree> {
+        if self.key == key {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(key))
+    }
+}
+
+/// Derivation of `calculate_tonnage` as a tree of formula terms
+pub fn breakdown(params: &CoreParams, truck_class: Option<&str>) -> CalcTree {
+    let c = &SPEC.constants;
+    let result = calculate_tonnage(params, truck_class);
+    let b = result.breakdown;
+
+    let effective_l = CalcTree::node(
+        "effectiveL",
+        "有効長さ比",
+        b.effective_l,
+        "",
+        "fillRatioL × taperRatio",
+        vec![
+            CalcTree::leaf("fillRatioL", "充填率 (長さ)", params.fill_ratio_l, ""),
+            CalcTree::leaf("taperRatio", "テーパー比", params.taper_ratio, ""),
+        ],
+    );
+    let effective_w = CalcTree::node(
+        "effectiveW",
+        "有効幅比",
+        b.effective_w,
+        "",
+        "(bottomFill + fillRatioW) / 2",
+        vec![
+            CalcTree::leaf("bottomFill", "底部充填率", c.bottom_fill, ""),
+            CalcTree::leaf("fillRatioW", "充填率 (幅)", params.fill_ratio_w, ""),
+        ],
+    );
+    let volume = CalcTree::node(
+        "volume",
+        "体積",
+        result.volume,
+        "m³",
+        "bedLength × bedWidth × height × effectiveL × effectiveW",
+        vec![
+            CalcTree::leaf("bedLength", "荷台長", b.bed_length, "m"),
+            CalcTree::leaf("bedWidth", "荷台幅", b.bed_width, "m"),
+            CalcTree::leaf("height", "積載高さ", params.height, "m"),
+            effective_l,
+            effective_w,
+        ],
+    );
+    let compression = CalcTree::node(
+        "compressionFactor",
+        "圧縮補正係数",
+        b.compression_factor,
+        "",
+        "1 + k × (volume - refVolume)",
+        vec![
+            CalcTree::leaf("k", "圧縮係数", c.compression_factor, ""),
+            CalcTree::leaf("refVolume", "基準体積", c.compression_ref_volume, "m³"),
+        ],
+    );
+    let effective_packing = CalcTree::node(
+        "effectivePacking",
+        "有効充填密度",
+        result.effective_packing,
+        "",
+        &format!(
+            "clamp(packingDensity × compressionFactor, {}, {})",
+            c.effective_packing_min, c.effective_packing_max
+        ),
+        vec![
+            CalcTree::leaf("packingDensity", "充填密度", params.packing_density, ""),
+            compression,
+        ],
+    );
+    let density = CalcTree::leaf("density", &format!("比重 ({})", params.material_type), result.density, "t/m³");
+
+    CalcTree::node(
+        "tonnage",
+        "推定重量",
+        result.tonnage,
+        "t",
+        "volume × density × effectivePacking",
+        vec![volume, density, effective_packing],
+    )
+}
+
 /// WASM-friendly version
 #[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "wasm")]
+use crate::wasm::{export, require_spec, ExportValue};
 
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = "calculateTonnage")]
@@ -128,7 +761,8 @@ pub fn calculate_tonnage_wasm(
     packing_density: f64,
     material_type: &str,
     truck_class: Option<String>,
-) -> String {
+) -> Result<ExportValue, JsError> {
+    require_spec()?;
     let params = CoreParams {
         height,
         fill_ratio_l,
@@ -136,14 +770,42 @@ pub fn calculate_tonnage_wasm(
         taper_ratio,
         packing_density,
         material_type: material_type.to_string(),
+        formula: FormulaVersion::default(),
     };
     let result = calculate_tonnage(&params, truck_class.as_deref());
-    serde_json::json!({
+    Ok(export(&serde_json::json!({
         "volume": result.volume,
         "tonnage": result.tonnage,
+        "tonnageMin": result.tonnage_min,
+        "tonnageMax": result.tonnage_max,
         "effectivePacking": result.effective_packing,
         "density": result.density,
-    }).to_string()
+        "breakdown": result.breakdown,
+    })))
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = "calculationBreakdown")]
+pub fn breakdown_wasm(
+    height: f64,
+    fill_ratio_l: f64,
+    fill_ratio_w: f64,
+    taper_ratio: f64,
+    packing_density: f64,
+    material_type: &str,
+    truck_class: Option<String>,
+) -> Result<ExportValue, JsError> {
+    require_spec()?;
+    let params = CoreParams {
+        height,
+        fill_ratio_l,
+        fill_ratio_w,
+        taper_ratio,
+        packing_density,
+        material_type: material_type.to_string(),
+        formula: FormulaVersion::default(),
+    };
+    Ok(export(&breakdown(&params, truck_class.as_deref())))
 }
 
 #[cfg(feature = "wasm")]
@@ -154,14 +816,15 @@ pub fn height_from_geometry_wasm(
     cargo_top: f64,
     plate_box_json: Option<String>,
     bed_height: f64,
-) -> String {
+) -> Result<ExportValue, JsError> {
+    require_spec()?;
     let plate_box: Option<[f64; 4]> = plate_box_json
         .and_then(|s| serde_json::from_str(&s).ok());
     let (height_m, method) = height_from_geometry(tg_top, tg_bot, cargo_top, plate_box, bed_height);
-    serde_json::json!({
+    Ok(export(&serde_json::json!({
         "heightM": height_m,
         "scaleMethod": method,
-    }).to_string()
+    })))
 }
 
 #[cfg(test)]
@@ -176,6 +839,7 @@ mod tests {
             taper_ratio: 0.85,
             packing_density: 0.80,
             material_type: "As殻".to_string(),
+            formula: FormulaVersion::default(),
         }
     }
 
@@ -212,6 +876,21 @@ mod tests {
         assert!((result_as.volume - result_soil.volume).abs() < 0.001);
     }
 
+    #[test]
+    fn test_tonnage_interval_from_density_range() {
+        let mut params = default_params();
+        params.material_type = "土砂".to_string(); // 1.6 - 2.0
+        let r = calculate_tonnage(&params, Some("4t"));
+        assert!(r.tonnage_min < r.tonnage && r.tonnage < r.tonnage_max);
+        let scale = r.tonnage / r.density;
+        assert!((r.tonnage_min - scale * 1.6).abs() < 0.02);
+        assert!((r.tonnage_max - scale * 2.0).abs() < 0.02);
+
+        // No range given: the interval collapses to the nominal value
+        let r = calculate_tonnage_with(&params, (3.4, 2.06), 1.8, &Range { min: 1.8, max: 1.8 });
+        assert_eq!((r.tonnage_min, r.tonnage_max), (r.tonnage, r.tonnage));
+    }
+
     #[test]
     fn test_formula_matches_ts() {
         // Match the TypeScript calculateBoxOverlay function exactly
@@ -222,6 +901,7 @@ mod tests {
             taper_ratio: 0.9,
             packing_density: 0.80,
             material_type: "As殻".to_string(),
+            formula: FormulaVersion::default(),
         };
         let result = calculate_tonnage(&params, Some("4t"));
 
@@ -247,11 +927,49 @@ mod tests {
             taper_ratio: 1.0,
             packing_density: 0.9,
             material_type: "As殻".to_string(),
+            formula: FormulaVersion::default(),
         };
         let result = calculate_tonnage(&params, Some("10t"));
         assert!(result.effective_packing <= 0.95);
     }
 
+    #[test]
+    fn test_calculate_multi_param() {
+        let params = MultiParams {
+            height: 0.4,
+            fill_ratio_l: 0.8,
+            fill_ratio_w: 0.85,
+            fill_ratio_z: 0.9,
+            packing_density: 0.7,
+            material_type: "As殻".to_string(),
+        };
+        let result = calculate_multi_param(&params, Some("4t"));
+        // 3.4 * 2.06 * 0.4 * 0.8 * 0.85 * 0.9 = 1.7146..., * 2.5 * 0.7 = 3.00
+        assert_eq!(result.volume, 1.715);
+        assert_eq!(result.tonnage, 3.0);
+        assert_eq!(result.breakdown.compression_factor, 1.0);
+    }
+
+    #[test]
+    fn test_formula_versions() {
+        let current = calculate_tonnage(&default_params(), Some("4t"));
+        let legacy_params = CoreParams {
+            formula: FormulaVersion::V2_0,
+            ..default_params()
+        };
+        let legacy = calculate_tonnage(&legacy_params, Some("4t"));
+        assert_eq!(legacy.volume, current.volume);
+        // v2.0 takes the estimated packing as is; below the 2 m³ reference
+        // volume v2.1 compresses it
+        assert_eq!(legacy.effective_packing, 0.8);
+        assert!(current.effective_packing < legacy.effective_packing);
+        assert!(current.tonnage < legacy.tonnage);
+
+        assert_eq!("v2.0".parse::<FormulaVersion>(), Ok(FormulaVersion::V2_0));
+        assert_eq!(serde_json::to_string(&FormulaVersion::default()).unwrap(), r#""2.1""#);
+        assert!("3.0".parse::<FormulaVersion>().is_err());
+    }
+
     #[test]
     fn test_height_from_geometry_tailgate() {
         // tailgate top=0.3, bot=0.5, cargo_top=0.2, bed_height=0.32
@@ -285,4 +1003,159 @@ mod tests {
         let (h, _) = height_from_geometry(0.5, 0.9, 0.0, None, 0.50);
         assert!(h <= 0.8);
     }
+
+    #[test]
+    fn test_breakdown_matches_formula() {
+        let p = default_params();
+        let r = calculate_tonnage(&p, Some("4t"));
+        let b = r.breakdown;
+        assert!((b.effective_l - p.fill_ratio_l * p.taper_ratio).abs() < 1e-12);
+        let volume = b.bed_length * b.bed_width * p.height * b.effective_l * b.effective_w;
+        assert!((round3(volume) - r.volume).abs() < 1e-12);
+        let c = &SPEC.constants;
+        assert!((b.compression_factor - (1.0 + c.compression_factor * (volume - c.compression_ref_volume))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_breakdown_tree_matches_calculation() {
+        let p = default_params();
+        let r = calculate_tonnage(&p, Some("4t"));
+        let tree = breakdown(&p, Some("4t"));
+        assert_eq!(tree.key, "tonnage");
+        assert_eq!(tree.value, r.tonnage);
+        assert_eq!(tree.find("volume").unwrap().value, r.volume);
+        assert_eq!(tree.find("taperRatio").unwrap().value, p.taper_ratio);
+        assert_eq!(tree.find("bedLength").unwrap().unit, "m");
+
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(json["children"][0]["key"], "volume");
+        assert!(json["children"][1].get("children").is_none());
+    }
+
+    #[test]
+    fn test_validated_rejects_absurd_height() {
+        assert!(default_params().validated().is_ok());
+
+        let params = CoreParams {
+            height: 5.0,
+            taper_ratio: 0.1,
+            ..default_params()
+        };
+        let errors = calculate_tonnage_checked(&params, Some("4t")).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["height", "taperRatio"]);
+    }
+
+    #[test]
+    fn test_fused_scale_combines_references() {
+        // Tailgate: 0.32 m / 0.2 = 1.6 m/unit; plate: 0.22 m / 0.11 = 2.0 m/unit
+        let plate = Some([0.4, 0.6, 0.6, 0.71]);
+        let options = ScaleOptions {
+            fuse_plate: true,
+            ..Default::default()
+        };
+        let est = estimate_height(0.3, 0.5, 0.3, plate, 0.32, &options);
+        assert_eq!(est.method, "fused");
+        let fused = (1.6 * 0.2 + 2.0 * 0.11) / 0.31;
+        assert!((est.height_m - 0.2 * fused).abs() < 1e-9);
+        assert!((est.scale_disagreement.unwrap() - 0.2).abs() < 1e-9);
+        assert!(!est.is_inconsistent());
+
+        // Default options ignore the plate when a tailgate is present
+        let (h, method) = height_from_geometry(0.3, 0.5, 0.3, plate, 0.32);
+        assert_eq!(method, "tailgate");
+        assert!((h - 0.32).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fused_scale_flags_inconsistent_references() {
+        let tiny_plate = Some([0.4, 0.6, 0.6, 0.63]);
+        let options = ScaleOptions {
+            fuse_plate: true,
+            ..Default::default()
+        };
+        let est = estimate_height(0.3, 0.5, 0.3, tiny_plate, 0.32, &options);
+        assert!(est.is_inconsistent());
+    }
+
+    #[test]
+    fn test_plate_aspect_check() {
+        // 4:3 landscape photo; plate 0.15 wide x 0.1 tall -> 0.15 * 1.333 / 0.1 = 2.0
+        let plate = [0.4, 0.7, 0.55, 0.8];
+        let options = ScaleOptions {
+            image_aspect: Some(4.0 / 3.0),
+            ..Default::default()
+        };
+        assert!(options.plate_aspect_ok(plate));
+        // Tall, narrow hallucinated box
+        assert!(!options.plate_aspect_ok([0.4, 0.6, 0.45, 0.8]));
+        // Unknown image aspect: check skipped
+        assert!(ScaleOptions::default().plate_aspect_ok([0.4, 0.6, 0.45, 0.8]));
+
+        let est = estimate_height(0.3, 0.0, 0.2, Some([0.4, 0.6, 0.45, 0.8]), 0.32, &options);
+        assert!(est.plate_rejected);
+        assert_eq!(est.method, "none");
+    }
+
+    #[test]
+    fn test_plate_class_changes_plate_scale() {
+        let plate = Some([0.4, 0.7, 0.6, 0.81]);
+        let large = estimate_height(0.3, 0.0, 0.2, plate, 0.32, &ScaleOptions::default());
+        let medium = ScaleOptions {
+            plate_class: Some("中板".into()),
+            ..Default::default()
+        };
+        let small = estimate_height(0.3, 0.0, 0.2, plate, 0.32, &medium);
+        assert!((large.plate_scale.unwrap() - 0.22 / 0.11).abs() < 1e-9);
+        assert!((small.plate_scale.unwrap() - 0.165 / 0.11).abs() < 1e-9);
+        assert!(small.height_m < large.height_m);
+    }
+
+    #[test]
+    fn test_debug_checks() {
+        let params = CoreParams {
+            height: 0.4,
+            fill_ratio_l: 0.8,
+            fill_ratio_w: 0.85,
+            taper_ratio: 0.9,
+            packing_density: 0.8,
+            material_type: "As殻".to_string(),
+            formula: FormulaVersion::default(),
+        };
+        let result = calculate_tonnage(&params, Some("4t"));
+        assert!(debug_checks(&params, &result).is_empty());
+
+        // A height the estimator never reports fills more than the bed allows
+        let tall = CoreParams { height: 1.5, ..params.clone() };
+        let violations = debug_checks(&tall, &calculate_tonnage(&tall, Some("4t")));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].code(), "INVARIANT_VOLUME_EXCEEDS_BED");
+
+        let mut broken = result.clone();
+        broken.effective_packing = 1.4;
+        broken.tonnage = 50.0;
+        let codes: Vec<&str> = debug_checks(&params, &broken).iter().map(|v| v.code()).collect();
+        assert_eq!(codes, ["INVARIANT_NOT_MONOTONIC", "INVARIANT_PACKING_OUT_OF_RANGE"]);
+
+        broken.volume = f64::NAN;
+        assert_eq!(debug_checks(&params, &broken)[0].code(), "INVARIANT_NON_FINITE");
+    }
+
+    #[test]
+    fn test_landmark_height() {
+        let heights: Vec<f64> = [
+            Landmark::BelowBackPanel,
+            Landmark::AtBackPanel,
+            Landmark::BetweenBackPanelAndHinge,
+            Landmark::AtHinge,
+            Landmark::AboveHinge,
+        ]
+        .into_iter()
+        .map(landmark_height)
+        .collect();
+        let expected = [0.15, 0.30, 0.45, 0.60, 0.75];
+        for (h, e) in heights.iter().zip(expected) {
+            assert!((h - e).abs() < 1e-9, "{} != {}", h, e);
+        }
+    }
 }
@@ -0,0 +1,112 @@
+//! Before/after comparison of analysis results
+//!
+//! Used when a load is re-analyzed with a different spec, prompt variant or
+//! backend, to see what moved and which runs answered differently.
+
+use crate::pipeline::{BoxOverlayResult, FillRunLog, GeometryRunLog};
+
+/// Differences between two results (`other - self` for numeric deltas)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultDiff {
+    pub height_m: f64,
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub taper_ratio: f64,
+    pub effective_packing: f64,
+    pub volume: f64,
+    pub tonnage: f64,
+    /// (before, after) when the material changed
+    pub material_type: Option<(String, String)>,
+    /// Indices of geometry runs that differ or exist on one side only
+    pub geometry_runs: Vec<usize>,
+    /// Indices of fill runs that differ or exist on one side only
+    pub fill_runs: Vec<usize>,
+}
+
+impl ResultDiff {
+    /// True when no value, material or run changed
+    pub fn is_empty(&self) -> bool {
+        [
+            self.height_m,
+            self.fill_ratio_l,
+            self.fill_ratio_w,
+            self.taper_ratio,
+            self.effective_packing,
+            self.volume,
+            self.tonnage,
+        ]
+        .iter()
+        .all(|d| *d == 0.0)
+            && self.material_type.is_none()
+            && self.geometry_runs.is_empty()
+            && self.fill_runs.is_empty()
+    }
+}
+
+impl BoxOverlayResult {
+    /// Compare this result (before) with `other` (after)
+    pub fn diff(&self, other: &BoxOverlayResult) -> ResultDiff {
+        ResultDiff {
+            height_m: other.height_m - self.height_m,
+            fill_ratio_l: other.fill_ratio_l - self.fill_ratio_l,
+            fill_ratio_w: other.fill_ratio_w - self.fill_ratio_w,
+            taper_ratio: other.taper_ratio - self.taper_ratio,
+            effective_packing: other.effective_packing - self.effective_packing,
+            volume: other.volume - self.volume,
+            tonnage: other.tonnage - self.tonnage,
+            material_type: (self.material_type != other.material_type)
+                .then(|| (self.material_type.clone(), other.material_type.clone())),
+            geometry_runs: changed_runs(&self.geometry_runs, &other.geometry_runs, same_geometry_run),
+            fill_runs: changed_runs(&self.fill_runs, &other.fill_runs, same_fill_run),
+        }
+    }
+}
+
+fn changed_runs<T>(before: &[T], after: &[T], same: fn(&T, &T) -> bool) -> Vec<usize> {
+    (0..before.len().max(after.len()))
+        .filter(|&i| match (before.get(i), after.get(i)) {
+            (Some(a), Some(b)) => !same(a, b),
+            _ => true,
+        })
+        .collect()
+}
+
+fn same_geometry_run(a: &GeometryRunLog, b: &GeometryRunLog) -> bool {
+    a.variant == b.variant
+        && a.backend == b.backend
+        && a.raw_response == b.raw_response
+        && a.scale_method == b.scale_method
+        && a.height_m == b.height_m
+}
+
+fn same_fill_run(a: &FillRunLog, b: &FillRunLog) -> bool {
+    a.variant == b.variant && a.backend == b.backend && a.raw_response == b.raw_response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    fn run(cargo_top: f64) -> crate::pipeline::BoxOverlayResult {
+        analyze_box_overlay(&fixed_backend().with_cargo_top(cargo_top), &[], &BoxOverlayConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_identical_results_have_empty_diff() {
+        assert!(run(0.2).diff(&run(0.2)).is_empty());
+    }
+
+    #[test]
+    fn test_diff_reports_deltas_and_changed_runs() {
+        let before = run(0.2);
+        let after = run(0.25);
+        let d = before.diff(&after);
+        assert!(d.height_m < 0.0);
+        assert!(d.tonnage < 0.0);
+        assert!((d.volume - (after.volume - before.volume)).abs() < 1e-12);
+        assert_eq!(d.geometry_runs, [0, 1]);
+        assert!(d.fill_runs.is_empty());
+        assert!(d.material_type.is_none());
+    }
+}
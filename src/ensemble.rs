@@ -0,0 +1,530 @@
+//! Ensemble aggregation and agreement statistics
+//!
+//! Helpers used by the pipeline to combine multiple AI runs and to judge how
+//! well those runs agree with each other.
+
+use crate::i18n::{self, language, Language, Localize};
+
+/// Median as `sorted[len / 2]` (upper median for even lengths).
+///
+/// This is what results before `MedianMode` were computed with; the pipeline
+/// now takes `BoxOverlayConfig::median`, which by default averages the two
+/// middle values like the TypeScript implementation.
+pub fn median(arr: &[f64]) -> f64 {
+    MedianMode::Upper.median(arr)
+}
+
+fn sorted(arr: &[f64]) -> Vec<f64> {
+    let mut sorted = arr.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+}
+
+/// `q`-quantile (`q` clamped to 0..=1), interpolating linearly between the
+/// two closest ranks at `(len - 1) * q` (numpy's default); NaN when empty
+pub fn quantile(arr: &[f64], q: f64) -> f64 {
+    if arr.is_empty() {
+        return f64::NAN;
+    }
+    let sorted = sorted(arr);
+    let rank = (sorted.len() - 1) as f64 * q.clamp(0.0, 1.0);
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// Which value the median of an even number of runs takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MedianMode {
+    /// Mean of the two middle values (`quantile(.., 0.5)`), as
+    /// boxOverlayService.ts computes it
+    #[default]
+    Average,
+    /// Upper middle value, `sorted[len / 2]` (results before this setting)
+    Upper,
+    /// Lower middle value
+    Lower,
+}
+
+impl MedianMode {
+    /// Median of `arr`; NaN when empty
+    pub fn median(self, arr: &[f64]) -> f64 {
+        if arr.is_empty() {
+            return f64::NAN;
+        }
+        let sorted = sorted(arr);
+        let len = sorted.len();
+        match self {
+            Self::Average => quantile(&sorted, 0.5),
+            Self::Upper => sorted[len / 2],
+            Self::Lower => sorted[(len - 1) / 2],
+        }
+    }
+}
+
+/// Arithmetic mean
+pub fn average(arr: &[f64]) -> f64 {
+    arr.iter().sum::<f64>() / arr.len() as f64
+}
+
+/// Population standard deviation (0 for fewer than two values)
+pub fn std_dev(arr: &[f64]) -> f64 {
+    if arr.len() < 2 {
+        return 0.0;
+    }
+    let mean = average(arr);
+    (arr.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / arr.len() as f64).sqrt()
+}
+
+/// Coefficient of variation (std_dev / mean, 0 when the mean is 0)
+pub fn coefficient_of_variation(arr: &[f64]) -> f64 {
+    if arr.is_empty() {
+        return 0.0;
+    }
+    let mean = average(arr);
+    if mean.abs() < f64::EPSILON {
+        return 0.0;
+    }
+    std_dev(arr) / mean.abs()
+}
+
+/// max - min (0 for an empty slice)
+pub fn spread(arr: &[f64]) -> f64 {
+    if arr.is_empty() {
+        return 0.0;
+    }
+    let max = arr.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let min = arr.iter().cloned().fold(f64::INFINITY, f64::min);
+    max - min
+}
+
+// ─── Run statistics ──────────────────────────────────────────────────
+
+/// Per-run values of one parameter with summary statistics
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParamStats {
+    /// Values of the valid runs, in run order
+    pub values: Vec<f64>,
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+}
+
+impl ParamStats {
+    /// Summarize values (all statistics 0 when empty), with the default
+    /// `MedianMode`
+    pub fn from_values(values: &[f64]) -> Self {
+        Self::with_median(values, MedianMode::default())
+    }
+
+    /// `from_values` with the median taken by `mode`
+    pub fn with_median(values: &[f64], mode: MedianMode) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        Self {
+            values: values.to_vec(),
+            mean: average(values),
+            median: mode.median(values),
+            std_dev: std_dev(values),
+        }
+    }
+
+    /// Coefficient of variation of the values
+    pub fn cv(&self) -> f64 {
+        coefficient_of_variation(&self.values)
+    }
+}
+
+/// Per-parameter statistics over all valid ensemble runs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunStatistics {
+    pub height: ParamStats,
+    pub fill_ratio_l: ParamStats,
+    pub fill_ratio_w: ParamStats,
+    pub taper_ratio: ParamStats,
+    pub packing_density: ParamStats,
+}
+
+impl RunStatistics {
+    pub fn new(heights: &[f64], fill: &FillSamples) -> Self {
+        Self::with_median(heights, fill, MedianMode::default())
+    }
+
+    /// `new` with the medians taken by `mode`
+    pub fn with_median(heights: &[f64], fill: &FillSamples, mode: MedianMode) -> Self {
+        let stats = |values: &[f64]| ParamStats::with_median(values, mode);
+        Self {
+            height: stats(heights),
+            fill_ratio_l: stats(&fill.fill_ratio_l),
+            fill_ratio_w: stats(&fill.fill_ratio_w),
+            taper_ratio: stats(&fill.taper_ratio),
+            packing_density: stats(&fill.packing_density),
+        }
+    }
+}
+
+// ─── Material voting ─────────────────────────────────────────────────
+
+/// Runs that reported one material
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaterialVote {
+    pub material: String,
+    /// Runs that reported it
+    pub count: usize,
+    /// Sum of the confidences of those runs (1.0 for a run without one)
+    pub weight: f64,
+    /// First run, in run order, that reported it
+    pub first_run: usize,
+}
+
+/// Material votes of an ensemble, strongest first: by weight, then count,
+/// then the earliest reporting run, so ties always resolve the same way
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+#[serde(transparent)]
+pub struct MaterialVotes {
+    pub votes: Vec<MaterialVote>,
+}
+
+impl MaterialVotes {
+    /// Tally `(run, material, confidence)` ballots. Blank and `?` materials
+    /// are ignored; confidences are clamped to 0..=1.
+    pub fn tally<'a>(ballots: impl IntoIterator<Item = (usize, &'a str, Option<f64>)>) -> Self {
+        let mut votes: Vec<MaterialVote> = Vec::new();
+        for (run, material, confidence) in ballots {
+            if material.is_empty() || material == "?" {
+                continue;
+            }
+            let weight = confidence.map_or(1.0, |c| c.clamp(0.0, 1.0));
+            match votes.iter_mut().find(|v| v.material == material) {
+                Some(vote) => {
+                    vote.count += 1;
+                    vote.weight += weight;
+                    vote.first_run = vote.first_run.min(run);
+                }
+                None => votes.push(MaterialVote {
+                    material: material.to_string(),
+                    count: 1,
+                    weight,
+                    first_run: run,
+                }),
+            }
+        }
+        votes.sort_by(|a, b| {
+            b.weight
+                .total_cmp(&a.weight)
+                .then(b.count.cmp(&a.count))
+                .then(a.first_run.cmp(&b.first_run))
+        });
+        Self { votes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.votes.is_empty()
+    }
+
+    /// Strongest material (None = no run reported one)
+    pub fn winner(&self) -> Option<&str> {
+        self.votes.first().map(|v| v.material.as_str())
+    }
+
+    /// Share of the votes held by the winner: by weight, or by count when
+    /// every run had zero confidence (0 when there are no votes)
+    pub fn agreement(&self) -> f64 {
+        let Some(winner) = self.votes.first() else {
+            return 0.0;
+        };
+        let total_weight: f64 = self.votes.iter().map(|v| v.weight).sum();
+        if total_weight > 0.0 {
+            winner.weight / total_weight
+        } else {
+            winner.count as f64 / self.votes.iter().map(|v| v.count).sum::<usize>() as f64
+        }
+    }
+
+    /// Winner when its `agreement` reaches `min_agreement`
+    pub fn decide(&self, min_agreement: f64) -> Option<&str> {
+        self.winner().filter(|_| self.agreement() >= min_agreement)
+    }
+
+    /// Reported materials in order of first report
+    pub fn materials(&self) -> Vec<String> {
+        let mut votes: Vec<&MaterialVote> = self.votes.iter().collect();
+        votes.sort_by_key(|v| v.first_run);
+        votes.into_iter().map(|v| v.material.clone()).collect()
+    }
+}
+
+// ─── Disagreement ────────────────────────────────────────────────────
+
+/// Thresholds above which ensemble runs are considered to disagree
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisagreementThresholds {
+    /// Maximum coefficient of variation of the run heights
+    pub max_height_cv: f64,
+    /// Maximum max-min spread of any fill parameter across runs
+    pub max_fill_spread: f64,
+}
+
+impl Default for DisagreementThresholds {
+    fn default() -> Self {
+        Self {
+            max_height_cv: 0.15,
+            max_fill_spread: 0.2,
+        }
+    }
+}
+
+/// Ensemble runs diverged beyond the configured thresholds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disagreement {
+    /// Coefficient of variation of the valid run heights
+    pub height_cv: f64,
+    /// Largest spread among fillRatioL, fillRatioW, taperRatio, packingDensity
+    pub fill_spread: f64,
+    /// Parameters that exceeded their threshold (spec field names)
+    pub fields: Vec<String>,
+}
+
+/// Per-parameter values of the valid fill runs
+#[derive(Debug, Clone, Default)]
+pub struct FillSamples {
+    pub fill_ratio_l: Vec<f64>,
+    pub fill_ratio_w: Vec<f64>,
+    pub taper_ratio: Vec<f64>,
+    pub packing_density: Vec<f64>,
+}
+
+impl FillSamples {
+    fn named(&self) -> [(&'static str, &[f64]); 4] {
+        [
+            ("fillRatioL", &self.fill_ratio_l),
+            ("fillRatioW", &self.fill_ratio_w),
+            ("taperRatio", &self.taper_ratio),
+            ("packingDensity", &self.packing_density),
+        ]
+    }
+}
+
+/// Compare run statistics against thresholds. Returns None when runs agree.
+pub fn detect_disagreement(
+    heights: &[f64],
+    fill: &FillSamples,
+    thresholds: &DisagreementThresholds,
+) -> Option<Disagreement> {
+    let height_cv = coefficient_of_variation(heights);
+    let mut fields = Vec::new();
+    if height_cv > thresholds.max_height_cv {
+        fields.push("height".to_string());
+    }
+
+    let mut fill_spread: f64 = 0.0;
+    for (name, values) in fill.named() {
+        let s = spread(values);
+        fill_spread = fill_spread.max(s);
+        if s > thresholds.max_fill_spread {
+            fields.push(name.to_string());
+        }
+    }
+
+    if fields.is_empty() {
+        None
+    } else {
+        Some(Disagreement {
+            height_cv,
+            fill_spread,
+            fields,
+        })
+    }
+}
+
+// ─── Review ──────────────────────────────────────────────────────────
+
+/// Coefficient-of-variation limits above which a result needs human review
+#[derive(Debug, Clone)]
+pub struct ReviewThresholds {
+    /// Maximum CV of the run heights
+    pub max_height_cv: f64,
+    /// Maximum CV of fillRatioL and of fillRatioW
+    pub max_fill_cv: f64,
+}
+
+impl Default for ReviewThresholds {
+    fn default() -> Self {
+        Self {
+            max_height_cv: 0.1,
+            max_fill_cv: 0.1,
+        }
+    }
+}
+
+/// Statistic that exceeded its review threshold
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReviewFlag {
+    /// "height", "fillRatioL" or "fillRatioW"
+    pub field: String,
+    pub cv: f64,
+    pub threshold: f64,
+}
+
+impl Localize for ReviewFlag {
+    fn localize(&self, lang: Language) -> String {
+        i18n::REVIEW_FLAG.format(
+            lang,
+            &[&self.field, &format!("{:.3}", self.cv), &format!("{:.3}", self.threshold)],
+        )
+    }
+}
+
+impl std::fmt::Display for ReviewFlag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// Heights and fill ratios whose run-to-run CV exceeds the thresholds
+pub fn review_flags(stats: &RunStatistics, thresholds: &ReviewThresholds) -> Vec<ReviewFlag> {
+    [
+        ("height", &stats.height, thresholds.max_height_cv),
+        ("fillRatioL", &stats.fill_ratio_l, thresholds.max_fill_cv),
+        ("fillRatioW", &stats.fill_ratio_w, thresholds.max_fill_cv),
+    ]
+    .into_iter()
+    .filter(|(_, param, threshold)| param.cv() > *threshold)
+    .map(|(field, param, threshold)| ReviewFlag {
+        field: field.to_string(),
+        cv: param.cv(),
+        threshold,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_median_odd() {
+        assert!((median(&[3.0, 1.0, 2.0]) - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_median_even() {
+        // Our median takes sorted[len/2], so for [1,2,3,4] -> sorted[2] = 3
+        assert!((median(&[4.0, 1.0, 3.0, 2.0]) - 3.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_median_modes_and_quantile() {
+        let even = [4.0, 1.0, 3.0, 2.0];
+        assert_eq!(MedianMode::Average.median(&even), 2.5);
+        assert_eq!(MedianMode::Upper.median(&even), 3.0);
+        assert_eq!(MedianMode::Lower.median(&even), 2.0);
+        for mode in [MedianMode::Average, MedianMode::Upper, MedianMode::Lower] {
+            assert_eq!(mode.median(&[3.0, 1.0, 2.0]), 2.0, "{:?}", mode);
+            assert!(mode.median(&[]).is_nan());
+        }
+
+        assert_eq!(quantile(&even, 0.0), 1.0);
+        assert_eq!(quantile(&even, 1.0), 4.0);
+        assert_eq!(quantile(&even, 0.25), 1.75);
+        assert_eq!(quantile(&[0.4], 0.9), 0.4);
+        assert!(quantile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn test_average() {
+        assert!((average(&[1.0, 2.0, 3.0]) - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_std_dev_and_cv() {
+        assert!(std_dev(&[0.5]).abs() < f64::EPSILON);
+        assert!((std_dev(&[2.0, 4.0]) - 1.0).abs() < 1e-12);
+        assert!((coefficient_of_variation(&[2.0, 4.0]) - 1.0 / 3.0).abs() < 1e-12);
+        assert!(coefficient_of_variation(&[]).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_run_statistics() {
+        let fill = FillSamples {
+            fill_ratio_l: vec![0.7, 0.9],
+            ..Default::default()
+        };
+        let stats = RunStatistics::new(&[0.4, 0.5, 0.6], &fill);
+        assert_eq!(stats.height.values, [0.4, 0.5, 0.6]);
+        assert!((stats.height.mean - 0.5).abs() < 1e-12);
+        assert!((stats.height.median - 0.5).abs() < 1e-12);
+        assert!((stats.fill_ratio_l.std_dev - 0.1).abs() < 1e-12);
+        assert_eq!(stats.taper_ratio, ParamStats::default());
+    }
+
+    #[test]
+    fn test_agreeing_runs_not_flagged() {
+        let fill = FillSamples {
+            fill_ratio_l: vec![0.8, 0.82],
+            ..Default::default()
+        };
+        let d = detect_disagreement(&[0.48, 0.46], &fill, &DisagreementThresholds::default());
+        assert!(d.is_none());
+    }
+
+    #[test]
+    fn test_divergent_runs_flagged() {
+        let fill = FillSamples {
+            fill_ratio_l: vec![0.4, 0.9],
+            taper_ratio: vec![0.9, 0.85],
+            ..Default::default()
+        };
+        let d = detect_disagreement(&[0.2, 0.6], &fill, &DisagreementThresholds::default()).unwrap();
+        assert_eq!(d.fields, ["height", "fillRatioL"]);
+        assert!((d.height_cv - 0.5).abs() < 1e-12);
+        assert!((d.fill_spread - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_review_flags_on_high_cv() {
+        let fill = FillSamples {
+            fill_ratio_l: vec![0.8, 0.8],
+            fill_ratio_w: vec![0.5, 0.9],
+            ..Default::default()
+        };
+        let stats = RunStatistics::new(&[0.40, 0.44], &fill);
+        let flags = review_flags(&stats, &ReviewThresholds::default());
+        assert_eq!(flags.len(), 1);
+        assert_eq!(flags[0].field, "fillRatioW");
+        assert!((flags[0].cv - 2.0 / 7.0).abs() < 1e-12);
+
+        let strict = ReviewThresholds {
+            max_height_cv: 0.01,
+            max_fill_cv: 1.0,
+        };
+        let flags = review_flags(&stats, &strict);
+        assert_eq!(flags.iter().map(|f| f.field.as_str()).collect::<Vec<_>>(), ["height"]);
+        assert!(review_flags(&RunStatistics::default(), &strict).is_empty());
+    }
+
+    #[test]
+    fn test_material_votes() {
+        let plain = MaterialVotes::tally([(0, "As殻", None), (1, "Co殻", None), (2, "?", None), (3, "", None)]);
+        // Tie: the material reported first wins, every time
+        assert_eq!(plain.winner(), Some("As殻"));
+        assert_eq!(plain.agreement(), 0.5);
+        assert_eq!(plain.materials(), ["As殻", "Co殻"]);
+
+        let weighted = MaterialVotes::tally([(0, "As殻", Some(0.3)), (1, "Co殻", Some(0.9)), (2, "As殻", Some(0.2))]);
+        assert_eq!(weighted.winner(), Some("Co殻"));
+        assert_eq!(weighted.votes[1].count, 2);
+        assert!((weighted.agreement() - 0.9 / 1.4).abs() < 1e-12);
+        assert_eq!(weighted.decide(0.6), Some("Co殻"));
+        assert_eq!(weighted.decide(0.7), None);
+
+        let unsure = MaterialVotes::tally([(0, "土砂", Some(0.0)), (1, "土砂", Some(-1.0)), (2, "As殻", Some(0.0))]);
+        assert_eq!(unsure.winner(), Some("土砂"));
+        assert!((unsure.agreement() - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(MaterialVotes::default().agreement(), 0.0);
+    }
+}
@@ -0,0 +1,120 @@
+//! Crate-level error type
+//!
+//! Applications that use several subsystems (spec loading, parsing,
+//! validation, the pipeline) can work with one `Result<T, TonsuuError>`.
+
+use crate::i18n::{self, language, Language, Localize};
+use crate::parse::ParseError;
+use crate::pipeline::{ConfigError, PipelineError};
+use crate::redact::RedactionError;
+use crate::spec::SpecError;
+use crate::validation::ValidationError;
+
+#[cfg(feature = "image")]
+use crate::preprocess::PreprocessError;
+
+/// Any error produced by this crate
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum TonsuuError {
+    #[error(transparent)]
+    Spec(#[from] SpecError),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    /// One or more parameters outside the spec ranges
+    #[error("{}", self.localize(language()))]
+    Validation(Vec<ValidationError>),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Pipeline(#[from] PipelineError),
+    #[error(transparent)]
+    Redaction(#[from] RedactionError),
+    #[cfg(feature = "image")]
+    #[error(transparent)]
+    Preprocess(#[from] PreprocessError),
+}
+
+impl TonsuuError {
+    /// Stable machine-readable error code of the underlying error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Spec(e) => e.code(),
+            Self::Parse(e) => e.code(),
+            Self::Validation(_) => "OUT_OF_RANGE",
+            Self::Config(e) => e.code(),
+            Self::Pipeline(e) => e.code(),
+            Self::Redaction(e) => e.code(),
+            #[cfg(feature = "image")]
+            Self::Preprocess(_) => "PREPROCESS_FAILED",
+        }
+    }
+}
+
+impl From<ValidationError> for TonsuuError {
+    fn from(e: ValidationError) -> Self {
+        Self::Validation(vec![e])
+    }
+}
+
+impl From<Vec<ValidationError>> for TonsuuError {
+    fn from(errors: Vec<ValidationError>) -> Self {
+        Self::Validation(errors)
+    }
+}
+
+impl Localize for TonsuuError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Spec(e) => e.localize(lang),
+            Self::Parse(e) => e.localize(lang),
+            Self::Validation(errors) => {
+                let errors: Vec<String> = errors.iter().map(|e| e.localize(lang)).collect();
+                i18n::PARAMS_OUT_OF_RANGE.format(lang, &[&errors.join("; ")])
+            }
+            Self::Config(e) => e.localize(lang),
+            Self::Pipeline(e) => e.localize(lang),
+            Self::Redaction(e) => e.localize(lang),
+            #[cfg(feature = "image")]
+            Self::Preprocess(e) => e.localize(lang),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculation::{calculate_tonnage_checked, CoreParams, FormulaVersion};
+    use crate::parse::parse_geometry;
+    use crate::spec::PromptSpec;
+
+    fn estimate(height: f64, geometry_reply: &str) -> Result<f64, TonsuuError> {
+        parse_geometry(geometry_reply)?;
+        let params = CoreParams {
+            height,
+            fill_ratio_l: 0.8,
+            fill_ratio_w: 0.8,
+            taper_ratio: 0.9,
+            packing_density: 0.8,
+            material_type: "As殻".into(),
+            formula: FormulaVersion::default(),
+        };
+        Ok(calculate_tonnage_checked(&params, Some("4t"))?.tonnage)
+    }
+
+    #[test]
+    fn test_question_mark_across_subsystems() {
+        assert!(estimate(0.4, r#"{"tailgateTopY":0.3}"#).is_ok());
+        assert_eq!(estimate(0.4, "no json").unwrap_err().code(), "PARSE_NO_JSON");
+
+        let err = estimate(5.0, r#"{"tailgateTopY":0.3}"#).unwrap_err();
+        assert_eq!(err.code(), "OUT_OF_RANGE");
+        assert!(err.to_string().contains("height"));
+    }
+
+    #[test]
+    fn test_spec_load_error() {
+        let err: TonsuuError = PromptSpec::from_json("{}").unwrap_err().into();
+        assert_eq!(err.code(), "SPEC_INVALID");
+    }
+}
@@ -0,0 +1,312 @@
+//! Photo EXIF metadata (feature `image`)
+//!
+//! Reads the capture time and GPS position from the EXIF block of the input
+//! photos, before preprocessing re-encodes them without it. Only the handful
+//! of TIFF tags needed here are parsed.
+
+use std::io::Cursor;
+
+use image::{ImageDecoder, ImageReader};
+
+use crate::history::JST_OFFSET_SECS;
+use crate::input::{GpsPosition, ImageInfo};
+
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF: u16 = 0x0005;
+const TAG_GPS_ALTITUDE: u16 = 0x0006;
+
+/// Capture time and position read from EXIF
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhotoMetadata {
+    /// Capture time (Unix seconds). EXIF stores local time; without an
+    /// `OffsetTimeOriginal` tag the photo is assumed to be taken in JST.
+    pub captured_at: Option<i64>,
+    pub gps: Option<GpsPosition>,
+}
+
+/// Read the EXIF metadata of an encoded image; None when it has no EXIF block
+pub fn read_photo_metadata(bytes: &[u8]) -> Option<PhotoMetadata> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let exif = decoder.exif_metadata().ok()??;
+    Some(parse_exif(&exif))
+}
+
+/// Parse a raw EXIF block (TIFF header, optionally preceded by `Exif\0\0`)
+pub fn parse_exif(exif: &[u8]) -> PhotoMetadata {
+    let data = exif.strip_prefix(b"Exif\0\0").unwrap_or(exif);
+    let Some(tiff) = Tiff::new(data) else {
+        return PhotoMetadata::default();
+    };
+    let ifd0 = tiff.u32(4).map(|o| o as usize);
+    let exif_ifd = ifd0.and_then(|ifd| tiff.offset(ifd, TAG_EXIF_IFD));
+    let gps_ifd = ifd0.and_then(|ifd| tiff.offset(ifd, TAG_GPS_IFD));
+
+    let original = exif_ifd.and_then(|ifd| tiff.ascii(ifd, TAG_DATE_TIME_ORIGINAL));
+    let local = original.or_else(|| ifd0.and_then(|ifd| tiff.ascii(ifd, TAG_DATE_TIME)));
+    let offset = exif_ifd
+        .and_then(|ifd| tiff.ascii(ifd, TAG_OFFSET_TIME_ORIGINAL))
+        .and_then(parse_offset)
+        .unwrap_or(JST_OFFSET_SECS);
+    PhotoMetadata {
+        captured_at: local.and_then(parse_date_time).map(|t| t - offset),
+        gps: gps_ifd.and_then(|ifd| gps_position(&tiff, ifd)),
+    }
+}
+
+/// Fill capture time (unless already set) and GPS position from the image's EXIF
+pub(crate) fn apply_photo_metadata(mut info: ImageInfo, bytes: &[u8]) -> ImageInfo {
+    if let Some(meta) = read_photo_metadata(bytes) {
+        info.captured_at = info.captured_at.or(meta.captured_at);
+        info.gps = info.gps.or(meta.gps);
+    }
+    info
+}
+
+fn gps_position(tiff: &Tiff, ifd: usize) -> Option<GpsPosition> {
+    let coordinate = |value_tag, ref_tag, negative: &str, limit: f64| {
+        let dms = tiff.rationals(ifd, value_tag)?;
+        let [d, m, s] = dms[..] else { return None };
+        let value = d + m / 60.0 + s / 3600.0;
+        let sign = if tiff.ascii(ifd, ref_tag)? == negative { -1.0 } else { 1.0 };
+        (value.is_finite() && value <= limit).then_some(sign * value)
+    };
+    let latitude = coordinate(TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, "S", 90.0)?;
+    let longitude = coordinate(TAG_GPS_LONGITUDE, TAG_GPS_LONGITUDE_REF, "W", 180.0)?;
+    let altitude = tiff
+        .rationals(ifd, TAG_GPS_ALTITUDE)
+        .and_then(|v| v.first().copied())
+        .filter(|a| a.is_finite())
+        .map(|a| if tiff.byte(ifd, TAG_GPS_ALTITUDE_REF) == Some(1) { -a } else { a });
+    Some(GpsPosition {
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+/// "YYYY:MM:DD HH:MM:SS" as seconds since the epoch, read as UTC
+fn parse_date_time(s: &str) -> Option<i64> {
+    let b = s.as_bytes();
+    if b.len() < 19 || b[4] != b':' || b[7] != b':' || b[10] != b' ' || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    let num = |range: std::ops::Range<usize>| s.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// "+09:00" as seconds east of UTC
+fn parse_offset(s: &str) -> Option<i64> {
+    let sign = match s.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let (h, m) = s.get(1..)?.split_once(':')?;
+    Some(sign * (h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60))
+}
+
+/// Days since 1970-01-01 (proleptic Gregorian), inverse of `history::local_day`
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Minimal TIFF reader over the EXIF block
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+/// IFD entry: field type, value count and position of the value bytes
+struct Entry {
+    kind: u16,
+    count: usize,
+    at: usize,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..4)? {
+            [b'I', b'I', 42, 0] => true,
+            [b'M', b'M', 0, 42] => false,
+            _ => return None,
+        };
+        Some(Self { data, little_endian })
+    }
+
+    fn u16(&self, at: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+
+    fn u32(&self, at: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    fn entry(&self, ifd: usize, tag: u16) -> Option<Entry> {
+        let count = self.u16(ifd)? as usize;
+        (0..count).map(|i| ifd + 2 + i * 12).find_map(|e| {
+            if self.u16(e)? != tag {
+                return None;
+            }
+            let kind = self.u16(e + 2)?;
+            let count = self.u32(e + 4)? as usize;
+            let size: usize = match kind {
+                1 | 2 | 7 => 1,
+                3 => 2,
+                4 | 9 => 4,
+                5 | 10 => 8,
+                _ => return None,
+            };
+            let at = if size.saturating_mul(count) <= 4 { e + 8 } else { self.u32(e + 8)? as usize };
+            Some(Entry { kind, count, at })
+        })
+    }
+
+    /// Offset of a sub-IFD (EXIF, GPS)
+    fn offset(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let e = self.entry(ifd, tag)?;
+        match e.kind {
+            4 => self.u32(e.at).map(|o| o as usize),
+            _ => None,
+        }
+    }
+
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<&'a str> {
+        let e = self.entry(ifd, tag)?;
+        if e.kind != 2 {
+            return None;
+        }
+        let raw = self.data.get(e.at..e.at.checked_add(e.count)?)?;
+        let text = std::str::from_utf8(raw).ok()?;
+        Some(text.trim_end_matches('\0').trim())
+    }
+
+    fn byte(&self, ifd: usize, tag: u16) -> Option<u8> {
+        let e = self.entry(ifd, tag)?;
+        matches!(e.kind, 1 | 7).then(|| self.data.get(e.at).copied())?
+    }
+
+    fn rationals(&self, ifd: usize, tag: u16) -> Option<Vec<f64>> {
+        let e = self.entry(ifd, tag)?;
+        if e.kind != 5 {
+            return None;
+        }
+        (0..e.count)
+            .map(|i| {
+                let at = e.at + i * 8;
+                let den = self.u32(at + 4)?;
+                (den != 0).then(|| self.u32(at).map(|num| num as f64 / den as f64))?
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Little-endian EXIF block with DateTimeOriginal, OffsetTimeOriginal and GPS
+    fn sample_exif(offset: &str) -> Vec<u8> {
+        fn entry(out: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: u32) {
+            out.extend(tag.to_le_bytes());
+            out.extend(kind.to_le_bytes());
+            out.extend(count.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        }
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        // IFD0 at 8: EXIF and GPS pointers
+        out.extend(2u16.to_le_bytes());
+        entry(&mut out, TAG_EXIF_IFD, 4, 1, 38);
+        entry(&mut out, TAG_GPS_IFD, 4, 1, 68);
+        out.extend(0u32.to_le_bytes());
+        // EXIF IFD at 38
+        out.extend(2u16.to_le_bytes());
+        entry(&mut out, TAG_DATE_TIME_ORIGINAL, 2, 20, 122);
+        entry(&mut out, TAG_OFFSET_TIME_ORIGINAL, 2, 7, 142);
+        out.extend(0u32.to_le_bytes());
+        // GPS IFD at 68
+        out.extend(4u16.to_le_bytes());
+        entry(&mut out, TAG_GPS_LATITUDE_REF, 2, 2, u32::from_le_bytes(*b"N\0\0\0"));
+        entry(&mut out, TAG_GPS_LATITUDE, 5, 3, 150);
+        entry(&mut out, TAG_GPS_LONGITUDE_REF, 2, 2, u32::from_le_bytes(*b"E\0\0\0"));
+        entry(&mut out, TAG_GPS_LONGITUDE, 5, 3, 174);
+        out.extend(0u32.to_le_bytes());
+        assert_eq!(out.len(), 122);
+        out.extend(b"2026:10:16 08:30:00\0");
+        out.extend(format!("{offset:<6}\0\0").as_bytes());
+        for (num, den) in [(35, 1), (41, 1), (2250, 100), (139, 1), (41, 1), (3000, 100)] {
+            out.extend((num as u32).to_le_bytes());
+            out.extend((den as u32).to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_parse_exif_time_and_gps() {
+        let meta = parse_exif(&sample_exif("+09:00"));
+        // 2026-10-16 08:30 JST = 2026-10-15 23:30 UTC
+        assert_eq!(meta.captured_at, Some(1_792_107_000));
+        let gps = meta.gps.unwrap();
+        assert!((gps.latitude - 35.689_583).abs() < 1e-6);
+        assert!((gps.longitude - 139.691_667).abs() < 1e-6);
+        assert_eq!(gps.altitude, None);
+
+        let utc = parse_exif(&sample_exif("+00:00"));
+        assert_eq!(utc.captured_at, Some(1_792_107_000 + 9 * 3600));
+        assert_eq!(parse_exif(b"not exif"), PhotoMetadata::default());
+        assert_eq!(parse_date_time("1970:01:01 00:00:00"), Some(0));
+        assert_eq!(parse_date_time("0000:00:00 00:00:00"), None);
+    }
+
+    #[test]
+    fn test_read_photo_metadata_from_jpeg() {
+        use image::{DynamicImage, ImageFormat, RgbImage};
+
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(8, 8))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+        assert_eq!(read_photo_metadata(&jpeg), None);
+
+        // APP1 segment right after SOI
+        let exif = [b"Exif\0\0".as_slice(), &sample_exif("+09:00")].concat();
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend(((exif.len() + 2) as u16).to_be_bytes());
+        segment.extend(exif);
+        jpeg.splice(2..2, segment);
+
+        let meta = read_photo_metadata(&jpeg).unwrap();
+        assert_eq!(meta.captured_at, Some(1_792_107_000));
+        assert!(meta.gps.is_some());
+
+        let image = crate::input::InputImage::new(jpeg).unwrap().captured_at(42);
+        let info = apply_photo_metadata(image.info(), &image.bytes);
+        assert_eq!(info.captured_at, Some(42), "explicit capture time wins");
+        assert!((info.gps.unwrap().latitude - 35.689_583).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,540 @@
+//! Per-vehicle registry
+//!
+//! Truck classes (2t, 4t, 10t) only approximate a vehicle's bed. A `Fleet`
+//! maps vehicle IDs and plate numbers to the measured `TruckSpec` of each
+//! known truck; the pipeline uses it instead of the class spec when
+//! `BoxOverlayConfig::vehicle` names a registered vehicle. `Fleet::from_csv`
+//! imports the fleet list transport companies already keep.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+use crate::i18n::{self, language, Language, Localize, Message};
+use crate::spec::{get_truck_spec, normalize_truck_class, TruckSpec};
+
+/// Measured scale references of one vehicle
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VehicleCalibration {
+    /// Physical tailgate height in metres (None = `spec.bed_height`)
+    pub tailgate_height: Option<f64>,
+    /// Plate class (中板 / 大板) mounted on the vehicle
+    pub plate_class: Option<String>,
+}
+
+/// One registered truck
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+    pub id: String,
+    /// License plate as written on the vehicle (None = not registered)
+    pub plate: Option<String>,
+    /// Nominal truck class reported in results
+    pub truck_class: String,
+    /// Measured bed of this vehicle
+    pub spec: TruckSpec,
+    pub calibration: VehicleCalibration,
+}
+
+impl Vehicle {
+    /// Tailgate height used as the scale reference
+    pub fn tailgate_height(&self) -> f64 {
+        self.calibration.tailgate_height.unwrap_or(self.spec.bed_height)
+    }
+}
+
+/// Vehicles keyed by ID, also reachable by plate number
+#[derive(Debug, Clone, Default)]
+pub struct Fleet {
+    vehicles: HashMap<String, Vehicle>,
+    /// Normalized plate -> vehicle ID
+    plates: HashMap<String, String>,
+}
+
+impl Fleet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a vehicle, replacing any vehicle with the same ID
+    pub fn insert(&mut self, vehicle: Vehicle) {
+        if let Some(old) = self.vehicles.remove(&vehicle.id) {
+            if let Some(plate) = &old.plate {
+                self.plates.remove(&normalize_plate(plate));
+            }
+        }
+        if let Some(plate) = &vehicle.plate {
+            self.plates.insert(normalize_plate(plate), vehicle.id.clone());
+        }
+        self.vehicles.insert(vehicle.id.clone(), vehicle);
+    }
+
+    /// Vehicle by ID
+    pub fn get(&self, id: &str) -> Option<&Vehicle> {
+        self.vehicles.get(id)
+    }
+
+    /// Vehicle by plate number, in any spacing or width ("品川500あ12-34")
+    pub fn find_by_plate(&self, plate: &str) -> Option<&Vehicle> {
+        self.plates.get(&normalize_plate(plate)).and_then(|id| self.vehicles.get(id))
+    }
+
+    /// Vehicle by ID, falling back to plate number
+    pub fn lookup(&self, key: &str) -> Option<&Vehicle> {
+        self.get(key).or_else(|| self.find_by_plate(key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.vehicles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vehicles.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vehicle> {
+        self.vehicles.values()
+    }
+
+    /// Import a fleet list.
+    ///
+    /// The first row names the columns (English or Japanese, any order):
+    /// plate (ナンバー), class (車格), bed_length (荷台長), bed_width (荷台幅),
+    /// bed_height (荷台高), max_payload (最大積載量), and optionally
+    /// id (車両ID, default: the plate), plate_class (板種) and
+    /// tailgate_height (後板高). Lengths are in metres, payload in tonnes.
+    /// Fields may be quoted; quoted line breaks are not supported.
+    /// Every invalid row is reported, with its line number.
+    pub fn from_csv(mut reader: impl Read) -> Result<Self, Vec<FleetImportError>> {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .map_err(|e| vec![FleetImportError::Io(e.to_string())])?;
+        let mut lines = text
+            .trim_start_matches('\u{FEFF}')
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l))
+            .filter(|(_, l)| !l.trim().is_empty());
+
+        let Some((_, header)) = lines.next() else {
+            return Err(vec![FleetImportError::MissingColumn("plate".into())]);
+        };
+        let columns = CsvColumns::from_header(&split_csv_line(header))?;
+
+        let mut fleet = Fleet::new();
+        let mut errors = Vec::new();
+        for (line, text) in lines {
+            let vehicle = columns.vehicle(&split_csv_line(text)).and_then(|v| {
+                if fleet.get(&v.id).is_some() {
+                    Err(RowError::DuplicateId(v.id))
+                } else if v.plate.as_deref().is_some_and(|p| fleet.find_by_plate(p).is_some()) {
+                    Err(RowError::DuplicatePlate(v.plate.unwrap_or_default()))
+                } else {
+                    Ok(v)
+                }
+            });
+            match vehicle {
+                Ok(vehicle) => fleet.insert(vehicle),
+                Err(reason) => errors.push(FleetImportError::InvalidRow { line, reason }),
+            }
+        }
+        if errors.is_empty() {
+            Ok(fleet)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Fleet list that could not be imported
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum FleetImportError {
+    Io(String),
+    MissingColumn(String),
+    InvalidRow { line: usize, reason: RowError },
+}
+
+impl FleetImportError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "FLEET_IO",
+            Self::MissingColumn(_) => "FLEET_MISSING_COLUMN",
+            Self::InvalidRow { .. } => "FLEET_INVALID_ROW",
+        }
+    }
+}
+
+impl Localize for FleetImportError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Io(message) => i18n::FLEET_IO.format(lang, &[message]),
+            Self::MissingColumn(column) => i18n::FLEET_MISSING_COLUMN.format(lang, &[column]),
+            Self::InvalidRow { line, reason } => {
+                i18n::FLEET_INVALID_ROW.format(lang, &[line, &reason.localize(lang)])
+            }
+        }
+    }
+}
+
+impl fmt::Display for FleetImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// Why a fleet list row was rejected
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum RowError {
+    EmptyPlate,
+    DuplicateId(String),
+    DuplicatePlate(String),
+    /// `column` is the catalog label of the column
+    NotANumber { column: Message, value: String },
+    OutOfRange { column: Message, value: f64, max: f64 },
+}
+
+impl Localize for RowError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::EmptyPlate => i18n::FLEET_EMPTY_PLATE.get(lang).to_string(),
+            Self::DuplicateId(id) => i18n::FLEET_DUPLICATE_ID.format(lang, &[id]),
+            Self::DuplicatePlate(plate) => i18n::FLEET_DUPLICATE_PLATE.format(lang, &[plate]),
+            Self::NotANumber { column, value } => i18n::FLEET_NOT_A_NUMBER.format(lang, &[&column.get(lang), value]),
+            Self::OutOfRange { column, value, max } => {
+                i18n::FLEET_OUT_OF_RANGE.format(lang, &[&column.get(lang), value, max])
+            }
+        }
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// Column positions of a fleet list
+struct CsvColumns {
+    plate: usize,
+    class: usize,
+    bed_length: usize,
+    bed_width: usize,
+    bed_height: usize,
+    max_payload: usize,
+    id: Option<usize>,
+    plate_class: Option<usize>,
+    tailgate_height: Option<usize>,
+}
+
+impl CsvColumns {
+    fn from_header(header: &[String]) -> Result<Self, Vec<FleetImportError>> {
+        let find = |names: &[&str]| {
+            header
+                .iter()
+                .position(|h| names.iter().any(|n| h.trim().eq_ignore_ascii_case(n)))
+        };
+        let mut missing = Vec::new();
+        let mut required = |names: &[&str]| {
+            find(names).unwrap_or_else(|| {
+                missing.push(FleetImportError::MissingColumn(names[0].to_string()));
+                0
+            })
+        };
+        let columns = Self {
+            plate: required(&["plate", "ナンバー"]),
+            class: required(&["class", "車格"]),
+            bed_length: required(&["bed_length", "荷台長"]),
+            bed_width: required(&["bed_width", "荷台幅"]),
+            bed_height: required(&["bed_height", "荷台高"]),
+            max_payload: required(&["max_payload", "最大積載量"]),
+            id: find(&["id", "車両ID"]),
+            plate_class: find(&["plate_class", "板種"]),
+            tailgate_height: find(&["tailgate_height", "後板高"]),
+        };
+        if missing.is_empty() {
+            Ok(columns)
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Validated vehicle of one row
+    fn vehicle(&self, row: &[String]) -> Result<Vehicle, RowError> {
+        let field = |i: usize| row.get(i).map(|s| s.trim()).unwrap_or("");
+        let optional = |i: Option<usize>| i.map(field).filter(|s| !s.is_empty());
+        let positive = |i: usize, column: Message, max: f64| -> Result<f64, RowError> {
+            let value: f64 = field(i).parse().map_err(|_| RowError::NotANumber {
+                column,
+                value: field(i).to_string(),
+            })?;
+            if value > 0.0 && value <= max {
+                Ok(value)
+            } else {
+                Err(RowError::OutOfRange { column, value, max })
+            }
+        };
+
+        let plate = field(self.plate);
+        if plate.is_empty() {
+            return Err(RowError::EmptyPlate);
+        }
+        let class = field(self.class);
+        let truck_class = normalize_truck_class(class).canonical().unwrap_or(class).to_string();
+        let bed_length = positive(self.bed_length, i18n::FLEET_BED_LENGTH, 12.0)?;
+        let bed_width = positive(self.bed_width, i18n::FLEET_BED_WIDTH, 3.0)?;
+        let bed_height = positive(self.bed_height, i18n::FLEET_BED_HEIGHT, 2.0)?;
+        let max_capacity = positive(self.max_payload, i18n::FLEET_MAX_PAYLOAD, 30.0)?;
+        let tailgate_height = match self.tailgate_height.filter(|&i| !field(i).is_empty()) {
+            Some(i) => Some(positive(i, i18n::FLEET_TAILGATE_HEIGHT, 2.0)?),
+            None => None,
+        };
+
+        // Heaped capacity in the same proportion as the class spec
+        let level_volume = bed_length * bed_width * bed_height;
+        let heap_ratio = get_truck_spec(&truck_class)
+            .map(|s| s.heap_volume / s.level_volume)
+            .unwrap_or(1.3);
+        Ok(Vehicle {
+            id: optional(self.id).unwrap_or(plate).to_string(),
+            plate: Some(plate.to_string()),
+            truck_class,
+            spec: TruckSpec {
+                bed_length,
+                bed_width,
+                bed_height,
+                level_volume,
+                heap_volume: level_volume * heap_ratio,
+                max_capacity,
+            },
+            calibration: VehicleCalibration {
+                tailgate_height,
+                plate_class: optional(self.plate_class).map(str::to_string),
+            },
+        })
+    }
+}
+
+/// Fields of one CSV line (quotes removed, `""` unescaped)
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(ch),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Plate number folded for comparison: half-width, without spaces or
+/// separators ("品川 500 あ 12-34" -> "品川500あ1234")
+pub fn normalize_plate(plate: &str) -> String {
+    plate
+        .chars()
+        .map(|ch| match ch {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(ch as u32 - 0xFEE0).unwrap_or(ch),
+            _ => ch,
+        })
+        .filter(|ch| !ch.is_whitespace() && !matches!(ch, '-' | '‐' | '－' | '.' | '・' | '･'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ImageFormat, InputImage};
+    use crate::pipeline::{
+        analyze_box_overlay, analyze_box_overlay_frames, analyze_box_overlay_partial, plan_box_overlay, AiBackend,
+        AnalysisWarning, BoxOverlayConfig, PipelineError,
+    };
+    use crate::prompt::PromptStage;
+    use crate::testing::fixed_backend;
+
+    fn vehicle(id: &str, plate: &str) -> Vehicle {
+        Vehicle {
+            id: id.into(),
+            plate: Some(plate.into()),
+            truck_class: "4t".into(),
+            spec: TruckSpec {
+                bed_length: 3.5,
+                bed_width: 2.1,
+                bed_height: 0.34,
+                level_volume: 2.5,
+                heap_volume: 3.2,
+                max_capacity: 4.0,
+            },
+            calibration: VehicleCalibration::default(),
+        }
+    }
+
+    #[test]
+    fn test_lookup_by_id_and_plate() {
+        let mut fleet = Fleet::new();
+        fleet.insert(vehicle("D-12", "品川 500 あ 12-34"));
+        assert_eq!(fleet.len(), 1);
+        assert_eq!(fleet.get("D-12").unwrap().spec.bed_length, 3.5);
+        assert_eq!(fleet.find_by_plate("品川５００あ１２３４").unwrap().id, "D-12");
+        assert_eq!(fleet.lookup("品川500あ12-34").unwrap().id, "D-12");
+        assert!(fleet.lookup("練馬100さ1").is_none());
+    }
+
+    #[test]
+    fn test_reinsert_replaces_plate() {
+        let mut fleet = Fleet::new();
+        fleet.insert(vehicle("D-12", "品川500あ1234"));
+        fleet.insert(vehicle("D-12", "品川500あ5678"));
+        assert_eq!(fleet.len(), 1);
+        assert!(fleet.find_by_plate("品川500あ1234").is_none());
+        assert!(fleet.find_by_plate("品川500あ5678").is_some());
+        assert_eq!(fleet.get("D-12").unwrap().tailgate_height(), 0.34);
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let csv = "\u{FEFF}車両ID,ナンバー,車格,荷台長,荷台幅,荷台高,最大積載量,板種\n\
+                   D-12,\"品川 500 あ 12-34\",４ｔ,3.45,2.08,0.34,4.0,中板\n\
+                   \n\
+                   ,練馬100さ56-78,10t,5.3,2.25,0.5,9.5,\n";
+        let fleet = Fleet::from_csv(csv.as_bytes()).unwrap();
+        assert_eq!(fleet.len(), 2);
+        let v = fleet.find_by_plate("品川500あ1234").unwrap();
+        assert_eq!((v.id.as_str(), v.truck_class.as_str()), ("D-12", "4t"));
+        assert_eq!(v.calibration.plate_class.as_deref(), Some("中板"));
+        assert!((v.spec.level_volume - 3.45 * 2.08 * 0.34).abs() < 1e-9);
+        assert!(v.spec.heap_volume > v.spec.level_volume);
+        // Empty ID: the plate is the ID
+        assert!(fleet.get("練馬100さ56-78").is_some());
+    }
+
+    #[test]
+    fn test_from_csv_reports_every_bad_row() {
+        let csv = "plate,class,bed_length,bed_width,bed_height,max_payload\n\
+                   品川500あ1234,4t,3.4,2.06,0.32,4\n\
+                   品川500あ5678,4t,abc,2.06,0.32,4\n\
+                   品川500あ12-34,4t,3.4,2.06,0.32,4\n\
+                   ,4t,3.4,2.06,0.32,4\n";
+        let errors = Fleet::from_csv(csv.as_bytes()).unwrap_err();
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|e| match e {
+                FleetImportError::InvalidRow { line, .. } => *line,
+                other => panic!("{other}"),
+            })
+            .collect();
+        assert_eq!(lines, [3, 4, 5]);
+        assert_eq!(errors[0].code(), "FLEET_INVALID_ROW");
+
+        let errors = Fleet::from_csv("plate,class\n".as_bytes()).unwrap_err();
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[0], FleetImportError::MissingColumn("bed_length".into()));
+    }
+
+    #[test]
+    fn test_split_csv_line() {
+        assert_eq!(split_csv_line(r#"a,"b,c","d ""e""",,"#), ["a", "b,c", r#"d "e""#, "", ""]);
+    }
+
+    #[test]
+    fn test_pipeline_uses_vehicle_bed() {
+        let mut fleet = Fleet::new();
+        let mut v = vehicle("D-12", "品川500あ1234");
+        v.calibration.tailgate_height = Some(0.4);
+        fleet.insert(v);
+        let fleet = std::sync::Arc::new(fleet);
+
+        let config = BoxOverlayConfig::builder()
+            .truck_class("2t")
+            .fleet(fleet.clone())
+            .vehicle("品川 500 あ 12-34")
+            .build()
+            .unwrap();
+        let result = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        assert_eq!(result.vehicle_id.as_deref(), Some("D-12"));
+        assert_eq!(result.truck_class, "4t");
+        assert_eq!(result.breakdown.bed_length, 3.5);
+        // Tailgate 0.4m instead of the class's 0.32m scales the height up
+        assert!((result.height_m - 0.6).abs() < 1e-9);
+
+        let config = BoxOverlayConfig::builder().fleet(fleet).vehicle("D-99").build().unwrap();
+        let result = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        assert!(result.vehicle_id.is_none());
+        assert!(result.warnings.contains(&AnalysisWarning::UnknownVehicle { vehicle: "D-99".into() }));
+    }
+
+    /// Reads the given plate, otherwise answers like `fixed_backend()`
+    struct PlateBackend(&'static str);
+    impl AiBackend for PlateBackend {
+        fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+            if prompt.contains("plateNumber") {
+                Ok(format!(r#"{{"plateNumber":{}}}"#, self.0))
+            } else {
+                fixed_backend().send_prompt(prompt, images)
+            }
+        }
+    }
+
+    #[test]
+    fn test_plate_stage_selects_vehicle() {
+        let mut fleet = Fleet::new();
+        fleet.insert(vehicle("D-12", "品川500あ1234"));
+        let config = BoxOverlayConfig::builder()
+            .fleet(fleet)
+            .plate_recognition(true)
+            .build()
+            .unwrap();
+        assert_eq!(plan_box_overlay(&config, 1).calls[0].stage, PromptStage::Plate);
+
+        let result = analyze_box_overlay(&PlateBackend(r#""品川 500 あ 12-34""#), &[], &config).unwrap();
+        assert_eq!(result.plate_number.as_deref(), Some("品川500あ1234"));
+        assert_eq!(result.vehicle_id.as_deref(), Some("D-12"));
+        assert_eq!(result.breakdown.bed_length, 3.5);
+
+        let result = analyze_box_overlay(&PlateBackend(r#""練馬100さ5678""#), &[], &config).unwrap();
+        assert!(result.vehicle_id.is_none());
+        assert!(result.warnings.contains(&AnalysisWarning::UnknownVehicle { vehicle: "練馬100さ5678".into() }));
+
+        let result = analyze_box_overlay(&PlateBackend("null"), &[], &config).unwrap();
+        assert!(result.plate_number.is_none());
+        assert!(result.warnings.contains(&AnalysisWarning::PlateUnread));
+    }
+
+    #[test]
+    fn test_plate_stage_on_partial_and_frames() {
+        let mut fleet = Fleet::new();
+        fleet.insert(vehicle("D-12", "品川500あ1234"));
+        let config = BoxOverlayConfig::builder()
+            .fleet(fleet)
+            .plate_recognition(true)
+            .build()
+            .unwrap();
+        let backend = PlateBackend(r#""品川 500 あ 12-34""#);
+
+        let partial = analyze_box_overlay_partial(&backend, &[], &config).unwrap();
+        assert_eq!(partial.plate, Some(Some("品川500あ1234".into())));
+        let result = partial.complete(&config, None, None).unwrap();
+        assert_eq!(result.plate_number.as_deref(), Some("品川500あ1234"));
+        assert_eq!(result.vehicle_id.as_deref(), Some("D-12"));
+        assert_eq!(result.breakdown.bed_length, 3.5);
+
+        let frames = [b"f0", b"f1"].map(|f| InputImage::with_format(f.to_vec(), ImageFormat::Jpeg));
+        let multi = analyze_box_overlay_frames(&backend, &frames, &config).unwrap();
+        assert_eq!(multi.result.plate_number.as_deref(), Some("品川500あ1234"));
+        assert_eq!(multi.result.vehicle_id.as_deref(), Some("D-12"));
+
+        let partial = analyze_box_overlay_partial(&PlateBackend("null"), &[], &config).unwrap();
+        let result = partial.complete(&config, None, None).unwrap();
+        assert!(result.warnings.contains(&AnalysisWarning::PlateUnread));
+    }
+}
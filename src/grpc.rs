@@ -0,0 +1,474 @@
+//! gRPC service (feature `grpc`)
+//!
+//! The `tonsuu.v1.Tonsuu` service of proto/tonsuu.proto, for gate systems
+//! that integrate over gRPC: `Analyze`, `Validate` and `GetSpec` mirror
+//! `analyze_box_overlay`, `validate_params` and the embedded spec.
+//!
+//! Messages and the server glue are written out by hand (in the shape
+//! `tonic-build` generates) so the crate builds without protoc. Serve
+//! `TonsuuServer::new(PipelineService::new(backend))` with tonic's transport.
+//! Error statuses carry the crate's error code in the `error-code` metadata.
+
+use std::convert::Infallible;
+
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, Arc, Body, BoxFuture, Context, Poll, Service, StdError};
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::{Code, Request, Response, Status};
+
+use crate::i18n::{self, language};
+use crate::input::InputImage;
+use crate::pipeline::{analyze_box_overlay, AiBackend, BoxOverlayConfig, BoxOverlayResult, ConfigError, PipelineError};
+use crate::report::field_summary;
+use crate::spec::{spec_json, SPEC};
+use crate::validation::{validate_params, EstimationParams};
+
+// ─── Messages ────────────────────────────────────────────────────────
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AnalyzeRequest {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub images: Vec<Vec<u8>>,
+    #[prost(string, optional, tag = "2")]
+    pub truck_class: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub material_type: Option<String>,
+    #[prost(uint32, optional, tag = "4")]
+    pub ensemble_count: Option<u32>,
+    #[prost(string, optional, tag = "5")]
+    pub driver_id: Option<String>,
+    #[prost(string, optional, tag = "6")]
+    pub job_number: Option<String>,
+    #[prost(string, optional, tag = "7")]
+    pub destination: Option<String>,
+    #[prost(uint32, optional, tag = "8")]
+    pub geometry_runs: Option<u32>,
+    #[prost(uint32, optional, tag = "9")]
+    pub fill_runs: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AnalyzeResponse {
+    #[prost(string, tag = "1")]
+    pub truck_class: String,
+    #[prost(string, tag = "2")]
+    pub material_type: String,
+    #[prost(double, tag = "3")]
+    pub height_m: f64,
+    #[prost(double, tag = "4")]
+    pub volume: f64,
+    #[prost(double, tag = "5")]
+    pub tonnage: f64,
+    #[prost(double, tag = "6")]
+    pub tonnage_min: f64,
+    #[prost(double, tag = "7")]
+    pub tonnage_max: f64,
+    #[prost(double, optional, tag = "8")]
+    pub billed_tonnage: Option<f64>,
+    /// `Verdict::code`
+    #[prost(string, optional, tag = "9")]
+    pub verdict: Option<String>,
+    #[prost(bool, tag = "10")]
+    pub disagreement: bool,
+    #[prost(string, optional, tag = "11")]
+    pub vehicle_id: Option<String>,
+    #[prost(string, optional, tag = "12")]
+    pub plate_number: Option<String>,
+    #[prost(string, repeated, tag = "13")]
+    pub warnings: Vec<String>,
+    #[prost(string, tag = "14")]
+    pub summary: String,
+}
+
+impl From<&BoxOverlayResult> for AnalyzeResponse {
+    fn from(result: &BoxOverlayResult) -> Self {
+        Self {
+            truck_class: result.truck_class.clone(),
+            material_type: result.material_type.clone(),
+            height_m: result.height_m,
+            volume: result.volume,
+            tonnage: result.tonnage,
+            tonnage_min: result.tonnage_min,
+            tonnage_max: result.tonnage_max,
+            billed_tonnage: result.billed_tonnage,
+            verdict: result.verdict.map(|v| v.code().to_string()),
+            disagreement: result.disagreement.is_some(),
+            vehicle_id: result.vehicle_id.clone(),
+            plate_number: result.plate_number.clone(),
+            warnings: result.warnings.iter().map(|w| w.to_string()).collect(),
+            summary: field_summary(result, language()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ValidateRequest {
+    #[prost(double, optional, tag = "1")]
+    pub height: Option<f64>,
+    #[prost(double, optional, tag = "2")]
+    pub fill_ratio_l: Option<f64>,
+    #[prost(double, optional, tag = "3")]
+    pub fill_ratio_w: Option<f64>,
+    #[prost(double, optional, tag = "4")]
+    pub taper_ratio: Option<f64>,
+    #[prost(double, optional, tag = "5")]
+    pub packing_density: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ValidationIssue {
+    #[prost(string, tag = "1")]
+    pub code: String,
+    #[prost(string, tag = "2")]
+    pub field: String,
+    #[prost(double, tag = "3")]
+    pub value: f64,
+    #[prost(double, tag = "4")]
+    pub min: f64,
+    #[prost(double, tag = "5")]
+    pub max: f64,
+    #[prost(string, tag = "6")]
+    pub message: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ValidateResponse {
+    #[prost(bool, tag = "1")]
+    pub ok: bool,
+    #[prost(message, repeated, tag = "2")]
+    pub errors: Vec<ValidationIssue>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SpecRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SpecResponse {
+    #[prost(string, tag = "1")]
+    pub version: String,
+    #[prost(string, tag = "2")]
+    pub spec_json: String,
+}
+
+// ─── Service ─────────────────────────────────────────────────────────
+
+/// Methods of `tonsuu.v1.Tonsuu`
+#[tonic::async_trait]
+pub trait Tonsuu: Send + Sync + 'static {
+    async fn analyze(&self, request: Request<AnalyzeRequest>) -> Result<Response<AnalyzeResponse>, Status>;
+    async fn validate(&self, request: Request<ValidateRequest>) -> Result<Response<ValidateResponse>, Status>;
+    async fn get_spec(&self, request: Request<SpecRequest>) -> Result<Response<SpecResponse>, Status>;
+}
+
+/// `Tonsuu` backed by the pipeline
+#[derive(Clone)]
+pub struct PipelineService {
+    backend: Arc<dyn AiBackend + Send + Sync>,
+    config: BoxOverlayConfig,
+}
+
+impl PipelineService {
+    pub fn new(backend: impl AiBackend + Send + Sync + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            config: BoxOverlayConfig::default(),
+        }
+    }
+
+    /// Configuration requests start from; their set fields override it
+    pub fn config(mut self, config: BoxOverlayConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn request_config(&self, request: &AnalyzeRequest) -> Result<BoxOverlayConfig, ConfigError> {
+        let mut builder = self.config.clone().into_builder();
+        if let Some(truck_class) = &request.truck_class {
+            builder = builder.truck_class(truck_class.as_str());
+        }
+        if let Some(material_type) = &request.material_type {
+            builder = builder.material_type(material_type.as_str());
+        }
+        if let Some(count) = request.ensemble_count {
+            builder = builder.ensemble_count(count as usize);
+        }
+        if let Some(count) = request.geometry_runs {
+            builder = builder.geometry_runs(count as usize);
+        }
+        if let Some(count) = request.fill_runs {
+            builder = builder.fill_runs(count as usize);
+        }
+        let mut metadata = self.config.metadata.clone();
+        if let Some(id) = &request.driver_id {
+            metadata = metadata.driver_id(id.as_str());
+        }
+        if let Some(number) = &request.job_number {
+            metadata = metadata.job_number(number.as_str());
+        }
+        if let Some(destination) = &request.destination {
+            metadata = metadata.destination(destination.as_str());
+        }
+        builder.metadata(metadata).build()
+    }
+}
+
+/// Status with the crate's error code in the `error-code` metadata
+fn status(code: Code, error_code: &'static str, message: impl Into<String>) -> Status {
+    let mut metadata = MetadataMap::new();
+    metadata.insert("error-code", MetadataValue::from_static(error_code));
+    Status::with_metadata(code, message, metadata)
+}
+
+fn pipeline_status(e: PipelineError) -> Status {
+    let code = match e {
+        PipelineError::UnusableImage(_)
+        | PipelineError::UnknownTruckClass(_)
+        | PipelineError::Preprocess(_)
+        | PipelineError::ImageTooLarge { .. } => {
+            Code::InvalidArgument
+        }
+        PipelineError::CallTimeout(_) | PipelineError::Timeout { .. } => Code::DeadlineExceeded,
+        _ => Code::Unavailable,
+    };
+    status(code, e.code(), e.to_string())
+}
+
+#[tonic::async_trait]
+impl Tonsuu for PipelineService {
+    async fn analyze(&self, request: Request<AnalyzeRequest>) -> Result<Response<AnalyzeResponse>, Status> {
+        let request = request.into_inner();
+        if request.images.is_empty() {
+            return Err(status(Code::InvalidArgument, "NO_IMAGE", i18n::NO_IMAGE.get(language())));
+        }
+        let config = self
+            .request_config(&request)
+            .map_err(|e| status(Code::InvalidArgument, e.code(), e.to_string()))?;
+        let images = request
+            .images
+            .into_iter()
+            .map(InputImage::new)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| status(Code::InvalidArgument, e.code(), e.to_string()))?;
+        let backend = Arc::clone(&self.backend);
+        let result = tokio::task::spawn_blocking(move || analyze_box_overlay(backend.as_ref(), &images, &config))
+            .await
+            .map_err(|e| status(Code::Internal, "INTERNAL", e.to_string()))?
+            .map_err(pipeline_status)?;
+        Ok(Response::new(AnalyzeResponse::from(&result)))
+    }
+
+    async fn validate(&self, request: Request<ValidateRequest>) -> Result<Response<ValidateResponse>, Status> {
+        let request = request.into_inner();
+        let errors = validate_params(&EstimationParams {
+            height: request.height,
+            fill_ratio_l: request.fill_ratio_l,
+            fill_ratio_w: request.fill_ratio_w,
+            taper_ratio: request.taper_ratio,
+            packing_density: request.packing_density,
+        });
+        Ok(Response::new(ValidateResponse {
+            ok: errors.is_empty(),
+            errors: errors
+                .into_iter()
+                .map(|e| ValidationIssue {
+                    code: e.code().to_string(),
+                    field: e.field,
+                    value: e.value,
+                    min: e.min,
+                    max: e.max,
+                    message: e.message,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_spec(&self, _request: Request<SpecRequest>) -> Result<Response<SpecResponse>, Status> {
+        Ok(Response::new(SpecResponse {
+            version: SPEC.version.clone(),
+            spec_json: spec_json().to_string(),
+        }))
+    }
+}
+
+// ─── Server glue ─────────────────────────────────────────────────────
+
+/// Tower service routing `tonsuu.v1.Tonsuu` calls to a `Tonsuu`
+#[derive(Debug)]
+pub struct TonsuuServer<T> {
+    inner: Arc<T>,
+}
+
+impl<T> TonsuuServer<T> {
+    pub fn new(inner: T) -> Self {
+        Self { inner: Arc::new(inner) }
+    }
+}
+
+impl<T> Clone for TonsuuServer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T> NamedService for TonsuuServer<T> {
+    const NAME: &'static str = "tonsuu.v1.Tonsuu";
+}
+
+type Handler<T, Req, Res> = fn(Arc<T>, Request<Req>) -> BoxFuture<Response<Res>, Status>;
+
+/// One unary method bound to the service
+struct Unary<T, Req, Res> {
+    inner: Arc<T>,
+    handler: Handler<T, Req, Res>,
+}
+
+impl<T, Req, Res> UnaryService<Req> for Unary<T, Req, Res> {
+    type Response = Res;
+    type Future = BoxFuture<Response<Res>, Status>;
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        (self.handler)(Arc::clone(&self.inner), request)
+    }
+}
+
+fn unary<T, Req, Res, B>(
+    inner: Arc<T>,
+    handler: Handler<T, Req, Res>,
+    request: http::Request<B>,
+) -> BoxFuture<http::Response<BoxBody>, Infallible>
+where
+    T: Send + Sync + 'static,
+    Req: prost::Message + Default + Send + 'static,
+    Res: prost::Message + Send + 'static,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = Grpc::new(ProstCodec::<Res, Req>::default());
+        Ok(grpc.unary(Unary { inner, handler }, request).await)
+    })
+}
+
+impl<T, B> Service<http::Request<B>> for TonsuuServer<T>
+where
+    T: Tonsuu,
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let inner = Arc::clone(&self.inner);
+        match request.uri().path() {
+            "/tonsuu.v1.Tonsuu/Analyze" => unary(
+                inner,
+                |svc: Arc<T>, r| Box::pin(async move { svc.analyze(r).await }),
+                request,
+            ),
+            "/tonsuu.v1.Tonsuu/Validate" => unary(
+                inner,
+                |svc: Arc<T>, r| Box::pin(async move { svc.validate(r).await }),
+                request,
+            ),
+            "/tonsuu.v1.Tonsuu/GetSpec" => unary(
+                inner,
+                |svc: Arc<T>, r| Box::pin(async move { svc.get_spec(r).await }),
+                request,
+            ),
+            _ => Box::pin(async { Ok(Status::unimplemented("").into_http()) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixed_backend;
+    use http_body_util::{BodyExt, Full};
+    use prost::Message;
+    use tonic::codegen::Bytes;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_analyze() {
+        let service = PipelineService::new(fixed_backend());
+        let request = AnalyzeRequest {
+            images: vec![b"\xFF\xD8\xFFphoto".to_vec()],
+            job_number: Some("J-1".into()),
+            ..Default::default()
+        };
+        let response = service.analyze(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(response.truck_class, "4t");
+        assert!(response.tonnage > 0.0);
+        assert!(response.summary.contains("工事番号:J-1"));
+
+        let err = service.analyze(Request::new(AnalyzeRequest::default())).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert_eq!(err.metadata().get("error-code").unwrap(), "NO_IMAGE");
+
+        let request = AnalyzeRequest {
+            images: vec![b"\xFF\xD8\xFFphoto".to_vec()],
+            ensemble_count: Some(0),
+            ..Default::default()
+        };
+        let err = service.analyze(Request::new(request)).await.unwrap_err();
+        assert_eq!(err.metadata().get("error-code").unwrap(), "ZERO_ENSEMBLE_COUNT");
+    }
+
+    /// Unary call over HTTP with gRPC framing
+    async fn call<Req: Message, Res: Message + Default>(path: &str, message: Req) -> (Option<Res>, String) {
+        let payload = message.encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+        let request = http::Request::post(path)
+            .header(http::header::CONTENT_TYPE, "application/grpc")
+            .body(Full::new(Bytes::from(frame)))
+            .unwrap();
+        let response = TonsuuServer::new(PipelineService::new(fixed_backend()))
+            .oneshot(request)
+            .await
+            .unwrap();
+        let header_status = response.headers().get("grpc-status").cloned();
+        let collected = response.into_body().collect().await.unwrap();
+        let grpc_status = collected
+            .trailers()
+            .and_then(|t| t.get("grpc-status").cloned())
+            .or(header_status)
+            .unwrap();
+        let body = collected.to_bytes();
+        let message = (body.len() > 5).then(|| Res::decode(&body[5..]).unwrap());
+        (message, grpc_status.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let request = ValidateRequest {
+            height: Some(0.4),
+            fill_ratio_l: Some(5.0),
+            ..Default::default()
+        };
+        let (response, status) = call::<_, ValidateResponse>("/tonsuu.v1.Tonsuu/Validate", request).await;
+        assert_eq!(status, "0");
+        let response = response.unwrap();
+        assert!(!response.ok);
+        assert_eq!(response.errors[0].field, "fillRatioL");
+
+        let (response, _) = call::<_, SpecResponse>("/tonsuu.v1.Tonsuu/GetSpec", SpecRequest {}).await;
+        assert_eq!(response.unwrap().version, "2.1.0");
+
+        let (response, status) = call::<_, SpecResponse>("/tonsuu.v1.Tonsuu/Unknown", SpecRequest {}).await;
+        assert!(response.is_none());
+        assert_eq!(status, (Code::Unimplemented as i32).to_string());
+    }
+}
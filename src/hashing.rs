@@ -0,0 +1,319 @@
+//! Input image hashing and duplicate detection
+//!
+//! Every analyzed image gets a SHA-256 digest (exact duplicates) and, with the
+//! `image` feature, a 64-bit difference hash (near-duplicates such as
+//! re-encoded or slightly resized copies of the same photo).
+//! `DuplicateDetector` remembers recent hashes and flags re-submissions,
+//! and optionally the same truck (plate or registered vehicle) coming back
+//! sooner than a real round trip allows.
+
+use sha2::{Digest, Sha256};
+
+use crate::fleet::normalize_plate;
+use crate::pipeline::BoxOverlayResult;
+
+/// Hashes of a single input image
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageHash {
+    /// Lowercase hex SHA-256 of the raw bytes
+    pub sha256: String,
+    /// Perceptual difference hash (None without the `image` feature or if undecodable)
+    pub dhash: Option<u64>,
+}
+
+/// Hash a single image
+pub fn hash_image(bytes: &[u8]) -> ImageHash {
+    ImageHash {
+        sha256: sha256_hex(bytes),
+        dhash: dhash(bytes),
+    }
+}
+
+/// Lowercase hex SHA-256 of `bytes`
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hash every image in order
+pub fn hash_images<B: AsRef<[u8]>>(images: &[B]) -> Vec<ImageHash> {
+    images.iter().map(|b| hash_image(b.as_ref())).collect()
+}
+
+/// Number of differing bits between two perceptual hashes
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+#[cfg(feature = "image")]
+fn dhash(bytes: &[u8]) -> Option<u64> {
+    use image::imageops::FilterType;
+
+    let img = image::load_from_memory(bytes).ok()?;
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(left > right);
+        }
+    }
+    Some(hash)
+}
+
+#[cfg(not(feature = "image"))]
+fn dhash(_bytes: &[u8]) -> Option<u64> {
+    None
+}
+
+// ─── Duplicate detection ─────────────────────────────────────────────
+
+/// How a duplicate was recognized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKind {
+    /// Byte-identical image
+    Exact,
+    /// Perceptual hash within `max_distance` bits
+    NearDuplicate { distance: u32 },
+    /// Different photo of the same truck within `truck_window_secs`
+    SameTruck,
+}
+
+/// A previously seen submission matching the current one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateMatch {
+    /// Caller-supplied id of the earlier submission
+    pub previous_id: String,
+    /// Unix timestamp (seconds) of the earlier submission
+    pub previous_at: u64,
+    pub kind: DuplicateKind,
+}
+
+#[derive(Debug, Clone)]
+struct SeenEntry {
+    id: String,
+    at: u64,
+    hashes: Vec<ImageHash>,
+    /// Normalized plate or vehicle ID
+    truck: Option<String>,
+}
+
+/// Flags images that were already analyzed within a time window
+#[derive(Debug, Clone)]
+pub struct DuplicateDetector {
+    /// Look-back window in seconds
+    pub window_secs: u64,
+    /// Maximum dHash Hamming distance treated as the same photo
+    pub max_distance: u32,
+    /// Look-back window in seconds for the same truck (0 = trucks not compared);
+    /// set it below the shortest real round trip
+    pub truck_window_secs: u64,
+    seen: Vec<SeenEntry>,
+}
+
+impl DuplicateDetector {
+    pub fn new(window_secs: u64, max_distance: u32) -> Self {
+        Self {
+            window_secs,
+            max_distance,
+            truck_window_secs: 0,
+            seen: Vec::new(),
+        }
+    }
+
+    /// Also flag the same truck within `secs`
+    pub fn with_truck_window(mut self, secs: u64) -> Self {
+        self.truck_window_secs = secs;
+        self
+    }
+
+    /// Compare against recent submissions without recording
+    pub fn check(&self, hashes: &[ImageHash], now: u64) -> Option<DuplicateMatch> {
+        self.check_load(hashes, None, now)
+    }
+
+    /// Check, then remember this submission under `id`
+    pub fn check_and_record(
+        &mut self,
+        id: &str,
+        hashes: &[ImageHash],
+        now: u64,
+    ) -> Option<DuplicateMatch> {
+        self.check_and_record_load(id, hashes, None, now)
+    }
+
+    /// `check` that also compares the truck (plate number or vehicle ID).
+    /// A photo match wins over a truck match; the most recent match is reported.
+    pub fn check_load(&self, hashes: &[ImageHash], truck: Option<&str>, now: u64) -> Option<DuplicateMatch> {
+        let truck = truck.map(normalize_plate).filter(|t| !t.is_empty());
+        let recent = |window: u64| {
+            self.seen
+                .iter()
+                .rev()
+                .filter(move |e| now.saturating_sub(e.at) <= window)
+        };
+        let found = |e: &SeenEntry, kind| DuplicateMatch {
+            previous_id: e.id.clone(),
+            previous_at: e.at,
+            kind,
+        };
+        recent(self.window_secs)
+            .find_map(|e| self.compare(&e.hashes, hashes).map(|kind| found(e, kind)))
+            .or_else(|| {
+                let truck = truck.as_ref().filter(|_| self.truck_window_secs > 0)?;
+                recent(self.truck_window_secs)
+                    .find(|e| e.truck.as_ref() == Some(truck))
+                    .map(|e| found(e, DuplicateKind::SameTruck))
+            })
+    }
+
+    /// `check_load`, then remember this submission under `id`
+    pub fn check_and_record_load(
+        &mut self,
+        id: &str,
+        hashes: &[ImageHash],
+        truck: Option<&str>,
+        now: u64,
+    ) -> Option<DuplicateMatch> {
+        let found = self.check_load(hashes, truck, now);
+        self.prune(now);
+        self.seen.push(SeenEntry {
+            id: id.to_string(),
+            at: now,
+            hashes: hashes.to_vec(),
+            truck: truck.map(normalize_plate).filter(|t| !t.is_empty()),
+        });
+        found
+    }
+
+    /// `check_and_record_load` for an analysis result: its image hashes, and
+    /// its registered vehicle or else the plate read by the plate stage
+    pub fn check_and_record_result(
+        &mut self,
+        id: &str,
+        result: &BoxOverlayResult,
+        now: u64,
+    ) -> Option<DuplicateMatch> {
+        let truck = result.vehicle_id.as_deref().or(result.plate_number.as_deref());
+        self.check_and_record_load(id, &result.image_hashes, truck, now)
+    }
+
+    /// Drop entries older than both windows
+    pub fn prune(&mut self, now: u64) {
+        let window = self.window_secs.max(self.truck_window_secs);
+        self.seen.retain(|e| now.saturating_sub(e.at) <= window);
+    }
+
+    fn compare(&self, previous: &[ImageHash], current: &[ImageHash]) -> Option<DuplicateKind> {
+        let mut best: Option<DuplicateKind> = None;
+        for a in previous {
+            for b in current {
+                if a.sha256 == b.sha256 {
+                    return Some(DuplicateKind::Exact);
+                }
+                if let (Some(da), Some(db)) = (a.dhash, b.dhash) {
+                    let distance = hamming_distance(da, db);
+                    let closer = match best {
+                        Some(DuplicateKind::NearDuplicate { distance: d }) => distance < d,
+                        _ => true,
+                    };
+                    if distance <= self.max_distance && closer {
+                        best = Some(DuplicateKind::NearDuplicate { distance });
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_known_value() {
+        let h = hash_image(b"abc");
+        assert_eq!(
+            h.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_hamming_distance() {
+        assert_eq!(hamming_distance(0b1011, 0b0001), 2);
+        assert_eq!(hamming_distance(u64::MAX, 0), 64);
+    }
+
+    #[test]
+    fn test_exact_duplicate_within_window() {
+        let mut det = DuplicateDetector::new(3600, 5);
+        let hashes = hash_images(&[b"photo".to_vec()]);
+
+        assert!(det.check_and_record("load-1", &hashes, 1000).is_none());
+        let dup = det.check_and_record("load-2", &hashes, 1500).unwrap();
+        assert_eq!(dup.previous_id, "load-1");
+        assert_eq!(dup.kind, DuplicateKind::Exact);
+    }
+
+    #[test]
+    fn test_duplicate_outside_window_ignored() {
+        let mut det = DuplicateDetector::new(60, 5);
+        let hashes = hash_images(&[b"photo".to_vec()]);
+        det.check_and_record("load-1", &hashes, 0);
+        assert!(det.check(&hashes, 61).is_none());
+    }
+
+    #[test]
+    fn test_near_duplicate_by_dhash() {
+        let det_hash = |dhash| ImageHash { sha256: format!("{:x}", dhash), dhash: Some(dhash) };
+        let mut det = DuplicateDetector::new(3600, 4);
+        det.check_and_record("a", &[det_hash(0xFF00)], 0);
+
+        let near = det.check(&[det_hash(0xFF03)], 10).unwrap();
+        assert_eq!(near.kind, DuplicateKind::NearDuplicate { distance: 2 });
+        assert!(det.check(&[det_hash(0x00FF)], 10).is_none());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_reencoded_photo_is_near_duplicate() {
+        use crate::preprocess::{preprocess_image, PreprocessConfig};
+        use image::{DynamicImage, ImageFormat, RgbImage};
+
+        let img = RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, 128]));
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let jpeg = preprocess_image(&png, &PreprocessConfig { max_dimension: 32, jpeg_quality: 70 }).unwrap();
+
+        let a = hash_image(&png);
+        let b = hash_image(&jpeg);
+        assert_ne!(a.sha256, b.sha256);
+        assert!(hamming_distance(a.dhash.unwrap(), b.dhash.unwrap()) <= 6);
+    }
+
+    #[test]
+    fn test_same_truck_within_truck_window() {
+        let mut det = DuplicateDetector::new(3600, 4).with_truck_window(900);
+        let first = hash_images(&[b"photo-1".to_vec()]);
+        let second = hash_images(&[b"photo-2".to_vec()]);
+        assert!(det.check_and_record_load("load-1", &first, Some("品川500あ12-34"), 0).is_none());
+
+        // Different photo, same plate written differently, 10 minutes later
+        let dup = det.check_load(&second, Some("品川 500 あ 1234"), 600).unwrap();
+        assert_eq!((dup.previous_id.as_str(), dup.kind), ("load-1", DuplicateKind::SameTruck));
+        // A real round trip later, or another truck, is fine
+        assert!(det.check_load(&second, Some("品川500あ1234"), 1200).is_none());
+        assert!(det.check_load(&second, Some("練馬100さ5678"), 600).is_none());
+        // The photo itself is still caught within its window
+        assert_eq!(det.check_load(&first, None, 1200).unwrap().kind, DuplicateKind::Exact);
+        // Without a truck window trucks are not compared
+        let mut plain = DuplicateDetector::new(3600, 4);
+        plain.check_and_record_load("load-1", &first, Some("品川500あ1234"), 0);
+        assert!(plain.check_load(&second, Some("品川500あ1234"), 600).is_none());
+    }
+}
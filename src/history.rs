@@ -0,0 +1,346 @@
+//! Analysis history records
+//!
+//! Flat, serializable rows for storing results: a `LoadRecord` per analysis,
+//! a `RunRecord` per ensemble run and a `DailyTotal` per day and material.
+//! Storage backends persist these instead of the full `BoxOverlayResult`,
+//! so stored history does not change shape with every pipeline field.
+//!
+//! Serialized records carry `schemaVersion`; `LoadRecord::from_versioned_json`
+//! reads records of any earlier version, so history files written by an older
+//! crate keep loading after an upgrade.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::calculation::FormulaVersion;
+use crate::i18n::{self, language, Language, Localize};
+use crate::metadata::LoadMetadata;
+use crate::pipeline::BoxOverlayResult;
+use crate::policy::Verdict;
+
+/// UTC offset of Japan Standard Time, the default for calendar days
+pub const JST_OFFSET_SECS: i64 = 9 * 3600;
+
+/// Shape version of serialized records written by this crate.
+///
+/// 1: records without `schemaVersion` (before the field existed)
+/// 2: `schemaVersion` added
+/// 3: `formula` added
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Storage failure
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum HistoryError {
+    Storage(String),
+    /// A stored record that is not a JSON object
+    NotARecord,
+    /// `schemaVersion` that is not an unsigned integer
+    InvalidSchemaVersion(String),
+    /// Unreadable line (1-based) of a JSON Lines store
+    InvalidLine { line: usize, message: String },
+    DuplicateId(String),
+    /// Written by a newer crate than this one
+    UnsupportedSchema(u64),
+}
+
+impl HistoryError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Storage(_) | Self::NotARecord | Self::InvalidSchemaVersion(_) | Self::InvalidLine { .. } => {
+                "HISTORY_STORAGE"
+            }
+            Self::DuplicateId(_) => "HISTORY_DUPLICATE_ID",
+            Self::UnsupportedSchema(_) => "HISTORY_UNSUPPORTED_SCHEMA",
+        }
+    }
+}
+
+impl Localize for HistoryError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Storage(message) => i18n::HISTORY_STORAGE.format(lang, &[message]),
+            Self::NotARecord => i18n::HISTORY_NOT_A_RECORD.get(lang).to_string(),
+            Self::InvalidSchemaVersion(version) => i18n::HISTORY_INVALID_SCHEMA_VERSION.format(lang, &[version]),
+            Self::InvalidLine { line, message } => i18n::HISTORY_INVALID_LINE.format(lang, &[line, message]),
+            Self::DuplicateId(id) => i18n::HISTORY_DUPLICATE_ID.format(lang, &[id]),
+            Self::UnsupportedSchema(version) => i18n::HISTORY_UNSUPPORTED_SCHEMA.format(lang, &[version]),
+        }
+    }
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// One stored analysis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadRecord {
+    /// Shape version the record is in (`SCHEMA_VERSION` once read)
+    pub schema_version: u32,
+    pub id: String,
+    /// Unix time (seconds) of the analysis
+    pub recorded_at: i64,
+    /// Local calendar day of `recorded_at` ("YYYY-MM-DD")
+    pub day: String,
+    pub truck_class: String,
+    pub vehicle_id: Option<String>,
+    pub plate_number: Option<String>,
+    pub material_type: String,
+    pub height_m: f64,
+    pub volume: f64,
+    pub tonnage: f64,
+    pub tonnage_min: f64,
+    pub tonnage_max: f64,
+    pub billed_tonnage: Option<f64>,
+    /// Formula the tonnage was calculated with
+    pub formula: FormulaVersion,
+    pub verdict: Option<Verdict>,
+    /// Ensemble runs diverged beyond the configured thresholds
+    pub disagreement: bool,
+    pub metadata: LoadMetadata,
+    /// Warning texts as displayed
+    pub warnings: Vec<String>,
+}
+
+impl LoadRecord {
+    /// Record of a result analyzed at `recorded_at`, with the day taken in
+    /// the given UTC offset
+    pub fn new(id: impl Into<String>, recorded_at: i64, utc_offset_secs: i64, result: &BoxOverlayResult) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            id: id.into(),
+            recorded_at,
+            day: local_day(recorded_at, utc_offset_secs),
+            truck_class: result.truck_class.clone(),
+            vehicle_id: result.vehicle_id.clone(),
+            plate_number: result.plate_number.clone(),
+            material_type: result.material_type.clone(),
+            height_m: result.height_m,
+            volume: result.volume,
+            tonnage: result.tonnage,
+            tonnage_min: result.tonnage_min,
+            tonnage_max: result.tonnage_max,
+            billed_tonnage: result.billed_tonnage,
+            formula: result.formula,
+            verdict: result.verdict,
+            disagreement: result.disagreement.is_some(),
+            metadata: result.metadata.clone(),
+            warnings: result.warnings.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+
+    /// Billed tonnage when set, else the estimate
+    pub fn billable_tonnage(&self) -> f64 {
+        self.billed_tonnage.unwrap_or(self.tonnage)
+    }
+
+    /// Read a stored record of any supported schema version, upgraded to
+    /// the current shape
+    pub fn from_versioned_json(json: &str) -> Result<Self, HistoryError> {
+        let value = serde_json::from_str(json).map_err(|e| HistoryError::Storage(e.to_string()))?;
+        Self::from_versioned_value(value)
+    }
+
+    /// `from_versioned_json` for an already parsed value
+    pub fn from_versioned_value(value: Value) -> Result<Self, HistoryError> {
+        serde_json::from_value(upgrade_record(value)?).map_err(|e| HistoryError::Storage(e.to_string()))
+    }
+}
+
+/// Apply the upgrade steps from the record's version to `SCHEMA_VERSION`
+fn upgrade_record(mut value: Value) -> Result<Value, HistoryError> {
+    let version = match value.get("schemaVersion") {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .ok_or_else(|| HistoryError::InvalidSchemaVersion(v.to_string()))?,
+    };
+    if version > u64::from(SCHEMA_VERSION) {
+        return Err(HistoryError::UnsupportedSchema(version));
+    }
+    let Some(record) = value.as_object_mut() else {
+        return Err(HistoryError::NotARecord);
+    };
+    // 1 -> 2: only the version field itself was added
+    // 2 -> 3: every earlier record was calculated with formula 2.1
+    if version < 3 {
+        record.insert("formula".into(), FormulaVersion::V2_1.as_str().into());
+    }
+    record.insert("schemaVersion".into(), SCHEMA_VERSION.into());
+    Ok(value)
+}
+
+/// One stored ensemble run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunRecord {
+    pub load_id: String,
+    /// "geometry" or "fill"
+    pub stage: String,
+    /// Run index within the stage
+    pub run: u32,
+    pub variant: String,
+    pub backend: String,
+    pub raw_response: String,
+    /// The response parsed
+    pub parsed: bool,
+    /// Geometry runs: height and scale method
+    pub height_m: Option<f64>,
+    pub scale_method: Option<String>,
+    /// Fill runs: parsed values
+    pub fill_ratio_l: Option<f64>,
+    pub fill_ratio_w: Option<f64>,
+    pub taper_ratio: Option<f64>,
+    pub packing_density: Option<f64>,
+}
+
+/// Run records of a result, geometry runs first
+pub fn run_records(load_id: &str, result: &BoxOverlayResult) -> Vec<RunRecord> {
+    let geometry = result.geometry_runs.iter().enumerate().map(|(i, r)| RunRecord {
+        load_id: load_id.to_string(),
+        stage: "geometry".to_string(),
+        run: i as u32,
+        variant: r.variant.clone(),
+        backend: r.backend.clone(),
+        raw_response: r.raw_response.clone(),
+        parsed: r.parsed.is_some() || r.landmark.is_some(),
+        height_m: Some(r.height_m),
+        scale_method: Some(r.scale_method.clone()),
+        fill_ratio_l: None,
+        fill_ratio_w: None,
+        taper_ratio: None,
+        packing_density: None,
+    });
+    let fill = result.fill_runs.iter().enumerate().map(|(i, r)| RunRecord {
+        load_id: load_id.to_string(),
+        stage: "fill".to_string(),
+        run: i as u32,
+        variant: r.variant.clone(),
+        backend: r.backend.clone(),
+        raw_response: r.raw_response.clone(),
+        parsed: r.parsed.is_some(),
+        height_m: None,
+        scale_method: None,
+        fill_ratio_l: r.parsed.as_ref().map(|f| f.fill_ratio_l),
+        fill_ratio_w: r.parsed.as_ref().map(|f| f.fill_ratio_w),
+        taper_ratio: r.parsed.as_ref().map(|f| f.taper_ratio),
+        packing_density: r.parsed.as_ref().map(|f| f.packing_density),
+    });
+    geometry.chain(fill).collect()
+}
+
+/// Totals of one material on one day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyTotal {
+    pub day: String,
+    pub material_type: String,
+    pub loads: u32,
+    pub tonnage: f64,
+    pub volume: f64,
+    /// Sum of `LoadRecord::billable_tonnage`
+    pub billed_tonnage: f64,
+}
+
+/// Per-day, per-material totals, ordered by day then material
+pub fn daily_totals<'a>(records: impl IntoIterator<Item = &'a LoadRecord>) -> Vec<DailyTotal> {
+    let mut totals: BTreeMap<(&str, &str), DailyTotal> = BTreeMap::new();
+    for r in records {
+        let total = totals
+            .entry((r.day.as_str(), r.material_type.as_str()))
+            .or_insert_with(|| DailyTotal {
+                day: r.day.clone(),
+                material_type: r.material_type.clone(),
+                loads: 0,
+                tonnage: 0.0,
+                volume: 0.0,
+                billed_tonnage: 0.0,
+            });
+        total.loads += 1;
+        total.tonnage += r.tonnage;
+        total.volume += r.volume;
+        total.billed_tonnage += r.billable_tonnage();
+    }
+    totals.into_values().collect()
+}
+
+/// Calendar day ("YYYY-MM-DD") of a Unix time in the given UTC offset
+pub fn local_day(unix_secs: i64, utc_offset_secs: i64) -> String {
+    // Civil-from-days (proleptic Gregorian), see H. Hinnant's date algorithms
+    let days = (unix_secs + utc_offset_secs).div_euclid(86_400);
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    #[test]
+    fn test_local_day() {
+        assert_eq!(local_day(0, 0), "1970-01-01");
+        // 2026-10-15 15:00 UTC is already the 16th in Japan
+        assert_eq!(local_day(1_792_076_400, 0), "2026-10-15");
+        assert_eq!(local_day(1_792_076_400, JST_OFFSET_SECS), "2026-10-16");
+        assert_eq!(local_day(951_782_400, 0), "2000-02-29");
+        assert_eq!(local_day(-1, 0), "1969-12-31");
+    }
+
+    #[test]
+    fn test_records_and_daily_totals() {
+        let result = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        let a = LoadRecord::new("a", 1_792_076_400, JST_OFFSET_SECS, &result);
+        let mut b = LoadRecord::new("b", 1_792_080_000, JST_OFFSET_SECS, &result);
+        b.billed_tonnage = Some(4.5);
+        let mut c = LoadRecord::new("c", 1_792_080_000, JST_OFFSET_SECS, &result);
+        c.material_type = "土砂".into();
+
+        let totals = daily_totals([&a, &b, &c]);
+        assert_eq!(totals.len(), 2);
+        assert_eq!((totals[0].material_type.as_str(), totals[0].loads), ("As殻", 2));
+        assert!((totals[0].billed_tonnage - (result.tonnage + 4.5)).abs() < 1e-9);
+
+        let runs = run_records("a", &result);
+        assert_eq!(runs.len(), 4);
+        assert_eq!(runs[0].stage, "geometry");
+        assert_eq!(runs[3].fill_ratio_l, Some(0.8));
+    }
+
+    #[test]
+    fn test_versioned_json() {
+        let result = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        let record = LoadRecord::new("a", 1_792_076_400, JST_OFFSET_SECS, &result);
+        let mut json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["schemaVersion"], SCHEMA_VERSION);
+        assert_eq!(LoadRecord::from_versioned_value(json.clone()).unwrap(), record);
+
+        // Version 1 records have no version field, nor a formula before 3
+        json.as_object_mut().unwrap().remove("schemaVersion");
+        json.as_object_mut().unwrap().remove("formula");
+        let upgraded = LoadRecord::from_versioned_json(&json.to_string()).unwrap();
+        assert_eq!(upgraded, record);
+
+        json["schemaVersion"] = 99.into();
+        let err = LoadRecord::from_versioned_value(json).unwrap_err();
+        assert_eq!(err, HistoryError::UnsupportedSchema(99));
+        assert_eq!(err.code(), "HISTORY_UNSUPPORTED_SCHEMA");
+    }
+}
@@ -0,0 +1,504 @@
+//! Localized user-facing messages
+//!
+//! Every error, warning and report label the crate shows a person is an
+//! entry of the catalog below, in Japanese and English. `Display` renders in
+//! the process-wide language (`set_language`, Japanese by default); the
+//! `Localize` trait renders one value in a given language for callers that
+//! serve several users at once.
+//!
+//! Entries are templates: `{0}`, `{1}`, ... are replaced by the arguments
+//! given to `Message::format`, already formatted by the caller (precision and
+//! units stay with the code that knows the value).
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Output language for generated text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    Ja,
+    En,
+}
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(0);
+
+/// Set the language `Display` uses for every message of the crate
+pub fn set_language(lang: Language) {
+    LANGUAGE.store(lang as u8, Ordering::Relaxed);
+}
+
+/// Language `Display` currently uses
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        1 => Language::En,
+        _ => Language::Ja,
+    }
+}
+
+/// A value with user-facing text in every catalog language
+pub trait Localize {
+    fn localize(&self, lang: Language) -> String;
+}
+
+/// One catalog entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    pub ja: &'static str,
+    pub en: &'static str,
+}
+
+impl Message {
+    pub fn get(&self, lang: Language) -> &'static str {
+        match lang {
+            Language::Ja => self.ja,
+            Language::En => self.en,
+        }
+    }
+
+    /// Template for `lang` with `{n}` replaced by `args[n]`; placeholders
+    /// without an argument are kept as written
+    pub fn format(&self, lang: Language, args: &[&dyn fmt::Display]) -> String {
+        let template = self.get(lang);
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+            let arg = after
+                .find('}')
+                .and_then(|close| Some((close, args.get(after[..close].parse::<usize>().ok()?)?)));
+            match arg {
+                Some((close, arg)) => {
+                    out.push_str(&arg.to_string());
+                    rest = &after[close + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+const fn msg(ja: &'static str, en: &'static str) -> Message {
+    Message { ja, en }
+}
+
+// ─── Pipeline errors ─────────────────────────────────────────────────
+
+pub const AI_ERROR: Message = msg("AI エラー: {0}", "AI error: {0}");
+pub const PARSE_ERROR: Message = msg("AI応答を解析できません: {0}", "Parse error: {0}");
+pub const NO_VALID_GEOMETRY: Message = msg(
+    "幾何学検出が全ての試行で失敗しました",
+    "Geometry detection failed in every run",
+);
+pub const NO_VALID_FILL: Message = msg("充填率推定が全ての試行で失敗しました", "Fill estimation failed in every run");
+pub const NO_VALID_MULTI_PARAM: Message = msg(
+    "多パラメータ推定が全ての試行で失敗しました",
+    "Multi-parameter estimation failed in every run",
+);
+pub const PREPROCESS_FAILED: Message = msg("画像前処理エラー: {0}", "Image preprocessing failed: {0}");
+pub const UNUSABLE_IMAGE: Message = msg("解析に使えない画像です: {0}", "Image cannot be analyzed: {0}");
+pub const UNKNOWN_TRUCK_CLASS: Message = msg("未登録の車格です: {0}", "Unknown truck class: {0}");
+pub const CALL_TIMEOUT: Message = msg("AI 呼び出しが {0}ms でタイムアウトしました", "AI call timed out after {0}ms");
+pub const TIMEOUT: Message = msg(
+    "解析が制限時間を超えました ({0}ms 経過)",
+    "Analysis exceeded its deadline ({0}ms elapsed)",
+);
+pub const IMAGE_TOO_LARGE: Message = msg(
+    "画像 {0} が {1} バイトで、バックエンドの上限 {2} バイトを超えています",
+    "Image {0} is {1} bytes, over the backend limit of {2} bytes",
+);
+pub const CIRCUIT_OPEN: Message = msg(
+    "{0} は連続で失敗しているため呼び出しを停止中です (再開まで {1}ms)",
+    "{0} is paused after repeated failures (retrying in {1}ms)",
+);
+pub const ALL_BACKENDS_FAILED: Message = msg("全てのバックエンドが失敗しました ({0})", "Every backend failed ({0})");
+pub const BACKEND_THREAD_EXITED: Message = msg(
+    "バックエンドの呼び出しスレッドが異常終了しました",
+    "The backend call thread exited abnormally",
+);
+pub const UNPLANNED_CALL: Message = msg("計画にない問い合わせです", "Call not in the plan");
+pub const REPLY_NOT_STRING: Message = msg("応答が文字列ではありません", "Reply is not a string");
+pub const OFFLINE_NO_REPLY: Message = msg("オフライン応答がありません (タグ: {0})", "No offline reply (tags: {0})");
+pub const FIXTURE_INVALID: Message = msg("フィクスチャの設定が不正です: {0}", "Invalid fixture configuration: {0}");
+pub const RUN_BACKEND_FAILED: Message = msg("AI 呼び出しに失敗しました: {0}", "AI call failed: {0}");
+pub const RUN_PARSE_FAILED: Message = msg("応答を解析できません: {0}", "Reply could not be parsed: {0}");
+pub const RUN_REJECTED: Message = msg("応答を採用できません: {0}", "Reply not used: {0}");
+pub const REJECT_OCCLUDED: Message = msg("荷台の {0}% が隠れています", "{0}% of the bed is occluded");
+pub const REJECT_NO_TAILGATE: Message = msg("後板上端が検出されていません", "Tailgate top not detected");
+pub const REJECT_NO_BED: Message = msg("荷台が検出されませんでした", "No truck bed detected");
+pub const REJECT_NO_SCALE: Message = msg(
+    "後板・ナンバープレートのいずれからも縮尺を求められません",
+    "Neither the tailgate nor the license plate gives a scale",
+);
+
+// ─── Response parsing ────────────────────────────────────────────────
+
+pub const PARSE_NO_JSON: Message = msg("JSONオブジェクトが見つかりません", "No JSON object found");
+pub const PARSE_INCOMPLETE: Message = msg("不完全なJSONオブジェクト", "Incomplete JSON object");
+pub const PARSE_INVALID_JSON: Message = msg("JSON抽出後もパース失敗: {0}", "Extracted JSON is invalid: {0}");
+pub const PARSE_INPUT_TOO_LARGE: Message = msg(
+    "応答が長すぎます ({0} バイト、上限 {1} バイト)",
+    "Reply is too long ({0} bytes, limit {1} bytes)",
+);
+pub const PARSE_TOO_DEEP: Message = msg(
+    "JSONの入れ子が深すぎます (深さ {0}、上限 {1})",
+    "JSON is nested too deeply (depth {0}, limit {1})",
+);
+
+// ─── Configuration errors ────────────────────────────────────────────
+
+pub const ZERO_ENSEMBLE_COUNT: Message = msg(
+    "ensemble_count / geometry_runs / fill_runs は 1 以上が必要です",
+    "ensemble_count / geometry_runs / fill_runs must be at least 1",
+);
+pub const UNKNOWN_MATERIAL: Message = msg("未登録の材質です: {0}", "Unknown material: {0}");
+pub const MATERIAL_NOT_ALLOWED: Message = msg("この現場では扱わない材質です: {0}", "Material not accepted at this site: {0}");
+pub const INVALID_TARP_DEFLATION: Message = msg(
+    "シート掛け補正係数は 0 より大きく 1 以下にしてください: {0}",
+    "Tarp deflation must be in (0, 1]: {0}",
+);
+
+// ─── Validation ──────────────────────────────────────────────────────
+
+pub const OUT_OF_RANGE: Message = msg("範囲外: {0}~{1}", "out of range: {0}~{1}");
+pub const PARAMS_OUT_OF_RANGE: Message = msg("入力値が範囲外です: {0}", "Parameters out of range: {0}");
+
+// ─── Input and calculation ───────────────────────────────────────────
+
+pub const EMPTY_IMAGE: Message = msg("画像データが空です", "Image data is empty");
+pub const UNKNOWN_IMAGE_FORMAT: Message = msg(
+    "画像の形式を判別できません (JPEG / PNG / WebP / HEIC に対応)",
+    "Unrecognized image format (JPEG / PNG / WebP / HEIC are supported)",
+);
+pub const IMAGE_UNREADABLE: Message = msg("画像を読み込めません: {0}", "Cannot read the image: {0}");
+pub const IMAGE_PROCESSING_FAILED: Message = msg("画像処理に失敗しました: {0}", "Image processing failed: {0}");
+pub const IMAGE_UNDECODABLE: Message = msg("画像{0}を読み込めません", "Cannot decode image {0}");
+pub const IMAGE_TOO_DARK: Message = msg(
+    "画像{0}が暗すぎます (輝度 {1} < {2})",
+    "Image {0} is too dark (brightness {1} < {2})",
+);
+pub const IMAGE_BLURRY: Message = msg(
+    "画像{0}がぼやけています (鮮明度 {1} < {2})",
+    "Image {0} is blurry (sharpness {1} < {2})",
+);
+pub const IMAGE_NO_TRUCK_BED: Message = msg("荷台が確認できません", "No truck bed visible");
+pub const INVARIANT_NON_FINITE: Message = msg(
+    "計算結果が数値ではありません: {0} = {1}",
+    "Calculation result is not a number: {0} = {1}",
+);
+pub const INVARIANT_NOT_MONOTONIC: Message = msg(
+    "積載高さ {0}m の重量 {1}t が {2}m の {3}t を下回ります",
+    "Weight {1}t at load height {0}m is below {3}t at {2}m",
+);
+pub const INVARIANT_VOLUME_EXCEEDS_BED: Message = msg(
+    "体積 {0}m³ が荷台の上限 {1}m³ を超えています",
+    "Volume {0}m³ exceeds the bed limit of {1}m³",
+);
+pub const INVARIANT_PACKING_OUT_OF_RANGE: Message = msg(
+    "実効充填密度 {0} が範囲 {1}〜{2} の外です",
+    "Effective packing {0} is outside {1}–{2}",
+);
+
+// ─── Spec, records and exports ───────────────────────────────────────
+
+pub const SPEC_INVALID: Message = msg("プロンプト仕様を読み込めません: {0}", "Cannot load the prompt spec: {0}");
+pub const SPEC_NOT_LOADED: Message = msg(
+    "プロンプト仕様が読み込まれていません: 先に loadSpec を呼んでください",
+    "The prompt spec is not loaded: call loadSpec first",
+);
+pub const REDACTION_PATTERN_INVALID: Message = msg("伏字パターンが不正です: {0}: {1}", "Invalid redaction pattern: {0}: {1}");
+pub const FLEET_IO: Message = msg("車両台帳を読み込めません: {0}", "Cannot read the fleet list: {0}");
+pub const FLEET_MISSING_COLUMN: Message = msg(
+    "車両台帳に必須列 '{0}' がありません",
+    "Fleet list is missing the required column '{0}'",
+);
+pub const FLEET_INVALID_ROW: Message = msg("車両台帳 {0} 行目: {1}", "Fleet list line {0}: {1}");
+pub const FLEET_EMPTY_PLATE: Message = msg("ナンバーが空です", "Plate number is empty");
+pub const FLEET_DUPLICATE_ID: Message = msg("車両ID '{0}' が重複しています", "Duplicate vehicle ID '{0}'");
+pub const FLEET_DUPLICATE_PLATE: Message = msg("ナンバー '{0}' が重複しています", "Duplicate plate number '{0}'");
+pub const FLEET_NOT_A_NUMBER: Message = msg("{0} '{1}' が数値ではありません", "{0} '{1}' is not a number");
+pub const FLEET_OUT_OF_RANGE: Message = msg("{0} {1} が範囲外です (0〜{2})", "{0} {1} is out of range (0–{2})");
+pub const FLEET_BED_LENGTH: Message = msg("荷台長", "Bed length");
+pub const FLEET_BED_WIDTH: Message = msg("荷台幅", "Bed width");
+pub const FLEET_BED_HEIGHT: Message = msg("荷台高", "Bed height");
+pub const FLEET_MAX_PAYLOAD: Message = msg("最大積載量", "Max payload");
+pub const FLEET_TAILGATE_HEIGHT: Message = msg("後板高", "Tailgate height");
+pub const HISTORY_STORAGE: Message = msg("履歴を保存・読込できません: {0}", "Cannot store or load the history: {0}");
+pub const HISTORY_NOT_A_RECORD: Message = msg(
+    "積載記録がオブジェクトではありません",
+    "Load record is not a JSON object",
+);
+pub const HISTORY_INVALID_SCHEMA_VERSION: Message = msg("schemaVersion が不正です: {0}", "Invalid schemaVersion: {0}");
+pub const HISTORY_INVALID_LINE: Message = msg("{0} 行目: {1}", "line {0}: {1}");
+pub const HISTORY_DUPLICATE_ID: Message = msg(
+    "同じIDの積載記録が既にあります: {0}",
+    "A load record with this ID already exists: {0}",
+);
+pub const HISTORY_UNSUPPORTED_SCHEMA: Message = msg(
+    "履歴の形式 (schema {0}) に対応していません",
+    "Unsupported history format (schema {0})",
+);
+pub const XLSX_FAILED: Message = msg("Excel ファイルを作成できません: {0}", "Cannot create the Excel file: {0}");
+pub const NO_PRICE: Message = msg("単価が登録されていない材質です: {0}", "No unit price for material: {0}");
+pub const RESULT_UNREADABLE: Message = msg("解析結果を読み込めません: {0}", "Cannot read the analysis result: {0}");
+
+// ─── Service requests ────────────────────────────────────────────────
+
+pub const INVALID_CONFIG: Message = msg("config が不正です: {0}", "Invalid config: {0}");
+pub const UNKNOWN_FIELD: Message = msg("不明なフィールドです: {0}", "Unknown field: {0}");
+pub const NO_IMAGE: Message = msg("画像がありません", "No image given");
+pub const NO_IMAGE_FIELD: Message = msg("画像 (image) がありません", "No image given (field image)");
+
+// ─── Analysis warnings ───────────────────────────────────────────────
+
+pub const WARN_FILL_CLAMPED: Message = msg("{0}: {1} を範囲内の {2} に補正しました", "{0}: {1} clamped to {2}");
+pub const WARN_UNKNOWN_TRUCK_CLASS: Message = msg(
+    "未登録の車格 '{0}' のため既定の荷台寸法を使用しました",
+    "Unknown truck class '{0}'; default bed dimensions were used",
+);
+pub const WARN_PLATE_SCALE_FALLBACK: Message = msg(
+    "{0} 回の試行でナンバープレート基準のスケールを使用しました",
+    "{0} run(s) were scaled from the license plate",
+);
+pub const WARN_MATERIAL_DISAGREEMENT: Message = msg(
+    "試行ごとに材質の判定が異なります: {0}",
+    "Runs reported different materials: {0}",
+);
+pub const WARN_OVER_CAPACITY: Message = msg(
+    "推定体積 {0} m³ が山積み容量 {1} m³ を超えています",
+    "Estimated volume {0} m³ exceeds the heaped capacity of {1} m³",
+);
+pub const WARN_SCALE_INCONSISTENT: Message = msg(
+    "{0} 回の試行で後板とナンバープレートのスケールが食い違っています",
+    "Tailgate and license plate scales disagree in {0} run(s)",
+);
+pub const WARN_PLATE_REJECTED: Message = msg(
+    "{0} 回の試行でナンバープレートの縦横比が不自然なため無視しました",
+    "License plate ignored in {0} run(s) for an implausible aspect ratio",
+);
+pub const WARN_FILL_FALLBACK: Message = msg(
+    "充填率推定に失敗したため、幾何学検出から保守的な値を使用しました",
+    "Fill estimation failed; conservative values from the geometry stage were used",
+);
+pub const REASONING_FILL_FALLBACK: Message = msg(
+    "充填率推定が全試行で失敗したため、積載高さから保守的に算出",
+    "Fill estimation failed in every run; conservative values derived from the load height",
+);
+pub const REASONING_EMPTY_BED: Message = msg("荷台が空のため充填率推定を省略", "Bed is empty; fill estimation skipped");
+pub const WARN_UNKNOWN_VEHICLE: Message = msg(
+    "車両 '{0}' が車両台帳にないため車格の寸法を使用しました",
+    "Vehicle '{0}' is not in the fleet list; truck class dimensions were used",
+);
+pub const WARN_PLATE_UNREAD: Message = msg(
+    "ナンバープレートを読み取れなかったため車格の寸法を使用しました",
+    "License plate unreadable; truck class dimensions were used",
+);
+pub const WARN_LANDMARK_FALLBACK: Message = msg(
+    "座標検出に失敗したため {0} 回の試行で目印基準の高さを使用しました",
+    "Coordinate detection failed; {0} run(s) used the landmark height",
+);
+pub const WARN_RESPONSE_VALUE_CLAMPED: Message = msg(
+    "{0}: AI応答の異常値 {1} を {2} に補正しました",
+    "{0}: extreme value {1} in the model reply clamped to {2}",
+);
+pub const WARN_GEOMETRY_ESCALATED: Message = msg(
+    "試行間のばらつきが大きいため高さ推定を {0} 回追加しました",
+    "Runs disagreed; {0} extra height run(s) were made",
+);
+pub const WARN_FILL_ESCALATED: Message = msg(
+    "試行間のばらつきが大きいため充填率推定を {0} 回追加しました",
+    "Runs disagreed; {0} extra fill run(s) were made",
+);
+
+pub const REVIEW_FLAG: Message = msg(
+    "{0} の変動係数 {1} が閾値 {2} を超えています",
+    "Coefficient of variation of {0} is {1}, over the threshold of {2}",
+);
+pub const WARN_OCCLUSION_REJECTED: Message = msg(
+    "荷台の遮蔽が大きいため高さ推定を {0} 回除外しました",
+    "{0} height run(s) were rejected because the bed was occluded",
+);
+pub const WARN_OCCLUDED: Message = msg(
+    "荷台の {0}% が隠れているため推定範囲を広げました",
+    "{0}% of the bed was occluded; the tonnage range was widened",
+);
+
+// ─── Report labels ───────────────────────────────────────────────────
+
+pub const SCALE_TAILGATE: Message = msg("後板 (テールゲート)", "tailgate");
+pub const SCALE_PLATE: Message = msg("ナンバープレート", "license plate");
+pub const SCALE_FUSED: Message = msg("後板 + ナンバープレート", "tailgate + license plate");
+pub const SCALE_MANUAL: Message = msg("手入力", "manual input");
+pub const EXPLAIN_SCALE: Message = msg("スケール基準: {0} ({1}/{2} 回の試行が有効)", "Scale reference: {0} ({1}/{2} runs valid)");
+pub const EXPLAIN_HEIGHT: Message = msg("積載高さ: {0} m", "Load height: {0} m");
+pub const EXPLAIN_FILL: Message = msg(
+    "有効充填: 長さ {0} × テーパー {1} = {2}, 幅 ({3} + {4}) / 2 = {5}",
+    "Effective fill: length {0} × taper {1} = {2}, width ({3} + {4}) / 2 = {5}",
+);
+pub const EXPLAIN_VOLUME: Message = msg(
+    "体積: {0} m × {1} m × {2} m × {3} × {4} = {5} m³",
+    "Volume: {0} m × {1} m × {2} m × {3} × {4} = {5} m³",
+);
+pub const EXPLAIN_COMPRESSION: Message = msg(
+    "圧縮補正: 係数 {0} → 有効充填密度 {1}",
+    "Compression: factor {0} → effective packing {1}",
+);
+pub const EXPLAIN_DENSITY: Message = msg("比重: {0} {1} t/m³", "Density: {0} {1} t/m³");
+pub const EXPLAIN_WEIGHT: Message = msg(
+    "推定重量: {0} × {1} × {2} = {3} t (比重の幅で {4}〜{5} t)",
+    "Estimated weight: {0} × {1} × {2} = {3} t ({4}–{5} t over the density range)",
+);
+pub const EXPLAIN_BANK_VOLUME: Message = msg(
+    "地山換算: {0} / 土量変化率 {1} = {2} m³",
+    "Bank volume: {0} / swell factor {1} = {2} m³",
+);
+pub const OVERLAY_BED: Message = msg("荷台 (後板)", "Bed (tailgate)");
+pub const OVERLAY_TAILGATE_TOP: Message = msg("後板上端", "Tailgate top");
+pub const OVERLAY_TAILGATE_BOTTOM: Message = msg("後板下端", "Tailgate bottom");
+pub const OVERLAY_CARGO_TOP: Message = msg("荷山頂部 {0}m", "Cargo top {0}m");
+pub const SUMMARY_WEIGHT: Message = msg(
+    "{0}車 {1} 積載高さ{2}m 体積{3}m³ 推定{4}t({5})",
+    "{0} truck {1} load height {2}m volume {3}m³ estimated {4}t ({5})",
+);
+pub const SUMMARY_VOLUME: Message = msg(
+    "{0}車 {1} 積載高さ{2}m ほぐし{3}m³{4} 参考重量{5}t({6})",
+    "{0} truck {1} load height {2}m loose {3}m³{4} reference weight {5}t ({6})",
+);
+pub const SUMMARY_BANK_VOLUME: Message = msg("(地山{0}m³)", " (bank {0}m³)");
+pub const SUMMARY_OVERLOAD: Message = msg("過積載の疑い: 最大積載量{0}t", "possible overload: max payload {0}t");
+pub const SUMMARY_NOT_OVERLOADED: Message = msg("過積載なし", "not overloaded");
+pub const SUMMARY_CAPACITY_UNKNOWN: Message = msg("最大積載量不明", "max payload unknown");
+pub const SUMMARY_NEEDS_REVIEW: Message = msg(" ※試行間のばらつき大・要確認", " * runs disagree, check required");
+pub const METADATA_JOB_NUMBER: Message = msg("工事番号", "Job number");
+pub const METADATA_DRIVER: Message = msg("運転者", "Driver");
+pub const METADATA_DESTINATION: Message = msg("搬出先", "Destination");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{AnalysisWarning, PipelineError};
+
+    #[test]
+    fn test_format_fills_placeholders() {
+        assert_eq!(
+            IMAGE_TOO_LARGE.format(Language::En, &[&2, &10, &5]),
+            "Image 2 is 10 bytes, over the backend limit of 5 bytes"
+        );
+        // Arguments are inserted verbatim, never re-expanded
+        assert_eq!(FLEET_INVALID_ROW.format(Language::En, &[&"{1}", &"x"]), "Fleet list line {1}: x");
+        assert_eq!(FLEET_INVALID_ROW.format(Language::En, &[&3]), "Fleet list line 3: {1}");
+    }
+
+    #[test]
+    fn test_localize_per_call() {
+        let err = PipelineError::UnknownTruckClass("8t".into());
+        let warning = AnalysisWarning::PlateScaleFallback { runs: 2 };
+        assert_eq!(err.localize(Language::En), "Unknown truck class: 8t");
+        assert_eq!(err.localize(Language::Ja), "未登録の車格です: 8t");
+        assert_eq!(warning.localize(Language::En), "2 run(s) were scaled from the license plate");
+        // Display follows the global language (not switched here: other
+        // tests running in parallel compare Japanese text)
+        assert_eq!(err.to_string(), err.localize(language()));
+    }
+
+    #[test]
+    fn test_english_has_no_japanese() {
+        use std::time::Duration;
+
+        use crate::calculation::InvariantViolation;
+        use crate::ensemble::ReviewFlag;
+        use crate::fleet::{FleetImportError, RowError};
+        use crate::history::HistoryError;
+        use crate::input::InputError;
+        use crate::parse::{parse_geometry, parse_json_safe_with, GeometryResponse, NumericWarning, ParseLimits};
+        use crate::pipeline::{ConfigError, RunFailure};
+        use crate::quality::ImageIssue;
+
+        let limits = ParseLimits { max_input_bytes: 4, max_depth: 1 };
+        let parse_errors = [
+            parse_geometry("no json").unwrap_err(),
+            parse_geometry("{").unwrap_err(),
+            parse_geometry(r#"{"cargoTopY":"high"}"#).unwrap_err(),
+            parse_json_safe_with::<GeometryResponse>("{ too long }", &limits).unwrap_err(),
+            parse_json_safe_with::<GeometryResponse>("{[]}", &ParseLimits { max_input_bytes: 64, ..limits })
+                .unwrap_err(),
+        ];
+        let issues = [
+            ImageIssue::Undecodable { image: 1 },
+            ImageIssue::TooDark { image: 1, brightness: 10.0, min: 40.0 },
+            ImageIssue::Blurry { image: 2, sharpness: 3.0, min: 15.0 },
+            ImageIssue::NoTruckBed(None),
+        ];
+        let mut values: Vec<Box<dyn Localize>> = vec![
+            Box::new(PipelineError::AiError("x".into())),
+            Box::new(PipelineError::NoValidGeometry),
+            Box::new(PipelineError::NoValidFill),
+            Box::new(PipelineError::NoValidMultiParam),
+            Box::new(PipelineError::Preprocess("x".into())),
+            Box::new(PipelineError::UnknownTruckClass("8t".into())),
+            Box::new(PipelineError::CallTimeout(Duration::from_secs(1))),
+            Box::new(PipelineError::Timeout {
+                elapsed: Duration::from_secs(1),
+                geometry_runs: Vec::new(),
+                fill_runs: Vec::new(),
+            }),
+            Box::new(PipelineError::ImageTooLarge { index: 0, bytes: 2, max: 1 }),
+            Box::new(PipelineError::CircuitOpen { backend: "b".into(), retry_after: Duration::from_secs(1) }),
+            Box::new(ConfigError::ZeroEnsembleCount),
+            Box::new(ConfigError::UnknownMaterial("x".into())),
+            Box::new(ConfigError::MaterialNotAllowed("x".into())),
+            Box::new(ConfigError::InvalidTarpDeflation(2.0)),
+            Box::new(RunFailure::Backend { code: "AI_ERROR".into(), message: "x".into() }),
+            Box::new(RunFailure::Timeout { limit_ms: 5 }),
+            Box::new(RunFailure::Parse { code: "PARSE_NO_JSON".into(), message: "x".into() }),
+            Box::new(RunFailure::Rejected { reason: "x".into() }),
+            Box::new(ReviewFlag { field: "height".into(), cv: 0.2, threshold: 0.1 }),
+            Box::new(NumericWarning { field: "cargoTopY".into(), value: 1e308, clamped: 2.0 }),
+            Box::new(FleetImportError::Io("x".into())),
+            Box::new(FleetImportError::MissingColumn("plate".into())),
+            Box::new(HistoryError::Storage("x".into())),
+            Box::new(HistoryError::NotARecord),
+            Box::new(HistoryError::InvalidSchemaVersion("x".into())),
+            Box::new(HistoryError::InvalidLine { line: 2, message: "x".into() }),
+            Box::new(HistoryError::DuplicateId("x".into())),
+            Box::new(HistoryError::UnsupportedSchema(9)),
+            Box::new(InputError::Empty),
+            Box::new(InputError::UnknownFormat),
+            Box::new(crate::spec::SpecError::NotLoaded),
+            Box::new(InvariantViolation::NonFinite { field: "volume", value: f64::NAN }),
+            Box::new(InvariantViolation::VolumeExceedsBed { volume: 9.0, limit: 5.0 }),
+        ];
+        let rows = [
+            RowError::EmptyPlate,
+            RowError::DuplicateId("x".into()),
+            RowError::DuplicatePlate("x".into()),
+            RowError::NotANumber { column: FLEET_BED_LENGTH, value: "x".into() },
+            RowError::OutOfRange { column: FLEET_MAX_PAYLOAD, value: 99.0, max: 30.0 },
+        ];
+        for reason in rows {
+            values.push(Box::new(FleetImportError::InvalidRow { line: 2, reason }));
+        }
+        for issue in issues {
+            values.push(Box::new(PipelineError::UnusableImage(issue)));
+        }
+        for e in parse_errors {
+            values.push(Box::new(PipelineError::ParseError(e)));
+        }
+        #[cfg(feature = "image")]
+        values.push(Box::new(crate::preprocess::PreprocessError::Image("x".into())));
+
+        let japanese =
+            |c: char| matches!(c, '\u{3000}'..='\u{30ff}' | '\u{4e00}'..='\u{9fff}' | '\u{ff00}'..='\u{ffef}');
+        for value in &values {
+            let text = value.localize(Language::En);
+            assert!(!text.chars().any(japanese), "{}", text);
+            assert!(value.localize(Language::Ja).chars().any(japanese), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Typed input images
+//!
+//! Photos enter the pipeline as `InputImage`: the bytes, a format detected
+//! from the magic bytes (providers such as Gemini REST need the MIME type),
+//! and optionally the file name, capture time and position. The result keeps
+//! an `ImageInfo` per input for traceability; with the `image` feature capture
+//! time and position are also read from the photo's EXIF.
+
+use std::fmt;
+
+use crate::i18n::{self, language, Language, Localize};
+
+/// Accepted image formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Webp,
+    Heic,
+}
+
+impl ImageFormat {
+    /// Detect the format from the leading bytes
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0xFF, 0xD8, 0xFF, ..] => Some(Self::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(Self::Png),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(Self::Webp),
+            [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if brand.len() >= 4 => {
+                matches!(&brand[..4], b"heic" | b"heix" | b"hevc" | b"hevx" | b"mif1" | b"msf1").then_some(Self::Heic)
+            }
+            _ => None,
+        }
+    }
+
+    /// MIME type sent to providers
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+            Self::Heic => "image/heic",
+        }
+    }
+}
+
+/// Rejected input image
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum InputError {
+    Empty,
+    UnknownFormat,
+}
+
+impl InputError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Empty => "EMPTY_IMAGE",
+            Self::UnknownFormat => "UNKNOWN_IMAGE_FORMAT",
+        }
+    }
+}
+
+impl Localize for InputError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Empty => i18n::EMPTY_IMAGE.get(lang).to_string(),
+            Self::UnknownFormat => i18n::UNKNOWN_IMAGE_FORMAT.get(lang).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// One photo of the load
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputImage {
+    pub bytes: Vec<u8>,
+    pub mime: ImageFormat,
+    /// File name as uploaded
+    pub name: Option<String>,
+    /// Capture time (Unix seconds)
+    pub captured_at: Option<i64>,
+    /// Where the photo was taken
+    pub gps: Option<GpsPosition>,
+}
+
+impl InputImage {
+    /// Wrap image bytes, detecting the format from the magic bytes
+    pub fn new(bytes: Vec<u8>) -> Result<Self, InputError> {
+        if bytes.is_empty() {
+            return Err(InputError::Empty);
+        }
+        let mime = ImageFormat::detect(&bytes).ok_or(InputError::UnknownFormat)?;
+        Ok(Self::with_format(bytes, mime))
+    }
+
+    /// Wrap image bytes of a format the caller already knows (not checked)
+    pub fn with_format(bytes: Vec<u8>, mime: ImageFormat) -> Self {
+        Self {
+            bytes,
+            mime,
+            name: None,
+            captured_at: None,
+            gps: None,
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn captured_at(mut self, unix_secs: i64) -> Self {
+        self.captured_at = Some(unix_secs);
+        self
+    }
+
+    pub fn gps(mut self, position: GpsPosition) -> Self {
+        self.gps = Some(position);
+        self
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        self.mime.mime_type()
+    }
+
+    /// Metadata recorded in the result
+    pub fn info(&self) -> ImageInfo {
+        ImageInfo {
+            mime: self.mime,
+            name: self.name.clone(),
+            captured_at: self.captured_at,
+            gps: self.gps,
+            size_bytes: self.bytes.len(),
+        }
+    }
+}
+
+impl AsRef<[u8]> for InputImage {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Input image as recorded in `BoxOverlayResult::inputs` (before preprocessing)
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageInfo {
+    pub mime: ImageFormat,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub captured_at: Option<i64>,
+    #[serde(default)]
+    pub gps: Option<GpsPosition>,
+    pub size_bytes: usize,
+}
+
+/// WGS84 position in degrees (south and west negative)
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Metres above sea level
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_detected_from_magic_bytes() {
+        assert_eq!(ImageFormat::detect(b"\xFF\xD8\xFF\xE0rest"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::detect(b"\x89PNG\r\n\x1a\nIHDR"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::detect(b"RIFF\x10\0\0\0WEBPVP8 "), Some(ImageFormat::Webp));
+        assert_eq!(ImageFormat::detect(b"\0\0\0\x18ftypheic\0\0"), Some(ImageFormat::Heic));
+        assert_eq!(ImageFormat::detect(b"\0\0\0\x18ftypisom\0\0"), None);
+        assert_eq!(ImageFormat::detect(b"GIF89a"), None);
+
+        let img = InputImage::new(b"\x89PNG\r\n\x1a\n".to_vec()).unwrap().name("rear.png").captured_at(1_700_000_000);
+        assert_eq!(img.mime_type(), "image/png");
+        assert_eq!(
+            img.info(),
+            ImageInfo {
+                mime: ImageFormat::Png,
+                name: Some("rear.png".into()),
+                captured_at: Some(1_700_000_000),
+                gps: None,
+                size_bytes: 8,
+            }
+        );
+        assert_eq!(InputImage::new(b"photo".to_vec()).unwrap_err().code(), "UNKNOWN_IMAGE_FORMAT");
+        assert_eq!(InputImage::new(Vec::new()).unwrap_err(), InputError::Empty);
+    }
+}
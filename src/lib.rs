@@ -6,66 +6,174 @@
 //! Compiles to both native (rlib) and WebAssembly (cdylib via wasm-pack).
 
 pub mod spec;
+pub mod backend;
+pub mod billing;
 pub mod calculation;
+pub mod diff;
+#[cfg(feature = "image")]
+pub mod exif;
+pub mod ensemble;
+pub mod fleet;
+pub mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hashing;
+pub mod history;
+pub mod i18n;
+pub mod input;
+pub mod metadata;
+pub mod metrics;
+pub mod offline;
+pub mod overlay;
 pub mod parse;
 pub mod pipeline;
+pub mod policy;
+#[cfg(feature = "image")]
+pub mod preprocess;
 pub mod prompt;
+pub mod quality;
+pub mod reconcile;
+pub mod redact;
+pub mod report;
+pub mod repro;
+pub mod runlog;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod site;
+pub mod sites;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod store;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
 pub mod validation;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "xlsx")]
+pub mod xlsx;
 
 // Re-exports for convenience
-pub use spec::{PromptSpec, TruckSpec, MaterialEntry, Range, HeightRange, Constants};
-pub use calculation::{calculate_tonnage, height_from_geometry, TonnageResult, CoreParams};
-pub use parse::{parse_geometry, parse_fill, GeometryResponse, FillResponse, ParseError};
+pub use spec::{
+    get_category, material_category, materials_in_category, normalize_truck_class, PromptSpec, PlateClass,
+    SpecError, TruckClassMatch, TruckSpec, MaterialCategory, MaterialEntry,
+    Range, HeightRange, Constants,
+};
+pub use calculation::{
+    calculate_tonnage, calculate_tonnage_checked, calculate_tonnage_with, debug_checks, estimate_height, height_from_geometry,
+    CalcTree, InvariantViolation, ScaleCalibration, ScaleEstimate, ScaleOptions, TonnageBreakdown, TonnageResult,
+    CoreParams, FormulaVersion, MAX_CARGO_HEIGHT_M, landmark_height, Landmark, calculate_multi_param,
+    calculate_multi_param_with, MultiParams,
+};
+pub use backend::{FallbackBackend, Session, StatefulBackend};
+pub use billing::{invoice, line_item, BillingError, Invoice, InvoiceRounding, LineItem, PriceTable, RoundingMode};
+#[cfg(not(target_arch = "wasm32"))]
+pub use backend::{CircuitBreaker, CircuitState, Watchdog};
+pub use diff::ResultDiff;
+pub use repro::{reproduce, ReproManifest, Reproduction};
+pub use error::TonsuuError;
+pub use fleet::{normalize_plate, Fleet, FleetImportError, RowError, Vehicle, VehicleCalibration};
+pub use ensemble::{
+    quantile, Disagreement, DisagreementThresholds, MaterialVote, MaterialVotes, MedianMode, ParamStats, ReviewFlag,
+    ReviewThresholds, RunStatistics,
+};
+pub use history::{
+    daily_totals, local_day, run_records, DailyTotal, HistoryError, LoadRecord, RunRecord, JST_OFFSET_SECS, SCHEMA_VERSION,
+};
+pub use hashing::{hash_image, DuplicateDetector, DuplicateKind, DuplicateMatch, ImageHash};
+pub use input::{GpsPosition, ImageFormat, ImageInfo, InputError, InputImage};
+pub use metadata::{LoadMetadata, MetadataValue};
+pub use metrics::{Counter, Histogram, Metrics, MetricsSink, NoopMetrics};
+pub use offline::{CannedResponse, OfflineBackend, ResponseLibrary};
+pub use runlog::{JsonlLogSink, LogSink, RunEvent, RunLogs};
+#[cfg(feature = "prometheus")]
+pub use metrics::PrometheusMetrics;
+pub use parse::{
+    parse_geometry, parse_fill, parse_json_safe_with, parse_landmark, parse_plate, parse_reasoning_summary,
+    parse_geometry_ref, parse_fill_ref, GeometryResponseRef, FillResponseRef,
+    GeometryResponse, FillResponse, JsonObjectScanner, LandmarkResponse, NumericWarning, ParseError, ParseLimits,
+    PlateResponse, ReasoningSummaryResponse, RESPONSE_VALUE_MAX, parse_multi_param, MultiParamResponse,
+};
 pub use pipeline::{
-    analyze_box_overlay, AiBackend, BoxOverlayConfig, BoxOverlayResult,
-    PipelineError, GeometryRunLog, FillRunLog,
+    analyze_box_overlay, analyze_box_overlay_partial, analyze_box_overlay_with_geometry, analyze_fill,
+    analyze_geometry,
+    AiBackend, AnalysisWarning, BoxOverlayConfig, ClampRecord, BoxOverlayConfigBuilder, BoxOverlayResult,
+    BackendCapabilities, BackendOptions, ConfigError, PipelineError, GeometryRunLog,
+    FillRunLog, FillSource,
+    GeometryStageResult, FillStageResult, PromptVariant, plan_box_overlay, CallPlan, PlannedCall,
+    StageSchedule, MissingStage, ManualFill, PartialBoxOverlay, PlateRegion, analyze_box_overlay_frames,
+    LoadState, EMPTY_BED_HEIGHT_M,
+    FrameHeight, MultiFrameResult, GeometryMode, LogRetention, RunFailure, ReasoningSummary, RunReasoning,
+    analyze, analyze_delta, analyze_multi_param, analyze_with, compare_strategies, BoxOverlayRun, BoxOverlayStrategy,
+    DeltaResult, EstimationStrategy, MultiParamEstimate, MultiParamResult, MultiParamRunLog, MultiParamStrategy,
+    Strategy, OcclusionCheck, StrategyCall, StrategyComparison, StrategyContext, StrategyReply, StrategyResult, TarpMode,
 };
+#[cfg(not(target_arch = "wasm32"))]
+pub use pipeline::{analyze_box_overlay_batch, analyze_box_overlay_concurrent};
+pub use policy::{TolerancePolicy, ToleranceRule, Verdict};
+pub use site::{SiteProfile, SpecOverlay};
+pub use sites::{Geofence, RegisteredSite, SiteRegistry};
+pub use store::{FileStore, LoadQuery, MemoryStore, ResultStore};
 #[allow(deprecated)]
-pub use prompt::build_core_prompt;
+pub use prompt::{build_core_prompt, registry, PromptEntry, PromptStage};
+pub use overlay::{displayed_size, CoordinateSpace, OverlayData, OverlayItem, OverlayKind, OverlayShape};
+pub use reconcile::{
+    reconcile, reconcile_batch, BatchReconciliation, ManifestEntry, ReconcileStatus, Reconciliation, Tolerance,
+};
+pub use redact::{RedactionConfig, RedactionError, Redactor};
+pub use i18n::{language, set_language, Language, Localize};
+pub use report::ReportingMode;
 pub use validation::{validate_params, ValidationError};
+#[cfg(feature = "server")]
+pub use server::{router, AnalyzeOptions, ServerState};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteHistory;
+#[cfg(feature = "xlsx")]
+pub use xlsx::{write_xlsx, ExportError};
 
 // ─── WASM exports for prompt access and parsing ──────────────────────
 
 #[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "wasm")]
+use crate::wasm::{export, require_spec, ExportValue};
 
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = "getGeometryPrompt")]
-pub fn get_geometry_prompt_wasm() -> String {
-    spec::SPEC.geometry_prompt.clone()
+pub fn get_geometry_prompt_wasm() -> Result<String, JsError> {
+    require_spec()?;
+    Ok(spec::SPEC.geometry_prompt.clone())
 }
 
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = "getFillPrompt")]
-pub fn get_fill_prompt_wasm() -> String {
-    spec::SPEC.fill_prompt.clone()
+pub fn get_fill_prompt_wasm() -> Result<String, JsError> {
+    require_spec()?;
+    Ok(spec::SPEC.fill_prompt.clone())
 }
 
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = "parseGeometry")]
-pub fn parse_geometry_wasm(text: &str) -> String {
+pub fn parse_geometry_wasm(text: &str) -> ExportValue {
     match parse::parse_geometry(text) {
-        Ok(geo) => serde_json::json!({
+        Ok(geo) => export(&serde_json::json!({
             "ok": true,
             "plateBox": geo.plate_box,
             "tailgateTopY": geo.tailgate_top_y,
             "tailgateBottomY": geo.tailgate_bottom_y,
             "cargoTopY": geo.cargo_top_y,
-        })
-        .to_string(),
-        Err(e) => serde_json::json!({
+        })),
+        Err(e) => export(&serde_json::json!({
             "ok": false,
-            "error": e.message,
-        })
-        .to_string(),
+            "error": e.to_string(),
+        })),
     }
 }
 
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = "parseFill")]
-pub fn parse_fill_wasm(text: &str) -> String {
+pub fn parse_fill_wasm(text: &str) -> ExportValue {
     match parse::parse_fill(text) {
-        Ok(fill) => serde_json::json!({
+        Ok(fill) => export(&serde_json::json!({
             "ok": true,
             "fillRatioL": fill.fill_ratio_l,
             "fillRatioW": fill.fill_ratio_w,
@@ -73,13 +181,11 @@ pub fn parse_fill_wasm(text: &str) -> String {
             "packingDensity": fill.packing_density,
             "materialType": fill.material_type,
             "reasoning": fill.reasoning,
-        })
-        .to_string(),
-        Err(e) => serde_json::json!({
+        })),
+        Err(e) => export(&serde_json::json!({
             "ok": false,
-            "error": e.message,
-        })
-        .to_string(),
+            "error": e.to_string(),
+        })),
     }
 }
 
@@ -120,6 +226,7 @@ mod integration_tests {
             taper_ratio: 0.9,
             packing_density: 0.80,
             material_type: "As殻".to_string(),
+            formula: FormulaVersion::default(),
         };
 
         let result = calculate_tonnage(&params, Some("4t"));
@@ -138,27 +245,21 @@ mod integration_tests {
     /// Verify full pipeline with mock backend produces consistent results
     #[test]
     fn test_pipeline_end_to_end_consistency() {
-        use pipeline::{AiBackend, BoxOverlayConfig, PipelineError};
-
-        struct FixedBackend;
-        impl AiBackend for FixedBackend {
-            fn send_prompt(&self, prompt: &str, _images: &[Vec<u8>]) -> Result<String, PipelineError> {
-                if prompt.contains("tailgateTopY") {
-                    Ok(r#"{"plateBox":[0.4,0.7,0.6,0.84],"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#.to_string())
-                } else {
-                    Ok(r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,"reasoning":"Integration test"}"#.to_string())
-                }
-            }
-        }
+        use pipeline::BoxOverlayConfig;
+
+        let backend = testing::fixed_backend()
+            .with_geometry_fields(r#""plateBox":[0.4,0.7,0.6,0.84]"#)
+            .with_fill_fields(r#""reasoning":"Integration test""#);
 
         let config = BoxOverlayConfig {
             truck_class: "4t".to_string(),
             material_type: "As殻".to_string(),
             ensemble_count: 1,
+            ..Default::default()
         };
 
-        let r1 = analyze_box_overlay(&FixedBackend, &[], &config).unwrap();
-        let r2 = analyze_box_overlay(&FixedBackend, &[], &config).unwrap();
+        let r1 = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let r2 = analyze_box_overlay(&backend, &[], &config).unwrap();
 
         // Determinism: same fixed input -> same output
         assert!((r1.height_m - r2.height_m).abs() < f64::EPSILON, "height_m mismatch");
@@ -0,0 +1,163 @@
+//! Load metadata
+//!
+//! Dispatch systems identify a load by driver, job number and disposal
+//! destination. `LoadMetadata` is set on `BoxOverlayConfig::metadata` and
+//! copied unchanged to `BoxOverlayResult::metadata`, billing line items and
+//! field reports, so results can be joined with dispatch records downstream.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::{self, Language};
+
+/// Value of a custom metadata field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Bool(bool),
+    Integer(i64),
+    Number(f64),
+    Text(String),
+}
+
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(v) => write!(f, "{}", v),
+            Self::Integer(v) => write!(f, "{}", v),
+            Self::Number(v) => write!(f, "{}", v),
+            Self::Text(v) => f.write_str(v),
+        }
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(v: i64) -> Self {
+        Self::Integer(v)
+    }
+}
+
+impl From<i32> for MetadataValue {
+    fn from(v: i32) -> Self {
+        Self::Integer(v.into())
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(v: f64) -> Self {
+        Self::Number(v)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(v: &str) -> Self {
+        Self::Text(v.to_string())
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(v: String) -> Self {
+        Self::Text(v)
+    }
+}
+
+/// Dispatch identifiers of one load
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver_id: Option<String>,
+    /// Job (工事) number the load is billed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_number: Option<String>,
+    /// Disposal site or other destination of the load
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Further fields keyed by name, serialized alongside the fixed ones
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, MetadataValue>,
+}
+
+impl LoadMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn driver_id(mut self, id: impl Into<String>) -> Self {
+        self.driver_id = Some(id.into());
+        self
+    }
+
+    pub fn job_number(mut self, number: impl Into<String>) -> Self {
+        self.job_number = Some(number.into());
+        self
+    }
+
+    pub fn destination(mut self, destination: impl Into<String>) -> Self {
+        self.destination = Some(destination.into());
+        self
+    }
+
+    /// Set a custom field
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        self.extra.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.driver_id.is_none() && self.job_number.is_none() && self.destination.is_none() && self.extra.is_empty()
+    }
+
+    /// (label, value) of every set field for reports: fixed fields with
+    /// labels in `lang` first, then custom fields by key
+    pub fn entries(&self, lang: Language) -> Vec<(String, String)> {
+        let fixed = [
+            (i18n::METADATA_JOB_NUMBER, &self.job_number),
+            (i18n::METADATA_DRIVER, &self.driver_id),
+            (i18n::METADATA_DESTINATION, &self.destination),
+        ];
+        fixed
+            .into_iter()
+            .filter_map(|(label, value)| value.as_ref().map(|v| (label.get(lang).to_string(), v.clone())))
+            .chain(self.extra.iter().map(|(k, v)| (k.clone(), v.to_string())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialized_shape() {
+        let meta = LoadMetadata::new()
+            .driver_id("D-7")
+            .job_number("J-2026-041")
+            .with("trip", 3)
+            .with("night", true);
+        let json = serde_json::to_value(&meta).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"driverId": "D-7", "jobNumber": "J-2026-041", "trip": 3, "night": true})
+        );
+        let back: LoadMetadata = serde_json::from_value(json).unwrap();
+        assert_eq!(back, meta);
+        assert_eq!(back.extra["trip"], MetadataValue::Integer(3));
+    }
+
+    #[test]
+    fn test_entries_order() {
+        let meta = LoadMetadata::new().destination("中央処分場").job_number("J-1").with("trip", 2);
+        let labels: Vec<String> = meta.entries(Language::Ja).into_iter().map(|(k, v)| format!("{k}:{v}")).collect();
+        assert_eq!(labels, ["工事番号:J-1", "搬出先:中央処分場", "trip:2"]);
+        assert!(LoadMetadata::new().is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+//! Pipeline telemetry
+//!
+//! The pipeline reports counters and histograms into a `MetricsSink` set on
+//! `BoxOverlayConfig::metrics`, so operators can watch estimator health
+//! (failure rates, clamping, latency, tonnage distribution) in production.
+//! Nothing is recorded, and no clock is read, unless a sink is set.
+//! A Prometheus sink is available behind the `prometheus` feature.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::prompt::PromptStage;
+
+/// Counted pipeline events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Counter {
+    /// Completed analyses (one per `BoxOverlayResult`)
+    Analyses,
+    /// Backend calls issued by a stage
+    Runs,
+    /// Backend calls that returned an error or timed out
+    CallErrors,
+    /// Responses that could not be parsed
+    ParseFailures,
+    /// Values clamped to their spec range
+    Clamps,
+}
+
+impl Counter {
+    /// Metric name used by exporters
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Analyses => "tonsuu_analyses_total",
+            Self::Runs => "tonsuu_runs_total",
+            Self::CallErrors => "tonsuu_call_errors_total",
+            Self::ParseFailures => "tonsuu_parse_failures_total",
+            Self::Clamps => "tonsuu_clamps_total",
+        }
+    }
+}
+
+/// Observed pipeline values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Histogram {
+    /// Duration of one backend call in seconds (not recorded on wasm32)
+    CallLatencySeconds,
+    /// Estimated tonnage of a completed analysis
+    Tonnage,
+}
+
+impl Histogram {
+    /// Metric name used by exporters
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CallLatencySeconds => "tonsuu_call_latency_seconds",
+            Self::Tonnage => "tonsuu_tonnage",
+        }
+    }
+}
+
+/// Receiver of pipeline telemetry.
+///
+/// `stage` is None for events that belong to the analysis as a whole.
+/// Both methods default to doing nothing.
+pub trait MetricsSink: Send + Sync {
+    fn increment(&self, counter: Counter, stage: Option<PromptStage>, by: u64) {
+        let _ = (counter, stage, by);
+    }
+
+    fn observe(&self, histogram: Histogram, stage: Option<PromptStage>, value: f64) {
+        let _ = (histogram, stage, value);
+    }
+}
+
+/// Sink that discards everything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl MetricsSink for NoopMetrics {}
+
+/// Optional shared sink held by `BoxOverlayConfig` (default: none)
+#[derive(Clone, Default)]
+pub struct Metrics(Option<Arc<dyn MetricsSink>>);
+
+impl Metrics {
+    pub fn new(sink: impl MetricsSink + 'static) -> Self {
+        Self(Some(Arc::new(sink)))
+    }
+
+    pub fn from_arc(sink: Arc<dyn MetricsSink>) -> Self {
+        Self(Some(sink))
+    }
+
+    /// True when a sink is set
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn increment(&self, counter: Counter, stage: Option<PromptStage>, by: u64) {
+        if let Some(sink) = &self.0 {
+            sink.increment(counter, stage, by);
+        }
+    }
+
+    pub fn observe(&self, histogram: Histogram, stage: Option<PromptStage>, value: f64) {
+        if let Some(sink) = &self.0 {
+            sink.observe(histogram, stage, value);
+        }
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.is_enabled() { "Metrics(enabled)" } else { "Metrics(none)" })
+    }
+}
+
+/// Label value for a stage ("" for analysis-wide events)
+pub fn stage_label(stage: Option<PromptStage>) -> &'static str {
+    match stage {
+        Some(PromptStage::Geometry) => "geometry",
+        Some(PromptStage::Fill) => "fill",
+        Some(PromptStage::Quality) => "quality",
+        Some(PromptStage::Plate) => "plate",
+        Some(PromptStage::Landmark) => "landmark",
+        Some(PromptStage::Reasoning) => "reasoning",
+        Some(PromptStage::MultiParam) => "multi_param",
+        None => "",
+    }
+}
+
+// ─── Prometheus ──────────────────────────────────────────────────────
+
+/// Sink that records into Prometheus collectors labelled by `stage`
+#[cfg(feature = "prometheus")]
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    counters: Vec<(Counter, prometheus::IntCounterVec)>,
+    histograms: Vec<(Histogram, prometheus::HistogramVec)>,
+}
+
+#[cfg(feature = "prometheus")]
+impl PrometheusMetrics {
+    /// Create the collectors and register them with `registry`
+    pub fn register(registry: &prometheus::Registry) -> Result<Self, prometheus::Error> {
+        use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts};
+
+        let counters = [
+            (Counter::Analyses, "完了した解析数"),
+            (Counter::Runs, "バックエンド呼び出し数"),
+            (Counter::CallErrors, "失敗・タイムアウトした呼び出し数"),
+            (Counter::ParseFailures, "解析できなかった応答数"),
+            (Counter::Clamps, "範囲外で補正された値の数"),
+        ]
+        .into_iter()
+        .map(|(c, help)| {
+            let vec = IntCounterVec::new(Opts::new(c.name(), help), &["stage"])?;
+            registry.register(Box::new(vec.clone()))?;
+            Ok((c, vec))
+        })
+        .collect::<Result<Vec<_>, prometheus::Error>>()?;
+
+        let histograms = [
+            (
+                Histogram::CallLatencySeconds,
+                "バックエンド呼び出しの所要時間 (秒)",
+                vec![0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0],
+            ),
+            (
+                Histogram::Tonnage,
+                "推定重量 (t)",
+                vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0, 12.0],
+            ),
+        ]
+        .into_iter()
+        .map(|(h, help, buckets)| {
+            let vec = HistogramVec::new(HistogramOpts::new(h.name(), help).buckets(buckets), &["stage"])?;
+            registry.register(Box::new(vec.clone()))?;
+            Ok((h, vec))
+        })
+        .collect::<Result<Vec<_>, prometheus::Error>>()?;
+
+        Ok(Self { counters, histograms })
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl MetricsSink for PrometheusMetrics {
+    fn increment(&self, counter: Counter, stage: Option<PromptStage>, by: u64) {
+        if let Some((_, vec)) = self.counters.iter().find(|(c, _)| *c == counter) {
+            vec.with_label_values(&[stage_label(stage)]).inc_by(by);
+        }
+    }
+
+    fn observe(&self, histogram: Histogram, stage: Option<PromptStage>, value: f64) {
+        if let Some((_, vec)) = self.histograms.iter().find(|(h, _)| *h == histogram) {
+            vec.with_label_values(&[stage_label(stage)]).observe(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unset_metrics_is_noop() {
+        let m = Metrics::default();
+        assert!(!m.is_enabled());
+        m.increment(Counter::Runs, Some(PromptStage::Fill), 1);
+        assert_eq!(format!("{:?}", m), "Metrics(none)");
+    }
+
+    #[cfg(feature = "prometheus")]
+    #[test]
+    fn test_prometheus_sink_exports_counters() {
+        let registry = prometheus::Registry::new();
+        let sink = PrometheusMetrics::register(&registry).unwrap();
+        sink.increment(Counter::Runs, Some(PromptStage::Geometry), 2);
+        sink.observe(Histogram::Tonnage, None, 3.5);
+
+        let families = registry.gather();
+        let runs = families.iter().find(|f| f.get_name() == "tonsuu_runs_total").unwrap();
+        assert_eq!(runs.get_metric()[0].get_counter().get_value(), 2.0);
+        assert!(families.iter().any(|f| f.get_name() == "tonsuu_tonnage"));
+    }
+}
@@ -0,0 +1,221 @@
+//! Offline backend
+//!
+//! `OfflineBackend` answers every prompt from a library of canned replies so
+//! the full pipeline runs end-to-end without any network: demos, training
+//! sessions, development in airplane mode. Entries are indexed by tags such
+//! as the truck class and fill level (`"4t"`, `"full"`); the backend is given
+//! the tags of the scene to simulate and replies with the best-matching entry.
+//! Libraries load from JSON; `ResponseLibrary::builtin` covers the three fill
+//! levels for any truck class.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::i18n::{self, language};
+use crate::input::InputImage;
+use crate::pipeline::{AiBackend, PipelineError};
+use crate::prompt::PromptStage;
+
+/// Canned replies for one simulated scene
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CannedResponse {
+    /// Scene tags; the entry matches when the backend was given all of them
+    pub tags: Vec<String>,
+    /// Geometry stage reply
+    pub geometry: Value,
+    /// Fill stage reply
+    pub fill: Value,
+    /// Landmark stage answer (None = "後板と同じ")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub landmark: Option<String>,
+    /// Plate stage answer (None = unreadable)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plate: Option<String>,
+}
+
+/// Canned replies indexed by tags
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ResponseLibrary {
+    entries: Vec<CannedResponse>,
+}
+
+impl ResponseLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mound above the rim (`"full"`), level with it (`"level"`) and half a
+    /// bed (`"half"`), untagged by truck class
+    pub fn builtin() -> Self {
+        let level = |tag: &str, cargo_top: f64, fill: [f64; 4], landmark: &str| CannedResponse {
+            tags: vec![tag.to_string()],
+            geometry: json!({
+                "plateBox": [0.42, 0.72, 0.58, 0.8],
+                "tailgateTopY": 0.3,
+                "tailgateBottomY": 0.5,
+                "cargoTopY": cargo_top,
+            }),
+            fill: json!({
+                "fillRatioL": fill[0],
+                "fillRatioW": fill[1],
+                "taperRatio": fill[2],
+                "packingDensity": fill[3],
+                "materialType": "As殻",
+                "reasoning": format!("オフライン応答 ({})", tag),
+            }),
+            landmark: Some(landmark.to_string()),
+            plate: None,
+        };
+        Self {
+            entries: vec![
+                level("full", 0.2, [0.85, 0.9, 0.85, 0.8], "後板とヒンジの間"),
+                level("level", 0.3, [0.8, 0.85, 0.9, 0.75], "後板と同じ"),
+                level("half", 0.4, [0.6, 0.75, 0.95, 0.7], "後板未満"),
+            ],
+        }
+    }
+
+    /// Library from a JSON array of entries
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn insert(&mut self, entry: CannedResponse) {
+        self.entries.push(entry);
+    }
+
+    /// The entry whose tags are all among `tags`, preferring the most
+    /// specific one; earlier entries win ties
+    pub fn select<S: AsRef<str>>(&self, tags: &[S]) -> Option<&CannedResponse> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| e.tags.iter().all(|t| tags.iter().any(|s| s.as_ref() == t)))
+            .max_by_key(|e| e.tags.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CannedResponse> {
+        self.entries.iter()
+    }
+}
+
+/// Backend that never leaves the process: every reply comes from a `ResponseLibrary`
+#[derive(Debug, Clone)]
+pub struct OfflineBackend {
+    library: ResponseLibrary,
+    tags: Vec<String>,
+}
+
+impl OfflineBackend {
+    pub fn new(library: ResponseLibrary) -> Self {
+        Self {
+            library,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Offline backend over `ResponseLibrary::builtin`, simulating a full load
+    pub fn builtin() -> Self {
+        Self::new(ResponseLibrary::builtin()).tags(["full"])
+    }
+
+    /// Tags of the scene to simulate (replaces any set before)
+    pub fn tags<S: Into<String>>(mut self, tags: impl IntoIterator<Item = S>) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn entry(&self) -> Result<&CannedResponse, PipelineError> {
+        self.library
+            .select(&self.tags)
+            .ok_or_else(|| PipelineError::AiError(i18n::OFFLINE_NO_REPLY.format(language(), &[&self.tags.join(", ")])))
+    }
+}
+
+/// Which stage a prompt belongs to, from the JSON keys it asks for
+fn prompt_stage(prompt: &str) -> Option<PromptStage> {
+    [
+        ("\"tailgateTopY\"", PromptStage::Geometry),
+        // The legacy multi-param template also asks for fillRatioL
+        ("\"fillRatioZ\"", PromptStage::MultiParam),
+        ("\"fillRatioL\"", PromptStage::Fill),
+        ("\"landmark\"", PromptStage::Landmark),
+        ("\"usable\"", PromptStage::Quality),
+        ("\"plateNumber\"", PromptStage::Plate),
+        ("\"summary\"", PromptStage::Reasoning),
+    ]
+    .into_iter()
+    .find(|(key, _)| prompt.contains(key))
+    .map(|(_, stage)| stage)
+}
+
+impl AiBackend for OfflineBackend {
+    fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+        let entry = self.entry()?;
+        let reply = match prompt_stage(prompt) {
+            Some(PromptStage::Geometry) => entry.geometry.clone(),
+            Some(PromptStage::Fill) => entry.fill.clone(),
+            Some(PromptStage::Landmark) => json!({ "landmark": entry.landmark.as_deref().unwrap_or("後板と同じ") }),
+            Some(PromptStage::Quality) => json!({ "usable": true, "reason": "オフライン応答" }),
+            Some(PromptStage::Plate) => json!({ "plateNumber": entry.plate }),
+            Some(PromptStage::Reasoning) => json!({ "summary": "オフライン応答" }),
+            _ => return Err(PipelineError::AiError("オフライン応答のない問い合わせです".into())),
+        };
+        Ok(reply.to_string())
+    }
+
+    fn name(&self) -> &str {
+        "offline"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+
+    #[test]
+    fn test_offline_pipeline_end_to_end() {
+        let config = BoxOverlayConfig::default();
+        let full = analyze_box_overlay(&OfflineBackend::builtin(), &[], &config).unwrap();
+        let half = analyze_box_overlay(&OfflineBackend::builtin().tags(["half"]), &[], &config).unwrap();
+        assert!(full.height_m > half.height_m && full.tonnage > half.tonnage);
+        assert!(full.geometry_runs.iter().all(|r| r.backend == "offline"));
+        assert_eq!(full.reasoning, "オフライン応答 (full)");
+
+        let err = analyze_box_overlay(&OfflineBackend::builtin().tags(["empty"]), &[], &config).unwrap_err();
+        assert!(matches!(err, PipelineError::NoValidGeometry));
+    }
+
+    #[test]
+    fn test_library_selects_most_specific_entry() {
+        let mut library = ResponseLibrary::from_json(
+            r#"[{"tags":["full"],"geometry":{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2},
+                 "fill":{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}}]"#,
+        )
+        .unwrap();
+        let mut entry = library.iter().next().unwrap().clone();
+        entry.tags = vec!["10t".into(), "full".into()];
+        entry.plate = Some("品川 100 あ 12-34".into());
+        library.insert(entry);
+
+        assert_eq!(library.select(&["4t", "full"]).unwrap().tags, ["full"]);
+        assert_eq!(library.select(&["10t", "full"]).unwrap().tags, ["10t", "full"]);
+        assert!(library.select(&["10t"]).is_none());
+
+        let backend = OfflineBackend::new(library).tags(["10t", "full"]);
+        let reply = backend.send_prompt(&crate::spec::SPEC.plate_prompt, &[]).unwrap();
+        assert_eq!(reply, r#"{"plateNumber":"品川 100 あ 12-34"}"#);
+        assert!(backend.send_prompt("hello", &[]).is_err());
+    }
+}
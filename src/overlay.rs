@@ -0,0 +1,371 @@
+//! Overlay geometry for drawing over the analysed photo
+//!
+//! `OverlayData` lists what the geometry stage used (tailgate panel, tailgate
+//! edges, cargo top, license plate) in the normalized 0-1 coordinates of the
+//! prompts, with labels and colors, so the web canvas and image annotators
+//! draw exactly what the pipeline measured. `to_pixels` / `to_normalized`
+//! convert to and from the pixel coordinates of the displayed image.
+
+use serde::Serialize;
+
+use crate::calculation::PLATE_ASPECT_RATIO;
+use crate::i18n::{self, language};
+use crate::parse::GeometryResponse;
+use crate::spec::{get_truck_spec, plate_height_m};
+
+/// What an overlay shape marks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverlayKind {
+    /// Tailgate panel (the bed as seen from the rear)
+    Bed,
+    TailgateTop,
+    TailgateBottom,
+    CargoTop,
+    Plate,
+}
+
+impl OverlayKind {
+    /// Stroke color (CSS hex)
+    pub fn color(self) -> &'static str {
+        match self {
+            Self::Bed => "#3b82f6",
+            Self::TailgateTop => "#22c55e",
+            Self::TailgateBottom => "#16a34a",
+            Self::CargoTop => "#ef4444",
+            Self::Plate => "#eab308",
+        }
+    }
+}
+
+/// Shape in the coordinate space of its `OverlayData`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum OverlayShape {
+    /// Axis-aligned rectangle
+    Rect { x0: f64, y0: f64, x1: f64, y1: f64 },
+    /// Horizontal line at `y` from `x0` to `x1`
+    HLine { y: f64, x0: f64, x1: f64 },
+}
+
+impl OverlayShape {
+    fn scaled(self, sx: f64, sy: f64) -> Self {
+        match self {
+            Self::Rect { x0, y0, x1, y1 } => Self::Rect {
+                x0: x0 * sx,
+                y0: y0 * sy,
+                x1: x1 * sx,
+                y1: y1 * sy,
+            },
+            Self::HLine { y, x0, x1 } => Self::HLine {
+                y: y * sy,
+                x0: x0 * sx,
+                x1: x1 * sx,
+            },
+        }
+    }
+}
+
+/// Coordinate space of overlay shapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum CoordinateSpace {
+    /// 0-1 fractions of the image width / height, as used in prompts
+    #[default]
+    Normalized,
+    /// Pixels of a displayed image of this size
+    Pixels { width: u32, height: u32 },
+}
+
+/// Displayed (width, height) of an image stored as `width` × `height` with
+/// the given EXIF orientation tag; tags 5-8 rotate by 90° and swap the sides.
+/// Model coordinates always refer to the displayed image.
+pub fn displayed_size(width: u32, height: u32, exif_orientation: u8) -> (u32, u32) {
+    match exif_orientation {
+        5..=8 => (height, width),
+        _ => (width, height),
+    }
+}
+
+/// One labelled overlay element
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayItem {
+    pub kind: OverlayKind,
+    pub shape: OverlayShape,
+    pub label: String,
+    pub color: String,
+}
+
+/// Everything to draw over the photo, in drawing order
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayData {
+    pub space: CoordinateSpace,
+    pub items: Vec<OverlayItem>,
+}
+
+impl OverlayData {
+    /// Overlay for one geometry response.
+    ///
+    /// The horizontal extent of the bed is derived from the plate width and
+    /// the truck's bed width, centered on the plate; without a plate it spans
+    /// the full image width. Labels are in the language of `language()`.
+    pub fn from_geometry(geo: &GeometryResponse, height_m: f64, truck_class: &str) -> Self {
+        let lang = language();
+        let plate = geo
+            .plate_box
+            .filter(|b| b[2] > b[0] && b[3] > b[1])
+            .map(|b| b.map(|v| v.clamp(0.0, 1.0)));
+        let (x0, x1) = match (plate, get_truck_spec(truck_class)) {
+            (Some(b), Some(truck)) => {
+                let plate_width_m = plate_height_m(geo.plate_class.as_deref()) * PLATE_ASPECT_RATIO;
+                let half = (b[2] - b[0]) * truck.bed_width / plate_width_m / 2.0;
+                let center = (b[0] + b[2]) / 2.0;
+                ((center - half).max(0.0), (center + half).min(1.0))
+            }
+            _ => (0.0, 1.0),
+        };
+
+        let mut items = Vec::new();
+        let mut push = |kind: OverlayKind, shape: OverlayShape, label: String| {
+            items.push(OverlayItem {
+                kind,
+                shape,
+                label,
+                color: kind.color().to_string(),
+            });
+        };
+        if geo.tailgate_top_y > 0.0 && geo.tailgate_bottom_y > geo.tailgate_top_y {
+            push(
+                OverlayKind::Bed,
+                OverlayShape::Rect {
+                    x0,
+                    y0: geo.tailgate_top_y,
+                    x1,
+                    y1: geo.tailgate_bottom_y,
+                },
+                i18n::OVERLAY_BED.get(lang).to_string(),
+            );
+        }
+        if geo.tailgate_top_y > 0.0 {
+            push(
+                OverlayKind::TailgateTop,
+                OverlayShape::HLine { y: geo.tailgate_top_y, x0, x1 },
+                i18n::OVERLAY_TAILGATE_TOP.get(lang).to_string(),
+            );
+        }
+        if geo.tailgate_bottom_y > 0.0 {
+            push(
+                OverlayKind::TailgateBottom,
+                OverlayShape::HLine { y: geo.tailgate_bottom_y, x0, x1 },
+                i18n::OVERLAY_TAILGATE_BOTTOM.get(lang).to_string(),
+            );
+        }
+        if geo.cargo_top_y > 0.0 {
+            push(
+                OverlayKind::CargoTop,
+                OverlayShape::HLine { y: geo.cargo_top_y, x0, x1 },
+                i18n::OVERLAY_CARGO_TOP.format(lang, &[&format!("{:.2}", height_m)]),
+            );
+        }
+        if let Some(b) = plate {
+            push(
+                OverlayKind::Plate,
+                OverlayShape::Rect {
+                    x0: b[0],
+                    y0: b[1],
+                    x1: b[2],
+                    y1: b[3],
+                },
+                i18n::SCALE_PLATE.get(lang).to_string(),
+            );
+        }
+        Self {
+            space: CoordinateSpace::Normalized,
+            items,
+        }
+    }
+
+    /// Same overlay in pixels of a displayed image of the given size
+    /// (use `displayed_size` for EXIF-rotated photos)
+    pub fn to_pixels(&self, width: u32, height: u32) -> Self {
+        let normalized = self.to_normalized();
+        normalized.rescaled(
+            CoordinateSpace::Pixels { width, height },
+            width as f64,
+            height as f64,
+        )
+    }
+
+    /// Same overlay in normalized 0-1 coordinates
+    pub fn to_normalized(&self) -> Self {
+        match self.space {
+            CoordinateSpace::Normalized => self.clone(),
+            CoordinateSpace::Pixels { width, height } => self.rescaled(
+                CoordinateSpace::Normalized,
+                1.0 / width.max(1) as f64,
+                1.0 / height.max(1) as f64,
+            ),
+        }
+    }
+
+    fn rescaled(&self, space: CoordinateSpace, sx: f64, sy: f64) -> Self {
+        Self {
+            space,
+            items: self
+                .items
+                .iter()
+                .map(|i| OverlayItem {
+                    shape: i.shape.scaled(sx, sy),
+                    ..i.clone()
+                })
+                .collect(),
+        }
+    }
+
+    /// First item of the given kind
+    pub fn find(&self, kind: OverlayKind) -> Option<&OverlayItem> {
+        self.items.iter().find(|i| i.kind == kind)
+    }
+}
+
+// ─── WASM export ─────────────────────────────────────────────────────
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+#[cfg(feature = "wasm")]
+use crate::wasm::{export, require_spec, ExportValue};
+
+/// Canvas-ready overlay for an analysis result JSON (camelCase geometry
+/// fields plus `heightM` and `truckClass`), in pixels of the displayed image.
+/// With `withSvg` the SVG layer is included as `svg`.
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = "getOverlayData")]
+pub fn get_overlay_data_wasm(
+    result_json: &str,
+    img_width: u32,
+    img_height: u32,
+    with_svg: Option<bool>,
+) -> Result<ExportValue, JsError> {
+    require_spec()?;
+    Ok(export(&overlay_data_json(result_json, img_width, img_height, with_svg.unwrap_or(false))))
+}
+
+#[cfg(any(feature = "wasm", test))]
+fn overlay_data_json(result_json: &str, img_width: u32, img_height: u32, with_svg: bool) -> serde_json::Value {
+    #[derive(serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ResultGeometry {
+        #[serde(flatten)]
+        geometry: GeometryResponse,
+        height_m: f64,
+        #[serde(default)]
+        truck_class: String,
+    }
+
+    match serde_json::from_str::<ResultGeometry>(result_json) {
+        Ok(r) => {
+            let overlay = OverlayData::from_geometry(&r.geometry, r.height_m, &r.truck_class);
+            let mut out = serde_json::json!({
+                "ok": true,
+                "overlay": overlay.to_pixels(img_width, img_height),
+            });
+            if with_svg {
+                out["svg"] = crate::report::overlay_svg(&overlay, img_width, img_height).into();
+            }
+            out
+        }
+        Err(e) => serde_json::json!({
+            "ok": false,
+            "error": i18n::RESULT_UNREADABLE.format(language(), &[&e]),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geo(plate_box: Option<[f64; 4]>) -> GeometryResponse {
+        GeometryResponse {
+            plate_box,
+            tailgate_top_y: 0.3,
+            tailgate_bottom_y: 0.5,
+            cargo_top_y: 0.2,
+            plate_class: None,
+            occluded_ratio: None,
+            numeric_warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_overlay_bed_sized_from_plate() {
+        // 4t bed is 2.06m wide, the 大板 plate 0.44m: 0.1 wide plate -> ~0.468 wide bed
+        let data = OverlayData::from_geometry(&geo(Some([0.45, 0.7, 0.55, 0.75])), 0.48, "4t");
+        assert_eq!(data.items.len(), 5);
+        let OverlayShape::Rect { x0, y0, x1, y1 } = data.find(OverlayKind::Bed).unwrap().shape else {
+            panic!("bed must be a rectangle");
+        };
+        assert!((x1 - x0 - 0.1 * 2.06 / 0.44).abs() < 1e-9);
+        assert_eq!((y0, y1), (0.3, 0.5));
+        let cargo = data.find(OverlayKind::CargoTop).unwrap();
+        assert_eq!(cargo.label, "荷山頂部 0.48m");
+        assert_eq!(cargo.color, "#ef4444");
+    }
+
+    fn coords(shape: OverlayShape) -> Vec<f64> {
+        match shape {
+            OverlayShape::Rect { x0, y0, x1, y1 } => vec![x0, y0, x1, y1],
+            OverlayShape::HLine { y, x0, x1 } => vec![y, x0, x1],
+        }
+    }
+
+    #[test]
+    fn test_pixel_round_trip() {
+        let data = OverlayData::from_geometry(&geo(Some([0.45, 0.7, 0.55, 0.75])), 0.48, "4t");
+        // 4000x3000 sensor image rotated by EXIF orientation 6 (90° CW)
+        let (w, h) = displayed_size(4000, 3000, 6);
+        assert_eq!((w, h), (3000, 4000));
+
+        let px = data.to_pixels(w, h);
+        assert_eq!(px.space, CoordinateSpace::Pixels { width: 3000, height: 4000 });
+        let plate = coords(px.find(OverlayKind::Plate).unwrap().shape);
+        for (a, b) in plate.iter().zip([1350.0, 2800.0, 1650.0, 3000.0]) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        let back = px.to_normalized();
+        assert_eq!(back.space, CoordinateSpace::Normalized);
+        for (a, b) in back.items.iter().zip(&data.items) {
+            for (p, q) in coords(a.shape).into_iter().zip(coords(b.shape)) {
+                assert!((p - q).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlay_without_plate_spans_width() {
+        let data = OverlayData::from_geometry(&geo(None), 0.48, "4t");
+        assert!(data.find(OverlayKind::Plate).is_none());
+        assert_eq!(
+            data.find(OverlayKind::TailgateTop).unwrap().shape,
+            OverlayShape::HLine { y: 0.3, x0: 0.0, x1: 1.0 }
+        );
+    }
+
+    #[test]
+    fn test_overlay_data_json_for_result() {
+        let result = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2,"heightM":0.48,"truckClass":"4t","tonnage":3.4}"#;
+        let out = overlay_data_json(result, 800, 600, true);
+        assert_eq!(out["ok"], true);
+        assert_eq!(out["overlay"]["space"], serde_json::json!({"type": "pixels", "width": 800, "height": 600}));
+        let cargo = &out["overlay"]["items"][3];
+        assert_eq!(cargo["kind"], "cargoTop");
+        assert_eq!(cargo["shape"], serde_json::json!({"type": "hLine", "y": 120.0, "x0": 0.0, "x1": 800.0}));
+        assert!(out["svg"].as_str().unwrap().starts_with("<svg"));
+
+        let out = overlay_data_json("{}", 800, 600, false);
+        assert_eq!(out["ok"], false);
+    }
+}
@@ -2,22 +2,122 @@
 //!
 //! Extracts and parses JSON from AI model responses, handling cases where
 //! the response contains extra text around the JSON object.
+//!
+//! Input is bounded by `ParseLimits` (length and nesting depth), since the
+//! WASM exports parse untrusted strings from the browser.
+//!
+//! Numeric fields of geometry, fill and multi-param responses are pulled into
+//! `0..=RESPONSE_VALUE_MAX` after parsing, each move recorded as a
+//! `NumericWarning`, so one `1e308` reply cannot overflow later arithmetic.
+//! Literals beyond the f64 range (`1e400`) are rejected as `InvalidJson`.
 
+use std::borrow::Cow;
 use std::fmt;
+use std::sync::Arc;
+
+use memchr::{memchr, memchr2, memchr3};
+
+use crate::calculation::Landmark;
+use crate::i18n::{self, language, Language, Localize};
+
+/// What kind of parse failure occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// No `{` found in the response
+    NoJsonObject,
+    /// A JSON object was found but never closed
+    Incomplete,
+    /// The extracted object is not valid JSON for the target type
+    InvalidJson,
+    /// JSON object longer than `ParseLimits::max_input_bytes`
+    InputTooLarge { bytes: usize, max: usize },
+    /// Brackets nested deeper than `ParseLimits::max_depth`
+    TooDeep { depth: usize, max: usize },
+}
 
 /// Parse error
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{}", self.localize(language()))]
+#[non_exhaustive]
 pub struct ParseError {
-    pub message: String,
+    pub kind: ParseErrorKind,
+    /// Underlying serde_json error, if any
+    #[source]
+    pub source: Option<Arc<serde_json::Error>>,
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
+impl ParseError {
+    pub fn new(kind: ParseErrorKind) -> Self {
+        Self { kind, source: None }
+    }
+
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            ParseErrorKind::NoJsonObject => "PARSE_NO_JSON",
+            ParseErrorKind::Incomplete => "PARSE_INCOMPLETE",
+            ParseErrorKind::InvalidJson => "PARSE_INVALID_JSON",
+            ParseErrorKind::InputTooLarge { .. } => "PARSE_INPUT_TOO_LARGE",
+            ParseErrorKind::TooDeep { .. } => "PARSE_TOO_DEEP",
+        }
+    }
+}
+
+impl Localize for ParseError {
+    fn localize(&self, lang: Language) -> String {
+        match self.kind {
+            ParseErrorKind::NoJsonObject => i18n::PARSE_NO_JSON.get(lang).to_string(),
+            ParseErrorKind::Incomplete => i18n::PARSE_INCOMPLETE.get(lang).to_string(),
+            ParseErrorKind::InvalidJson => {
+                let detail = self.source.as_ref().map(|e| e.to_string()).unwrap_or_default();
+                i18n::PARSE_INVALID_JSON.format(lang, &[&detail])
+            }
+            ParseErrorKind::InputTooLarge { bytes, max } => i18n::PARSE_INPUT_TOO_LARGE.format(lang, &[&bytes, &max]),
+            ParseErrorKind::TooDeep { depth, max } => i18n::PARSE_TOO_DEEP.format(lang, &[&depth, &max]),
+        }
     }
 }
 
-impl std::error::Error for ParseError {}
+/// Largest accepted response value; every geometry and fill number is an
+/// image fraction or a ratio, so anything past this is a malformed reply
+/// (the spec ranges still clamp legitimate overestimates later)
+pub const RESPONSE_VALUE_MAX: f64 = 2.0;
+
+/// A response value pulled into `0..=RESPONSE_VALUE_MAX` at parse time
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct NumericWarning {
+    /// Response field name (`fillRatioL`, `plateBox[3]`, ...)
+    pub field: String,
+    /// Value as sent by the model
+    pub value: f64,
+    pub clamped: f64,
+}
+
+impl NumericWarning {
+    /// Clamp `value` in place, returning a warning only when it moved
+    fn check(field: impl Into<String>, value: &mut f64) -> Option<Self> {
+        let clamped = value.clamp(0.0, RESPONSE_VALUE_MAX);
+        if clamped == *value {
+            return None;
+        }
+        let warning = Self { field: field.into(), value: *value, clamped };
+        *value = clamped;
+        Some(warning)
+    }
+}
+
+impl Localize for NumericWarning {
+    fn localize(&self, lang: Language) -> String {
+        i18n::WARN_RESPONSE_VALUE_CLAMPED.format(lang, &[&self.field, &format!("{:e}", self.value), &self.clamped])
+    }
+}
+
+impl fmt::Display for NumericWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
 
 /// Geometry detection response from AI
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -31,6 +131,87 @@ pub struct GeometryResponse {
     pub tailgate_bottom_y: f64,
     #[serde(default)]
     pub cargo_top_y: f64,
+    /// Plate class (中板 / 大板) when the prompt asks the model to classify it
+    #[serde(default)]
+    pub plate_class: Option<String>,
+    /// Hidden fraction (0-1) of the tailgate and bed edges, when the prompt
+    /// asks for it (`BoxOverlayConfig::occlusion`)
+    #[serde(default)]
+    pub occluded_ratio: Option<f64>,
+    /// Values clamped by `parse_geometry`
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub numeric_warnings: Vec<NumericWarning>,
+}
+
+impl GeometryResponse {
+    /// Clamp every coordinate into `0..=RESPONSE_VALUE_MAX`
+    fn sanitize(&mut self) {
+        self.numeric_warnings = sanitize_geometry(
+            [&mut self.tailgate_top_y, &mut self.tailgate_bottom_y, &mut self.cargo_top_y],
+            self.plate_box.as_mut(),
+            self.occluded_ratio.as_mut(),
+        );
+    }
+}
+
+/// Geometry response borrowing its text from the reply (`parse_geometry_ref`)
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeometryResponseRef<'a> {
+    #[serde(default)]
+    pub plate_box: Option<[f64; 4]>,
+    #[serde(default)]
+    pub tailgate_top_y: f64,
+    #[serde(default)]
+    pub tailgate_bottom_y: f64,
+    #[serde(default)]
+    pub cargo_top_y: f64,
+    #[serde(default, borrow, deserialize_with = "borrowed_str")]
+    pub plate_class: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub occluded_ratio: Option<f64>,
+    /// Values clamped by `parse_geometry_ref`
+    #[serde(skip)]
+    pub numeric_warnings: Vec<NumericWarning>,
+}
+
+impl GeometryResponseRef<'_> {
+    pub fn into_owned(self) -> GeometryResponse {
+        GeometryResponse {
+            plate_box: self.plate_box,
+            tailgate_top_y: self.tailgate_top_y,
+            tailgate_bottom_y: self.tailgate_bottom_y,
+            cargo_top_y: self.cargo_top_y,
+            plate_class: self.plate_class.map(Cow::into_owned),
+            occluded_ratio: self.occluded_ratio,
+            numeric_warnings: self.numeric_warnings,
+        }
+    }
+}
+
+/// Clamp the tailgate/cargo coordinates and plate box, in field order, and
+/// the occluded ratio into `0..=1`
+fn sanitize_geometry(
+    coordinates: [&mut f64; 3],
+    plate_box: Option<&mut [f64; 4]>,
+    occluded_ratio: Option<&mut f64>,
+) -> Vec<NumericWarning> {
+    let names = ["tailgateTopY", "tailgateBottomY", "cargoTopY"];
+    let mut warnings: Vec<NumericWarning> =
+        names.into_iter().zip(coordinates).filter_map(|(name, v)| NumericWarning::check(name, v)).collect();
+    if let Some(plate_box) = plate_box {
+        for (i, v) in plate_box.iter_mut().enumerate() {
+            warnings.extend(NumericWarning::check(format!("plateBox[{}]", i), v));
+        }
+    }
+    if let Some(ratio) = occluded_ratio {
+        let clamped = ratio.clamp(0.0, 1.0);
+        if clamped != *ratio {
+            warnings.push(NumericWarning { field: "occludedRatio".into(), value: *ratio, clamped });
+            *ratio = clamped;
+        }
+    }
+    warnings
 }
 
 /// Fill estimation response from AI
@@ -49,76 +230,437 @@ pub struct FillResponse {
     pub material_type: Option<String>,
     #[serde(default)]
     pub reasoning: Option<String>,
+    /// Model's confidence (0-1) in the run, weighting its material vote
+    #[serde(default)]
+    pub confidence_score: Option<f64>,
+    /// Values clamped by `parse_fill`
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub numeric_warnings: Vec<NumericWarning>,
+}
+
+impl FillResponse {
+    /// Clamp every ratio into `0..=RESPONSE_VALUE_MAX`
+    fn sanitize(&mut self) {
+        self.numeric_warnings = sanitize_fill([
+            &mut self.fill_ratio_l,
+            &mut self.fill_ratio_w,
+            &mut self.taper_ratio,
+            &mut self.packing_density,
+        ]);
+    }
+}
+
+/// Fill response borrowing its material and reasoning text from the reply
+/// (`parse_fill_ref`); escaped strings are the only ones copied
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FillResponseRef<'a> {
+    #[serde(default = "default_fill_l")]
+    pub fill_ratio_l: f64,
+    #[serde(default = "default_fill_w")]
+    pub fill_ratio_w: f64,
+    #[serde(default = "default_taper")]
+    pub taper_ratio: f64,
+    #[serde(default = "default_packing")]
+    pub packing_density: f64,
+    #[serde(default, borrow, deserialize_with = "borrowed_str")]
+    pub material_type: Option<Cow<'a, str>>,
+    #[serde(default, borrow, deserialize_with = "borrowed_str")]
+    pub reasoning: Option<Cow<'a, str>>,
+    #[serde(default)]
+    pub confidence_score: Option<f64>,
+    /// Values clamped by `parse_fill_ref`
+    #[serde(skip)]
+    pub numeric_warnings: Vec<NumericWarning>,
+}
+
+impl FillResponseRef<'_> {
+    pub fn into_owned(self) -> FillResponse {
+        FillResponse {
+            fill_ratio_l: self.fill_ratio_l,
+            fill_ratio_w: self.fill_ratio_w,
+            taper_ratio: self.taper_ratio,
+            packing_density: self.packing_density,
+            material_type: self.material_type.map(Cow::into_owned),
+            reasoning: self.reasoning.map(Cow::into_owned),
+            confidence_score: self.confidence_score,
+            numeric_warnings: self.numeric_warnings,
+        }
+    }
+}
+
+/// Optional string borrowed from the input unless it holds escapes (serde
+/// only borrows a `Cow` that is not wrapped in an `Option`)
+fn borrowed_str<'de: 'a, 'a, D: serde::Deserializer<'de>>(d: D) -> Result<Option<Cow<'a, str>>, D::Error> {
+    #[derive(serde::Deserialize)]
+    struct Borrowed<'a>(#[serde(borrow)] Cow<'a, str>);
+    let value: Option<Borrowed> = serde::Deserialize::deserialize(d)?;
+    Ok(value.map(|b| b.0))
+}
+
+/// Clamp the four fill ratios, in field order
+fn sanitize_fill(ratios: [&mut f64; 4]) -> Vec<NumericWarning> {
+    let names = ["fillRatioL", "fillRatioW", "taperRatio", "packingDensity"];
+    names.into_iter().zip(ratios).filter_map(|(name, v)| NumericWarning::check(name, v)).collect()
+}
+
+/// Legacy multi-param response: height and fill ratios from a single call
+/// (`multiParamPrompt`)
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiParamResponse {
+    /// False when the model saw no truck bed in the photo
+    #[serde(default = "default_detected")]
+    pub is_target_detected: bool,
+    #[serde(default)]
+    pub truck_type: Option<String>,
+    #[serde(default)]
+    pub license_plate: Option<String>,
+    #[serde(default)]
+    pub material_type: Option<String>,
+    /// Cargo height above the bed floor (m)
+    pub height: f64,
+    #[serde(default = "default_packing")]
+    pub packing_density: f64,
+    #[serde(default = "default_fill_l")]
+    pub fill_ratio_l: f64,
+    #[serde(default = "default_fill_w")]
+    pub fill_ratio_w: f64,
+    /// Shape of the mound relative to a full frustum (1.0)
+    #[serde(default = "default_fill_z")]
+    pub fill_ratio_z: f64,
+    #[serde(default)]
+    pub confidence_score: f64,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    /// Values clamped by `parse_multi_param`
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    pub numeric_warnings: Vec<NumericWarning>,
+}
+
+/// Landmark height response from AI
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct LandmarkResponse {
+    /// Cargo peak relative to the tailgate top and hinges
+    pub landmark: Landmark,
+}
+
+/// Image usability verdict from the quality pre-check
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct QualityResponse {
+    pub usable: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Plate number read by the plate stage
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlateResponse {
+    /// Plate as printed (None = not visible or unreadable)
+    #[serde(default)]
+    pub plate_number: Option<String>,
+}
+
+/// Reply to the reasoning summary prompt
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ReasoningSummaryResponse {
+    pub summary: String,
 }
 
 fn default_fill_l() -> f64 { 0.8 }
 fn default_fill_w() -> f64 { 0.7 }
 fn default_taper() -> f64 { 0.75 }
 fn default_packing() -> f64 { 0.7 }
+fn default_fill_z() -> f64 { 1.0 }
+fn default_detected() -> bool { true }
+
+/// Default `ParseLimits::max_input_bytes`; model replies are a few KB
+pub const DEFAULT_MAX_INPUT_BYTES: usize = 64 * 1024;
+
+/// Default `ParseLimits::max_depth`; the response schemas nest two levels
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
+/// Bounds on the text `parse_json_safe_with` accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Longest accepted JSON object (bytes from its opening brace)
+    pub max_input_bytes: usize,
+    /// Deepest accepted nesting of objects and arrays
+    pub max_depth: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_input_bytes: DEFAULT_MAX_INPUT_BYTES,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
 
 /// Extract and parse JSON from AI response text.
 ///
-/// First tries direct parse. On failure, extracts the first `{...}` block
-/// (respecting string literals and nested braces) and parses that.
-/// Matches the TypeScript `parseJsonSafe` function in boxOverlayService.ts.
-pub fn parse_json_safe<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, ParseError> {
-    // Try direct parse first
-    if let Ok(v) = serde_json::from_str(text) {
-        return Ok(v);
+/// Extracts the first `{...}` block (respecting string literals and nested
+/// braces) and parses only that, so prose around the object costs one scan
+/// rather than a failed full parse. Matches the TypeScript `parseJsonSafe`
+/// function in boxOverlayService.ts. Input is bounded by the default
+/// `ParseLimits`.
+///
+/// `T` may borrow from `text` (`Cow<'a, str>` fields with `#[serde(borrow)]`).
+pub fn parse_json_safe<'a, T: serde::Deserialize<'a>>(text: &'a str) -> Result<T, ParseError> {
+    parse_json_safe_with(text, &ParseLimits::default())
+}
+
+/// `parse_json_safe` with explicit limits, applied to the extracted object:
+/// the scan stops `max_input_bytes` past its opening brace (`InputTooLarge`)
+/// and an object nested too deeply fails with `TooDeep`, both before any
+/// JSON parsing. Prose around the object is not limited.
+pub fn parse_json_safe_with<'a, T: serde::Deserialize<'a>>(
+    text: &'a str,
+    limits: &ParseLimits,
+) -> Result<T, ParseError> {
+    let bytes = text.as_bytes();
+    let start = memchr(b'{', bytes)
+        .ok_or_else(|| ParseError::new(ParseErrorKind::NoJsonObject))?;
+    let window = &bytes[..bytes.len().min(start.saturating_add(limits.max_input_bytes))];
+
+    let mut state = BraceState::default();
+    let Some(end) = state.advance(window, start) else {
+        if window.len() < bytes.len() {
+            return Err(ParseError::new(ParseErrorKind::InputTooLarge {
+                bytes: bytes.len() - start,
+                max: limits.max_input_bytes,
+            }));
+        }
+        return Err(ParseError::new(ParseErrorKind::Incomplete));
+    };
+    // Prose before the object may hold stray quotes and prose after it
+    // stray brackets, so depth is counted within the object only
+    let depth = nesting_depth(&bytes[start..end]);
+    if depth > limits.max_depth {
+        return Err(ParseError::new(ParseErrorKind::TooDeep {
+            depth,
+            max: limits.max_depth,
+        }));
     }
 
-    // Extract first JSON object
-    let start = text.find('{').ok_or_else(|| ParseError {
-        message: "JSONオブジェクトが見つかりません".to_string(),
-    })?;
+    serde_json::from_str(&text[start..end]).map_err(|e| ParseError {
+        kind: ParseErrorKind::InvalidJson,
+        source: Some(Arc::new(e)),
+    })
+}
 
-    let bytes = text.as_bytes();
-    let mut depth = 0i32;
-    let mut in_string = false;
-    let mut escape = false;
-
-    for i in start..bytes.len() {
-        let ch = bytes[i];
-        if escape {
-            escape = false;
-            continue;
+/// Deepest nesting of `{`/`[` outside string literals
+fn nesting_depth(bytes: &[u8]) -> usize {
+    let (mut depth, mut deepest) = (0usize, 0usize);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => match skip_string(bytes, i + 1, &mut false) {
+                Some(end) => i = end,
+                None => break,
+            },
+            b'{' | b'[' => {
+                depth += 1;
+                deepest = deepest.max(depth);
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
         }
-        if ch == b'\\' && in_string {
-            escape = true;
-            continue;
+        i += 1;
+    }
+    deepest
+}
+
+/// Index of the quote closing a string literal whose body starts at
+/// `bytes[from]`, jumping between quotes and backslashes. `escape` carries a
+/// pending backslash in and out for scans split across chunks; None when the
+/// string is still open at the end.
+fn skip_string(bytes: &[u8], mut from: usize, escape: &mut bool) -> Option<usize> {
+    if *escape {
+        if from >= bytes.len() {
+            return None;
         }
-        if ch == b'"' {
-            in_string = !in_string;
-            continue;
+        *escape = false;
+        from += 1;
+    }
+    while from < bytes.len() {
+        let i = from + memchr2(b'"', b'\\', &bytes[from..])?;
+        if bytes[i] == b'"' {
+            return Some(i);
         }
-        if in_string {
-            continue;
+        if i + 1 == bytes.len() {
+            *escape = true;
+            return None;
         }
-        if ch == b'{' {
-            depth += 1;
-        } else if ch == b'}' {
-            depth -= 1;
+        from = i + 2;
+    }
+    None
+}
+
+/// Brace/string tracking shared by `parse_json_safe` and `JsonObjectScanner`
+#[derive(Debug, Clone, Default)]
+struct BraceState {
+    depth: i32,
+    in_string: bool,
+    escape: bool,
+}
+
+impl BraceState {
+    /// Scan `bytes[from..]`; returns the index just past the closing brace
+    fn advance(&mut self, bytes: &[u8], mut from: usize) -> Option<usize> {
+        while from < bytes.len() {
+            if self.in_string {
+                from = skip_string(bytes, from, &mut self.escape)? + 1;
+                self.in_string = false;
+                continue;
+            }
+            let i = from + memchr3(b'"', b'{', b'}', &bytes[from..])?;
+            match bytes[i] {
+                b'"' => self.in_string = true,
+                b'{' => self.depth += 1,
+                _ => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                }
+            }
+            from = i + 1;
         }
-        if depth == 0 {
-            let extracted = &text[start..=i];
-            return serde_json::from_str(extracted).map_err(|e| ParseError {
-                message: format!("JSON抽出後もパース失敗: {}", e),
-            });
+        None
+    }
+}
+
+/// Incrementally detects when the first JSON object in streamed text closes.
+///
+/// Each chunk is scanned once, so a backend can stop generation as soon as
+/// `push` returns true instead of waiting for trailing prose.
+#[derive(Debug, Clone, Default)]
+pub struct JsonObjectScanner {
+    text: String,
+    start: Option<usize>,
+    end: Option<usize>,
+    state: BraceState,
+}
+
+impl JsonObjectScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a chunk; returns true once the first object is complete
+    pub fn push(&mut self, chunk: &str) -> bool {
+        if self.end.is_some() {
+            self.text.push_str(chunk);
+            return true;
         }
+        let scanned = self.text.len();
+        self.text.push_str(chunk);
+        let from = match self.start {
+            Some(_) => scanned,
+            None => match memchr(b'{', &self.text.as_bytes()[scanned..]) {
+                Some(offset) => {
+                    self.start = Some(scanned + offset);
+                    scanned + offset
+                }
+                None => return false,
+            },
+        };
+        self.end = self.state.advance(self.text.as_bytes(), from);
+        self.end.is_some()
     }
 
-    Err(ParseError {
-        message: "不完全なJSONオブジェクト".to_string(),
-    })
+    pub fn is_complete(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// The first complete object, once closed
+    pub fn object(&self) -> Option<&str> {
+        Some(&self.text[self.start?..self.end?])
+    }
+
+    /// All text received so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
 }
 
-/// Parse a geometry detection response
+/// Parse a geometry detection response, clamping extreme coordinates
 pub fn parse_geometry(text: &str) -> Result<GeometryResponse, ParseError> {
-    parse_json_safe(text)
+    let mut geo: GeometryResponse = parse_json_safe(text)?;
+    geo.sanitize();
+    Ok(geo)
 }
 
-/// Parse a fill estimation response
+/// Parse a fill estimation response, clamping extreme ratios
 pub fn parse_fill(text: &str) -> Result<FillResponse, ParseError> {
+    let mut fill: FillResponse = parse_json_safe(text)?;
+    fill.sanitize();
+    Ok(fill)
+}
+
+/// `parse_geometry` borrowing the plate class from `text`
+pub fn parse_geometry_ref(text: &str) -> Result<GeometryResponseRef<'_>, ParseError> {
+    let mut geo: GeometryResponseRef = parse_json_safe(text)?;
+    geo.numeric_warnings = sanitize_geometry(
+        [&mut geo.tailgate_top_y, &mut geo.tailgate_bottom_y, &mut geo.cargo_top_y],
+        geo.plate_box.as_mut(),
+        geo.occluded_ratio.as_mut(),
+    );
+    Ok(geo)
+}
+
+/// `parse_fill` borrowing the material and reasoning from `text`, for replay
+/// and evaluation jobs over many logged responses
+pub fn parse_fill_ref(text: &str) -> Result<FillResponseRef<'_>, ParseError> {
+    let mut fill: FillResponseRef = parse_json_safe(text)?;
+    fill.numeric_warnings = sanitize_fill([
+        &mut fill.fill_ratio_l,
+        &mut fill.fill_ratio_w,
+        &mut fill.taper_ratio,
+        &mut fill.packing_density,
+    ]);
+    Ok(fill)
+}
+
+/// Parse a legacy multi-param response, clamping its numbers like `parse_fill`
+pub fn parse_multi_param(text: &str) -> Result<MultiParamResponse, ParseError> {
+    let mut multi: MultiParamResponse = parse_json_safe(text)?;
+    let fields = [
+        ("height", &mut multi.height),
+        ("packingDensity", &mut multi.packing_density),
+        ("fillRatioL", &mut multi.fill_ratio_l),
+        ("fillRatioW", &mut multi.fill_ratio_w),
+        ("fillRatioZ", &mut multi.fill_ratio_z),
+    ];
+    multi.numeric_warnings = fields.into_iter().filter_map(|(name, v)| NumericWarning::check(name, v)).collect();
+    Ok(multi)
+}
+
+/// Parse a landmark height response; an answer outside the five landmark
+/// phrases is `InvalidJson`
+pub fn parse_landmark(text: &str) -> Result<LandmarkResponse, ParseError> {
+    parse_json_safe(text)
+}
+
+/// Parse a quality pre-check response
+pub fn parse_quality(text: &str) -> Result<QualityResponse, ParseError> {
+    parse_json_safe(text)
+}
+
+/// Parse a plate reading response
+pub fn parse_plate(text: &str) -> Result<PlateResponse, ParseError> {
+    parse_json_safe(text)
+}
+
+/// Parse a reasoning summary response
+pub fn parse_reasoning_summary(text: &str) -> Result<ReasoningSummaryResponse, ParseError> {
     parse_json_safe(text)
 }
 
@@ -159,6 +701,22 @@ Some trailing text"#;
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_multi_param() {
+        let text = r#"{"isTargetDetected":true,"truckType":"4t","licensePlate":null,"materialType":"As殻",
+            "height":0.45,"packingDensity":0.7,"fillRatioL":0.8,"fillRatioW":0.85,"fillRatioZ":5,
+            "confidenceScore":0.6,"reasoning":"山形"}"#;
+        let multi = parse_multi_param(text).unwrap();
+        assert!(multi.is_target_detected);
+        assert_eq!((multi.height, multi.fill_ratio_w), (0.45, 0.85));
+        assert_eq!(multi.fill_ratio_z, RESPONSE_VALUE_MAX);
+        assert_eq!(multi.numeric_warnings[0].field, "fillRatioZ");
+
+        let defaults = parse_multi_param(r#"{"height":0.3}"#).unwrap();
+        assert_eq!((defaults.fill_ratio_z, defaults.confidence_score), (1.0, 0.0));
+        assert!(parse_multi_param(r#"{"fillRatioL":0.8}"#).is_err(), "height is required");
+    }
+
     #[test]
     fn test_parse_fill_clean_json() {
         let json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,"reasoning":"Cargo is well packed"}"#;
@@ -203,5 +761,135 @@ Some trailing text"#;
         let text = r#"{"fillRatioL":0.8,"fillRatioW":0.85"#;
         let result: Result<FillResponse, _> = parse_json_safe(text);
         assert!(result.is_err());
+        assert_eq!(result.unwrap_err().code(), "PARSE_INCOMPLETE");
+    }
+
+    #[test]
+    fn test_parse_error_preserves_serde_source() {
+        use std::error::Error;
+
+        let err = parse_geometry(r#"text {"tailgateTopY":"high"} text"#).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidJson);
+        assert!(err.source().is_some());
+        assert_eq!(parse_geometry("no json").unwrap_err().code(), "PARSE_NO_JSON");
+    }
+
+    #[test]
+    fn test_scanner_detects_object_across_chunks() {
+        let mut scanner = JsonObjectScanner::new();
+        assert!(!scanner.push("Here you go: {\"fillRatioL\":0.8,"));
+        assert!(!scanner.push("\"reasoning\":\"a } in text\""));
+        assert!(scanner.push("} and some trailing"));
+        assert_eq!(
+            scanner.object(),
+            Some(r#"{"fillRatioL":0.8,"reasoning":"a } in text"}"#)
+        );
+        assert!(scanner.text().ends_with("trailing"));
+        let fill = parse_fill(scanner.object().unwrap()).unwrap();
+        assert!((fill.fill_ratio_l - 0.8).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_scanner_without_object_never_completes() {
+        let mut scanner = JsonObjectScanner::new();
+        assert!(!scanner.push("no json "));
+        assert!(!scanner.push("here"));
+        assert!(scanner.object().is_none());
+    }
+
+    #[test]
+    fn test_parse_plate() {
+        let plate = parse_plate(r#"ナンバー: {"plateNumber":"品川 500 あ 12-34"}"#).unwrap();
+        assert_eq!(plate.plate_number.as_deref(), Some("品川 500 あ 12-34"));
+        assert!(parse_plate(r#"{"plateNumber":null}"#).unwrap().plate_number.is_none());
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let long = format!(r#"{{"cargoTopY":0.2,"pad":"{}"}}"#, "x".repeat(DEFAULT_MAX_INPUT_BYTES));
+        let err = parse_geometry(&long).unwrap_err();
+        assert_eq!(err.code(), "PARSE_INPUT_TOO_LARGE");
+
+        let deep = format!(r#"{{"cargoTopY":0.2,"x":{}{}}}"#, "[".repeat(40), "]".repeat(40));
+        assert_eq!(parse_geometry(&deep).unwrap_err().code(), "PARSE_TOO_DEEP");
+
+        // Brackets inside strings and before the object do not count
+        let text = format!(r#"[[[ note {{"cargoTopY":0.2,"x":"{}"}}"#, "[".repeat(40));
+        assert!(parse_geometry(&text).is_ok());
+
+        let limits = ParseLimits { max_input_bytes: 16, ..ParseLimits::default() };
+        let err = parse_json_safe_with::<GeometryResponse>(r#"{"cargoTopY":0.25}"#, &limits).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InputTooLarge { bytes: 18, max: 16 });
+    }
+
+    #[test]
+    fn test_parse_limits_ignore_trailing_text() {
+        let limits = ParseLimits { max_input_bytes: 32, max_depth: 2 };
+        let prose = format!(r#" 補足: {} {{"a":{{"b":{{}}}}}} {}"#, "{[".repeat(40), "x".repeat(100));
+        let text = format!(r#"{{"cargoTopY":0.2}}{}"#, prose);
+        let geo = parse_json_safe_with::<GeometryResponse>(&text, &limits).unwrap();
+        assert_eq!(geo.cargo_top_y, 0.2);
+        assert!(parse_geometry(&format!("{}{}", text, "}".repeat(DEFAULT_MAX_INPUT_BYTES))).is_ok());
+    }
+
+    #[test]
+    fn test_extreme_values_clamped() {
+        let text = r#"{"tailgateTopY":-0.3,"tailgateBottomY":1e308,"cargoTopY":2E-1,"plateBox":[0.4,0.7,0.5,0.75]}"#;
+        let geo = parse_geometry(text).unwrap();
+        assert_eq!((geo.tailgate_top_y, geo.tailgate_bottom_y, geo.cargo_top_y), (0.0, RESPONSE_VALUE_MAX, 0.2));
+        assert_eq!(geo.numeric_warnings.len(), 2);
+        let expected = NumericWarning { field: "tailgateBottomY".into(), value: 1e308, clamped: 2.0 };
+        assert_eq!(geo.numeric_warnings[1], expected);
+
+        let fill = parse_fill(r#"{"fillRatioL":1.2,"fillRatioW":-5,"taperRatio":9e99,"packingDensity":8e-1}"#).unwrap();
+        assert_eq!((fill.fill_ratio_l, fill.fill_ratio_w, fill.taper_ratio), (1.2, 0.0, RESPONSE_VALUE_MAX));
+        let fields: Vec<&str> = fill.numeric_warnings.iter().map(|w| w.field.as_str()).collect();
+        assert_eq!(fields, ["fillRatioW", "taperRatio"]);
+
+        // Beyond f64 range is a parse error, never an infinity
+        assert_eq!(parse_fill(r#"{"fillRatioL":1e400}"#).unwrap_err().code(), "PARSE_INVALID_JSON");
+    }
+
+    #[test]
+    fn test_parse_landmark() {
+        let parsed = parse_landmark(r#"荷山の頂点は {"landmark": "ヒンジ超え"} です"#).unwrap();
+        assert_eq!(parsed.landmark, Landmark::AboveHinge);
+        assert_eq!(parse_landmark(r#"{"landmark":"後板と同じ"}"#).unwrap().landmark, Landmark::AtBackPanel);
+        assert_eq!(parse_landmark(r#"{"landmark":"高い"}"#).unwrap_err().code(), "PARSE_INVALID_JSON");
+    }
+
+    #[test]
+    fn test_scanner_escapes_split_across_chunks() {
+        let mut scanner = JsonObjectScanner::new();
+        assert!(!scanner.push(r#"{"reasoning":"quote \"#));
+        assert!(!scanner.push(r#"" and } brace \"#));
+        assert!(!scanner.push(r#"\"#));
+        assert!(scanner.push(r#"","fillRatioL":0.6} trailing"#));
+        let fill = parse_fill(scanner.object().unwrap()).unwrap();
+        assert_eq!(fill.reasoning.as_deref(), Some(r#"quote " and } brace \"#));
+
+        // Escapes and braces inside long strings, prose before the object
+        let text = format!(r#"Note: "odd quote. {{"reasoning":"{}\"}}\\","cargoTopY":0.2}} done"#, "x".repeat(5000));
+        assert!((parse_geometry(&text).unwrap().cargo_top_y - 0.2).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_borrowed_parsing() {
+        let text = r#"Result: {"fillRatioL":0.8,"fillRatioW":3.0,"materialType":"As殻","reasoning":"flat \"mound\""}"#;
+        let fill = parse_fill_ref(text).unwrap();
+        assert!(matches!(fill.material_type, Some(Cow::Borrowed("As殻"))));
+        assert!(matches!(fill.reasoning, Some(Cow::Owned(ref r)) if r == r#"flat "mound""#), "escapes need a copy");
+        assert_eq!(fill.numeric_warnings.len(), 1);
+
+        let owned = fill.into_owned();
+        let parsed = parse_fill(text).unwrap();
+        assert_eq!(
+            (owned.fill_ratio_w, owned.reasoning, owned.numeric_warnings),
+            (parsed.fill_ratio_w, parsed.reasoning, parsed.numeric_warnings)
+        );
+
+        let geo = parse_geometry_ref(r#"{"tailgateTopY":-1,"cargoTopY":0.2,"plateClass":"中板"}"#).unwrap();
+        assert!(matches!(geo.plate_class, Some(Cow::Borrowed("中板"))));
+        assert_eq!(geo.into_owned().numeric_warnings[0].field, "tailgateTopY");
     }
 }
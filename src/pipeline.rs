@@ -3,44 +3,165 @@
 //! Provides the `AiBackend` trait and `analyze_box_overlay` function that
 //! encapsulates the full ensemble geometry + fill estimation flow.
 //! This ensures CLI and Web produce identical results from the same AI responses.
+//!
+//! Other ways of estimating the load implement `EstimationStrategy` (calls,
+//! parsing, aggregation, calculation) and run through `analyze_with`. The
+//! legacy single-shot estimate (`Strategy::MultiParam`, `analyze_multi_param`)
+//! is one, kept so both strategies can be run on the same photos (`analyze`,
+//! `compare_strategies`).
+
+use crate::billing::InvoiceRounding;
+use crate::diff::ResultDiff;
+use crate::ensemble::{
+    average, detect_disagreement, review_flags, Disagreement, DisagreementThresholds, FillSamples, MaterialVotes,
+    MedianMode, ReviewFlag, ReviewThresholds, RunStatistics,
+};
+use crate::fleet::{normalize_plate, Fleet, Vehicle};
+use crate::metadata::LoadMetadata;
+use crate::hashing::{hash_images, sha256_hex, ImageHash};
+use crate::i18n::{self, language, Language, Localize};
+#[cfg(feature = "image")]
+use crate::exif::apply_photo_metadata;
+use crate::input::{GpsPosition, ImageInfo, InputImage};
+use crate::metrics::{Counter, Histogram, Metrics};
+use crate::overlay::OverlayData;
+use crate::policy::{TolerancePolicy, Verdict};
+use crate::site::SiteProfile;
+use crate::sites::SiteRegistry;
+use crate::calculation::{
+    bed_dimensions, calculate_multi_param_with, calculate_tonnage_with, debug_checks, estimate_height, landmark_height,
+    CoreParams, FormulaVersion, InvariantViolation, Landmark, MultiParams, ScaleCalibration, ScaleOptions,
+    TonnageBreakdown, TonnageResult,
+    SCALE_DISAGREEMENT_TOLERANCE,
+};
+use crate::parse::{
+    parse_fill, parse_geometry, parse_landmark, parse_multi_param, parse_plate, parse_reasoning_summary, FillResponse,
+    GeometryResponse, JsonObjectScanner, MultiParamResponse, NumericWarning, ParseError,
+};
+use crate::prompt::{find_prompt, PromptStage};
+use crate::quality::{check_images, ImageIssue, QualityCheckConfig};
+use crate::redact::Redactor;
+use crate::repro::ReproManifest;
+use crate::runlog::RunLogs;
+use crate::report::ReportingMode;
+use crate::spec::{
+    get_material, get_material_density, get_material_density_range, get_truck_spec, normalize_truck_class, MaterialEntry, Range, TruckClassMatch,
+    TruckSpec, SPEC,
+};
 
-use crate::calculation::{calculate_tonnage, height_from_geometry, CoreParams};
-use crate::parse::{parse_fill, parse_geometry, FillResponse, GeometryResponse, ParseError};
-use crate::spec::SPEC;
+#[cfg(feature = "image")]
+use crate::preprocess::{image_aspect, image_dimensions, preprocess_images, PreprocessConfig, PreprocessError};
 
+use std::borrow::Cow;
 use std::fmt;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 // ─── Errors ──────────────────────────────────────────────────────────
 
 /// Pipeline error
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum PipelineError {
     /// AI backend returned an error
     AiError(String),
+    /// AI backend failed with an underlying error (HTTP, subprocess, ...)
+    Backend {
+        message: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
     /// JSON parse failure
-    ParseError(String),
+    ParseError(#[from] ParseError),
     /// All geometry ensemble runs failed
     NoValidGeometry,
     /// All fill ensemble runs failed
     NoValidFill,
+    /// All multi-param runs failed (`Strategy::MultiParam`)
+    NoValidMultiParam,
+    /// Input image could not be preprocessed
+    Preprocess(String),
+    /// Image rejected by the quality pre-check
+    UnusableImage(ImageIssue),
+    /// Truck class missing from the spec (strict mode only)
+    UnknownTruckClass(String),
+    /// A single backend call exceeded its time limit
+    CallTimeout(Duration),
+    /// `BoxOverlayConfig::deadline` passed; carries the runs completed so far
+    Timeout {
+        elapsed: Duration,
+        geometry_runs: Vec<GeometryRunLog>,
+        fill_runs: Vec<FillRunLog>,
+    },
+    /// An input image exceeds the backend's `max_image_bytes`
+    ImageTooLarge { index: usize, bytes: usize, max: usize },
+    /// `CircuitBreaker` is open after repeated failures; no call was made
+    CircuitOpen { backend: String, retry_after: Duration },
 }
 
-impl fmt::Display for PipelineError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl PipelineError {
+    /// Wrap a backend-specific error, keeping it as the error source
+    pub fn backend(
+        message: impl Into<String>,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        Self::Backend {
+            message: message.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
         match self {
-            Self::AiError(s) => write!(f, "AI error: {}", s),
-            Self::ParseError(s) => write!(f, "Parse error: {}", s),
-            Self::NoValidGeometry => write!(f, "幾何学検出が全ての試行で失敗しました"),
-            Self::NoValidFill => write!(f, "充填率推定が全ての試行で失敗しました"),
+            Self::AiError(_) | Self::Backend { .. } => "AI_ERROR",
+            Self::ParseError(e) => e.code(),
+            Self::NoValidGeometry => "NO_VALID_GEOMETRY",
+            Self::NoValidFill => "NO_VALID_FILL",
+            Self::NoValidMultiParam => "NO_VALID_MULTI_PARAM",
+            Self::Preprocess(_) => "PREPROCESS_FAILED",
+            Self::UnusableImage(_) => "UNUSABLE_IMAGE",
+            Self::UnknownTruckClass(_) => "UNKNOWN_TRUCK_CLASS",
+            Self::CallTimeout(_) => "CALL_TIMEOUT",
+            Self::Timeout { .. } => "TIMEOUT",
+            Self::ImageTooLarge { .. } => "IMAGE_TOO_LARGE",
+            Self::CircuitOpen { .. } => "CIRCUIT_OPEN",
         }
     }
 }
 
-impl std::error::Error for PipelineError {}
+impl Localize for PipelineError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::AiError(message) | Self::Backend { message, .. } => i18n::AI_ERROR.format(lang, &[message]),
+            Self::ParseError(e) => i18n::PARSE_ERROR.format(lang, &[&e.localize(lang)]),
+            Self::NoValidGeometry => i18n::NO_VALID_GEOMETRY.get(lang).to_string(),
+            Self::NoValidFill => i18n::NO_VALID_FILL.get(lang).to_string(),
+            Self::NoValidMultiParam => i18n::NO_VALID_MULTI_PARAM.get(lang).to_string(),
+            Self::Preprocess(message) => i18n::PREPROCESS_FAILED.format(lang, &[message]),
+            Self::UnusableImage(issue) => i18n::UNUSABLE_IMAGE.format(lang, &[&issue.localize(lang)]),
+            Self::UnknownTruckClass(class) => i18n::UNKNOWN_TRUCK_CLASS.format(lang, &[class]),
+            Self::CallTimeout(limit) => i18n::CALL_TIMEOUT.format(lang, &[&limit.as_millis()]),
+            Self::Timeout { elapsed, .. } => i18n::TIMEOUT.format(lang, &[&elapsed.as_millis()]),
+            Self::ImageTooLarge { index, bytes, max } => i18n::IMAGE_TOO_LARGE.format(lang, &[index, bytes, max]),
+            Self::CircuitOpen { backend, retry_after } => {
+                i18n::CIRCUIT_OPEN.format(lang, &[backend, &retry_after.as_millis()])
+            }
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
 
-impl From<ParseError> for PipelineError {
-    fn from(e: ParseError) -> Self {
-        Self::ParseError(e.message)
+#[cfg(feature = "image")]
+impl From<PreprocessError> for PipelineError {
+    fn from(e: PreprocessError) -> Self {
+        Self::Preprocess(e.detail().to_string())
     }
 }
 
@@ -48,9 +169,164 @@ impl From<ParseError> for PipelineError {
 
 /// Trait for sending prompts to an AI model.
 /// Implemented differently by CLI (Gemini CLI subprocess) and Web (Google GenAI SDK).
+///
+/// `analyze_box_overlay` only needs `&self`; the multi-threaded entry points
+/// (`analyze_box_overlay_concurrent`, `analyze_box_overlay_batch`) also need
+/// the backend to be `Sync` (and `Send` for the batch API), so keep per-call
+/// state behind a `Mutex` or atomics rather than `Cell`/`RefCell`.
 pub trait AiBackend {
-    /// Send a text prompt with image data and return the raw text response.
-    fn send_prompt(&self, prompt: &str, images: &[Vec<u8>]) -> Result<String, PipelineError>;
+    /// Send a text prompt with images and return the raw text response.
+    /// `InputImage::mime_type` gives the MIME type providers such as Gemini REST require.
+    fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError>;
+
+    /// Backend name recorded in run logs
+    fn name(&self) -> &str {
+        "default"
+    }
+
+    /// What the provider accepts; the pipeline adapts its calls to these
+    /// limits instead of letting the provider reject them
+    fn capabilities(&self) -> BackendCapabilities {
+        BackendCapabilities::default()
+    }
+
+    /// Send a prompt and report the name of the backend that actually answered.
+    /// Combinators such as `FallbackBackend` override this.
+    fn send_prompt_traced(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+    ) -> Result<(String, String), PipelineError> {
+        self.send_prompt_until_json(prompt, images)
+            .map(|r| (r, self.name().to_string()))
+    }
+
+    /// Stream the response as text chunks to `on_chunk`, stopping generation
+    /// when it returns `ControlFlow::Break`. Returns the text received.
+    ///
+    /// The default delivers the whole `send_prompt` response as one chunk;
+    /// backends with a streaming API should override this.
+    fn send_prompt_streaming(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        on_chunk: &mut dyn FnMut(&str) -> ControlFlow<()>,
+    ) -> Result<String, PipelineError> {
+        let response = self.send_prompt(prompt, images)?;
+        let _ = on_chunk(&response);
+        Ok(response)
+    }
+
+    /// Stream the response and cut generation off once the first JSON object closes
+    fn send_prompt_until_json(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+        let mut scanner = JsonObjectScanner::new();
+        self.send_prompt_streaming(prompt, images, &mut |chunk| {
+            if scanner.push(chunk) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+        Ok(scanner.into_text())
+    }
+
+    /// Send a prompt with generation options and report the answering backend.
+    ///
+    /// The default ignores the options; backends whose provider accepts a
+    /// model, temperature, seed, ... should override this.
+    fn send_prompt_with_options(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        let _ = options;
+        self.send_prompt_traced(prompt, images)
+    }
+
+    /// Send a prompt with a time limit (None = unlimited).
+    ///
+    /// The default cannot interrupt a blocking `send_prompt`; it reports
+    /// `CallTimeout` once an overrunning call returns. Backends that can cancel
+    /// (subprocess kill, HTTP client or async runtime timeout) should override
+    /// this; `Watchdog` enforces the limit for any `Send + Sync` backend.
+    fn send_prompt_within(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        limit: Option<Duration>,
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        let Some(limit) = limit else {
+            return self.send_prompt_with_options(prompt, images, options);
+        };
+        let started = Instant::now();
+        let reply = self.send_prompt_with_options(prompt, images, options)?;
+        if started.elapsed() > limit {
+            return Err(PipelineError::CallTimeout(limit));
+        }
+        Ok(reply)
+    }
+}
+
+/// Generation parameters passed to the backend (None = provider default).
+///
+/// Providers that do not support a parameter ignore it.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendOptions {
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub seed: Option<u64>,
+    pub max_tokens: Option<u32>,
+    /// Ask for a JSON-only reply; set by the pipeline when the backend
+    /// reports `structured_output`
+    pub json_output: bool,
+}
+
+/// Limits and features of a backend's provider (default: no limits, plain text only)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BackendCapabilities {
+    /// Most images per call (None = unlimited); larger sets are split across
+    /// ensemble runs, run `i` getting batch `i % batches`
+    pub max_images: Option<usize>,
+    /// Largest accepted image (None = unlimited); bigger inputs are rejected
+    /// with `ImageTooLarge` before any call
+    pub max_image_bytes: Option<usize>,
+    /// Provider can constrain replies to JSON (`BackendOptions::json_output`)
+    pub structured_output: bool,
+    /// `send_prompt_streaming` delivers incremental chunks
+    pub streaming: bool,
+}
+
+impl BackendCapabilities {
+    /// What every one of both backends accepts
+    pub fn intersect(&self, other: &Self) -> Self {
+        fn min(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+        }
+        Self {
+            max_images: min(self.max_images, other.max_images),
+            max_image_bytes: min(self.max_image_bytes, other.max_image_bytes),
+            structured_output: self.structured_output && other.structured_output,
+            streaming: self.streaming && other.streaming,
+        }
+    }
+
+    /// The images run `run` sends: all of them, or one `max_images` batch
+    pub fn run_images<'a>(&self, images: &'a [InputImage], run: usize) -> &'a [InputImage] {
+        match self.max_images.filter(|&max| max > 0 && images.len() > max) {
+            Some(max) => {
+                let start = run % images.len().div_ceil(max) * max;
+                &images[start..(start + max).min(images.len())]
+            }
+            None => images,
+        }
+    }
 }
 
 // ─── Config / Result types ───────────────────────────────────────────
@@ -60,474 +336,5766 @@ pub trait AiBackend {
 pub struct BoxOverlayConfig {
     pub truck_class: String,
     pub material_type: String,
-    /// Number of ensemble runs (typically 2-3)
+    /// Number of ensemble runs (typically 2-3); shorthand for both stages
     pub ensemble_count: usize,
+    /// Geometry runs (None = `ensemble_count`)
+    pub geometry_runs: Option<usize>,
+    /// Fill runs (None = `ensemble_count`)
+    pub fill_runs: Option<usize>,
+    /// Override for the geometry prompt (None = `SPEC.geometry_prompt`)
+    pub geometry_prompt: Option<String>,
+    /// Override for the fill prompt (None = `SPEC.fill_prompt`)
+    pub fill_prompt: Option<String>,
+    /// Geometry prompt variants cycled across ensemble runs (empty = single prompt)
+    pub geometry_variants: Vec<PromptVariant>,
+    /// Fill prompt variants cycled across ensemble runs (empty = single prompt)
+    pub fill_variants: Vec<PromptVariant>,
+    /// Orient/downscale/re-encode images before sending (None = send as-is)
+    #[cfg(feature = "image")]
+    pub preprocess: Option<PreprocessConfig>,
+    /// Reject unusable photos before the ensemble (None = no pre-check)
+    pub quality_check: Option<QualityCheckConfig>,
+    /// Run-to-run divergence that triggers `BoxOverlayResult::disagreement`
+    pub disagreement: DisagreementThresholds,
+    /// Extra runs per stage, issued one at a time while the stage's runs
+    /// disagree beyond `disagreement` (0 = never; not in `plan_box_overlay`)
+    pub max_escalation_runs: usize,
+    /// Run-to-run CV above which `BoxOverlayResult::needs_review` is set
+    pub review: ReviewThresholds,
+    /// Median of an even number of run heights (default: mean of the middle
+    /// pair, as the TypeScript implementation)
+    pub median: MedianMode,
+    /// Fail with `UnknownTruckClass` instead of falling back to default bed dimensions
+    pub strict_truck_class: bool,
+    /// Time limit for each backend call (None = unlimited)
+    pub timeout_per_call: Option<Duration>,
+    /// Time limit for the whole analysis (None = unlimited)
+    pub deadline: Option<Duration>,
+    /// Order of geometry and fill calls
+    pub schedule: StageSchedule,
+    /// How the geometry stage derives the height
+    pub geometry_mode: GeometryMode,
+    /// Estimation strategy `analyze` runs
+    pub strategy: Strategy,
+    /// Ask geometry runs for the occluded ratio and act on it (None = not asked)
+    pub occlusion: Option<OcclusionCheck>,
+    /// Treat the load as covered by a tarp (None = uncovered)
+    pub tarp: Option<TarpMode>,
+    /// Share of the (confidence-weighted) material votes the winner needs to
+    /// replace `material_type` (default 0: any plurality)
+    pub material_agreement: f64,
+    /// Scale reference selection for the geometry stage
+    pub scale: ScaleOptions,
+    /// Derive a conservative fill from the geometry stage when every fill run
+    /// fails, instead of returning `NoValidFill`
+    pub fill_fallback: bool,
+    /// Append the typical values of `material_type` to the fill prompt
+    pub material_hint: bool,
+    /// Generation parameters for every backend call
+    pub backend_options: BackendOptions,
+    /// Per-run temperatures, cycled over the ensemble runs of each stage
+    /// (empty = `backend_options.temperature` for every run)
+    pub temperature_schedule: Vec<f64>,
+    /// Telemetry sink (default: none)
+    pub metrics: Metrics,
+    /// Receives each run log as the run completes (default: none)
+    pub log_sink: RunLogs,
+    /// Keep run logs in `BoxOverlayResult::geometry_runs` / `fill_runs`
+    /// (default: true). Turn off when `log_sink` persists them: raw
+    /// responses then go to the sink only, and report helpers that read run
+    /// logs see none.
+    pub keep_run_logs: bool,
+    /// Masking applied to model responses before they enter run logs and
+    /// results (None = stored verbatim)
+    pub redaction: Option<Redactor>,
+    /// How much of each raw response run logs keep (applied after redaction)
+    pub log_retention: LogRetention,
+    /// How the fill runs' reasonings become `BoxOverlayResult::reasoning`
+    pub reasoning_summary: ReasoningSummary,
+    /// Acceptance policy used to set `BoxOverlayResult::verdict`
+    pub policy: Option<TolerancePolicy>,
+    /// Site whose trucks, materials and spec entries apply (None = spec only)
+    pub site: Option<Arc<SiteProfile>>,
+    /// Geofenced sites; with no `site` selected, the one containing the
+    /// photos' position applies its spec entries, materials and policy
+    pub sites: Option<Arc<SiteRegistry>>,
+    /// Quantity the result is reported in (weight, or volume for contracts
+    /// billed by m³)
+    pub reporting: ReportingMode,
+    /// Tonnage formula (default: current); an older version reproduces
+    /// results stored under it
+    pub formula: FormulaVersion,
+    /// Rounding of `BoxOverlayResult::billed_tonnage` (None = not billed)
+    pub invoicing: Option<InvoiceRounding>,
+    /// Registry of known vehicles
+    pub fleet: Option<Arc<Fleet>>,
+    /// Vehicle ID or plate number looked up in `fleet`; a registered
+    /// vehicle's measured bed replaces the truck class spec
+    pub vehicle: Option<String>,
+    /// Read the license plate before the ensemble and select the matching
+    /// `fleet` vehicle (skipped when `vehicle` is set)
+    pub plate_recognition: bool,
+    /// Dispatch identifiers copied to the result
+    pub metadata: LoadMetadata,
+    /// Check the calculation against physical invariants and report
+    /// violations in `BoxOverlayResult::invariant_violations`
+    pub debug_checks: bool,
 }
 
-/// Full result of a box-overlay analysis
-#[derive(Debug, Clone)]
-pub struct BoxOverlayResult {
-    pub height_m: f64,
-    pub fill_ratio_l: f64,
-    pub fill_ratio_w: f64,
-    pub taper_ratio: f64,
-    pub packing_density: f64,
-    pub effective_packing: f64,
-    pub volume: f64,
-    pub tonnage: f64,
-    pub density: f64,
-    pub material_type: String,
-    pub reasoning: String,
-    pub geometry_runs: Vec<GeometryRunLog>,
-    pub fill_runs: Vec<FillRunLog>,
+/// How the geometry and fill ensembles are scheduled.
+///
+/// The two stages are independent until the tonnage calculation, so they
+/// need not run back to back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StageSchedule {
+    /// All geometry runs, then all fill runs
+    #[default]
+    Sequential,
+    /// Alternate geometry and fill runs so fill starts after the first
+    /// geometry reply; `analyze_box_overlay_concurrent` runs both stages in parallel.
+    /// The fill runs go out before the load height is known, so an empty bed
+    /// skips only the fill escalation runs.
+    Interleaved,
 }
 
-/// Log of a single geometry detection run
-#[derive(Debug, Clone)]
-pub struct GeometryRunLog {
-    pub raw_response: String,
-    pub parsed: Option<GeometryResponse>,
-    pub scale_method: String,
-    pub height_m: f64,
+/// How the geometry stage derives the load height
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GeometryMode {
+    /// Tailgate/plate coordinates scaled to meters (`geometryPrompt`)
+    #[default]
+    Coordinates,
+    /// Qualitative landmark answers mapped to the spec calibration heights
+    /// (`landmarkPrompt`, `landmark_height`)
+    Landmark,
+    /// Coordinates, with a landmark ensemble when no coordinate run yields
+    /// a height
+    CoordinatesWithLandmarkFallback,
 }
 
-/// Log of a single fill estimation run
-#[derive(Debug, Clone)]
-pub struct FillRunLog {
-    pub raw_response: String,
-    pub parsed: Option<FillResponse>,
+/// How the load is estimated (`analyze`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Geometry and fill ensembles (`analyze_box_overlay`)
+    #[default]
+    BoxOverlay,
+    /// Legacy single call per run estimating height, fillRatioL/W/Z and
+    /// packing together (`multiParamPrompt`, `analyze_multi_param`)
+    MultiParam,
 }
 
-// ─── Pipeline ────────────────────────────────────────────────────────
-
-/// Run the full box-overlay analysis pipeline.
+/// Occlusion handling in the geometry stage (`BoxOverlayConfig::occlusion`)
 ///
-/// 1. Geometry detection (ensemble) -> median height
-/// 2. Fill estimation (ensemble) -> average fill ratios (clamped to SPEC ranges)
-/// 3. Tonnage calculation
+/// Mirrors, tarps or a neighbouring vehicle hiding the tailgate make the
+/// model guess its edges. The geometry prompt then also asks for
+/// `occludedRatio`; runs above `reject_above` give no height, and the
+/// tonnage range widens with the largest ratio among the remaining runs.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcclusionCheck {
+    /// Occluded ratio above which a run is rejected
+    pub reject_above: f64,
+    /// Relative widening of `tonnage_min` / `tonnage_max` per unit of
+    /// occluded ratio
+    pub widening: f64,
+}
+
+impl Default for OcclusionCheck {
+    fn default() -> Self {
+        Self {
+            reject_above: 0.4,
+            widening: 1.0,
+        }
+    }
+}
+
+/// Handling of sheeted / tarped loads (`BoxOverlayConfig::tarp`)
 ///
-/// Matches the logic in `boxOverlayService.ts::analyzeBoxOverlayEnsemble`.
-pub fn analyze_box_overlay(
-    backend: &dyn AiBackend,
-    images: &[Vec<u8>],
-    config: &BoxOverlayConfig,
-) -> Result<BoxOverlayResult, PipelineError> {
-    let spec = &*SPEC;
-    let ranges = &spec.ranges;
+/// The cargo itself cannot be seen, so the geometry prompt asks for the top
+/// of the tarp profile and each run height is scaled by `deflation`: a tarp
+/// stretched over the heap peaks above the cargo beneath it. The fill prompt
+/// is told the load is covered, and the configured material is kept since
+/// the model cannot see it.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TarpMode {
+    /// Cargo height / tarp profile height, in `(0, 1]`
+    pub deflation: f64,
+}
 
-    let bed_height = spec
-        .truck_specs
-        .get(&config.truck_class)
-        .map(|s| s.bed_height)
-        .unwrap_or(0.32);
+impl Default for TarpMode {
+    fn default() -> Self {
+        Self { deflation: 0.9 }
+    }
+}
 
-    // ── Step 1: Geometry detection (ensemble, take median of height_m) ──
+/// Appended to the geometry prompt when `BoxOverlayConfig::tarp` is set
+const TARP_GEOMETRY_PROMPT: &str = "The load is covered by a tarp or sheet. cargoTopY = Y coordinate of the \
+HIGHEST point of the tarp profile over the cargo (not of ropes, frames or the sheet hanging over the sides).";
 
-    let mut height_list = Vec::new();
-    let mut geometry_runs = Vec::new();
+/// Appended to the fill prompt when `BoxOverlayConfig::tarp` is set
+const TARP_FILL_PROMPT: &str = "The load is covered by a tarp or sheet: judge fillRatioL, fillRatioW and \
+taperRatio from the shape of the tarp over the cargo.";
 
-    for _i in 0..config.ensemble_count {
-        match backend.send_prompt(&spec.geometry_prompt, images) {
-            Ok(response) => match parse_geometry(&response) {
-                Ok(geo) => {
-                    if geo.tailgate_top_y <= 0.0 {
-                        geometry_runs.push(GeometryRunLog {
-                            raw_response: response,
-                            parsed: Some(geo),
-                            scale_method: "none".into(),
-                            height_m: 0.0,
-                        });
-                        continue;
-                    }
+/// Appended to the geometry prompt when `BoxOverlayConfig::occlusion` is set
+const OCCLUSION_PROMPT: &str = "Also output \"occludedRatio\": the fraction (0.0-1.0) of the tailgate and \
+bed edges hidden by mirrors, tarps, other vehicles or anything else (0.0 = fully visible).";
 
-                    let (h, method) = height_from_geometry(
-                        geo.tailgate_top_y,
-                        geo.tailgate_bottom_y,
-                        geo.cargo_top_y,
-                        geo.plate_box,
-                        bed_height,
-                    );
-
-                    if method == "none" {
-                        geometry_runs.push(GeometryRunLog {
-                            raw_response: response,
-                            parsed: Some(geo),
-                            scale_method: "none".into(),
-                            height_m: 0.0,
-                        });
-                        continue;
-                    }
+/// How the reasonings of the fill runs are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReasoningSummary {
+    /// Distinct reasonings joined line by line, in run order
+    #[default]
+    Merge,
+    /// One extra text-only call (`reasoningPrompt`) summarizing differing
+    /// reasonings; the merged text is kept when it fails or time runs out
+    Model,
+}
 
-                    height_list.push(h);
-                    geometry_runs.push(GeometryRunLog {
-                        raw_response: response,
-                        parsed: Some(geo),
-                        scale_method: method.to_string(),
-                        height_m: h,
-                    });
-                }
-                Err(_e) => {
-                    geometry_runs.push(GeometryRunLog {
-                        raw_response: response,
-                        parsed: None,
-                        scale_method: "parse_error".into(),
-                        height_m: 0.0,
-                    });
-                }
-            },
-            Err(_e) => {
-                geometry_runs.push(GeometryRunLog {
-                    raw_response: String::new(),
-                    parsed: None,
-                    scale_method: "error".into(),
-                    height_m: 0.0,
-                });
+/// Reasoning given by one fill run
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunReasoning {
+    /// Index in `fill_runs`
+    pub run: usize,
+    pub text: String,
+}
+
+/// How much of each raw AI response run logs keep.
+///
+/// Long reasoning outputs add up over large batches, both in memory and in
+/// persisted history; parsed values are kept regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogRetention {
+    /// The whole response
+    #[default]
+    Full,
+    /// At most this many bytes (cut at a character boundary), followed by `…`
+    /// when cut
+    Truncated(usize),
+    /// `sha256:<hex>` of the response, enough to match it against a recording
+    HashOnly,
+    /// Nothing; `raw_response` is empty
+    None,
+}
+
+impl LogRetention {
+    /// Apply the policy to a response
+    pub fn apply(&self, text: String) -> String {
+        match *self {
+            Self::Full => text,
+            Self::Truncated(max) if text.len() <= max => text,
+            Self::Truncated(max) => {
+                let cut = (0..=max).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+                format!("{}…", &text[..cut])
             }
+            Self::HashOnly => format!("sha256:{}", sha256_hex(text.as_bytes())),
+            Self::None => String::new(),
         }
     }
+}
 
-    if height_list.is_empty() {
-        return Err(PipelineError::NoValidGeometry);
+/// A named prompt used for a subset of ensemble runs
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PromptVariant {
+    pub name: String,
+    pub prompt: String,
+}
+
+impl PromptVariant {
+    pub fn new(name: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            prompt: prompt.into(),
+        }
+    }
+
+    /// Build a variant from a registry prompt (name = `name@version`)
+    pub fn from_registry(key: &str) -> Option<Self> {
+        find_prompt(key).map(|p| Self::new(p.id(), p.text))
+    }
+}
+
+impl Default for BoxOverlayConfig {
+    fn default() -> Self {
+        Self {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 2,
+            geometry_runs: None,
+            fill_runs: None,
+            geometry_prompt: None,
+            fill_prompt: None,
+            geometry_variants: Vec::new(),
+            fill_variants: Vec::new(),
+            #[cfg(feature = "image")]
+            preprocess: None,
+            quality_check: None,
+            disagreement: DisagreementThresholds::default(),
+            max_escalation_runs: 0,
+            review: ReviewThresholds::default(),
+            median: MedianMode::default(),
+            strict_truck_class: false,
+            timeout_per_call: None,
+            deadline: None,
+            schedule: StageSchedule::Sequential,
+            geometry_mode: GeometryMode::Coordinates,
+            strategy: Strategy::BoxOverlay,
+            occlusion: None,
+            tarp: None,
+            material_agreement: 0.0,
+            scale: ScaleOptions::default(),
+            fill_fallback: false,
+            material_hint: false,
+            backend_options: BackendOptions::default(),
+            temperature_schedule: Vec::new(),
+            metrics: Metrics::default(),
+            log_sink: RunLogs::default(),
+            keep_run_logs: true,
+            redaction: None,
+            log_retention: LogRetention::Full,
+            reasoning_summary: ReasoningSummary::Merge,
+            policy: None,
+            site: None,
+            sites: None,
+            reporting: ReportingMode::Weight,
+            formula: FormulaVersion::default(),
+            invoicing: None,
+            fleet: None,
+            vehicle: None,
+            plate_recognition: false,
+            metadata: LoadMetadata::default(),
+            debug_checks: false,
+        }
     }
+}
 
-    let height_m = median(&height_list);
+impl BoxOverlayConfig {
+    /// Geometry prompt actually sent to the backend
+    pub fn geometry_prompt(&self) -> &str {
+        self.geometry_prompt.as_deref().unwrap_or(&SPEC.geometry_prompt)
+    }
 
-    // ── Step 2: Fill estimation (ensemble, average, clamp) ──
+    /// Fill prompt actually sent to the backend
+    pub fn fill_prompt(&self) -> &str {
+        self.fill_prompt.as_deref().unwrap_or(&SPEC.fill_prompt)
+    }
 
-    let mut fill_l_list = Vec::new();
-    let mut fill_w_list = Vec::new();
-    let mut taper_list = Vec::new();
-    let mut packing_list = Vec::new();
-    let mut last_reasoning = String::new();
-    let mut detected_materials: Vec<String> = Vec::new();
-    let mut fill_runs = Vec::new();
+    /// True when the plate stage runs (`plate_recognition` without `vehicle`)
+    pub fn reads_plate(&self) -> bool {
+        self.plate_recognition && self.vehicle.is_none()
+    }
 
-    for _i in 0..config.ensemble_count {
-        match backend.send_prompt(&spec.fill_prompt, images) {
-            Ok(response) => match parse_fill(&response) {
-                Ok(fill) => {
-                    fill_l_list.push(fill.fill_ratio_l);
-                    fill_w_list.push(fill.fill_ratio_w);
-                    taper_list.push(fill.taper_ratio);
-                    packing_list.push(fill.packing_density);
-                    if let Some(ref m) = fill.material_type {
-                        if !m.is_empty() && m != "?" {
-                            detected_materials.push(m.clone());
-                        }
-                    }
-                    if let Some(ref r) = fill.reasoning {
-                        last_reasoning = r.clone();
-                    }
-                    fill_runs.push(FillRunLog {
-                        raw_response: response,
-                        parsed: Some(fill),
-                    });
-                }
-                Err(_e) => {
-                    fill_runs.push(FillRunLog {
-                        raw_response: response,
-                        parsed: None,
-                    });
-                }
-            },
-            Err(_e) => {
-                fill_runs.push(FillRunLog {
-                    raw_response: String::new(),
-                    parsed: None,
-                });
+    /// Registered vehicle named by `vehicle`
+    pub fn registered_vehicle(&self) -> Option<&Vehicle> {
+        self.fleet.as_ref()?.lookup(self.vehicle.as_deref()?)
+    }
+
+    /// Truck spec of the registered vehicle, else of `truck_class` (site
+    /// entries first)
+    pub fn truck_spec(&self) -> Option<&TruckSpec> {
+        if let Some(vehicle) = self.registered_vehicle() {
+            return Some(&vehicle.spec);
+        }
+        match &self.site {
+            Some(site) => site.spec.truck_spec(&self.truck_class),
+            None => get_truck_spec(&self.truck_class),
+        }
+    }
+
+    /// Material entry by name, site entries first
+    pub fn material(&self, name: &str) -> Option<&MaterialEntry> {
+        match &self.site {
+            Some(site) => site.spec.material(name),
+            None => get_material(name),
+        }
+    }
+
+    /// True when the site (if any) accepts the material
+    pub fn allows_material(&self, name: &str) -> bool {
+        self.site.as_ref().is_none_or(|s| s.allows_material(name))
+    }
+
+    /// Canonical truck class, site classes included
+    fn truck_class_match(&self) -> TruckClassMatch {
+        if let Some(vehicle) = self.registered_vehicle() {
+            return TruckClassMatch::Known(vehicle.truck_class.clone());
+        }
+        match &self.site {
+            Some(site) if site.spec.truck_specs.contains_key(&self.truck_class) => {
+                TruckClassMatch::Known(self.truck_class.clone())
             }
+            _ => normalize_truck_class(&self.truck_class),
         }
     }
 
-    if fill_l_list.is_empty() {
-        return Err(PipelineError::NoValidFill);
+    /// Geometry runs per analysis (before landmark fallback or escalation)
+    pub fn geometry_run_count(&self) -> usize {
+        self.geometry_runs.unwrap_or(self.ensemble_count)
     }
 
-    let fill_l = average(&fill_l_list).clamp(ranges.fill_ratio_l.min, ranges.fill_ratio_l.max);
-    let fill_w = average(&fill_w_list).clamp(ranges.fill_ratio_w.min, ranges.fill_ratio_w.max);
-    let taper = average(&taper_list).clamp(ranges.taper_ratio.min, ranges.taper_ratio.max);
-    let packing = average(&packing_list).clamp(ranges.packing_density.min, ranges.packing_density.max);
+    /// Fill runs per analysis (before escalation)
+    pub fn fill_run_count(&self) -> usize {
+        self.fill_runs.unwrap_or(self.ensemble_count)
+    }
 
-    // ── Step 3: Calculate tonnage ──
+    /// (variant name, prompt) for the given geometry run index, with the
+    /// tarp instructions appended when `tarp` is set and the occlusion
+    /// question when `occlusion` is set
+    pub fn geometry_run_prompt(&self, run: usize) -> (&str, Cow<'_, str>) {
+        let (variant, prompt) =
+            pick_variant(&self.geometry_variants, run).unwrap_or(("default", self.geometry_prompt()));
+        let mut prompt = Cow::Borrowed(prompt);
+        if self.tarp.is_some() {
+            append_prompt(&mut prompt, TARP_GEOMETRY_PROMPT);
+        }
+        if self.occlusion.is_some() {
+            append_prompt(&mut prompt, OCCLUSION_PROMPT);
+        }
+        (variant, prompt)
+    }
 
-    // Use AI-detected material if available, otherwise fall back to config
-    let material_type = mode_string(&detected_materials)
-        .unwrap_or_else(|| config.material_type.clone());
+    /// Response text as it may be stored, after the configured redaction
+    fn redact(&self, text: String) -> String {
+        match &self.redaction {
+            Some(redactor) => redactor.redact(&text),
+            None => text,
+        }
+    }
 
-    let params = CoreParams {
-        height: height_m,
-        fill_ratio_l: fill_l,
-        fill_ratio_w: fill_w,
-        taper_ratio: taper,
-        packing_density: packing,
-        material_type,
-    };
+    /// Raw response as kept in a run log: redacted, then cut down to
+    /// `log_retention`
+    fn retain_response(&self, text: String) -> String {
+        self.log_retention.apply(self.redact(text))
+    }
 
-    let calc = calculate_tonnage(&params, Some(&config.truck_class));
+    /// Report a geometry run to `log_sink` and append it to `runs`
+    fn push_geometry_run(&self, runs: &mut Vec<GeometryRunLog>, log: GeometryRunLog) {
+        self.log_sink.push_geometry(runs, log, self.keep_run_logs);
+    }
 
-    Ok(BoxOverlayResult {
-        height_m: round3(height_m),
-        fill_ratio_l: round3(fill_l),
-        fill_ratio_w: round3(fill_w),
-        taper_ratio: round3(taper),
-        packing_density: round3(calc.effective_packing),
-        effective_packing: round3(calc.effective_packing),
-        volume: round4(calc.volume),
-        tonnage: round2(calc.tonnage),
-        density: calc.density,
-        material_type: params.material_type,
-        reasoning: last_reasoning,
-        geometry_runs,
-        fill_runs,
-    })
-}
+    /// Report a fill run to `log_sink` and append it to `runs`
+    fn push_fill_run(&self, runs: &mut Vec<FillRunLog>, log: FillRunLog) {
+        self.log_sink.push_fill(runs, log, self.keep_run_logs);
+    }
 
-// ─── Helpers ─────────────────────────────────────────────────────────
+    /// Backend options for the given run index of either stage
+    pub fn run_backend_options(&self, run: usize) -> BackendOptions {
+        let mut options = self.backend_options.clone();
+        if !self.temperature_schedule.is_empty() {
+            options.temperature = Some(self.temperature_schedule[run % self.temperature_schedule.len()]);
+        }
+        options
+    }
 
-fn median(arr: &[f64]) -> f64 {
-    let mut sorted = arr.to_vec();
-    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    sorted[sorted.len() / 2]
+    /// (variant name, prompt) for the given fill run index, with the material
+    /// hint appended when `material_hint` is set and the tarp note when
+    /// `tarp` is set
+    pub fn fill_run_prompt(&self, run: usize) -> (&str, Cow<'_, str>) {
+        let (variant, prompt) =
+            pick_variant(&self.fill_variants, run).unwrap_or(("default", self.fill_prompt()));
+        let mut prompt = Cow::Borrowed(prompt);
+        if let Some(material) = self.material(&self.material_type).filter(|_| self.material_hint) {
+            append_prompt(&mut prompt, &material.prompt_hint(&self.material_type));
+        }
+        if self.tarp.is_some() {
+            append_prompt(&mut prompt, TARP_FILL_PROMPT);
+        }
+        (variant, prompt)
+    }
 }
 
-fn average(arr: &[f64]) -> f64 {
-    arr.iter().sum::<f64>() / arr.len() as f64
+/// Invalid `BoxOverlayConfig` rejected by `BoxOverlayConfigBuilder::build`
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ConfigError {
+    ZeroEnsembleCount,
+    UnknownMaterial(String),
+    MaterialNotAllowed(String),
+    InvalidTarpDeflation(f64),
 }
 
-fn round2(v: f64) -> f64 {
-    (v * 100.0).round() / 100.0
+impl ConfigError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ZeroEnsembleCount => "ZERO_ENSEMBLE_COUNT",
+            Self::UnknownMaterial(_) => "UNKNOWN_MATERIAL",
+            Self::MaterialNotAllowed(_) => "MATERIAL_NOT_ALLOWED",
+            Self::InvalidTarpDeflation(_) => "INVALID_TARP_DEFLATION",
+        }
+    }
 }
 
-fn round3(v: f64) -> f64 {
-    (v * 1000.0).round() / 1000.0
+impl Localize for ConfigError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::ZeroEnsembleCount => i18n::ZERO_ENSEMBLE_COUNT.get(lang).to_string(),
+            Self::UnknownMaterial(material) => i18n::UNKNOWN_MATERIAL.format(lang, &[material]),
+            Self::MaterialNotAllowed(material) => i18n::MATERIAL_NOT_ALLOWED.format(lang, &[material]),
+            Self::InvalidTarpDeflation(deflation) => i18n::INVALID_TARP_DEFLATION.format(lang, &[deflation]),
+        }
+    }
 }
 
-fn round4(v: f64) -> f64 {
-    (v * 10000.0).round() / 10000.0
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
 }
 
-/// Get most common string from a list (mode). Returns None if empty.
-fn mode_string(values: &[String]) -> Option<String> {
-    if values.is_empty() {
-        return None;
+/// Builder for `BoxOverlayConfig`, starting from `BoxOverlayConfig::default()`
+#[derive(Debug, Clone, Default)]
+pub struct BoxOverlayConfigBuilder {
+    config: BoxOverlayConfig,
+}
+
+impl BoxOverlayConfig {
+    pub fn builder() -> BoxOverlayConfigBuilder {
+        BoxOverlayConfigBuilder::default()
     }
-    let mut counts = std::collections::HashMap::new();
-    for v in values {
-        *counts.entry(v.as_str()).or_insert(0usize) += 1;
+
+    /// Builder starting from this configuration, to derive variants of it
+    pub fn into_builder(self) -> BoxOverlayConfigBuilder {
+        BoxOverlayConfigBuilder { config: self }
     }
-    counts
-        .into_iter()
-        .max_by_key(|(_, c)| *c)
-        .map(|(v, _)| v.to_string())
 }
 
-// ─── Tests ───────────────────────────────────────────────────────────
+impl BoxOverlayConfigBuilder {
+    pub fn truck_class(mut self, truck_class: impl Into<String>) -> Self {
+        self.config.truck_class = truck_class.into();
+        self
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    pub fn material_type(mut self, material_type: impl Into<String>) -> Self {
+        self.config.material_type = material_type.into();
+        self
+    }
 
-    /// Mock AI backend that returns predefined responses
-    struct MockBackend {
-        geometry_responses: Vec<String>,
-        fill_responses: Vec<String>,
-        geo_call: std::cell::Cell<usize>,
-        fill_call: std::cell::Cell<usize>,
+    pub fn ensemble_count(mut self, count: usize) -> Self {
+        self.config.ensemble_count = count;
+        self
     }
 
-    impl MockBackend {
-        fn new(geo: Vec<&str>, fill: Vec<&str>) -> Self {
-            Self {
-                geometry_responses: geo.into_iter().map(String::from).collect(),
-                fill_responses: fill.into_iter().map(String::from).collect(),
-                geo_call: std::cell::Cell::new(0),
-                fill_call: std::cell::Cell::new(0),
-            }
-        }
+    pub fn geometry_runs(mut self, count: usize) -> Self {
+        self.config.geometry_runs = Some(count);
+        self
     }
 
-    impl AiBackend for MockBackend {
-        fn send_prompt(&self, prompt: &str, _images: &[Vec<u8>]) -> Result<String, PipelineError> {
-            // Distinguish geometry vs fill by checking prompt content
-            if prompt.contains("tailgateTopY") {
-                let idx = self.geo_call.get();
-                self.geo_call.set(idx + 1);
-                if idx < self.geometry_responses.len() {
-                    Ok(self.geometry_responses[idx].clone())
-                } else {
-                    // Cycle last response
-                    Ok(self.geometry_responses.last().unwrap().clone())
-                }
-            } else {
-                let idx = self.fill_call.get();
-                self.fill_call.set(idx + 1);
-                if idx < self.fill_responses.len() {
-                    Ok(self.fill_responses[idx].clone())
-                } else {
-                    Ok(self.fill_responses.last().unwrap().clone())
-                }
-            }
-        }
+    pub fn fill_runs(mut self, count: usize) -> Self {
+        self.config.fill_runs = Some(count);
+        self
     }
 
-    #[test]
-    fn test_full_pipeline_mock() {
-        let geo_json = r#"{"plateBox":[0.4,0.7,0.6,0.84],"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
-        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,"reasoning":"Well packed"}"#;
+    pub fn geometry_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.config.geometry_prompt = Some(prompt.into());
+        self
+    }
 
-        let backend = MockBackend::new(vec![geo_json, geo_json], vec![fill_json, fill_json]);
-        let config = BoxOverlayConfig {
-            truck_class: "4t".to_string(),
-            material_type: "As殻".to_string(),
-            ensemble_count: 2,
-        };
+    pub fn fill_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.config.fill_prompt = Some(prompt.into());
+        self
+    }
 
-        let result = analyze_box_overlay(&backend, &[vec![1, 2, 3]], &config).unwrap();
+    pub fn geometry_variant(mut self, variant: PromptVariant) -> Self {
+        self.config.geometry_variants.push(variant);
+        self
+    }
 
-        assert!(result.height_m > 0.0, "height should be > 0");
-        assert!(result.tonnage > 0.0, "tonnage should be > 0");
+    pub fn fill_variant(mut self, variant: PromptVariant) -> Self {
+        self.config.fill_variants.push(variant);
+        self
+    }
+
+    #[cfg(feature = "image")]
+    pub fn preprocess(mut self, preprocess: PreprocessConfig) -> Self {
+        self.config.preprocess = Some(preprocess);
+        self
+    }
+
+    pub fn quality_check(mut self, quality_check: QualityCheckConfig) -> Self {
+        self.config.quality_check = Some(quality_check);
+        self
+    }
+
+    pub fn disagreement(mut self, thresholds: DisagreementThresholds) -> Self {
+        self.config.disagreement = thresholds;
+        self
+    }
+
+    pub fn max_escalation_runs(mut self, runs: usize) -> Self {
+        self.config.max_escalation_runs = runs;
+        self
+    }
+
+    pub fn review(mut self, thresholds: ReviewThresholds) -> Self {
+        self.config.review = thresholds;
+        self
+    }
+
+    pub fn median(mut self, mode: MedianMode) -> Self {
+        self.config.median = mode;
+        self
+    }
+
+    pub fn strict_truck_class(mut self, strict: bool) -> Self {
+        self.config.strict_truck_class = strict;
+        self
+    }
+
+    pub fn timeout_per_call(mut self, timeout: Duration) -> Self {
+        self.config.timeout_per_call = Some(timeout);
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.config.deadline = Some(deadline);
+        self
+    }
+
+    pub fn geometry_mode(mut self, mode: GeometryMode) -> Self {
+        self.config.geometry_mode = mode;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.config.strategy = strategy;
+        self
+    }
+
+    pub fn occlusion(mut self, check: OcclusionCheck) -> Self {
+        self.config.occlusion = Some(check);
+        self
+    }
+
+    pub fn tarp(mut self, mode: TarpMode) -> Self {
+        self.config.tarp = Some(mode);
+        self
+    }
+
+    pub fn material_agreement(mut self, share: f64) -> Self {
+        self.config.material_agreement = share;
+        self
+    }
+
+    pub fn schedule(mut self, schedule: StageSchedule) -> Self {
+        self.config.schedule = schedule;
+        self
+    }
+
+    pub fn scale(mut self, scale: ScaleOptions) -> Self {
+        self.config.scale = scale;
+        self
+    }
+
+    pub fn fill_fallback(mut self, enabled: bool) -> Self {
+        self.config.fill_fallback = enabled;
+        self
+    }
+
+    pub fn material_hint(mut self, enabled: bool) -> Self {
+        self.config.material_hint = enabled;
+        self
+    }
+
+    pub fn backend_options(mut self, options: BackendOptions) -> Self {
+        self.config.backend_options = options;
+        self
+    }
+
+    pub fn temperature_schedule(mut self, temperatures: Vec<f64>) -> Self {
+        self.config.temperature_schedule = temperatures;
+        self
+    }
+
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.config.metrics = metrics;
+        self
+    }
+
+    pub fn log_sink(mut self, sink: RunLogs) -> Self {
+        self.config.log_sink = sink;
+        self
+    }
+
+    pub fn keep_run_logs(mut self, keep: bool) -> Self {
+        self.config.keep_run_logs = keep;
+        self
+    }
+
+    pub fn redaction(mut self, redactor: Redactor) -> Self {
+        self.config.redaction = Some(redactor);
+        self
+    }
+
+    pub fn log_retention(mut self, retention: LogRetention) -> Self {
+        self.config.log_retention = retention;
+        self
+    }
+
+    pub fn reasoning_summary(mut self, summary: ReasoningSummary) -> Self {
+        self.config.reasoning_summary = summary;
+        self
+    }
+
+    pub fn policy(mut self, policy: TolerancePolicy) -> Self {
+        self.config.policy = Some(policy);
+        self
+    }
+
+    /// Select a site; its default truck class and policy replace the current
+    /// ones (later setters still override them)
+    pub fn site(mut self, site: impl Into<Arc<SiteProfile>>) -> Self {
+        let site = site.into();
+        if let Some(truck_class) = site.default_truck_class() {
+            self.config.truck_class = truck_class.to_string();
+        }
+        if let Some(policy) = &site.policy {
+            self.config.policy = Some(policy.clone());
+        }
+        self.config.site = Some(site);
+        self
+    }
+
+    pub fn reporting(mut self, mode: ReportingMode) -> Self {
+        self.config.reporting = mode;
+        self
+    }
+
+    pub fn formula(mut self, formula: FormulaVersion) -> Self {
+        self.config.formula = formula;
+        self
+    }
+
+    pub fn invoicing(mut self, rounding: InvoiceRounding) -> Self {
+        self.config.invoicing = Some(rounding);
+        self
+    }
+
+    pub fn sites(mut self, sites: impl Into<Arc<SiteRegistry>>) -> Self {
+        self.config.sites = Some(sites.into());
+        self
+    }
+
+    pub fn fleet(mut self, fleet: impl Into<Arc<Fleet>>) -> Self {
+        self.config.fleet = Some(fleet.into());
+        self
+    }
+
+    pub fn vehicle(mut self, id_or_plate: impl Into<String>) -> Self {
+        self.config.vehicle = Some(id_or_plate.into());
+        self
+    }
+
+    pub fn plate_recognition(mut self, enabled: bool) -> Self {
+        self.config.plate_recognition = enabled;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: LoadMetadata) -> Self {
+        self.config.metadata = metadata;
+        self
+    }
+
+    pub fn debug_checks(mut self, enabled: bool) -> Self {
+        self.config.debug_checks = enabled;
+        self
+    }
+
+    /// Validate and return the config
+    pub fn build(self) -> Result<BoxOverlayConfig, ConfigError> {
+        let config = self.config;
+        if config.geometry_run_count() == 0 || config.fill_run_count() == 0 {
+            return Err(ConfigError::ZeroEnsembleCount);
+        }
+        if config.material(&config.material_type).is_none() {
+            return Err(ConfigError::UnknownMaterial(config.material_type));
+        }
+        if !config.allows_material(&config.material_type) {
+            return Err(ConfigError::MaterialNotAllowed(config.material_type));
+        }
+        if let Some(tarp) = config.tarp.filter(|t| !(t.deflation > 0.0 && t.deflation <= 1.0)) {
+            return Err(ConfigError::InvalidTarpDeflation(tarp.deflation));
+        }
+        Ok(config)
+    }
+}
+
+fn append_prompt(prompt: &mut Cow<'_, str>, text: &str) {
+    let prompt = prompt.to_mut();
+    prompt.push(' ');
+    prompt.push_str(text);
+}
+
+fn pick_variant(variants: &[PromptVariant], run: usize) -> Option<(&str, &str)> {
+    if variants.is_empty() {
+        return None;
+    }
+    let v = &variants[run % variants.len()];
+    Some((v.name.as_str(), v.prompt.as_str()))
+}
+
+/// Full result of a box-overlay analysis
+#[derive(Debug, Clone)]
+pub struct BoxOverlayResult {
+    pub truck_class: String,
+    pub height_m: f64,
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub taper_ratio: f64,
+    pub packing_density: f64,
+    pub effective_packing: f64,
+    pub volume: f64,
+    pub tonnage: f64,
+    /// Tonnage interval over the material's density range
+    pub tonnage_min: f64,
+    pub tonnage_max: f64,
+    pub density: f64,
+    pub material_type: String,
+    /// Reasonings of the fill runs merged or summarized (`ReasoningSummary`)
+    pub reasoning: String,
+    /// Reasoning of every fill run that gave one, in run order
+    pub reasonings: Vec<RunReasoning>,
+    pub geometry_runs: Vec<GeometryRunLog>,
+    pub fill_runs: Vec<FillRunLog>,
+    /// Hashes of the input images as supplied (before preprocessing)
+    pub image_hashes: Vec<ImageHash>,
+    /// Format, name, capture time and position of the input images as supplied
+    pub inputs: Vec<ImageInfo>,
+    /// Earliest capture time among the inputs (Unix seconds)
+    pub captured_at: Option<i64>,
+    /// Position of the first input that has one
+    pub location: Option<GpsPosition>,
+    /// Set when ensemble runs diverge beyond `BoxOverlayConfig::disagreement`
+    pub disagreement: Option<Disagreement>,
+    /// Set when the CV of the heights or fill ratios exceeds
+    /// `BoxOverlayConfig::review`; the operators' review queue picks these up
+    pub needs_review: bool,
+    /// Statistics that exceeded their review threshold
+    pub review_flags: Vec<ReviewFlag>,
+    pub warnings: Vec<AnalysisWarning>,
+    /// Per-run values and mean/median/stddev of every estimated parameter
+    pub statistics: RunStatistics,
+    /// Intermediate values of the tonnage formula
+    pub breakdown: TonnageBreakdown,
+    /// Estimated values that were clamped to their spec range
+    pub clamps: Vec<ClampRecord>,
+    /// Where the fill values came from
+    pub fill_source: FillSource,
+    /// Detected license plate location (None = no run reported a plate)
+    pub plate_region: Option<PlateRegion>,
+    /// Shapes of the representative geometry run for drawing over the photo
+    /// (None without a valid geometry run, e.g. a manually entered height)
+    pub overlay: Option<OverlayData>,
+    /// Classification by `BoxOverlayConfig::policy` (None = no policy set)
+    pub verdict: Option<Verdict>,
+    /// Site the analysis ran under, selected or located from the photos
+    pub site: Option<String>,
+    /// Quantity the result is reported in (`BoxOverlayConfig::reporting`)
+    pub reporting: ReportingMode,
+    /// Formula the tonnage was calculated with
+    pub formula: FormulaVersion,
+    /// Largest occluded ratio of the valid geometry runs (None = occlusion
+    /// not checked or not reported)
+    pub occluded_ratio: Option<f64>,
+    /// The load was analyzed as tarp-covered (`BoxOverlayConfig::tarp`)
+    pub covered: bool,
+    pub load_state: LoadState,
+    /// Material votes of the fill runs (`BoxOverlayConfig::material_agreement`)
+    pub material_votes: MaterialVotes,
+    /// In-situ volume (m³) from the material's swell factor (None = no factor)
+    pub bank_volume: Option<f64>,
+    /// `tonnage` rounded by `BoxOverlayConfig::invoicing` (None = not billed)
+    pub billed_tonnage: Option<f64>,
+    /// ID of the registered vehicle whose bed was used
+    pub vehicle_id: Option<String>,
+    /// Plate read by the plate stage, normalized (None = not run or unreadable)
+    pub plate_number: Option<String>,
+    /// Plate class the plate scale used: the configured one, else the one
+    /// most valid geometry runs detected (None = unknown)
+    pub plate_class: Option<String>,
+    /// Scale reference most valid coordinate runs used and their median
+    /// meters per normalized unit (None without one, e.g. landmark runs)
+    pub calibration: Option<ScaleCalibration>,
+    /// `BoxOverlayConfig::metadata` of the analysis
+    pub metadata: LoadMetadata,
+    /// Broken physical invariants (empty unless `BoxOverlayConfig::debug_checks`)
+    pub invariant_violations: Vec<InvariantViolation>,
+    /// Settings needed to re-run this analysis (`repro::reproduce`)
+    pub repro: ReproManifest,
+}
+
+/// License plate location for client-side masking of stored photos
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlateRegion {
+    /// [left, top, right, bottom], normalized 0-1; the union of the boxes
+    /// reported by the geometry runs, so masking covers every detection
+    pub normalized: [f64; 4],
+    /// Same box in pixels of the first input image as displayed
+    /// (None when the image size is unknown, e.g. without the `image` feature)
+    pub pixels: Option<[u32; 4]>,
+}
+
+impl PlateRegion {
+    fn from_runs(runs: &[GeometryRunLog], image_size: Option<(u32, u32)>) -> Option<Self> {
+        let normalized = runs
+            .iter()
+            .filter_map(|r| r.parsed.as_ref()?.plate_box)
+            .map(|b| b.map(|v| v.clamp(0.0, 1.0)))
+            .filter(|b| b[2] > b[0] && b[3] > b[1])
+            .reduce(|a, b| [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])])?;
+        Some(Self {
+            normalized,
+            pixels: image_size.map(|(w, h)| Self::box_to_pixels(&normalized, w, h)),
+        })
+    }
+
+    /// The box in pixels of an image of the given displayed size, rounded
+    /// outwards so the whole plate is covered
+    pub fn to_pixels(&self, width: u32, height: u32) -> [u32; 4] {
+        Self::box_to_pixels(&self.normalized, width, height)
+    }
+
+    fn box_to_pixels(b: &[f64; 4], width: u32, height: u32) -> [u32; 4] {
+        let (w, h) = (width as f64, height as f64);
+        [
+            (b[0] * w).floor() as u32,
+            (b[1] * h).floor() as u32,
+            ((b[2] * w).ceil() as u32).min(width),
+            ((b[3] * h).ceil() as u32).min(height),
+        ]
+    }
+}
+
+/// Result of the geometry stage alone
+#[derive(Debug, Clone)]
+pub struct GeometryStageResult {
+    /// Median of the valid run heights (unrounded)
+    pub height_m: f64,
+    /// Heights of the valid runs, in run order
+    pub heights: Vec<f64>,
+    /// Bed height used as the tailgate scale reference
+    pub bed_height: f64,
+    /// Plate class the plate scale used (`BoxOverlayResult::plate_class`)
+    pub plate_class: Option<String>,
+    /// Scale of the valid runs (`BoxOverlayResult::calibration`)
+    pub calibration: Option<ScaleCalibration>,
+    pub runs: Vec<GeometryRunLog>,
+    pub warnings: Vec<AnalysisWarning>,
+}
+
+/// Result of the fill stage alone
+#[derive(Debug, Clone)]
+pub struct FillStageResult {
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub taper_ratio: f64,
+    pub packing_density: f64,
+    /// Most frequently detected material (None = no run reported one)
+    pub material_type: Option<String>,
+    pub reasoning: String,
+    /// Reasoning of every valid run that gave one, in run order
+    pub reasonings: Vec<RunReasoning>,
+    /// Material votes of the valid runs (`material_type` is the winner)
+    pub material_votes: MaterialVotes,
+    /// Raw per-run values of the valid runs
+    pub samples: FillSamples,
+    /// Averaged values that were clamped to their spec range
+    pub clamps: Vec<ClampRecord>,
+    pub runs: Vec<FillRunLog>,
+    pub warnings: Vec<AnalysisWarning>,
+    pub source: FillSource,
+}
+
+/// Whether the bed carried a load
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LoadState {
+    #[default]
+    Loaded,
+    /// The median cargo top is at or below the bed floor
+    /// (`EMPTY_BED_HEIGHT_M`): 0 t, fill stage skipped, no review flags
+    Empty,
+}
+
+/// Highest median load height still treated as an empty bed (m); below it
+/// the heights are model noise around the floor, not cargo
+pub const EMPTY_BED_HEIGHT_M: f64 = 0.03;
+
+impl LoadState {
+    fn of(geometry: &GeometryStageResult) -> Self {
+        Self::of_height(geometry.height_m)
+    }
+
+    fn of_height(height_m: f64) -> Self {
+        if height_m <= EMPTY_BED_HEIGHT_M {
+            Self::Empty
+        } else {
+            Self::Loaded
+        }
+    }
+}
+
+/// Origin of the fill values in a result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillSource {
+    /// Averaged from the fill ensemble
+    #[default]
+    Estimated,
+    /// Every fill run failed; derived from the geometry stage (`fill_fallback`)
+    Fallback,
+    /// Entered by the user (`PartialBoxOverlay::complete`)
+    Manual,
+    /// Not estimated: the bed was empty (`LoadState::Empty`)
+    Skipped,
+}
+
+/// Non-fatal condition observed during analysis
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisWarning {
+    /// An averaged fill parameter was clamped to its spec range
+    FillClamped { field: String, value: f64, clamped: f64 },
+    /// Truck class missing from the spec; default bed dimensions were used
+    UnknownTruckClass { truck_class: String },
+    /// Some valid runs were scaled from the license plate instead of the tailgate
+    PlateScaleFallback { runs: usize },
+    /// Fill runs reported different materials
+    MaterialDisagreement { materials: Vec<String> },
+    /// Estimated volume exceeds the truck's heaped capacity
+    OverCapacity { volume: f64, heap_volume: f64 },
+    /// Tailgate and plate scales disagreed beyond tolerance in some runs
+    ScaleInconsistent { runs: usize },
+    /// Detected plate boxes with an implausible aspect ratio were ignored
+    PlateRejected { runs: usize },
+    /// Fill estimation failed; conservative geometry-derived values were used
+    FillFallback,
+    /// `BoxOverlayConfig::vehicle` is not in the fleet; the truck class was used
+    UnknownVehicle { vehicle: String },
+    /// The plate stage could not read the license plate
+    PlateUnread,
+    /// No coordinate run yielded a height; landmark runs were used instead
+    LandmarkFallback { runs: usize },
+    /// A model reply carried an extreme value that was clamped at parse time
+    ResponseValueClamped { field: String, value: f64, clamped: f64 },
+    /// Geometry runs disagreed; this many extra runs were issued
+    GeometryEscalated { runs: usize },
+    /// Fill runs disagreed; this many extra runs were issued
+    FillEscalated { runs: usize },
+    /// Geometry runs reporting more occlusion than `OcclusionCheck::reject_above`
+    OcclusionRejected { runs: usize },
+    /// Largest occluded ratio of the valid geometry runs; the tonnage range
+    /// was widened accordingly
+    Occluded { ratio: f64 },
+}
+
+impl Localize for AnalysisWarning {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::FillClamped { field, value, clamped } => {
+                i18n::WARN_FILL_CLAMPED.format(lang, &[field, &format!("{:.3}", value), &format!("{:.3}", clamped)])
+            }
+            Self::UnknownTruckClass { truck_class } => i18n::WARN_UNKNOWN_TRUCK_CLASS.format(lang, &[truck_class]),
+            Self::PlateScaleFallback { runs } => i18n::WARN_PLATE_SCALE_FALLBACK.format(lang, &[runs]),
+            Self::MaterialDisagreement { materials } => {
+                i18n::WARN_MATERIAL_DISAGREEMENT.format(lang, &[&materials.join(", ")])
+            }
+            Self::OverCapacity { volume, heap_volume } => {
+                i18n::WARN_OVER_CAPACITY.format(lang, &[&format!("{:.2}", volume), &format!("{:.2}", heap_volume)])
+            }
+            Self::ScaleInconsistent { runs } => i18n::WARN_SCALE_INCONSISTENT.format(lang, &[runs]),
+            Self::PlateRejected { runs } => i18n::WARN_PLATE_REJECTED.format(lang, &[runs]),
+            Self::FillFallback => i18n::WARN_FILL_FALLBACK.get(lang).to_string(),
+            Self::UnknownVehicle { vehicle } => i18n::WARN_UNKNOWN_VEHICLE.format(lang, &[vehicle]),
+            Self::PlateUnread => i18n::WARN_PLATE_UNREAD.get(lang).to_string(),
+            Self::LandmarkFallback { runs } => i18n::WARN_LANDMARK_FALLBACK.format(lang, &[runs]),
+            Self::ResponseValueClamped { field, value, clamped } => {
+                i18n::WARN_RESPONSE_VALUE_CLAMPED.format(lang, &[field, &format!("{:e}", value), clamped])
+            }
+            Self::GeometryEscalated { runs } => i18n::WARN_GEOMETRY_ESCALATED.format(lang, &[runs]),
+            Self::FillEscalated { runs } => i18n::WARN_FILL_ESCALATED.format(lang, &[runs]),
+            Self::OcclusionRejected { runs } => i18n::WARN_OCCLUSION_REJECTED.format(lang, &[runs]),
+            Self::Occluded { ratio } => i18n::WARN_OCCLUDED.format(lang, &[&format!("{:.0}", ratio * 100.0)]),
+        }
+    }
+}
+
+impl fmt::Display for AnalysisWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// A value moved into its spec range
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClampRecord {
+    /// Spec field name (`fillRatioL`, ...)
+    pub field: String,
+    /// Value before clamping
+    pub original: f64,
+    pub clamped: f64,
+    /// `clamped - original`
+    pub delta: f64,
+}
+
+impl ClampRecord {
+    /// Clamp `value` to `min..=max`, returning a record only when it moved
+    fn check(field: &str, value: f64, min: f64, max: f64) -> Option<Self> {
+        let clamped = value.clamp(min, max);
+        (clamped != value).then(|| Self {
+            field: field.to_string(),
+            original: value,
+            clamped,
+            delta: clamped - value,
+        })
+    }
+}
+
+/// Why a run yielded no usable value
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase", rename_all_fields = "camelCase")]
+#[non_exhaustive]
+pub enum RunFailure {
+    /// The backend call failed (`PipelineError::code`, redacted message)
+    Backend { code: String, message: String },
+    /// The call exceeded its time limit
+    Timeout { limit_ms: u64 },
+    /// The reply could not be parsed (`ParseError::code`)
+    Parse { code: String, message: String },
+    /// The reply parsed but gave no usable value
+    Rejected { reason: String },
+}
+
+impl RunFailure {
+    /// Stable machine-readable code
+    pub fn code(&self) -> &str {
+        match self {
+            Self::Backend { code, .. } | Self::Parse { code, .. } => code,
+            Self::Timeout { .. } => "CALL_TIMEOUT",
+            Self::Rejected { .. } => "RUN_REJECTED",
+        }
+    }
+
+    fn from_call(config: &BoxOverlayConfig, error: &PipelineError) -> Self {
+        match error {
+            PipelineError::CallTimeout(limit) => Self::Timeout {
+                limit_ms: limit.as_millis() as u64,
+            },
+            e => Self::Backend {
+                code: e.code().to_string(),
+                message: config.redact(e.to_string()),
+            },
+        }
+    }
+
+    fn from_parse(error: &ParseError) -> Self {
+        Self::Parse {
+            code: error.code().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl Localize for RunFailure {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Backend { message, .. } => i18n::RUN_BACKEND_FAILED.format(lang, &[message]),
+            Self::Timeout { limit_ms } => i18n::CALL_TIMEOUT.format(lang, &[limit_ms]),
+            Self::Parse { message, .. } => i18n::RUN_PARSE_FAILED.format(lang, &[message]),
+            Self::Rejected { reason } => i18n::RUN_REJECTED.format(lang, &[reason]),
+        }
+    }
+}
+
+impl fmt::Display for RunFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// Log of a single geometry detection run
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeometryRunLog {
+    /// Prompt variant that produced this run
+    pub variant: String,
+    /// Backend that answered this run
+    pub backend: String,
+    pub raw_response: String,
+    pub parsed: Option<GeometryResponse>,
+    /// Landmark answer of a landmark run (`scale_method` "landmark")
+    pub landmark: Option<Landmark>,
+    /// Scale reference of the height ("tailgate", "plate", "fused",
+    /// "landmark"; "none" when the run failed)
+    pub scale_method: String,
+    pub height_m: f64,
+    /// Meters per normalized image unit the height was scaled with (None =
+    /// landmark or failed run)
+    pub m_per_norm: Option<f64>,
+    /// |tailgate / plate - 1| when both scale references were detected
+    pub scale_disagreement: Option<f64>,
+    /// The detected plate box failed the aspect-ratio check and was ignored
+    pub plate_rejected: bool,
+    /// Generation parameters sent with this run
+    pub options: BackendOptions,
+    /// Why the run yielded no height (None = valid run)
+    pub failure: Option<RunFailure>,
+    /// Issued by ensemble escalation (`max_escalation_runs`)
+    pub escalated: bool,
+}
+
+/// Log of a single fill estimation run
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FillRunLog {
+    /// Prompt variant that produced this run
+    pub variant: String,
+    /// Backend that answered this run
+    pub backend: String,
+    pub raw_response: String,
+    pub parsed: Option<FillResponse>,
+    /// Parsed values outside the spec ranges (before averaging)
+    pub out_of_range: Vec<ClampRecord>,
+    /// Generation parameters sent with this run
+    pub options: BackendOptions,
+    /// Why the run yielded no values (None = valid run)
+    pub failure: Option<RunFailure>,
+    /// Issued by ensemble escalation (`max_escalation_runs`)
+    pub escalated: bool,
+}
+
+/// One backend call the pipeline would make
+#[derive(Debug, Clone)]
+pub struct PlannedCall {
+    pub stage: PromptStage,
+    /// Run index within the stage
+    pub run: usize,
+    pub variant: String,
+    pub prompt: String,
+    /// Generation parameters sent with the call
+    pub options: BackendOptions,
+    /// Estimated input tokens (prompt text + images)
+    pub estimated_tokens: usize,
+    /// Only made when no coordinate run yields a height
+    /// (`CoordinatesWithLandmarkFallback`)
+    pub fallback: bool,
+}
+
+/// Dry-run result: every call `analyze_box_overlay` would issue for a config
+#[derive(Debug, Clone)]
+pub struct CallPlan {
+    pub calls: Vec<PlannedCall>,
+    /// Box-overlay geometry and fill runs (0 for `Strategy::MultiParam`)
+    pub geometry_runs: usize,
+    pub fill_runs: usize,
+    /// Sum of `estimated_tokens` over all calls, fallback calls included
+    pub estimated_input_tokens: usize,
+}
+
+/// Approximate input tokens charged per image (Gemini fixed image cost)
+pub const IMAGE_TOKENS: usize = 258;
+
+// ─── Pipeline ────────────────────────────────────────────────────────
+
+/// Build the call plan for a config without contacting the backend.
+///
+/// Follows `config.strategy` as `analyze` does. Token counts are a rough
+/// estimate (~4 ASCII chars per token, one token per non-ASCII char)
+/// intended for cost previews, not billing.
+pub fn plan_box_overlay(config: &BoxOverlayConfig, image_count: usize) -> CallPlan {
+    let mut calls = Vec::new();
+
+    if config.quality_check.as_ref().is_some_and(|q| q.ai_check) {
+        calls.push(planned_call(config, PromptStage::Quality, 0, "default", &SPEC.quality_prompt, image_count));
+    }
+    if config.strategy == Strategy::MultiParam {
+        // The strategy's prompts depend on the config only
+        let cx = StrategyContext {
+            config,
+            images: &[],
+            image_hashes: Vec::new(),
+            inputs: Vec::new(),
+            image_size: None,
+        };
+        calls.extend(MultiParamStrategy.prompts(&cx).into_iter().map(|call| {
+            planned_call(config, call.stage, call.run, &call.variant, &call.prompt, image_count)
+        }));
+        return CallPlan {
+            estimated_input_tokens: calls.iter().map(|c| c.estimated_tokens).sum(),
+            geometry_runs: 0,
+            fill_runs: 0,
+            calls,
+        };
+    }
+    if config.reads_plate() {
+        calls.push(planned_call(config, PromptStage::Plate, 0, "default", &SPEC.plate_prompt, image_count));
+    }
+    let geometry = (0..config.geometry_run_count()).map(|run| {
+        if config.geometry_mode == GeometryMode::Landmark {
+            return planned_call(config, PromptStage::Landmark, run, "landmark", &SPEC.landmark_prompt, image_count);
+        }
+        let (variant, prompt) = config.geometry_run_prompt(run);
+        planned_call(config, PromptStage::Geometry, run, variant, &prompt, image_count)
+    });
+    let fill = (0..config.fill_run_count()).map(|run| {
+        let (variant, prompt) = config.fill_run_prompt(run);
+        planned_call(config, PromptStage::Fill, run, variant, &prompt, image_count)
+    });
+    let fallback_runs = match config.geometry_mode {
+        GeometryMode::CoordinatesWithLandmarkFallback => config.geometry_run_count(),
+        _ => 0,
+    };
+    let fallback = (0..fallback_runs).map(|run| PlannedCall {
+        fallback: true,
+        ..planned_call(config, PromptStage::Landmark, run, "landmark", &SPEC.landmark_prompt, image_count)
+    });
+    match config.schedule {
+        StageSchedule::Sequential => calls.extend(geometry.chain(fallback).chain(fill)),
+        StageSchedule::Interleaved => {
+            let (mut geometry, mut fill) = (geometry.fuse(), fill.fuse());
+            loop {
+                let pair = [geometry.next(), fill.next()];
+                if pair.iter().all(Option::is_none) {
+                    break;
+                }
+                calls.extend(pair.into_iter().flatten());
+            }
+            calls.extend(fallback);
+        }
+    }
+    // Only made when the runs' reasonings differ
+    if config.reasoning_summary == ReasoningSummary::Model && config.fill_run_count() > 1 {
+        calls.push(planned_call(config, PromptStage::Reasoning, 0, "default", &SPEC.reasoning_prompt, 0));
+    }
+
+    CallPlan {
+        estimated_input_tokens: calls.iter().map(|c| c.estimated_tokens).sum(),
+        geometry_runs: config.geometry_run_count(),
+        fill_runs: config.fill_run_count(),
+        calls,
+    }
+}
+
+fn planned_call(
+    config: &BoxOverlayConfig,
+    stage: PromptStage,
+    run: usize,
+    variant: &str,
+    prompt: &str,
+    image_count: usize,
+) -> PlannedCall {
+    PlannedCall {
+        stage,
+        run,
+        variant: variant.to_string(),
+        prompt: prompt.to_string(),
+        options: config.run_backend_options(run),
+        estimated_tokens: estimate_tokens(prompt) + image_count * IMAGE_TOKENS,
+        fallback: false,
+    }
+}
+
+fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(|c| c.is_ascii()).count();
+    let other = text.chars().count() - ascii;
+    ascii.div_ceil(4) + other
+}
+
+/// Run the full box-overlay analysis pipeline.
+///
+/// 0. Plate reading and fleet lookup (when `plate_recognition` is set)
+/// 1. Geometry detection (ensemble) -> median height
+/// 2. Fill estimation (ensemble) -> average fill ratios (clamped to SPEC ranges)
+/// 3. Tonnage calculation
+///
+/// Matches the logic in `boxOverlayService.ts::analyzeBoxOverlayEnsemble`.
+pub fn analyze_box_overlay(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<BoxOverlayResult, PipelineError> {
+    let inputs = input_infos(images);
+    let config = locate_site(config, &inputs);
+    let config = &*config;
+    check_truck_class(config)?;
+    let budget = CallBudget::start(config);
+    let image_hashes = hash_images(images);
+    let image_size = first_image_size(images);
+    let images = prepare_images(backend, images, config)?;
+    let (config, plate) = recognize_plate(backend, &images, config, &budget);
+    let config = &*config;
+
+    let (geometry, fill) = match config.schedule {
+        StageSchedule::Sequential => {
+            // ── Step 1: Geometry detection (ensemble, take median of height_m) ──
+
+            let geometry = run_geometry_stage(backend, &images, config, &budget)?;
+
+            // ── Step 2: Fill estimation (ensemble, average, clamp) ──
+
+            let fill_runs = match LoadState::of(&geometry) {
+                LoadState::Empty => Ok(Vec::new()),
+                LoadState::Loaded => run_fill_calls(backend, &images, config, &budget),
+            };
+            let fill_runs = match fill_runs {
+                Err(PipelineError::Timeout { elapsed, fill_runs, .. }) => {
+                    return Err(PipelineError::Timeout {
+                        elapsed,
+                        geometry_runs: geometry.runs,
+                        fill_runs,
+                    });
+                }
+                other => other?,
+            };
+            let fill = finish_fill(backend, config, &budget, fill_runs, Some(&geometry))?;
+            (geometry, fill)
+        }
+        StageSchedule::Interleaved => run_interleaved(backend, &images, config, &budget)?,
+    };
+
+    let mut result = finish_box_overlay(config, geometry, fill, image_hashes, inputs, image_size);
+    attach_plate(&mut result, plate);
+    Ok(result)
+}
+
+/// Run the pipeline with the geometry and fill stages on separate threads.
+///
+/// With `StageSchedule::Interleaved` the end-to-end latency becomes that of
+/// the slower stage instead of the sum of both; with `Sequential` this is the
+/// same as `analyze_box_overlay`. Results are identical either way. The fill
+/// runs start before the load height is known, so an empty bed still costs
+/// the whole fill stage (its runs are discarded).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn analyze_box_overlay_concurrent(
+    backend: &(dyn AiBackend + Sync),
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<BoxOverlayResult, PipelineError> {
+    if config.schedule == StageSchedule::Sequential {
+        return analyze_box_overlay(backend, images, config);
+    }
+
+    let inputs = input_infos(images);
+    let config = locate_site(config, &inputs);
+    let config = &*config;
+    check_truck_class(config)?;
+    let budget = CallBudget::start(config);
+    let image_hashes = hash_images(images);
+    let image_size = first_image_size(images);
+    let images = prepare_images(backend, images, config)?;
+    let (config, plate) = recognize_plate(backend, &images, config, &budget);
+    let config = &*config;
+
+    let (geometry, fill) = std::thread::scope(|s| {
+        let geometry = s.spawn(|| run_geometry_stage(backend, &images, config, &budget));
+        let fill = run_fill_calls(backend, &images, config, &budget);
+        let geometry = geometry.join().unwrap_or_else(|p| std::panic::resume_unwind(p));
+        (geometry, fill)
+    });
+
+    match (geometry, fill) {
+        (Ok(geometry), Ok(fill_runs)) => {
+            let fill = finish_fill(backend, config, &budget, fill_runs, Some(&geometry))?;
+            let mut result = finish_box_overlay(config, geometry, fill, image_hashes, inputs, image_size);
+            attach_plate(&mut result, plate);
+            Ok(result)
+        }
+        // Either stage ran out of time: report the runs both stages completed
+        (
+            Err(PipelineError::Timeout { elapsed, geometry_runs, .. }),
+            Err(PipelineError::Timeout { fill_runs, .. }),
+        ) => Err(PipelineError::Timeout { elapsed, geometry_runs, fill_runs }),
+        (Err(PipelineError::Timeout { elapsed, geometry_runs, .. }), Ok(fill_runs)) => {
+            Err(PipelineError::Timeout { elapsed, geometry_runs, fill_runs })
+        }
+        (Ok(geometry), Err(PipelineError::Timeout { elapsed, fill_runs, .. })) => {
+            Err(PipelineError::Timeout { elapsed, geometry_runs: geometry.runs, fill_runs })
+        }
+        (Err(e), _) | (_, Err(e)) => Err(e),
+    }
+}
+
+/// Analyze many loads on `workers` threads sharing one backend (0 = one per
+/// available core).
+///
+/// Each job is a full `analyze_box_overlay` of its images; results come back
+/// in job order. The backend must be `Send + Sync`, so the same value can
+/// also be moved into an `Arc` for a rayon or tokio pool.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn analyze_box_overlay_batch<J: AsRef<[InputImage]> + Sync>(
+    backend: &(dyn AiBackend + Send + Sync),
+    jobs: &[J],
+    config: &BoxOverlayConfig,
+    workers: usize,
+) -> Vec<Result<BoxOverlayResult, PipelineError>> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workers = match workers {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(jobs.len());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Result<BoxOverlayResult, PipelineError>)> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let job = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(images) = jobs.get(job) else {
+                            return done;
+                        };
+                        done.push((job, analyze_box_overlay(backend, images.as_ref(), config)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_else(|p| std::panic::resume_unwind(p)))
+            .collect()
+    });
+    results.sort_by_key(|(job, _)| *job);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// Configs, results and run logs cross threads in batch jobs
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BoxOverlayConfig>();
+    assert_send_sync::<BoxOverlayResult>();
+    assert_send_sync::<GeometryRunLog>();
+    assert_send_sync::<FillRunLog>();
+    assert_send_sync::<PipelineError>();
+    assert_send_sync::<InputImage>();
+    assert_send_sync::<CallPlan>();
+};
+
+/// Alternate geometry and fill calls on one thread (`StageSchedule::Interleaved`)
+fn run_interleaved(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+) -> Result<(GeometryStageResult, FillStageResult), PipelineError> {
+    let bed_height = bed_height_for(config);
+    let scale = scale_options(config, images);
+    let mut geometry_runs = Vec::new();
+    let mut fill_runs = Vec::new();
+
+    let (geometry_count, fill_count) = (config.geometry_run_count(), config.fill_run_count());
+    for i in 0..geometry_count.max(fill_count) {
+        let stages = [(PromptStage::Geometry, geometry_count), (PromptStage::Fill, fill_count)];
+        for stage in stages.into_iter().filter(|&(_, count)| i < count).map(|(stage, _)| stage) {
+            let limit = match budget.next_limit() {
+                Ok(limit) => limit,
+                Err(elapsed) => {
+                    return Err(PipelineError::Timeout {
+                        elapsed,
+                        geometry_runs,
+                        fill_runs,
+                    })
+                }
+            };
+            if stage == PromptStage::Geometry {
+                let log = geometry_run(backend, images, config, i, bed_height, &scale, limit);
+                config.push_geometry_run(&mut geometry_runs, log);
+            } else {
+                config.push_fill_run(&mut fill_runs, fill_run(backend, images, config, i, limit));
+            }
+        }
+    }
+    let escalated = landmark_fallback(backend, images, config, budget, &mut geometry_runs)
+        .and_then(|_| escalate_geometry(backend, images, config, budget, bed_height, &scale, &mut geometry_runs));
+    if let Err(elapsed) = escalated {
+        return Err(PipelineError::Timeout {
+            elapsed,
+            geometry_runs,
+            fill_runs,
+        });
+    }
+
+    let geometry = aggregate_geometry(config, bed_height, geometry_runs)?;
+    // The interleaved fill runs are already made; an empty bed only saves
+    // the escalation runs
+    if LoadState::of(&geometry) == LoadState::Loaded {
+        if let Err(elapsed) = escalate_fill(backend, images, config, budget, &mut fill_runs) {
+            return Err(PipelineError::Timeout {
+                elapsed,
+                geometry_runs: geometry.runs,
+                fill_runs,
+            });
+        }
+    }
+    let fill = finish_fill(backend, config, budget, fill_runs, Some(&geometry))?;
+    Ok((geometry, fill))
+}
+
+/// Run the pipeline with a precomputed (or manually corrected) height.
+///
+/// Skips the geometry stage entirely; only fill estimation and the tonnage
+/// calculation run. The height is clamped to the spec height range and
+/// `geometry_runs` is left empty. A height of an empty bed
+/// (`EMPTY_BED_HEIGHT_M` or less) skips the fill stage too.
+pub fn analyze_box_overlay_with_geometry(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    height_m: f64,
+) -> Result<BoxOverlayResult, PipelineError> {
+    let inputs = input_infos(images);
+    let config = locate_site(config, &inputs);
+    let config = &*config;
+    check_truck_class(config)?;
+    let budget = CallBudget::start(config);
+    let image_hashes = hash_images(images);
+    let image_size = first_image_size(images);
+    let images = prepare_images(backend, images, config)?;
+    let (config, plate) = recognize_plate(backend, &images, config, &budget);
+    let config = &*config;
+    let (geometry, height_clamp) = manual_geometry(config, height_m);
+    let fill_runs = match LoadState::of(&geometry) {
+        LoadState::Empty => Vec::new(),
+        LoadState::Loaded => run_fill_calls(backend, &images, config, &budget)?,
+    };
+    let mut fill = finish_fill(backend, config, &budget, fill_runs, Some(&geometry))?;
+    fill.clamps.extend(height_clamp);
+    let mut result = finish_box_overlay(config, geometry, fill, image_hashes, inputs, image_size);
+    attach_plate(&mut result, plate);
+    Ok(result)
+}
+
+/// Geometry stage result for a height supplied by the caller
+fn manual_geometry(config: &BoxOverlayConfig, height_m: f64) -> (GeometryStageResult, Option<ClampRecord>) {
+    let range = &SPEC.ranges.height;
+    let geometry = GeometryStageResult {
+        height_m: height_m.clamp(range.min, range.max),
+        heights: Vec::new(),
+        bed_height: bed_height_for(config),
+        plate_class: configured_plate_class(config),
+        calibration: None,
+        runs: Vec::new(),
+        warnings: truck_class_warnings(config),
+    };
+    (geometry, ClampRecord::check("height", height_m, range.min, range.max))
+}
+
+/// Plate stage: read the license plate and select the matching `fleet`
+/// vehicle for the rest of the analysis.
+///
+/// Returns the config to analyze with and the reading: None when the stage
+/// did not run, `Some(None)` when the plate could not be read. A failed call
+/// does not fail the analysis; the truck class is used instead.
+fn recognize_plate<'a>(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &'a BoxOverlayConfig,
+    budget: &CallBudget,
+) -> (Cow<'a, BoxOverlayConfig>, Option<Option<String>>) {
+    if !config.reads_plate() {
+        return (Cow::Borrowed(config), None);
+    }
+    // Out of time: leave the timeout to the ensemble stages
+    let Ok(limit) = budget.next_limit() else {
+        return (Cow::Borrowed(config), Some(None));
+    };
+    let options = config.run_backend_options(0);
+    let images = backend.capabilities().run_images(images, 0);
+    let (reply, _) =
+        call_backend(backend, &SPEC.plate_prompt, images, limit, &options, &config.metrics, PromptStage::Plate);
+    let plate = reply
+        .ok()
+        .and_then(|text| match parse_plate(&text) {
+            Ok(parsed) => parsed.plate_number,
+            Err(_) => {
+                config.metrics.increment(Counter::ParseFailures, Some(PromptStage::Plate), 1);
+                None
+            }
+        })
+        .map(|p| normalize_plate(&p))
+        .filter(|p| !p.is_empty());
+
+    (select_vehicle(config, plate.as_deref()), Some(plate))
+}
+
+/// Select the `fleet` vehicle registered under a read plate
+fn select_vehicle<'a>(config: &'a BoxOverlayConfig, plate: Option<&str>) -> Cow<'a, BoxOverlayConfig> {
+    match (plate, &config.fleet) {
+        (Some(p), Some(_)) => {
+            let mut config = config.clone();
+            config.vehicle = Some(p.to_string());
+            Cow::Owned(config)
+        }
+        _ => Cow::Borrowed(config),
+    }
+}
+
+/// Select the registered site whose fence contains the first input position,
+/// unless a site was chosen explicitly; the truck class is kept
+fn locate_site<'a>(config: &'a BoxOverlayConfig, inputs: &[ImageInfo]) -> Cow<'a, BoxOverlayConfig> {
+    let (None, Some(sites)) = (&config.site, &config.sites) else {
+        return Cow::Borrowed(config);
+    };
+    let Some(site) = inputs.iter().find_map(|i| i.gps).and_then(|p| sites.locate(&p)) else {
+        return Cow::Borrowed(config);
+    };
+    let mut config = config.clone();
+    if let Some(policy) = &site.policy {
+        config.policy = Some(policy.clone());
+    }
+    config.site = Some(Arc::clone(site));
+    Cow::Owned(config)
+}
+
+/// Record the plate stage outcome on the result
+fn attach_plate(result: &mut BoxOverlayResult, plate: Option<Option<String>>) {
+    match plate {
+        Some(Some(plate)) => result.plate_number = Some(plate),
+        Some(None) => result.warnings.push(AnalysisWarning::PlateUnread),
+        None => {}
+    }
+}
+
+// ─── Partial results ─────────────────────────────────────────────────
+
+/// Stage that produced no usable result, with its error and run logs
+#[derive(Debug)]
+pub enum MissingStage {
+    Geometry {
+        error: PipelineError,
+        runs: Vec<GeometryRunLog>,
+    },
+    Fill {
+        error: PipelineError,
+        runs: Vec<FillRunLog>,
+    },
+}
+
+/// Fill values entered by the user in place of a failed fill stage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ManualFill {
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub taper_ratio: f64,
+    pub packing_density: f64,
+}
+
+/// Outcome of `analyze_box_overlay_partial`: the stages that succeeded plus a
+/// `MissingStage` marker for each one that did not
+#[derive(Debug)]
+pub struct PartialBoxOverlay {
+    pub geometry: Option<GeometryStageResult>,
+    pub fill: Option<FillStageResult>,
+    pub missing: Vec<MissingStage>,
+    /// Hashes of the input images as supplied (before preprocessing)
+    pub image_hashes: Vec<ImageHash>,
+    /// Format, name and capture time of the input images
+    pub inputs: Vec<ImageInfo>,
+    /// Displayed size of the first input image (None = unknown)
+    pub image_size: Option<(u32, u32)>,
+    /// Plate stage outcome: None when the stage did not run, `Some(None)`
+    /// when the plate could not be read
+    pub plate: Option<Option<String>>,
+}
+
+impl PartialBoxOverlay {
+    /// True when both stages succeeded
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Finish the calculation, filling the missing stages with user input.
+    ///
+    /// `height_m` and `fill` are only used for stages that are missing; when a
+    /// missing stage has no replacement its original error is returned.
+    /// Manual values are clamped to the spec ranges like AI estimates.
+    pub fn complete(
+        self,
+        config: &BoxOverlayConfig,
+        height_m: Option<f64>,
+        fill: Option<ManualFill>,
+    ) -> Result<BoxOverlayResult, PipelineError> {
+        let mut geometry_error = None;
+        let mut fill_error = None;
+        let mut fill_runs = Vec::new();
+        for stage in self.missing {
+            match stage {
+                MissingStage::Geometry { error, .. } => geometry_error = Some(error),
+                MissingStage::Fill { error, runs } => {
+                    fill_error = Some(error);
+                    fill_runs = runs;
+                }
+            }
+        }
+
+        let mut clamps = Vec::new();
+        let geometry = match (self.geometry, height_m, geometry_error) {
+            (Some(geometry), _, _) => geometry,
+            (None, Some(height_m), _) => {
+                let (geometry, clamp) = manual_geometry(config, height_m);
+                clamps.extend(clamp);
+                geometry
+            }
+            (None, None, error) => return Err(error.unwrap_or(PipelineError::NoValidGeometry)),
+        };
+        let mut fill = match (self.fill, fill, fill_error) {
+            (Some(fill), _, _) => fill,
+            (None, Some(manual), _) => manual_fill(manual, fill_runs),
+            (None, None, error) => return Err(error.unwrap_or(PipelineError::NoValidFill)),
+        };
+        fill.clamps.extend(clamps);
+        let config = locate_site(config, &self.inputs);
+        let config = select_vehicle(&config, self.plate.as_ref().and_then(Option::as_deref));
+        let mut result = finish_box_overlay(&config, geometry, fill, self.image_hashes, self.inputs, self.image_size);
+        attach_plate(&mut result, self.plate);
+        Ok(result)
+    }
+}
+
+/// Run both stages and keep whatever succeeded instead of failing outright.
+///
+/// Stage failures (including a passed deadline) become `MissingStage` markers
+/// so the UI can ask the user for the missing values and call
+/// `PartialBoxOverlay::complete`. Errors before the stages run (unknown truck
+/// class in strict mode, unusable images) are still returned as `Err`.
+/// Stages always run sequentially, after the plate stage when
+/// `plate_recognition` is set; an empty bed skips the fill stage.
+pub fn analyze_box_overlay_partial(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<PartialBoxOverlay, PipelineError> {
+    let inputs = input_infos(images);
+    let config = locate_site(config, &inputs);
+    let config = &*config;
+    check_truck_class(config)?;
+    let budget = CallBudget::start(config);
+    let image_hashes = hash_images(images);
+    let image_size = first_image_size(images);
+    let images = prepare_images(backend, images, config)?;
+    let (config, plate) = recognize_plate(backend, &images, config, &budget);
+    let config = &*config;
+    let mut missing = Vec::new();
+
+    let geometry = match run_geometry_calls(backend, &images, config, &budget) {
+        Ok(geometry_runs) => match aggregate_geometry(config, bed_height_for(config), geometry_runs.clone()) {
+            Ok(geometry) => Some(geometry),
+            Err(error) => {
+                missing.push(MissingStage::Geometry { error, runs: geometry_runs });
+                None
+            }
+        },
+        Err(PipelineError::Timeout { elapsed, geometry_runs, .. }) => {
+            missing.push(MissingStage::Geometry {
+                error: PipelineError::Timeout {
+                    elapsed,
+                    geometry_runs: Vec::new(),
+                    fill_runs: Vec::new(),
+                },
+                runs: geometry_runs,
+            });
+            None
+        }
+        Err(error) => {
+            missing.push(MissingStage::Geometry { error, runs: Vec::new() });
+            None
+        }
+    };
+
+    let fill_calls = match geometry.as_ref().map(LoadState::of) {
+        Some(LoadState::Empty) => Ok(Vec::new()),
+        _ => run_fill_calls(backend, &images, config, &budget),
+    };
+    let fill = match fill_calls {
+        Ok(fill_runs) => {
+            let finished = finish_fill(backend, config, &budget, fill_runs.clone(), geometry.as_ref());
+            match finished {
+                Ok(fill) => Some(fill),
+                Err(error) => {
+                    missing.push(MissingStage::Fill { error, runs: fill_runs });
+                    None
+                }
+            }
+        }
+        Err(PipelineError::Timeout { elapsed, fill_runs, .. }) => {
+            missing.push(MissingStage::Fill {
+                error: PipelineError::Timeout {
+                    elapsed,
+                    geometry_runs: Vec::new(),
+                    fill_runs: Vec::new(),
+                },
+                runs: fill_runs,
+            });
+            None
+        }
+        Err(error) => {
+            missing.push(MissingStage::Fill { error, runs: Vec::new() });
+            None
+        }
+    };
+
+    Ok(PartialBoxOverlay {
+        geometry,
+        fill,
+        missing,
+        image_hashes,
+        inputs,
+        image_size,
+        plate,
+    })
+}
+
+/// Fill stage result for values entered by the user
+fn manual_fill(manual: ManualFill, fill_runs: Vec<FillRunLog>) -> FillStageResult {
+    let ranges = &SPEC.ranges;
+    let mut clamps = Vec::new();
+    let mut clamp = |field: &str, value: f64, range: &Range| {
+        clamps.extend(ClampRecord::check(field, value, range.min, range.max));
+        value.clamp(range.min, range.max)
+    };
+    let fill_ratio_l = clamp("fillRatioL", manual.fill_ratio_l, &ranges.fill_ratio_l);
+    let fill_ratio_w = clamp("fillRatioW", manual.fill_ratio_w, &ranges.fill_ratio_w);
+    let taper_ratio = clamp("taperRatio", manual.taper_ratio, &ranges.taper_ratio);
+    let packing_density = clamp("packingDensity", manual.packing_density, &ranges.packing_density);
+
+    FillStageResult {
+        fill_ratio_l,
+        fill_ratio_w,
+        taper_ratio,
+        packing_density,
+        material_type: None,
+        reasoning: String::new(),
+        reasonings: Vec::new(),
+        material_votes: MaterialVotes::default(),
+        samples: FillSamples::default(),
+        clamps,
+        runs: fill_runs,
+        warnings: Vec::new(),
+        source: FillSource::Manual,
+    }
+}
+
+/// Unrounded tonnage rounded by `BoxOverlayConfig::invoicing`; rounding
+/// the reported two-decimal value first would shift results for other units
+fn billed_tonnage(config: &BoxOverlayConfig, calc: &TonnageResult) -> Option<f64> {
+    config.invoicing.map(|r| r.round(calc.raw_tonnage))
+}
+
+/// Step 3: calculate tonnage from stage outputs and assemble the result
+fn finish_box_overlay(
+    config: &BoxOverlayConfig,
+    geometry: GeometryStageResult,
+    fill: FillStageResult,
+    image_hashes: Vec<ImageHash>,
+    inputs: Vec<ImageInfo>,
+    image_size: Option<(u32, u32)>,
+) -> BoxOverlayResult {
+    let height_m = geometry.height_m;
+    let load_state = LoadState::of(&geometry);
+    let fill = match (load_state, fill.source) {
+        (LoadState::Empty, FillSource::Estimated | FillSource::Fallback) => empty_fill(config, fill.runs),
+        _ => fill,
+    };
+    let disagreement = detect_disagreement(&geometry.heights, &fill.samples, &config.disagreement);
+    let statistics = RunStatistics::with_median(&geometry.heights, &fill.samples, config.median);
+    let review_flags = review_flags(&statistics, &config.review);
+    // Near-zero heights make any spread look huge; an empty bed is certain
+    let (disagreement, review_flags) = match load_state {
+        LoadState::Empty => (None, Vec::new()),
+        LoadState::Loaded => (disagreement, review_flags),
+    };
+    let plate_region = PlateRegion::from_runs(&geometry.runs, image_size);
+    let overlay = representative_overlay(config, &geometry.runs, geometry.height_m);
+
+    // Use AI-detected material if the runs agree enough, it is accepted at
+    // the site and not hidden under a tarp, otherwise fall back to config
+    let agreed = fill.material_votes.agreement() >= config.material_agreement;
+    let material_type = fill
+        .material_type
+        .filter(|m| agreed && config.tarp.is_none() && config.allows_material(m))
+        .unwrap_or_else(|| config.material_type.clone());
+
+    let params = CoreParams {
+        height: height_m,
+        fill_ratio_l: fill.fill_ratio_l,
+        fill_ratio_w: fill.fill_ratio_w,
+        taper_ratio: fill.taper_ratio,
+        packing_density: fill.packing_density,
+        material_type,
+        formula: config.formula,
+    };
+
+    let bed = bed_for(config);
+    let material = config.material(&params.material_type);
+    let (density, density_range) = density_for(config, &params.material_type);
+    let calc = calculate_tonnage_with(&params, bed, density, &density_range);
+    let invariant_violations = if config.debug_checks {
+        debug_checks(&params, &calc)
+    } else {
+        Vec::new()
+    };
+
+    let clamps = fill.clamps;
+    let mut warnings = geometry.warnings;
+    warnings.extend(fill.warnings);
+    // Report the canonical class when the input was an alias
+    let (TruckClassMatch::Known(truck_class) | TruckClassMatch::Unrecognized(truck_class)) =
+        config.truck_class_match();
+    // Widen the range by the worst occlusion the valid runs still reported
+    let occluded = config.occlusion.and_then(|check| {
+        let valid = geometry.runs.iter().filter(|r| is_valid_geometry_run(r));
+        valid.filter_map(occluded_ratio).reduce(f64::max).map(|ratio| (ratio, check))
+    });
+    let (mut tonnage_min, mut tonnage_max) = (calc.tonnage_min, calc.tonnage_max);
+    if let Some((ratio, check)) = occluded.filter(|(ratio, _)| *ratio > 0.0) {
+        tonnage_min = round2(tonnage_min * (1.0 - ratio * check.widening).max(0.0));
+        tonnage_max = round2(tonnage_max * (1.0 + ratio * check.widening));
+        warnings.push(AnalysisWarning::Occluded { ratio });
+    }
+    let truck = config.truck_spec();
+    if let Some(truck) = truck {
+        if calc.volume > truck.heap_volume {
+            warnings.push(AnalysisWarning::OverCapacity {
+                volume: calc.volume,
+                heap_volume: truck.heap_volume,
+            });
+        }
+    }
+
+    config.metrics.increment(Counter::Analyses, None, 1);
+    config.metrics.increment(Counter::Clamps, None, clamps.len() as u64);
+    config.metrics.observe(Histogram::Tonnage, None, calc.tonnage);
+
+    let mut result = BoxOverlayResult {
+        truck_class,
+        height_m: round3(height_m),
+        fill_ratio_l: round3(params.fill_ratio_l),
+        fill_ratio_w: round3(params.fill_ratio_w),
+        taper_ratio: round3(params.taper_ratio),
+        packing_density: round3(calc.effective_packing),
+        effective_packing: round3(calc.effective_packing),
+        volume: round4(calc.volume),
+        tonnage: round2(calc.tonnage),
+        tonnage_min,
+        tonnage_max,
+        density: calc.density,
+        material_type: params.material_type,
+        reasoning: fill.reasoning,
+        reasonings: fill.reasonings,
+        geometry_runs: if config.keep_run_logs { geometry.runs } else { Vec::new() },
+        fill_runs: if config.keep_run_logs { fill.runs } else { Vec::new() },
+        image_hashes,
+        captured_at: inputs.iter().filter_map(|i| i.captured_at).min(),
+        location: inputs.iter().find_map(|i| i.gps),
+        inputs,
+        disagreement,
+        needs_review: !review_flags.is_empty(),
+        review_flags,
+        warnings,
+        statistics,
+        breakdown: calc.breakdown,
+        clamps,
+        fill_source: fill.source,
+        plate_region,
+        overlay,
+        verdict: None,
+        site: config.site.as_ref().map(|s| s.name.clone()),
+        reporting: config.reporting,
+        formula: config.formula,
+        occluded_ratio: occluded.map(|(ratio, _)| ratio),
+        covered: config.tarp.is_some(),
+        load_state,
+        material_votes: fill.material_votes,
+        bank_volume: material
+            .and_then(|m| m.swell_factor)
+            .filter(|l| *l > 0.0)
+            .map(|l| round4(calc.volume / l)),
+        billed_tonnage: billed_tonnage(config, &calc),
+        vehicle_id: config.registered_vehicle().map(|v| v.id.clone()),
+        plate_number: None,
+        plate_class: geometry.plate_class,
+        calibration: geometry.calibration,
+        metadata: config.metadata.clone(),
+        invariant_violations,
+        repro: ReproManifest::capture(config),
+    };
+    result.verdict = config.policy.as_ref().map(|p| p.classify_for(&result, truck));
+    result
+}
+
+/// Overlay of the valid run closest to the median height
+fn representative_overlay(config: &BoxOverlayConfig, runs: &[GeometryRunLog], height_m: f64) -> Option<OverlayData> {
+    runs.iter()
+        .filter(|r| is_valid_geometry_run(r))
+        .min_by(|a, b| {
+            let da = (a.height_m - height_m).abs();
+            let db = (b.height_m - height_m).abs();
+            da.partial_cmp(&db).unwrap()
+        })
+        .and_then(|r| r.parsed.as_ref())
+        .map(|geo| OverlayData::from_geometry(geo, round3(height_m), &config.truck_class))
+}
+
+// ─── Multi-frame input ───────────────────────────────────────────────
+
+/// Geometry outcome of one frame of a multi-frame analysis
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameHeight {
+    /// Index into the supplied frames
+    pub index: usize,
+    /// Median of the frame's valid run heights (None = no valid run)
+    pub height_m: Option<f64>,
+    /// Heights of the frame's valid runs, in run order
+    pub heights: Vec<f64>,
+}
+
+/// Result of `analyze_box_overlay_frames`
+#[derive(Debug, Clone)]
+pub struct MultiFrameResult {
+    /// Combined result; the height is the median over every valid run of
+    /// every frame. `overlay` and `plate_region` refer to `best_frame`.
+    pub result: BoxOverlayResult,
+    pub frames: Vec<FrameHeight>,
+    /// Frame closest to the combined height; fill estimation ran on it
+    pub best_frame: usize,
+}
+
+/// Run the pipeline on frames sampled from a short clip of the truck.
+///
+/// The geometry ensemble runs on each frame separately and the height is the
+/// median over all frames and runs, so a frame that caught the load at a bad
+/// angle does not decide the result. Fill estimation runs once, on the frame
+/// whose own median is closest to the combined height, unless the bed is
+/// empty. Stages run sequentially;
+/// the plate stage, when `plate_recognition` is set, reads all frames at once.
+pub fn analyze_box_overlay_frames(
+    backend: &dyn AiBackend,
+    frames: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<MultiFrameResult, PipelineError> {
+    let inputs = input_infos(frames);
+    let config = locate_site(config, &inputs);
+    let config = &*config;
+    check_truck_class(config)?;
+    let budget = CallBudget::start(config);
+    let image_hashes = hash_images(frames);
+    let frame_sizes: Vec<_> = frames.iter().map(|f| first_image_size(std::slice::from_ref(f))).collect();
+    let frames = prepare_images(backend, frames, config)?;
+    let (config, plate) = recognize_plate(backend, &frames, config, &budget);
+    let config = &*config;
+
+    let mut geometry_runs = Vec::new();
+    let mut frame_heights = Vec::new();
+    let mut frame_runs = Vec::new();
+    for (index, frame) in frames.iter().enumerate() {
+        let runs = match run_geometry_calls(backend, std::slice::from_ref(frame), config, &budget) {
+            Err(PipelineError::Timeout { elapsed, geometry_runs: partial, .. }) => {
+                geometry_runs.extend(partial);
+                return Err(PipelineError::Timeout {
+                    elapsed,
+                    geometry_runs,
+                    fill_runs: Vec::new(),
+                });
+            }
+            other => other?,
+        };
+        let heights: Vec<f64> = runs.iter().filter(|r| is_valid_geometry_run(r)).map(|r| r.height_m).collect();
+        frame_heights.push(FrameHeight {
+            index,
+            height_m: (!heights.is_empty()).then(|| config.median.median(&heights)),
+            heights,
+        });
+        frame_runs.push(runs.len());
+        geometry_runs.extend(runs);
+    }
+
+    let geometry = aggregate_geometry(config, bed_height_for(config), geometry_runs)?;
+    let best_frame = frame_heights
+        .iter()
+        .filter_map(|f| Some((f.index, (f.height_m? - geometry.height_m).abs())))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+
+    let fill_calls = match LoadState::of(&geometry) {
+        LoadState::Empty => Ok(Vec::new()),
+        LoadState::Loaded => run_fill_calls(backend, std::slice::from_ref(&frames[best_frame]), config, &budget),
+    };
+    let fill_runs = match fill_calls {
+        Err(PipelineError::Timeout { elapsed, fill_runs, .. }) => {
+            return Err(PipelineError::Timeout {
+                elapsed,
+                geometry_runs: geometry.runs,
+                fill_runs,
+            });
+        }
+        other => other?,
+    };
+    let fill = finish_fill(backend, config, &budget, fill_runs, Some(&geometry))?;
+
+    let start: usize = frame_runs[..best_frame].iter().sum();
+    let best_runs = geometry.runs[start..start + frame_runs[best_frame]].to_vec();
+    let height_m = geometry.height_m;
+    let mut result = finish_box_overlay(config, geometry, fill, image_hashes, inputs, None);
+    result.plate_region = PlateRegion::from_runs(&best_runs, frame_sizes[best_frame]);
+    result.overlay = representative_overlay(config, &best_runs, height_m);
+    attach_plate(&mut result, plate);
+
+    Ok(MultiFrameResult {
+        result,
+        frames: frame_heights,
+        best_frame,
+    })
+}
+
+/// Run only the geometry detection stage (ensemble, median height).
+///
+/// Used by the full pipeline and by screens that only need the load height.
+pub fn analyze_geometry(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<GeometryStageResult, PipelineError> {
+    check_truck_class(config)?;
+    let budget = CallBudget::start(config);
+    let images = prepare_images(backend, images, config)?;
+    run_geometry_stage(backend, &images, config, &budget)
+}
+
+fn run_geometry_stage(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+) -> Result<GeometryStageResult, PipelineError> {
+    let geometry_runs = run_geometry_calls(backend, images, config, budget)?;
+    aggregate_geometry(config, bed_height_for(config), geometry_runs)
+}
+
+/// Run the geometry ensemble; only a passed deadline is an error here
+fn run_geometry_calls(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+) -> Result<Vec<GeometryRunLog>, PipelineError> {
+    let bed_height = bed_height_for(config);
+    let scale = scale_options(config, images);
+    let mut geometry_runs = Vec::new();
+
+    for i in 0..config.geometry_run_count() {
+        let limit = match budget.next_limit() {
+            Ok(limit) => limit,
+            Err(elapsed) => {
+                return Err(PipelineError::Timeout {
+                    elapsed,
+                    geometry_runs,
+                    fill_runs: Vec::new(),
+                })
+            }
+        };
+        let log = geometry_run(backend, images, config, i, bed_height, &scale, limit);
+        config.push_geometry_run(&mut geometry_runs, log);
+    }
+    let escalated = landmark_fallback(backend, images, config, budget, &mut geometry_runs)
+        .and_then(|_| escalate_geometry(backend, images, config, budget, bed_height, &scale, &mut geometry_runs));
+    if let Err(elapsed) = escalated {
+        return Err(PipelineError::Timeout {
+            elapsed,
+            geometry_runs,
+            fill_runs: Vec::new(),
+        });
+    }
+
+    Ok(geometry_runs)
+}
+
+/// Issue up to `max_escalation_runs` more geometry runs, one at a time, while
+/// the valid heights disagree; `Err(elapsed)` past the deadline
+fn escalate_geometry(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+    bed_height: f64,
+    scale: &ScaleOptions,
+    geometry_runs: &mut Vec<GeometryRunLog>,
+) -> Result<(), Duration> {
+    for _ in 0..config.max_escalation_runs {
+        let heights: Vec<f64> =
+            geometry_runs.iter().filter(|r| is_valid_geometry_run(r)).map(|r| r.height_m).collect();
+        if detect_disagreement(&heights, &FillSamples::default(), &config.disagreement).is_none() {
+            break;
+        }
+        let limit = budget.next_limit()?;
+        let mut log = geometry_run(backend, images, config, geometry_runs.len(), bed_height, scale, limit);
+        log.escalated = true;
+        config.push_geometry_run(geometry_runs, log);
+    }
+    Ok(())
+}
+
+/// Issue up to `max_escalation_runs` more fill runs, one at a time, while the
+/// fill values disagree; `Err(elapsed)` past the deadline
+fn escalate_fill(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+    fill_runs: &mut Vec<FillRunLog>,
+) -> Result<(), Duration> {
+    for _ in 0..config.max_escalation_runs {
+        if detect_disagreement(&[], &fill_samples(fill_runs), &config.disagreement).is_none() {
+            break;
+        }
+        let limit = budget.next_limit()?;
+        let mut log = fill_run(backend, images, config, fill_runs.len(), limit);
+        log.escalated = true;
+        config.push_fill_run(fill_runs, log);
+    }
+    Ok(())
+}
+
+/// Append a landmark ensemble when `CoordinatesWithLandmarkFallback` is set
+/// and no coordinate run yielded a height; `Err(elapsed)` past the deadline
+fn landmark_fallback(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+    geometry_runs: &mut Vec<GeometryRunLog>,
+) -> Result<(), Duration> {
+    if config.geometry_mode != GeometryMode::CoordinatesWithLandmarkFallback
+        || geometry_runs.iter().any(is_valid_geometry_run)
+    {
+        return Ok(());
+    }
+    for i in 0..config.geometry_run_count() {
+        let limit = budget.next_limit()?;
+        config.push_geometry_run(geometry_runs, landmark_run(backend, images, config, i, limit));
+    }
+    Ok(())
+}
+
+/// Issue one landmark call and map the answer to its calibration height
+fn landmark_run(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    run: usize,
+    limit: Option<Duration>,
+) -> GeometryRunLog {
+    let options = config.run_backend_options(run);
+    let (reply, used_backend) = call_backend(
+        backend,
+        &SPEC.landmark_prompt,
+        backend.capabilities().run_images(images, run),
+        limit,
+        &options,
+        &config.metrics,
+        PromptStage::Landmark,
+    );
+    landmark_run_log(config, used_backend, options, reply)
+}
+
+/// Map a landmark reply to its calibration height
+fn landmark_run_log(
+    config: &BoxOverlayConfig,
+    used_backend: String,
+    options: BackendOptions,
+    reply: Result<String, PipelineError>,
+) -> GeometryRunLog {
+    let mut log = GeometryRunLog {
+        variant: "landmark".to_string(),
+        backend: used_backend,
+        raw_response: String::new(),
+        parsed: None,
+        landmark: None,
+        scale_method: "none".into(),
+        height_m: 0.0,
+        m_per_norm: None,
+        scale_disagreement: None,
+        plate_rejected: false,
+        options,
+        failure: None,
+        escalated: false,
+    };
+
+    let response = match reply {
+        Ok(response) => response,
+        Err(e) => {
+            log.failure = Some(RunFailure::from_call(config, &e));
+            return log;
+        }
+    };
+    let parsed = parse_landmark(&response);
+    log.raw_response = config.retain_response(response);
+    match parsed {
+        Ok(answer) => {
+            log.scale_method = "landmark".into();
+            log.height_m = landmark_height(answer.landmark);
+            log.landmark = Some(answer.landmark);
+        }
+        Err(e) => {
+            config.metrics.increment(Counter::ParseFailures, Some(PromptStage::Landmark), 1);
+            log.failure = Some(RunFailure::from_parse(&e));
+        }
+    }
+    log
+}
+
+/// Issue one geometry call and derive the run height
+fn geometry_run(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    run: usize,
+    bed_height: f64,
+    scale: &ScaleOptions,
+    limit: Option<Duration>,
+) -> GeometryRunLog {
+    if config.geometry_mode == GeometryMode::Landmark {
+        return landmark_run(backend, images, config, run, limit);
+    }
+    let (variant, prompt) = config.geometry_run_prompt(run);
+    let options = config.run_backend_options(run);
+    let images = backend.capabilities().run_images(images, run);
+    let (reply, used_backend) =
+        call_backend(backend, &prompt, images, limit, &options, &config.metrics, PromptStage::Geometry);
+    geometry_run_log(config, variant, used_backend, options, reply, bed_height, scale)
+}
+
+/// Parse a geometry reply and derive the run height
+fn geometry_run_log(
+    config: &BoxOverlayConfig,
+    variant: &str,
+    used_backend: String,
+    options: BackendOptions,
+    reply: Result<String, PipelineError>,
+    bed_height: f64,
+    scale: &ScaleOptions,
+) -> GeometryRunLog {
+    let mut log = GeometryRunLog {
+        variant: variant.to_string(),
+        backend: used_backend,
+        raw_response: String::new(),
+        parsed: None,
+        landmark: None,
+        scale_method: "none".into(),
+        height_m: 0.0,
+        m_per_norm: None,
+        scale_disagreement: None,
+        plate_rejected: false,
+        options,
+        failure: None,
+        escalated: false,
+    };
+
+    let response = match reply {
+        Ok(response) => response,
+        Err(e) => {
+            log.failure = Some(RunFailure::from_call(config, &e));
+            return log;
+        }
+    };
+    let parsed = parse_geometry(&response);
+    log.raw_response = config.retain_response(response);
+    let geo = match parsed {
+        Ok(geo) => geo,
+        Err(e) => {
+            config.metrics.increment(Counter::ParseFailures, Some(PromptStage::Geometry), 1);
+            log.failure = Some(RunFailure::from_parse(&e));
+            return log;
+        }
+    };
+
+    if let Some(ratio) = geo.occluded_ratio.filter(|r| is_occlusion_rejected(config, *r)) {
+        log.failure = Some(RunFailure::Rejected {
+            reason: i18n::REJECT_OCCLUDED.format(language(), &[&format!("{:.0}", ratio * 100.0)]),
+        });
+    } else if geo.tailgate_top_y <= 0.0 {
+        log.failure = Some(RunFailure::Rejected {
+            reason: i18n::REJECT_NO_TAILGATE.get(language()).to_string(),
+        });
+    } else {
+        // A user-specified plate class wins over the one the model reported
+        let detected;
+        let scale = match (&scale.plate_class, &geo.plate_class) {
+            (None, Some(class)) => {
+                detected = ScaleOptions {
+                    plate_class: Some(class.clone()),
+                    ..scale.clone()
+                };
+                &detected
+            }
+            _ => scale,
+        };
+        let est = estimate_height(
+            geo.tailgate_top_y,
+            geo.tailgate_bottom_y,
+            geo.cargo_top_y,
+            geo.plate_box,
+            bed_height,
+            scale,
+        );
+        log.plate_rejected = est.plate_rejected;
+        if est.method != "none" {
+            log.scale_method = est.method.to_string();
+            log.m_per_norm = est.m_per_norm;
+            // Under a tarp the scaled height is that of the tarp profile
+            log.height_m = est.height_m * config.tarp.map_or(1.0, |t| t.deflation);
+            log.scale_disagreement = est.scale_disagreement;
+        } else {
+            log.failure = Some(RunFailure::Rejected {
+                reason: i18n::REJECT_NO_SCALE.get(language()).to_string(),
+            });
+        }
+    }
+    log.parsed = Some(geo);
+    log
+}
+
+/// True when `ratio` exceeds the configured occlusion limit
+fn is_occlusion_rejected(config: &BoxOverlayConfig, ratio: f64) -> bool {
+    config.occlusion.is_some_and(|o| ratio > o.reject_above)
+}
+
+/// Occluded ratio of a geometry run, when reported
+fn occluded_ratio(run: &GeometryRunLog) -> Option<f64> {
+    run.parsed.as_ref().and_then(|g| g.occluded_ratio)
+}
+
+/// A geometry run that produced a usable height
+fn is_valid_geometry_run(run: &GeometryRunLog) -> bool {
+    (run.parsed.is_some() || run.landmark.is_some()) && run.scale_method != "none"
+}
+
+/// Median height over the valid runs, plus stage warnings
+fn aggregate_geometry(
+    config: &BoxOverlayConfig,
+    bed_height: f64,
+    geometry_runs: Vec<GeometryRunLog>,
+) -> Result<GeometryStageResult, PipelineError> {
+    let height_list: Vec<f64> = geometry_runs
+        .iter()
+        .filter(|r| is_valid_geometry_run(r))
+        .map(|r| r.height_m)
+        .collect();
+
+    if height_list.is_empty() {
+        return Err(PipelineError::NoValidGeometry);
+    }
+
+    let height_m = config.median.median(&height_list);
+
+    let mut warnings = truck_class_warnings(config);
+    let plate_runs = geometry_runs.iter().filter(|r| r.scale_method == "plate").count();
+    if plate_runs > 0 {
+        warnings.push(AnalysisWarning::PlateScaleFallback { runs: plate_runs });
+    }
+    let inconsistent_runs = geometry_runs
+        .iter()
+        .filter(|r| r.scale_disagreement.is_some_and(|d| d > SCALE_DISAGREEMENT_TOLERANCE))
+        .count();
+    if inconsistent_runs > 0 {
+        warnings.push(AnalysisWarning::ScaleInconsistent { runs: inconsistent_runs });
+    }
+    let rejected_runs = geometry_runs.iter().filter(|r| r.plate_rejected).count();
+    if rejected_runs > 0 {
+        warnings.push(AnalysisWarning::PlateRejected { runs: rejected_runs });
+    }
+    let landmark_runs = geometry_runs.iter().filter(|r| r.scale_method == "landmark").count();
+    if landmark_runs > 0 && config.geometry_mode == GeometryMode::CoordinatesWithLandmarkFallback {
+        warnings.push(AnalysisWarning::LandmarkFallback { runs: landmark_runs });
+    }
+    let escalated_runs = geometry_runs.iter().filter(|r| r.escalated).count();
+    if escalated_runs > 0 {
+        warnings.push(AnalysisWarning::GeometryEscalated { runs: escalated_runs });
+    }
+    let occluded_runs = geometry_runs
+        .iter()
+        .filter(|r| occluded_ratio(r).is_some_and(|ratio| is_occlusion_rejected(config, ratio)))
+        .count();
+    if occluded_runs > 0 {
+        warnings.push(AnalysisWarning::OcclusionRejected { runs: occluded_runs });
+    }
+    let parsed_runs = geometry_runs.iter().filter_map(|r| r.parsed.as_ref());
+    warnings.extend(numeric_warnings(parsed_runs.map(|g| &g.numeric_warnings)));
+
+    let detected: Vec<String> = geometry_runs
+        .iter()
+        .filter(|r| is_valid_geometry_run(r))
+        .filter_map(|r| r.parsed.as_ref()?.plate_class.clone())
+        .collect();
+    let plate_class = configured_plate_class(config).or_else(|| mode_string(&detected));
+
+    Ok(GeometryStageResult {
+        height_m,
+        heights: height_list,
+        bed_height,
+        plate_class,
+        calibration: run_calibration(config, &geometry_runs),
+        runs: geometry_runs,
+        warnings,
+    })
+}
+
+/// Scale most valid coordinate runs used, at their median meters per
+/// normalized unit; ties go to the reference of the earlier run
+fn run_calibration(config: &BoxOverlayConfig, geometry_runs: &[GeometryRunLog]) -> Option<ScaleCalibration> {
+    let scaled: Vec<(&str, f64)> = geometry_runs
+        .iter()
+        .filter(|r| is_valid_geometry_run(r))
+        .filter_map(|r| Some((r.scale_method.as_str(), r.m_per_norm?)))
+        .collect();
+    let methods: Vec<String> = scaled.iter().map(|(method, _)| method.to_string()).collect();
+    let method = mode_string(&methods)?;
+    let method = ["tailgate", "plate", "fused"].into_iter().find(|m| *m == method)?;
+    let factors: Vec<f64> = scaled.iter().filter(|(m, _)| *m == method).map(|&(_, f)| f).collect();
+    Some(ScaleCalibration {
+        method,
+        m_per_norm: config.median.median(&factors),
+    })
+}
+
+/// Run only the fill estimation stage (ensemble, averaged and clamped to SPEC ranges).
+///
+/// Lets callers who measured the height themselves obtain fill/taper/packing
+/// estimates and feed them into `calculate_tonnage`.
+pub fn analyze_fill(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<FillStageResult, PipelineError> {
+    let budget = CallBudget::start(config);
+    let images = prepare_images(backend, images, config)?;
+    let fill_runs = run_fill_calls(backend, &images, config, &budget)?;
+    finish_fill(backend, config, &budget, fill_runs, None)
+}
+
+/// Run the fill ensemble; only a passed deadline is an error here
+fn run_fill_calls(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+) -> Result<Vec<FillRunLog>, PipelineError> {
+    let mut fill_runs = Vec::new();
+
+    for i in 0..config.fill_run_count() {
+        let limit = match budget.next_limit() {
+            Ok(limit) => limit,
+            Err(elapsed) => {
+                return Err(PipelineError::Timeout {
+                    elapsed,
+                    geometry_runs: Vec::new(),
+                    fill_runs,
+                })
+            }
+        };
+        config.push_fill_run(&mut fill_runs, fill_run(backend, images, config, i, limit));
+    }
+    if let Err(elapsed) = escalate_fill(backend, images, config, budget, &mut fill_runs) {
+        return Err(PipelineError::Timeout {
+            elapsed,
+            geometry_runs: Vec::new(),
+            fill_runs,
+        });
+    }
+
+    Ok(fill_runs)
+}
+
+/// Aggregate the fill runs, falling back to geometry-derived values when
+/// `fill_fallback` is set and no run produced a usable response
+fn finish_fill(
+    backend: &dyn AiBackend,
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+    fill_runs: Vec<FillRunLog>,
+    geometry: Option<&GeometryStageResult>,
+) -> Result<FillStageResult, PipelineError> {
+    if let Some(geometry) = geometry {
+        if LoadState::of(geometry) == LoadState::Empty {
+            return Ok(empty_fill(config, fill_runs));
+        }
+        if config.fill_fallback && fill_runs.iter().all(|r| r.parsed.is_none()) {
+            return Ok(fallback_fill(config, fill_runs, geometry));
+        }
+    }
+    let mut fill = aggregate_fill(fill_runs)?;
+    if config.reasoning_summary == ReasoningSummary::Model {
+        summarize_reasonings(backend, config, budget, &mut fill);
+    }
+    Ok(fill)
+}
+
+/// Reasoning texts with repeats (ignoring whitespace) removed, in run order
+fn distinct_reasonings(reasonings: &[RunReasoning]) -> Vec<&str> {
+    let mut seen: Vec<String> = Vec::new();
+    let mut distinct = Vec::new();
+    for r in reasonings {
+        let key: String = r.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !seen.contains(&key) {
+            seen.push(key);
+            distinct.push(r.text.as_str());
+        }
+    }
+    distinct
+}
+
+/// Replace the merged reasoning with a model summary when the runs differ
+fn summarize_reasonings(
+    backend: &dyn AiBackend,
+    config: &BoxOverlayConfig,
+    budget: &CallBudget,
+    fill: &mut FillStageResult,
+) {
+    let distinct = distinct_reasonings(&fill.reasonings);
+    if distinct.len() < 2 || SPEC.reasoning_prompt.is_empty() {
+        return;
+    }
+    let Ok(limit) = budget.next_limit() else {
+        return;
+    };
+    let list: Vec<String> = distinct.iter().map(|t| format!("- {}", t.replace('\n', " "))).collect();
+    let prompt = SPEC.reasoning_prompt.replace("{reasonings}", &list.join("\n"));
+    let options = config.run_backend_options(0);
+    let (reply, _) =
+        call_backend(backend, &prompt, &[], limit, &options, &config.metrics, PromptStage::Reasoning);
+    let Ok(text) = reply else {
+        return;
+    };
+    match parse_reasoning_summary(&text) {
+        Ok(parsed) if !parsed.summary.trim().is_empty() => fill.reasoning = config.redact(parsed.summary),
+        Ok(_) => {}
+        Err(_) => config.metrics.increment(Counter::ParseFailures, Some(PromptStage::Reasoning), 1),
+    }
+}
+
+/// Conservative fill derived from the load height alone.
+///
+/// The heap is taken as a flat layer: the higher the cargo top rises above the
+/// bed (relative to the bed height), the further fill and taper move from the
+/// lower end of the material's typical range, but never past the midpoint.
+/// Packing uses the material's typical value.
+fn fallback_fill(
+    config: &BoxOverlayConfig,
+    fill_runs: Vec<FillRunLog>,
+    geometry: &GeometryStageResult,
+) -> FillStageResult {
+    let material = config.material(&config.material_type);
+    let heap = if geometry.bed_height > 0.0 {
+        (geometry.height_m / (2.0 * geometry.bed_height)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let t = heap * 0.5;
+    let lerp = |r: &Range| r.min + (r.max - r.min) * t;
+    let ranges = &SPEC.ranges;
+
+    FillStageResult {
+        fill_ratio_l: lerp(material.map_or(&ranges.fill_ratio_l, |m| m.fill_ratio_l_range())),
+        fill_ratio_w: lerp(material.map_or(&ranges.fill_ratio_w, |m| m.fill_ratio_w_range())),
+        taper_ratio: lerp(material.map_or(&ranges.taper_ratio, |m| m.taper_ratio_range())),
+        packing_density: material.map_or(ranges.packing_density.min, |m| m.default_packing()),
+        material_type: None,
+        reasoning: i18n::REASONING_FILL_FALLBACK.get(language()).to_string(),
+        reasonings: Vec::new(),
+        material_votes: MaterialVotes::default(),
+        samples: FillSamples::default(),
+        clamps: Vec::new(),
+        runs: fill_runs,
+        warnings: vec![AnalysisWarning::FillFallback],
+        source: FillSource::Fallback,
+    }
+}
+
+/// Fill stage result for an empty bed: no fill, nothing to estimate
+fn empty_fill(config: &BoxOverlayConfig, fill_runs: Vec<FillRunLog>) -> FillStageResult {
+    let material = config.material(&config.material_type);
+    FillStageResult {
+        fill_ratio_l: 0.0,
+        fill_ratio_w: 0.0,
+        taper_ratio: 0.0,
+        packing_density: material.map_or(SPEC.ranges.packing_density.min, |m| m.default_packing()),
+        material_type: None,
+        reasoning: i18n::REASONING_EMPTY_BED.get(language()).to_string(),
+        reasonings: Vec::new(),
+        material_votes: MaterialVotes::default(),
+        samples: FillSamples::default(),
+        clamps: Vec::new(),
+        runs: fill_runs,
+        warnings: Vec::new(),
+        source: FillSource::Skipped,
+    }
+}
+
+/// Issue one fill call
+fn fill_run(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+    run: usize,
+    limit: Option<Duration>,
+) -> FillRunLog {
+    let (variant, prompt) = config.fill_run_prompt(run);
+    let options = config.run_backend_options(run);
+    let images = backend.capabilities().run_images(images, run);
+    let (reply, used_backend) =
+        call_backend(backend, &prompt, images, limit, &options, &config.metrics, PromptStage::Fill);
+    fill_run_log(config, variant, used_backend, options, reply)
+}
+
+/// Parse a fill reply
+fn fill_run_log(
+    config: &BoxOverlayConfig,
+    variant: &str,
+    used_backend: String,
+    options: BackendOptions,
+    reply: Result<String, PipelineError>,
+) -> FillRunLog {
+    let (raw_response, parsed, failure) = match reply {
+        Ok(response) => match parse_fill(&response) {
+            Ok(mut parsed) => {
+                parsed.reasoning = parsed.reasoning.take().map(|r| config.redact(r));
+                (config.retain_response(response), Some(parsed), None)
+            }
+            Err(e) => {
+                config.metrics.increment(Counter::ParseFailures, Some(PromptStage::Fill), 1);
+                (config.retain_response(response), None, Some(RunFailure::from_parse(&e)))
+            }
+        },
+        Err(e) => (String::new(), None, Some(RunFailure::from_call(config, &e))),
+    };
+    let out_of_range = parsed.as_ref().map(fill_range_violations).unwrap_or_default();
+    FillRunLog {
+        variant: variant.to_string(),
+        backend: used_backend,
+        raw_response,
+        parsed,
+        out_of_range,
+        options,
+        failure,
+        escalated: false,
+    }
+}
+
+/// Per-parameter values of the valid fill runs
+fn fill_samples(fill_runs: &[FillRunLog]) -> FillSamples {
+    let mut samples = FillSamples::default();
+    for fill in fill_runs.iter().filter_map(|r| r.parsed.as_ref()) {
+        samples.fill_ratio_l.push(fill.fill_ratio_l);
+        samples.fill_ratio_w.push(fill.fill_ratio_w);
+        samples.taper_ratio.push(fill.taper_ratio);
+        samples.packing_density.push(fill.packing_density);
+    }
+    samples
+}
+
+/// Average the valid runs (clamped to SPEC ranges) and pick the material
+fn aggregate_fill(fill_runs: Vec<FillRunLog>) -> Result<FillStageResult, PipelineError> {
+    let ranges = &SPEC.ranges;
+
+    let samples = fill_samples(&fill_runs);
+    let mut reasonings = Vec::new();
+    let valid_runs = || fill_runs.iter().enumerate().filter_map(|(i, r)| Some((i, r.parsed.as_ref()?)));
+    let material_votes = MaterialVotes::tally(
+        valid_runs().filter_map(|(run, fill)| Some((run, fill.material_type.as_deref()?, fill.confidence_score))),
+    );
+
+    for (run, fill) in valid_runs() {
+        if let Some(text) = fill.reasoning.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+            reasonings.push(RunReasoning {
+                run,
+                text: text.to_string(),
+            });
+        }
+    }
+
+    if samples.fill_ratio_l.is_empty() {
+        return Err(PipelineError::NoValidFill);
+    }
+
+    let mut clamps = Vec::new();
+    let fill_l = clamp_average("fillRatioL", &samples.fill_ratio_l, &ranges.fill_ratio_l, &mut clamps);
+    let fill_w = clamp_average("fillRatioW", &samples.fill_ratio_w, &ranges.fill_ratio_w, &mut clamps);
+    let taper = clamp_average("taperRatio", &samples.taper_ratio, &ranges.taper_ratio, &mut clamps);
+    let packing = clamp_average(
+        "packingDensity",
+        &samples.packing_density,
+        &ranges.packing_density,
+        &mut clamps,
+    );
+
+    let mut warnings: Vec<AnalysisWarning> =
+        numeric_warnings(fill_runs.iter().filter_map(|r| r.parsed.as_ref()).map(|f| &f.numeric_warnings)).collect();
+    warnings.extend(clamps.iter().map(|c| AnalysisWarning::FillClamped {
+        field: c.field.clone(),
+        value: c.original,
+        clamped: c.clamped,
+    }));
+
+    if material_votes.votes.len() > 1 {
+        warnings.push(AnalysisWarning::MaterialDisagreement { materials: material_votes.materials() });
+    }
+    let escalated_runs = fill_runs.iter().filter(|r| r.escalated).count();
+    if escalated_runs > 0 {
+        warnings.push(AnalysisWarning::FillEscalated { runs: escalated_runs });
+    }
+
+    Ok(FillStageResult {
+        fill_ratio_l: fill_l,
+        fill_ratio_w: fill_w,
+        taper_ratio: taper,
+        packing_density: packing,
+        material_type: material_votes.winner().map(str::to_string),
+        reasoning: distinct_reasonings(&reasonings).join("\n"),
+        reasonings,
+        material_votes,
+        samples,
+        clamps,
+        runs: fill_runs,
+        warnings,
+        source: FillSource::Estimated,
+    })
+}
+
+// ─── Before/after delta ──────────────────────────────────────────────
+
+/// Result of `analyze_delta`
+#[derive(Debug, Clone)]
+pub struct DeltaResult {
+    pub before: BoxOverlayResult,
+    pub after: BoxOverlayResult,
+    /// `before.diff(&after)`: negative tonnage when cargo was removed
+    pub diff: ResultDiff,
+}
+
+impl DeltaResult {
+    /// Tonnage change from before to after (t)
+    pub fn tonnage_delta(&self) -> f64 {
+        round2(self.diff.tonnage)
+    }
+
+    /// Tonnage unloaded between the photos (0 when the load grew)
+    pub fn removed(&self) -> f64 {
+        (-self.tonnage_delta()).max(0.0)
+    }
+
+    /// Tonnage loaded between the photos (0 when the load shrank)
+    pub fn added(&self) -> f64 {
+        self.tonnage_delta().max(0.0)
+    }
+}
+
+/// Estimate the tonnage removed or added between two photo sets of the same
+/// truck, e.g. before and after a partial unload.
+///
+/// Both photo sets are assumed to come from the same camera position. The
+/// second analysis reuses from the first:
+///
+/// - its geometry calibration (`BoxOverlayResult::calibration`): the after
+///   heights are measured from the tailgate top with the same meters per
+///   normalized unit, so no plate, tailgate height or landmark fallback is
+///   needed on the after photos
+/// - its plate class and the image aspect of the first photo set
+/// - the truck class and the fleet vehicle the plate stage selected
+/// - the material, as the fallback when the after runs do not agree on one;
+///   an empty bed has no material of its own, so `config`'s is kept then
+///
+/// Without a calibration (landmark runs) the after photos are measured
+/// like any other analysis.
+pub fn analyze_delta(
+    backend: &dyn AiBackend,
+    before_images: &[InputImage],
+    after_images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<DeltaResult, PipelineError> {
+    let before = analyze_box_overlay(backend, before_images, config)?;
+
+    let scale = ScaleOptions {
+        plate_class: before.plate_class.clone(),
+        calibration: before.calibration.clone(),
+        ..scale_options(config, before_images)
+    };
+    let geometry_mode = match (&scale.calibration, config.geometry_mode) {
+        (Some(_), GeometryMode::CoordinatesWithLandmarkFallback) => GeometryMode::Coordinates,
+        (_, mode) => mode,
+    };
+    let material_type = match before.load_state {
+        LoadState::Empty => config.material_type.clone(),
+        LoadState::Loaded => before.material_type.clone(),
+    };
+    let calibrated = BoxOverlayConfig {
+        truck_class: before.truck_class.clone(),
+        material_type,
+        vehicle: before.vehicle_id.clone().or_else(|| config.vehicle.clone()),
+        scale,
+        geometry_mode,
+        ..config.clone()
+    };
+    let after = analyze_box_overlay(backend, after_images, &calibrated)?;
+
+    Ok(DeltaResult {
+        diff: before.diff(&after),
+        before,
+        after,
+    })
+}
+
+// ─── Estimation strategies ───────────────────────────────────────────
+
+/// One model call an `EstimationStrategy` asks for
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrategyCall {
+    pub stage: PromptStage,
+    /// Run index within the stage; backend options and image subsets cycle on it
+    pub run: usize,
+    /// Prompt variant recorded in the run log ("default" = none)
+    pub variant: String,
+    pub prompt: String,
+}
+
+/// Reply to a `StrategyCall`
+#[derive(Debug)]
+pub struct StrategyReply {
+    pub call: StrategyCall,
+    /// Backend that answered (the one that succeeded, in a fallback chain)
+    pub backend: String,
+    pub options: BackendOptions,
+    pub reply: Result<String, PipelineError>,
+}
+
+/// The analysis a strategy step runs in
+#[derive(Debug)]
+pub struct StrategyContext<'a> {
+    /// Config with the located site applied
+    pub config: &'a BoxOverlayConfig,
+    /// Images as sent to the backend (after preprocessing)
+    pub images: &'a [InputImage],
+    /// Hashes of the images as supplied
+    pub image_hashes: Vec<ImageHash>,
+    pub inputs: Vec<ImageInfo>,
+    /// Pixel size of the first image, when it could be read
+    pub image_size: Option<(u32, u32)>,
+}
+
+/// A way of estimating the load from the photos, run by `analyze_with`.
+///
+/// A strategy says which calls to make, reads each reply into a run,
+/// combines the runs and turns the combined estimate into its result. The
+/// driver does everything around that (site, truck class, preprocessing,
+/// quality check, time limits, backend calls), so a new strategy (side view,
+/// plan view, ...) is added by implementing this trait alone.
+pub trait EstimationStrategy {
+    /// One reply, parsed or failed
+    type Run;
+    /// Parameters combined over the runs
+    type Estimate;
+    /// Result of the analysis
+    type Output;
+
+    /// Short name for logs and comparisons
+    fn name(&self) -> &str;
+
+    /// Calls of one analysis, in the order they are issued
+    fn prompts(&self, cx: &StrategyContext<'_>) -> Vec<StrategyCall>;
+
+    /// Whether `call` is still needed given the runs so far; a skipped call
+    /// makes no run (default: every call is made)
+    fn needs_call(&self, _cx: &StrategyContext<'_>, _runs: &[Self::Run], _call: &StrategyCall) -> bool {
+        true
+    }
+
+    /// Read one reply; a failed call is a run too
+    fn parse(&self, cx: &StrategyContext<'_>, reply: StrategyReply) -> Self::Run;
+
+    /// Combine the runs; an error when none is usable
+    fn aggregate(&self, cx: &StrategyContext<'_>, runs: Vec<Self::Run>) -> Result<Self::Estimate, PipelineError>;
+
+    /// Tonnage of the estimate, as the strategy's result
+    fn calculate(&self, cx: &StrategyContext<'_>, estimate: Self::Estimate) -> Self::Output;
+}
+
+/// Run an `EstimationStrategy`.
+///
+/// The site, truck-class check, preprocessing, quality pre-check and time
+/// limits apply as in `analyze_box_overlay`. The strategy's calls are issued
+/// in order; a passed deadline fails with a `Timeout` that carries no runs.
+pub fn analyze_with<S: EstimationStrategy + ?Sized>(
+    strategy: &S,
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<S::Output, PipelineError> {
+    let inputs = input_infos(images);
+    let config = locate_site(config, &inputs);
+    let config = &*config;
+    check_truck_class(config)?;
+    let budget = CallBudget::start(config);
+    let image_hashes = hash_images(images);
+    let image_size = first_image_size(images);
+    let images = prepare_images(backend, images, config)?;
+    let cx = StrategyContext {
+        config,
+        images: &images,
+        image_hashes,
+        inputs,
+        image_size,
+    };
+
+    let mut runs = Vec::new();
+    for call in strategy.prompts(&cx) {
+        if !strategy.needs_call(&cx, &runs, &call) {
+            continue;
+        }
+        let limit = budget.next_limit().map_err(|elapsed| PipelineError::Timeout {
+            elapsed,
+            geometry_runs: Vec::new(),
+            fill_runs: Vec::new(),
+        })?;
+        let options = config.run_backend_options(call.run);
+        let run_images = backend.capabilities().run_images(&images, call.run);
+        let (reply, used_backend) =
+            call_backend(backend, &call.prompt, run_images, limit, &options, &config.metrics, call.stage);
+        let reply = StrategyReply {
+            call,
+            backend: used_backend,
+            options,
+            reply,
+        };
+        runs.push(strategy.parse(&cx, reply));
+    }
+    let estimate = strategy.aggregate(&cx, runs)?;
+    Ok(strategy.calculate(&cx, estimate))
+}
+
+/// Box overlay as an `EstimationStrategy`: the geometry (or landmark) runs,
+/// then the fill runs, combined and calculated as `analyze_box_overlay` does;
+/// an empty bed skips the fill runs. Plate reading, escalation, interleaving and model reasoning summaries are
+/// left to `analyze_box_overlay`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoxOverlayStrategy;
+
+/// A run of `BoxOverlayStrategy`
+#[derive(Debug, Clone)]
+pub enum BoxOverlayRun {
+    Geometry(GeometryRunLog),
+    Fill(FillRunLog),
+}
+
+impl EstimationStrategy for BoxOverlayStrategy {
+    type Run = BoxOverlayRun;
+    type Estimate = (GeometryStageResult, FillStageResult);
+    type Output = BoxOverlayResult;
+
+    fn name(&self) -> &str {
+        "box-overlay"
+    }
+
+    fn prompts(&self, cx: &StrategyContext<'_>) -> Vec<StrategyCall> {
+        let config = cx.config;
+        let geometry = (0..config.geometry_run_count()).map(|run| match config.geometry_mode {
+            GeometryMode::Landmark => StrategyCall {
+                stage: PromptStage::Landmark,
+                run,
+                variant: "landmark".into(),
+                prompt: SPEC.landmark_prompt.clone(),
+            },
+            _ => {
+                let (variant, prompt) = config.geometry_run_prompt(run);
+                StrategyCall {
+                    stage: PromptStage::Geometry,
+                    run,
+                    variant: variant.into(),
+                    prompt: prompt.into_owned(),
+                }
+            }
+        });
+        let fill = (0..config.fill_run_count()).map(|run| {
+            let (variant, prompt) = config.fill_run_prompt(run);
+            StrategyCall {
+                stage: PromptStage::Fill,
+                run,
+                variant: variant.into(),
+                prompt: prompt.into_owned(),
+            }
+        });
+        geometry.chain(fill).collect()
+    }
+
+    fn needs_call(&self, cx: &StrategyContext<'_>, runs: &[BoxOverlayRun], call: &StrategyCall) -> bool {
+        if call.stage != PromptStage::Fill {
+            return true;
+        }
+        let heights: Vec<f64> = runs
+            .iter()
+            .filter_map(|run| match run {
+                BoxOverlayRun::Geometry(log) if is_valid_geometry_run(log) => Some(log.height_m),
+                _ => None,
+            })
+            .collect();
+        // No valid geometry: the aggregate fails either way
+        heights.is_empty() || LoadState::of_height(cx.config.median.median(&heights)) == LoadState::Loaded
+    }
+
+    fn parse(&self, cx: &StrategyContext<'_>, reply: StrategyReply) -> BoxOverlayRun {
+        let StrategyReply {
+            call,
+            backend,
+            options,
+            reply,
+        } = reply;
+        let config = cx.config;
+        match call.stage {
+            PromptStage::Landmark => BoxOverlayRun::Geometry(landmark_run_log(config, backend, options, reply)),
+            PromptStage::Geometry => {
+                let (bed_height, scale) = (bed_height_for(config), scale_options(config, cx.images));
+                let log = geometry_run_log(config, &call.variant, backend, options, reply, bed_height, &scale);
+                BoxOverlayRun::Geometry(log)
+            }
+            _ => BoxOverlayRun::Fill(fill_run_log(config, &call.variant, backend, options, reply)),
+        }
+    }
+
+    fn aggregate(
+        &self,
+        cx: &StrategyContext<'_>,
+        runs: Vec<BoxOverlayRun>,
+    ) -> Result<(GeometryStageResult, FillStageResult), PipelineError> {
+        let config = cx.config;
+        let mut geometry_runs = Vec::new();
+        let mut fill_runs = Vec::new();
+        for run in runs {
+            match run {
+                BoxOverlayRun::Geometry(log) => config.push_geometry_run(&mut geometry_runs, log),
+                BoxOverlayRun::Fill(log) => config.push_fill_run(&mut fill_runs, log),
+            }
+        }
+        let geometry = aggregate_geometry(config, bed_height_for(config), geometry_runs)?;
+        let fill = if LoadState::of(&geometry) == LoadState::Empty {
+            empty_fill(config, fill_runs)
+        } else if config.fill_fallback && fill_runs.iter().all(|r| r.parsed.is_none()) {
+            fallback_fill(config, fill_runs, &geometry)
+        } else {
+            aggregate_fill(fill_runs)?
+        };
+        Ok((geometry, fill))
+    }
+
+    fn calculate(
+        &self,
+        cx: &StrategyContext<'_>,
+        (geometry, fill): (GeometryStageResult, FillStageResult),
+    ) -> BoxOverlayResult {
+        finish_box_overlay(cx.config, geometry, fill, cx.image_hashes.clone(), cx.inputs.clone(), cx.image_size)
+    }
+}
+
+// ─── Legacy multi-param strategy ─────────────────────────────────────
+
+/// Log of a single multi-param run
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiParamRunLog {
+    pub backend: String,
+    pub raw_response: String,
+    pub parsed: Option<MultiParamResponse>,
+    pub options: BackendOptions,
+    /// Why the run gave no usable values (None = valid)
+    pub failure: Option<RunFailure>,
+}
+
+/// Averages of the valid multi-param runs, before the calculation
+#[derive(Debug, Clone)]
+pub struct MultiParamEstimate {
+    pub params: MultiParams,
+    /// Mean `confidenceScore` of the valid runs
+    pub confidence: f64,
+    /// Distinct reasonings of the valid runs, line by line
+    pub reasoning: String,
+    pub runs: Vec<MultiParamRunLog>,
+    /// Averages that were clamped to their spec range
+    pub clamps: Vec<ClampRecord>,
+    pub warnings: Vec<AnalysisWarning>,
+}
+
+/// Result of `analyze_multi_param`
+#[derive(Debug, Clone)]
+pub struct MultiParamResult {
+    pub truck_class: String,
+    pub material_type: String,
+    pub height_m: f64,
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub fill_ratio_z: f64,
+    pub packing_density: f64,
+    pub volume: f64,
+    pub tonnage: f64,
+    /// Tonnage interval over the material's density range
+    pub tonnage_min: f64,
+    pub tonnage_max: f64,
+    pub density: f64,
+    /// Mean `confidenceScore` of the valid runs
+    pub confidence: f64,
+    /// Distinct reasonings of the valid runs, line by line
+    pub reasoning: String,
+    pub runs: Vec<MultiParamRunLog>,
+    /// Averages that were clamped to their spec range
+    pub clamps: Vec<ClampRecord>,
+    pub warnings: Vec<AnalysisWarning>,
+    /// Intermediate values of the multi-param formula
+    pub breakdown: TonnageBreakdown,
+}
+
+/// Result of `analyze`, by strategy
+#[derive(Debug, Clone)]
+pub enum StrategyResult {
+    BoxOverlay(Box<BoxOverlayResult>),
+    MultiParam(Box<MultiParamResult>),
+}
+
+impl StrategyResult {
+    pub fn strategy(&self) -> Strategy {
+        match self {
+            Self::BoxOverlay(_) => Strategy::BoxOverlay,
+            Self::MultiParam(_) => Strategy::MultiParam,
+        }
+    }
+
+    pub fn height_m(&self) -> f64 {
+        match self {
+            Self::BoxOverlay(r) => r.height_m,
+            Self::MultiParam(r) => r.height_m,
+        }
+    }
+
+    pub fn tonnage(&self) -> f64 {
+        match self {
+            Self::BoxOverlay(r) => r.tonnage,
+            Self::MultiParam(r) => r.tonnage,
+        }
+    }
+}
+
+/// Both strategies run on the same photos (`compare_strategies`)
+#[derive(Debug, Clone)]
+pub struct StrategyComparison {
+    pub box_overlay: BoxOverlayResult,
+    pub multi_param: MultiParamResult,
+}
+
+impl StrategyComparison {
+    /// Multi-param tonnage minus box-overlay tonnage
+    pub fn tonnage_difference(&self) -> f64 {
+        round2(self.multi_param.tonnage - self.box_overlay.tonnage)
+    }
+
+    /// Multi-param height minus box-overlay height (m)
+    pub fn height_difference(&self) -> f64 {
+        round3(self.multi_param.height_m - self.box_overlay.height_m)
+    }
+}
+
+/// Run the strategy selected by `BoxOverlayConfig::strategy`
+pub fn analyze(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<StrategyResult, PipelineError> {
+    Ok(match config.strategy {
+        Strategy::BoxOverlay => StrategyResult::BoxOverlay(Box::new(analyze_box_overlay(backend, images, config)?)),
+        Strategy::MultiParam => StrategyResult::MultiParam(Box::new(analyze_multi_param(backend, images, config)?)),
+    })
+}
+
+/// Run both strategies on the same photos and config (`strategy` is ignored)
+pub fn compare_strategies(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<StrategyComparison, PipelineError> {
+    Ok(StrategyComparison {
+        box_overlay: analyze_box_overlay(backend, images, config)?,
+        multi_param: analyze_multi_param(backend, images, config)?,
+    })
+}
+
+/// Legacy multi-param estimate.
+///
+/// `ensemble_count` runs of `multiParamPrompt`, each returning the height,
+/// fillRatioL/W/Z and packing density at once; runs that saw no truck are
+/// rejected. The averages are clamped to the spec ranges and go through
+/// `calculate_multi_param`. Runs through `analyze_with` (`MultiParamStrategy`).
+pub fn analyze_multi_param(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<MultiParamResult, PipelineError> {
+    analyze_with(&MultiParamStrategy, backend, images, config)
+}
+
+/// The legacy multi-param estimate as an `EstimationStrategy`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MultiParamStrategy;
+
+impl EstimationStrategy for MultiParamStrategy {
+    type Run = MultiParamRunLog;
+    type Estimate = MultiParamEstimate;
+    type Output = MultiParamResult;
+
+    fn name(&self) -> &str {
+        "multi-param"
+    }
+
+    fn prompts(&self, cx: &StrategyContext<'_>) -> Vec<StrategyCall> {
+        let prompt = SPEC.multi_param_prompt.render();
+        (0..cx.config.ensemble_count)
+            .map(|run| StrategyCall {
+                stage: PromptStage::MultiParam,
+                run,
+                variant: "default".into(),
+                prompt: prompt.clone(),
+            })
+            .collect()
+    }
+
+    fn parse(&self, cx: &StrategyContext<'_>, reply: StrategyReply) -> MultiParamRunLog {
+        let config = cx.config;
+        let (raw_response, parsed, failure) = match reply.reply {
+            Ok(response) => match parse_multi_param(&response) {
+                Ok(mut parsed) => {
+                    parsed.reasoning = parsed.reasoning.take().map(|r| config.redact(r));
+                    let failure = (!parsed.is_target_detected).then(|| RunFailure::Rejected {
+                        reason: i18n::REJECT_NO_BED.get(language()).to_string(),
+                    });
+                    (config.retain_response(response), Some(parsed), failure)
+                }
+                Err(e) => {
+                    config.metrics.increment(Counter::ParseFailures, Some(PromptStage::MultiParam), 1);
+                    (config.retain_response(response), None, Some(RunFailure::from_parse(&e)))
+                }
+            },
+            Err(e) => (String::new(), None, Some(RunFailure::from_call(config, &e))),
+        };
+        MultiParamRunLog {
+            backend: reply.backend,
+            raw_response,
+            parsed,
+            options: reply.options,
+            failure,
+        }
+    }
+
+    /// Average the valid runs, clamped to SPEC ranges
+    fn aggregate(
+        &self,
+        cx: &StrategyContext<'_>,
+        runs: Vec<MultiParamRunLog>,
+    ) -> Result<MultiParamEstimate, PipelineError> {
+        let config = cx.config;
+        let ranges = &SPEC.ranges;
+        let valid: Vec<&MultiParamResponse> =
+            runs.iter().filter(|r| r.failure.is_none()).filter_map(|r| r.parsed.as_ref()).collect();
+        if valid.is_empty() {
+            return Err(PipelineError::NoValidMultiParam);
+        }
+        let values = |f: fn(&MultiParamResponse) -> f64| valid.iter().map(|m| f(m)).collect::<Vec<f64>>();
+
+        let mut clamps = Vec::new();
+        let height_range = Range {
+            min: ranges.height.min,
+            max: ranges.height.max,
+        };
+        let height = clamp_average("height", &values(|m| m.height), &height_range, &mut clamps);
+        let fill_l = clamp_average("fillRatioL", &values(|m| m.fill_ratio_l), &ranges.fill_ratio_l, &mut clamps);
+        let fill_w = clamp_average("fillRatioW", &values(|m| m.fill_ratio_w), &ranges.fill_ratio_w, &mut clamps);
+        let fill_z = clamp_average("fillRatioZ", &values(|m| m.fill_ratio_z), &ranges.fill_ratio_z, &mut clamps);
+        let packing = clamp_average(
+            "packingDensity",
+            &values(|m| m.packing_density),
+            &ranges.packing_density,
+            &mut clamps,
+        );
+
+        let mut warnings: Vec<AnalysisWarning> =
+            numeric_warnings(valid.iter().map(|m| &m.numeric_warnings)).collect();
+        warnings.extend(clamps.iter().map(|c| AnalysisWarning::FillClamped {
+            field: c.field.clone(),
+            value: c.original,
+            clamped: c.clamped,
+        }));
+        warnings.extend(truck_class_warnings(config));
+
+        let votes = MaterialVotes::tally(
+            valid
+                .iter()
+                .enumerate()
+                .filter_map(|(run, m)| Some((run, m.material_type.as_deref()?, Some(m.confidence_score)))),
+        );
+        let material_type = votes
+            .decide(config.material_agreement)
+            .filter(|m| config.allows_material(m))
+            .map_or_else(|| config.material_type.clone(), str::to_string);
+
+        let mut reasonings: Vec<&str> = Vec::new();
+        for text in valid.iter().filter_map(|m| m.reasoning.as_deref()).map(str::trim) {
+            if !text.is_empty() && !reasonings.contains(&text) {
+                reasonings.push(text);
+            }
+        }
+        let reasoning = reasonings.join("\n");
+        let confidence = average(&values(|m| m.confidence_score));
+
+        Ok(MultiParamEstimate {
+            params: MultiParams {
+                height: round3(height),
+                fill_ratio_l: fill_l,
+                fill_ratio_w: fill_w,
+                fill_ratio_z: fill_z,
+                packing_density: packing,
+                material_type,
+            },
+            confidence,
+            reasoning,
+            runs,
+            clamps,
+            warnings,
+        })
+    }
+
+    fn calculate(&self, cx: &StrategyContext<'_>, estimate: MultiParamEstimate) -> MultiParamResult {
+        let config = cx.config;
+        let params = estimate.params;
+        let (density, density_range) = density_for(config, &params.material_type);
+        let calc = calculate_multi_param_with(&params, bed_for(config), density, &density_range);
+        let (TruckClassMatch::Known(truck_class) | TruckClassMatch::Unrecognized(truck_class)) =
+            config.truck_class_match();
+
+        MultiParamResult {
+            truck_class,
+            height_m: params.height,
+            fill_ratio_l: round3(params.fill_ratio_l),
+            fill_ratio_w: round3(params.fill_ratio_w),
+            fill_ratio_z: round3(params.fill_ratio_z),
+            packing_density: round3(params.packing_density),
+            material_type: params.material_type,
+            volume: calc.volume,
+            tonnage: calc.tonnage,
+            tonnage_min: calc.tonnage_min,
+            tonnage_max: calc.tonnage_max,
+            density: calc.density,
+            confidence: round3(estimate.confidence),
+            reasoning: estimate.reasoning,
+            runs: estimate.runs,
+            clamps: estimate.clamps,
+            warnings: estimate.warnings,
+            breakdown: calc.breakdown,
+        }
+    }
+}
+
+// ─── Helpers ─────────────────────────────────────────────────────────
+
+/// Reject unknown truck classes up front when `strict_truck_class` is set
+fn check_truck_class(config: &BoxOverlayConfig) -> Result<(), PipelineError> {
+    if config.strict_truck_class && config.truck_spec().is_none() {
+        return Err(PipelineError::UnknownTruckClass(config.truck_class.clone()));
+    }
+    Ok(())
+}
+
+/// Parse-time clamps of every run, as analysis warnings
+fn numeric_warnings<'a>(
+    runs: impl Iterator<Item = &'a Vec<NumericWarning>> + 'a,
+) -> impl Iterator<Item = AnalysisWarning> + 'a {
+    runs.flatten().map(|w| AnalysisWarning::ResponseValueClamped {
+        field: w.field.clone(),
+        value: w.value,
+        clamped: w.clamped,
+    })
+}
+
+fn truck_class_warnings(config: &BoxOverlayConfig) -> Vec<AnalysisWarning> {
+    let mut warnings = Vec::new();
+    if let Some(vehicle) = config.vehicle.as_ref().filter(|_| config.registered_vehicle().is_none()) {
+        warnings.push(AnalysisWarning::UnknownVehicle {
+            vehicle: vehicle.clone(),
+        });
+    }
+    if let TruckClassMatch::Unrecognized(truck_class) = config.truck_class_match() {
+        warnings.push(AnalysisWarning::UnknownTruckClass { truck_class });
+    }
+    warnings
+}
+
+/// Average the samples and clamp to `range`, recording the clamp when it moved the value
+fn clamp_average(field: &str, values: &[f64], range: &Range, clamps: &mut Vec<ClampRecord>) -> f64 {
+    let value = average(values);
+    match ClampRecord::check(field, value, range.min, range.max) {
+        Some(record) => {
+            let clamped = record.clamped;
+            clamps.push(record);
+            clamped
+        }
+        None => value,
+    }
+}
+
+/// Parsed fill values of one run that fall outside the spec ranges
+fn fill_range_violations(fill: &FillResponse) -> Vec<ClampRecord> {
+    let ranges = &SPEC.ranges;
+    let check = |field, value, range: &Range| ClampRecord::check(field, value, range.min, range.max);
+    [
+        check("fillRatioL", fill.fill_ratio_l, &ranges.fill_ratio_l),
+        check("fillRatioW", fill.fill_ratio_w, &ranges.fill_ratio_w),
+        check("taperRatio", fill.taper_ratio, &ranges.taper_ratio),
+        check("packingDensity", fill.packing_density, &ranges.packing_density),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Configured scale options, with the image aspect read from the first image
+/// when not given (feature `image`)
+#[cfg_attr(not(feature = "image"), allow(unused_variables))]
+fn scale_options(config: &BoxOverlayConfig, images: &[InputImage]) -> ScaleOptions {
+    #[allow(unused_mut)]
+    let mut scale = config.scale.clone();
+    scale.plate_class = configured_plate_class(config);
+    #[cfg(feature = "image")]
+    if scale.image_aspect.is_none() {
+        scale.image_aspect = images.first().and_then(|img| image_aspect(&img.bytes));
+    }
+    scale
+}
+
+/// Plate class set in the scale options, else the registered vehicle's
+fn configured_plate_class(config: &BoxOverlayConfig) -> Option<String> {
+    config
+        .scale
+        .plate_class
+        .clone()
+        .or_else(|| config.registered_vehicle().and_then(|v| v.calibration.plate_class.clone()))
+}
+
+fn input_infos(images: &[InputImage]) -> Vec<ImageInfo> {
+    #[cfg(feature = "image")]
+    return images.iter().map(|img| apply_photo_metadata(img.info(), &img.bytes)).collect();
+    #[cfg(not(feature = "image"))]
+    images.iter().map(InputImage::info).collect()
+}
+
+/// Displayed size of the first input image as supplied
+fn first_image_size(images: &[InputImage]) -> Option<(u32, u32)> {
+    #[cfg(feature = "image")]
+    return images.first().and_then(|img| image_dimensions(&img.bytes));
+    #[cfg(not(feature = "image"))]
+    {
+        let _ = images;
+        None
+    }
+}
+
+/// Bed (length, width) of the configured truck
+fn bed_for(config: &BoxOverlayConfig) -> (f64, f64) {
+    match config.truck_spec() {
+        Some(truck) => (truck.bed_length, truck.bed_width),
+        None => bed_dimensions(Some(&config.truck_class)),
+    }
+}
+
+/// Nominal density and density range of a material, site entries first
+fn density_for(config: &BoxOverlayConfig, material_type: &str) -> (f64, Range) {
+    match config.material(material_type) {
+        Some(m) => (m.density, m.density_range()),
+        None => (get_material_density(material_type), get_material_density_range(material_type)),
+    }
+}
+
+fn bed_height_for(config: &BoxOverlayConfig) -> f64 {
+    if let Some(vehicle) = config.registered_vehicle() {
+        return vehicle.tailgate_height();
+    }
+    config
+        .truck_spec()
+        .map(|s| s.bed_height)
+        .unwrap_or(0.32)
+}
+
+/// Time budget of one analysis (`timeout_per_call` / `deadline`)
+struct CallBudget {
+    /// None when no limit is configured, so no clock is read (wasm32 has none)
+    started: Option<Instant>,
+    per_call: Option<Duration>,
+    deadline: Option<Duration>,
+}
+
+impl CallBudget {
+    fn start(config: &BoxOverlayConfig) -> Self {
+        let limited = config.timeout_per_call.is_some() || config.deadline.is_some();
+        Self {
+            started: limited.then(Instant::now),
+            per_call: config.timeout_per_call,
+            deadline: config.deadline,
+        }
+    }
+
+    /// Limit for the next call, or `Err(elapsed)` once the deadline has passed
+    fn next_limit(&self) -> Result<Option<Duration>, Duration> {
+        let Some(started) = self.started else {
+            return Ok(None);
+        };
+        let Some(deadline) = self.deadline else {
+            return Ok(self.per_call);
+        };
+        let elapsed = started.elapsed();
+        match deadline.checked_sub(elapsed) {
+            Some(remaining) if !remaining.is_zero() => {
+                Ok(Some(self.per_call.map_or(remaining, |p| p.min(remaining))))
+            }
+            _ => Err(elapsed),
+        }
+    }
+}
+
+/// Call the backend, returning the reply and the name of the backend that produced it
+fn call_backend(
+    backend: &dyn AiBackend,
+    prompt: &str,
+    images: &[InputImage],
+    limit: Option<Duration>,
+    options: &BackendOptions,
+    metrics: &Metrics,
+    stage: PromptStage,
+) -> (Result<String, PipelineError>, String) {
+    metrics.increment(Counter::Runs, Some(stage), 1);
+    let json_options;
+    let options = if backend.capabilities().structured_output && !options.json_output {
+        json_options = BackendOptions {
+            json_output: true,
+            ..options.clone()
+        };
+        &json_options
+    } else {
+        options
+    };
+    // wasm32 has no clock
+    #[cfg(not(target_arch = "wasm32"))]
+    let started = metrics.is_enabled().then(Instant::now);
+    let reply = backend.send_prompt_within(prompt, images, limit, options);
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(started) = started {
+        metrics.observe(Histogram::CallLatencySeconds, Some(stage), started.elapsed().as_secs_f64());
+    }
+    match reply {
+        Ok((response, name)) => (Ok(response), name),
+        Err(e) => {
+            metrics.increment(Counter::CallErrors, Some(stage), 1);
+            (Err(e), backend.name().to_string())
+        }
+    }
+}
+
+/// Apply the configured image preprocessing and quality pre-check
+fn prepare_images<'a>(
+    backend: &dyn AiBackend,
+    images: &'a [InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<Cow<'a, [InputImage]>, PipelineError> {
+    #[cfg(feature = "image")]
+    let images: Cow<'a, [InputImage]> = match config.preprocess {
+        Some(ref pre) => Cow::Owned(preprocess_images(images, pre)?),
+        None => Cow::Borrowed(images),
+    };
+    #[cfg(not(feature = "image"))]
+    let images = Cow::Borrowed(images);
+
+    if let Some(max) = backend.capabilities().max_image_bytes {
+        if let Some((index, image)) = images.iter().enumerate().find(|(_, i)| i.bytes.len() > max) {
+            return Err(PipelineError::ImageTooLarge {
+                index,
+                bytes: image.bytes.len(),
+                max,
+            });
+        }
+    }
+    if let Some(ref qc) = config.quality_check {
+        check_images(backend, &images, qc, &config.run_backend_options(0))?;
+    }
+    Ok(images)
+}
+
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+fn round3(v: f64) -> f64 {
+    (v * 1000.0).round() / 1000.0
+}
+
+fn round4(v: f64) -> f64 {
+    (v * 10000.0).round() / 10000.0
+}
+
+/// Get most common string from a list (mode); a tie goes to the value seen
+/// first, so identical runs always agree. Returns None if empty.
+fn mode_string(values: &[String]) -> Option<String> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for v in values {
+        match counts.iter_mut().find(|(seen, _)| seen == v) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((v, 1)),
+        }
+    }
+    let top = counts.iter().map(|&(_, count)| count).max()?;
+    counts.into_iter().find(|&(_, count)| count == top).map(|(v, _)| v.to_string())
+}
+
+// ─── Tests ───────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Session, StatefulBackend};
+    use crate::input::ImageFormat;
+
+    /// Mock AI backend that returns predefined responses in order
+    struct MockBackend {
+        geometry_responses: Vec<String>,
+        fill_responses: Vec<String>,
+        geo_call: usize,
+        fill_call: usize,
+    }
+
+    impl MockBackend {
+        /// The mock in the session that serves it to the pipeline
+        fn new(geo: Vec<&str>, fill: Vec<&str>) -> Session<Self> {
+            Session::new(Self {
+                geometry_responses: geo.into_iter().map(String::from).collect(),
+                fill_responses: fill.into_iter().map(String::from).collect(),
+                geo_call: 0,
+                fill_call: 0,
+            })
+        }
+    }
+
+    impl StatefulBackend for MockBackend {
+        fn send_prompt(&mut self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            // Distinguish geometry vs fill by checking prompt content
+            if prompt.contains("tailgateTopY") {
+                let idx = self.geo_call;
+                self.geo_call += 1;
+                if idx < self.geometry_responses.len() {
+                    Ok(self.geometry_responses[idx].clone())
+                } else {
+                    // Cycle last response
+                    Ok(self.geometry_responses.last().unwrap().clone())
+                }
+            } else {
+                let idx = self.fill_call;
+                self.fill_call += 1;
+                if idx < self.fill_responses.len() {
+                    Ok(self.fill_responses[idx].clone())
+                } else {
+                    Ok(self.fill_responses.last().unwrap().clone())
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_full_pipeline_mock() {
+        let geo_json = r#"{"plateBox":[0.4,0.7,0.6,0.84],"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,"reasoning":"Well packed"}"#;
+
+        let backend = MockBackend::new(vec![geo_json, geo_json], vec![fill_json, fill_json]);
+        let config = BoxOverlayConfig {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 2,
+            ..Default::default()
+        };
+
+        let images = [InputImage::with_format(vec![1, 2, 3], ImageFormat::Jpeg)];
+        let result = analyze_box_overlay(&backend, &images, &config).unwrap();
+
+        assert!(result.height_m > 0.0, "height should be > 0");
+        assert!(result.tonnage > 0.0, "tonnage should be > 0");
         assert!(result.volume > 0.0, "volume should be > 0");
         assert_eq!(result.geometry_runs.len(), 2);
         assert_eq!(result.fill_runs.len(), 2);
-        assert_eq!(result.reasoning, "Well packed");
-        assert!((result.density - 2.5).abs() < f64::EPSILON, "As殻 density = 2.5");
+        assert_eq!(result.reasoning, "Well packed");
+        assert!((result.density - 2.5).abs() < f64::EPSILON, "As殻 density = 2.5");
+    }
+
+    #[test]
+    fn test_pipeline_geometry_all_fail() {
+        let backend = MockBackend::new(
+            vec!["not json at all", "also bad"],
+            vec![r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#],
+        );
+        let config = BoxOverlayConfig {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 2,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config);
+        assert!(matches!(result, Err(PipelineError::NoValidGeometry)));
+    }
+
+    #[test]
+    fn test_pipeline_fill_all_fail() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let backend = MockBackend::new(vec![geo_json], vec!["bad fill"]);
+        let config = BoxOverlayConfig {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config);
+        assert!(matches!(result, Err(PipelineError::NoValidFill)));
+    }
+
+    #[test]
+    fn test_pipeline_partial_geometry_success() {
+        // First run fails, second succeeds
+        let good_geo = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        let backend = MockBackend::new(vec!["bad json", good_geo], vec![fill_json, fill_json]);
+        let config = BoxOverlayConfig {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 2,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!(result.height_m > 0.0);
+        // One failed, one succeeded
+        assert!(result.geometry_runs[0].parsed.is_none());
+        assert!(result.geometry_runs[1].parsed.is_some());
+    }
+
+    #[test]
+    fn test_pipeline_clamps_fill_to_spec_ranges() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        // AI returns out-of-range fill values
+        let fill_json = r#"{"fillRatioL":0.1,"fillRatioW":0.99,"taperRatio":0.2,"packingDensity":0.99}"#;
+
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let config = BoxOverlayConfig {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let r = &SPEC.ranges;
+        assert!(result.fill_ratio_l >= r.fill_ratio_l.min);
+        assert!(result.fill_ratio_w <= r.fill_ratio_w.max);
+        assert!(result.taper_ratio >= r.taper_ratio.min);
+    }
+
+    #[test]
+    fn test_pipeline_result_matches_ts_calculation() {
+        // Use known geometry: tailgate top=0.3, bot=0.5, cargo_top=0.2, bed_height=0.32
+        // height_from_geometry: tg_height_norm=0.2, m_per_norm=1.6, h=(0.5-0.2)*1.6=0.48
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let config = BoxOverlayConfig {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+
+        // height should be 0.48
+        assert!(
+            (result.height_m - 0.48).abs() < 0.01,
+            "height={}, expected ~0.48",
+            result.height_m
+        );
+
+        // Manual: bedL=3.4, bedW=2.06
+        // effectiveL = 0.8 * 0.9 = 0.72
+        // effectiveW = (0.9 + 0.85) / 2 = 0.875
+        // volume = 3.4 * 2.06 * 0.48 * 0.72 * 0.875 = ~2.117
+        assert!(result.volume > 2.0 && result.volume < 2.3, "volume={}", result.volume);
+        // tonnage should be in a reasonable range
+        assert!(result.tonnage > 3.0 && result.tonnage < 5.0, "tonnage={}", result.tonnage);
+    }
+
+    #[test]
+    fn test_pipeline_prompt_overrides() {
+        /// Records every prompt it receives
+        struct RecordingBackend {
+            prompts: std::cell::RefCell<Vec<String>>,
+        }
+        impl AiBackend for RecordingBackend {
+            fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+                self.prompts.borrow_mut().push(prompt.to_string());
+                if prompt.starts_with("GEO") {
+                    Ok(r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#.to_string())
+                } else {
+                    Ok(r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#.to_string())
+                }
+            }
+        }
+
+        let backend = RecordingBackend { prompts: Default::default() };
+        let config = BoxOverlayConfig {
+            ensemble_count: 1,
+            geometry_prompt: Some("GEO experiment".to_string()),
+            ..Default::default()
+        };
+
+        analyze_box_overlay(&backend, &[], &config).unwrap();
+        let prompts = backend.prompts.borrow();
+        assert_eq!(prompts[0], "GEO experiment");
+        // Fill prompt falls back to the spec default
+        assert_eq!(prompts[1], SPEC.fill_prompt);
+    }
+
+    #[test]
+    fn test_pipeline_mixed_variants_recorded_per_run() {
+        let geo_a = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let geo_b = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.25}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        let backend = MockBackend::new(vec![geo_a, geo_b, geo_a], vec![fill_json]);
+        let config = BoxOverlayConfig {
+            ensemble_count: 3,
+            geometry_variants: vec![
+                PromptVariant::new("a", format!("{} (A)", SPEC.geometry_prompt)),
+                PromptVariant::new("b", format!("{} (B)", SPEC.geometry_prompt)),
+            ],
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let variants: Vec<&str> = result.geometry_runs.iter().map(|r| r.variant.as_str()).collect();
+        assert_eq!(variants, ["a", "b", "a"]);
+        assert!(result.fill_runs.iter().all(|r| r.variant == "default"));
+        // Heterogeneous runs still aggregate by median: 0.48, 0.40, 0.48 -> 0.48
+        assert!((result.height_m - 0.48).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_prompt_variant_from_registry() {
+        let v = PromptVariant::from_registry("geometry").unwrap();
+        assert_eq!(v.name, "geometry@2.1.0");
+        assert_eq!(v.prompt, SPEC.geometry_prompt);
+        assert!(PromptVariant::from_registry("nope").is_none());
+    }
+
+    #[test]
+    fn test_analyze_geometry_only() {
+        let geo_a = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let geo_b = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.25}"#;
+
+        // Fill responses are never requested
+        let backend = MockBackend::new(vec![geo_a, "bad json", geo_b], vec!["unused"]);
+        let config = BoxOverlayConfig {
+            ensemble_count: 3,
+            ..Default::default()
+        };
+
+        let stage = analyze_geometry(&backend, &[], &config).unwrap();
+        assert_eq!(stage.runs.len(), 3);
+        assert_eq!(stage.heights.len(), 2);
+        assert!((stage.bed_height - 0.32).abs() < f64::EPSILON);
+        // median of [0.48, 0.40] averages the middle pair, as the TS side does
+        assert!((stage.height_m - 0.44).abs() < 0.01);
+        assert_eq!(backend.with(|m| m.fill_call), 0);
+
+        // Upper median reproduces results from before the setting
+        let backend = MockBackend::new(vec![geo_a, "bad json", geo_b], vec!["unused"]);
+        let upper = BoxOverlayConfig {
+            median: MedianMode::Upper,
+            ..config
+        };
+        let stage = analyze_geometry(&backend, &[], &upper).unwrap();
+        assert!((stage.height_m - 0.48).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_analyze_fill_only() {
+        let fill_a = r#"{"fillRatioL":0.8,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8,"materialType":"土砂"}"#;
+        let fill_b = r#"{"fillRatioL":0.6,"fillRatioW":0.99,"taperRatio":0.7,"packingDensity":0.9,"materialType":"土砂"}"#;
+
+        let backend = MockBackend::new(vec!["unused"], vec![fill_a, fill_b]);
+        let config = BoxOverlayConfig::default();
+
+        let stage = analyze_fill(&backend, &[], &config).unwrap();
+        assert_eq!(backend.with(|m| m.geo_call), 0);
+        assert_eq!(stage.runs.len(), 2);
+        assert!((stage.fill_ratio_l - 0.7).abs() < 1e-9);
+        assert!((stage.taper_ratio - 0.8).abs() < 1e-9);
+        // average 0.895 stays inside [0.7, 0.9]
+        assert!((stage.fill_ratio_w - 0.895).abs() < 1e-9);
+        assert_eq!(stage.material_type.as_deref(), Some("土砂"));
+
+        // Manually measured height feeds straight into the calculation
+        let params = CoreParams {
+            height: 0.45,
+            fill_ratio_l: stage.fill_ratio_l,
+            fill_ratio_w: stage.fill_ratio_w,
+            taper_ratio: stage.taper_ratio,
+            packing_density: stage.packing_density,
+            material_type: stage.material_type.unwrap(),
+            formula: FormulaVersion::default(),
+        };
+        assert!(crate::calculation::calculate_tonnage(&params, Some("4t")).tonnage > 0.0);
+    }
+
+    #[test]
+    fn test_pipeline_with_precomputed_height() {
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let config = BoxOverlayConfig {
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let resumed = analyze_box_overlay_with_geometry(&backend, &[], &config, 0.48).unwrap();
+        assert_eq!(backend.with(|m| m.geo_call), 0, "geometry stage must be skipped");
+        assert!(resumed.geometry_runs.is_empty());
+
+        // Same result as the full pipeline for the same height
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let full = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!((resumed.height_m - full.height_m).abs() < 1e-9);
+        assert!((resumed.tonnage - full.tonnage).abs() < 1e-9);
+
+        // Out-of-range heights are clamped to the spec range
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let clamped = analyze_box_overlay_with_geometry(&backend, &[], &config, 3.0).unwrap();
+        assert!((clamped.height_m - SPEC.ranges.height.max).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_plan_box_overlay_dry_run() {
+        let config = BoxOverlayConfig {
+            ensemble_count: 2,
+            fill_prompt: Some("abcdefgh".to_string()),
+            backend_options: BackendOptions { seed: Some(7), ..Default::default() },
+            temperature_schedule: vec![0.0, 0.5],
+            ..Default::default()
+        };
+
+        let plan = plan_box_overlay(&config, 1);
+        assert_eq!(plan.calls.len(), 4);
+        assert_eq!(plan.geometry_runs, 2);
+        assert_eq!(plan.calls[0].stage, PromptStage::Geometry);
+        assert_eq!(plan.calls[0].prompt, SPEC.geometry_prompt);
+        assert_eq!(plan.calls[3].stage, PromptStage::Fill);
+        assert_eq!(plan.calls[3].run, 1);
+        assert_eq!(plan.calls[3].options, config.run_backend_options(1));
+        assert_eq!((plan.calls[3].options.seed, plan.calls[3].options.temperature), (Some(7), Some(0.5)));
+        // 8 ASCII chars -> 2 tokens, plus one image
+        assert_eq!(plan.calls[3].estimated_tokens, 2 + IMAGE_TOKENS);
+        let total: usize = plan.calls.iter().map(|c| c.estimated_tokens).sum();
+        assert_eq!(plan.estimated_input_tokens, total);
+
+        // AI quality pre-check adds one leading call
+        let config = BoxOverlayConfig {
+            quality_check: Some(QualityCheckConfig { ai_check: true, ..Default::default() }),
+            ..config
+        };
+        let plan = plan_box_overlay(&config, 1);
+        assert_eq!(plan.calls.len(), 5);
+        assert_eq!(plan.calls[0].stage, PromptStage::Quality);
+    }
+
+    #[test]
+    fn test_plan_matches_sent_calls() {
+        struct Recorder(std::sync::Mutex<Vec<(String, BackendOptions)>>, &'static str);
+        impl AiBackend for Recorder {
+            fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+                unreachable!("every call carries options")
+            }
+
+            fn send_prompt_with_options(
+                &self,
+                prompt: &str,
+                _images: &[InputImage],
+                options: &BackendOptions,
+            ) -> Result<(String, String), PipelineError> {
+                self.0.lock().unwrap().push((prompt.to_string(), options.clone()));
+                let reply = if prompt == SPEC.quality_prompt {
+                    r#"{"usable":true}"#
+                } else if prompt.contains("tailgateTopY") {
+                    self.1
+                } else if prompt == SPEC.landmark_prompt {
+                    r#"{"landmark":"ヒンジ超え"}"#
+                } else if prompt.contains("fillRatioZ") {
+                    r#"{"isTargetDetected":true,"height":0.4,"packingDensity":0.8,
+                        "fillRatioL":0.8,"fillRatioW":0.85,"fillRatioZ":0.9,"confidenceScore":0.7}"#
+                } else {
+                    r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#
+                };
+                Ok((reply.to_string(), "recorder".to_string()))
+            }
+        }
+        let geometry = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let sent_and_planned = |backend: &Recorder, config: &BoxOverlayConfig| {
+            let planned: Vec<(String, BackendOptions)> =
+                plan_box_overlay(config, 0).calls.into_iter().map(|c| (c.prompt, c.options)).collect();
+            (std::mem::take(&mut *backend.0.lock().unwrap()), planned)
+        };
+
+        let config = BoxOverlayConfig::builder()
+            .ensemble_count(3)
+            .backend_options(BackendOptions { seed: Some(7), ..Default::default() })
+            .temperature_schedule(vec![0.0, 0.4, 0.8])
+            .quality_check(QualityCheckConfig { min_brightness: 0.0, min_sharpness: 0.0, ai_check: true })
+            .build()
+            .unwrap();
+        let backend = Recorder(std::sync::Mutex::new(Vec::new()), geometry);
+        analyze_box_overlay(&backend, &[], &config).unwrap();
+        let (sent, planned) = sent_and_planned(&backend, &config);
+        assert_eq!(sent, planned);
+
+        let config = BoxOverlayConfig { strategy: Strategy::MultiParam, ..config };
+        analyze(&backend, &[], &config).unwrap();
+        let (sent, planned) = sent_and_planned(&backend, &config);
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent, planned);
+
+        // Fallback calls are planned in the order they would be sent
+        let config = BoxOverlayConfig {
+            strategy: Strategy::BoxOverlay,
+            geometry_mode: GeometryMode::CoordinatesWithLandmarkFallback,
+            ..config
+        };
+        let backend = Recorder(std::sync::Mutex::new(Vec::new()), "garbage");
+        analyze_box_overlay(&backend, &[], &config).unwrap();
+        let (sent, planned) = sent_and_planned(&backend, &config);
+        assert_eq!(sent, planned);
+        let plan = plan_box_overlay(&config, 0);
+        let fallback: Vec<PromptStage> = plan.calls.iter().filter(|c| c.fallback).map(|c| c.stage).collect();
+        assert_eq!(fallback, [PromptStage::Landmark; 3]);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        assert_eq!(estimate_tokens("後板"), 2);
+    }
+
+    #[test]
+    fn test_pipeline_records_image_hashes() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let config = BoxOverlayConfig {
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let images = vec![
+            InputImage::with_format(b"front".to_vec(), ImageFormat::Jpeg).name("front.jpg"),
+            InputImage::with_format(b"rear".to_vec(), ImageFormat::Png).captured_at(1_700_000_000),
+        ];
+        let result = analyze_box_overlay(&backend, &images, &config).unwrap();
+        assert_eq!(result.image_hashes.len(), 2);
+        assert_eq!(result.image_hashes[0], crate::hashing::hash_image(b"front"));
+        assert_eq!(result.inputs[0].name.as_deref(), Some("front.jpg"));
+        assert_eq!((result.inputs[1].mime, result.inputs[1].captured_at), (ImageFormat::Png, Some(1_700_000_000)));
+        assert_eq!(result.inputs[1].size_bytes, 4);
+
+        let mut det = crate::hashing::DuplicateDetector::new(3600, 4);
+        assert!(det.check_and_record("first", &result.image_hashes, 0).is_none());
+        assert!(det.check(&result.image_hashes, 60).is_some());
+
+        // Same vehicle, new photos: flagged within the truck window
+        let mut det = crate::hashing::DuplicateDetector::new(3600, 4).with_truck_window(900);
+        let mut again = result.clone();
+        again.vehicle_id = Some("D-12".into());
+        again.image_hashes = crate::hashing::hash_images(&[b"other".to_vec()]);
+        assert!(det.check_and_record_result("first", &again, 0).is_none());
+        again.image_hashes = result.image_hashes.clone();
+        let dup = det.check_and_record_result("second", &again, 300).unwrap();
+        assert_eq!(dup.kind, crate::hashing::DuplicateKind::SameTruck);
+    }
+
+    #[test]
+    fn test_pipeline_quality_check_stops_before_ensemble() {
+        struct RejectingBackend {
+            calls: std::cell::Cell<usize>,
+        }
+        impl AiBackend for RejectingBackend {
+            fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+                self.calls.set(self.calls.get() + 1);
+                Ok(r#"{"usable":false,"reason":"暗すぎる"}"#.to_string())
+            }
+        }
+
+        let backend = RejectingBackend { calls: Default::default() };
+        let config = BoxOverlayConfig {
+            ensemble_count: 3,
+            quality_check: Some(QualityCheckConfig {
+                min_brightness: 0.0,
+                min_sharpness: 0.0,
+                ai_check: true,
+            }),
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config);
+        assert!(matches!(result, Err(PipelineError::UnusableImage(_))));
+        assert_eq!(backend.calls.get(), 1, "only the pre-check call is made");
+    }
+
+    #[test]
+    fn test_pipeline_records_fallback_backend_per_run() {
+        struct Flaky;
+        impl AiBackend for Flaky {
+            fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+                Err(PipelineError::AiError("429".into()))
+            }
+            fn name(&self) -> &str {
+                "primary"
+            }
+        }
+
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = crate::backend::FallbackBackend::new(Box::new(Flaky))
+            .with_fallback(Box::new(MockBackend::new(vec![geo_json], vec![fill_json])));
+        let config = BoxOverlayConfig {
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        // MockBackend keeps the default trait name
+        assert_eq!(result.geometry_runs[0].backend, "default");
+        assert_eq!(result.fill_runs[0].backend, "default");
+
+        // A plain backend failure is logged under its own name
+        let result = analyze_geometry(&Flaky, &[], &config);
+        assert!(matches!(result, Err(PipelineError::NoValidGeometry)));
+    }
+
+    #[test]
+    fn test_pipeline_invalid_tailgate_top_skipped() {
+        // tailgateTopY = 0 should be skipped (invalid)
+        let bad_geo = r#"{"tailgateTopY":0.0,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let good_geo = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        let backend = MockBackend::new(vec![bad_geo, good_geo], vec![fill_json, fill_json]);
+        let config = BoxOverlayConfig {
+            truck_class: "4t".to_string(),
+            material_type: "As殻".to_string(),
+            ensemble_count: 2,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!(result.height_m > 0.0);
+        assert_eq!(result.geometry_runs[0].scale_method, "none");
+        assert_ne!(result.geometry_runs[1].scale_method, "none");
+    }
+
+    #[test]
+    fn test_pipeline_flags_disagreement() {
+        let geo_low = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.45}"#;
+        let geo_high = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.15}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let config = BoxOverlayConfig {
+            ensemble_count: 2,
+            ..Default::default()
+        };
+
+        let backend = MockBackend::new(vec![geo_low, geo_high], vec![fill_json]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let d = result.disagreement.expect("heights 0.08 vs 0.56 must disagree");
+        assert_eq!(d.fields, ["height"]);
+        assert!(result.needs_review);
+        assert_eq!(result.review_flags[0].field, "height");
+
+        // Consistent runs produce no warning
+        let backend = MockBackend::new(vec![geo_high], vec![fill_json]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!(result.disagreement.is_none());
+        assert!(!result.needs_review && result.review_flags.is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_warnings() {
+        // Plate-only scale (no tailgate bottom), out-of-range fill, mixed materials
+        let geo_json = r#"{"plateBox":[0.4,0.7,0.6,0.84],"tailgateTopY":0.3,"tailgateBottomY":0.0,"cargoTopY":0.15}"#;
+        let fill_a = r#"{"fillRatioL":0.1,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8,"materialType":"As殻"}"#;
+        let fill_b = r#"{"fillRatioL":0.1,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8,"materialType":"土砂"}"#;
+
+        let backend = MockBackend::new(vec![geo_json], vec![fill_a, fill_b]);
+        let config = BoxOverlayConfig {
+            truck_class: "3t".to_string(),
+            ensemble_count: 2,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let w = &result.warnings;
+        assert!(w.contains(&AnalysisWarning::UnknownTruckClass { truck_class: "3t".into() }));
+        assert!(w.contains(&AnalysisWarning::PlateScaleFallback { runs: 2 }));
+        assert!(w.iter().any(|w| matches!(w, AnalysisWarning::FillClamped { field, .. } if field == "fillRatioL")));
+        assert!(w.iter().any(|w| matches!(w, AnalysisWarning::MaterialDisagreement { materials } if materials.len() == 2)));
+        assert!(w.iter().all(|w| !w.to_string().is_empty()));
+    }
+
+    #[test]
+    fn test_pipeline_over_capacity_warning() {
+        // Very high load on a 2t truck
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.0}"#;
+        let fill_json = r#"{"fillRatioL":0.9,"fillRatioW":0.9,"taperRatio":1.0,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let config = BoxOverlayConfig {
+            truck_class: "2t".to_string(),
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!(result.warnings.iter().any(|w| matches!(w, AnalysisWarning::OverCapacity { .. })));
+    }
+
+    #[test]
+    fn test_pipeline_strict_truck_class() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let mut config = BoxOverlayConfig {
+            truck_class: "7t".to_string(),
+            ensemble_count: 1,
+            strict_truck_class: true,
+            ..Default::default()
+        };
+
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let result = analyze_box_overlay(&backend, &[], &config);
+        assert!(matches!(result, Err(PipelineError::UnknownTruckClass(ref c)) if c == "7t"));
+        assert_eq!(backend.with(|m| m.geo_call), 0, "no backend calls in strict failure");
+
+        // Permissive mode falls back and reports a warning instead
+        config.strict_truck_class = false;
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!(result.warnings.iter().any(|w| matches!(w, AnalysisWarning::UnknownTruckClass { .. })));
+    }
+
+    #[test]
+    fn test_pipeline_run_statistics() {
+        let geo_a = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let geo_b = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.25}"#;
+        let fill_a = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let fill_b = r#"{"fillRatioL":0.7,"fillRatioW":0.75,"taperRatio":0.8,"packingDensity":0.9}"#;
+
+        let backend = MockBackend::new(vec![geo_a, geo_b], vec![fill_a, fill_b]);
+        let config = BoxOverlayConfig::default();
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let st = &result.statistics;
+        assert_eq!(st.height.values.len(), 2);
+        assert!((st.height.mean - 0.44).abs() < 1e-9);
+        assert_eq!(st.fill_ratio_l.values, [0.8, 0.7]);
+        assert!((st.packing_density.std_dev - 0.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pipeline_error_codes_and_sources() {
+        use std::error::Error;
+
+        let io = std::io::Error::new(std::io::ErrorKind::TimedOut, "gemini subprocess hung");
+        let err = PipelineError::backend("gemini CLI failed", io);
+        assert_eq!(err.code(), "AI_ERROR");
+        assert_eq!(err.localize(Language::En), "AI error: gemini CLI failed");
+        assert_eq!(err.localize(Language::Ja), "AI エラー: gemini CLI failed");
+        assert!(err.source().unwrap().to_string().contains("hung"));
+
+        let parse = crate::parse::parse_fill("not json").unwrap_err();
+        let err: PipelineError = parse.into();
+        assert_eq!(err.code(), "PARSE_NO_JSON");
+        assert!(err.source().is_some(), "parse error kept as source");
+
+        assert_eq!(PipelineError::NoValidFill.code(), "NO_VALID_FILL");
+    }
+
+    /// Sleeps before answering like a slow CLI subprocess
+    struct SlowBackend {
+        delay: Duration,
+        inner: Session<MockBackend>,
+    }
+
+    impl AiBackend for SlowBackend {
+        fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+            std::thread::sleep(self.delay);
+            self.inner.send_prompt(prompt, images)
+        }
+    }
+
+    #[test]
+    fn test_timeout_per_call_marks_runs() {
+        let backend = SlowBackend {
+            delay: Duration::from_millis(30),
+            inner: MockBackend::new(vec![r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#], vec![r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#]),
+        };
+        let config = BoxOverlayConfig {
+            timeout_per_call: Some(Duration::from_millis(5)),
+            ..Default::default()
+        };
+        let err = analyze_geometry(&backend, &[], &config).unwrap_err();
+        assert!(matches!(err, PipelineError::NoValidGeometry));
+    }
+
+    #[test]
+    fn test_deadline_returns_partial_logs() {
+        let backend = SlowBackend {
+            delay: Duration::from_millis(100),
+            inner: MockBackend::new(vec![r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#], vec![r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#]),
+        };
+        let config = BoxOverlayConfig {
+            deadline: Some(Duration::from_millis(150)),
+            ..Default::default()
+        };
+        match analyze_box_overlay(&backend, &[], &config).unwrap_err() {
+            PipelineError::Timeout { geometry_runs, fill_runs, .. } => {
+                assert_eq!(geometry_runs.len(), 2);
+                assert!(geometry_runs[0].height_m > 0.0);
+                assert!(matches!(geometry_runs[1].failure, Some(RunFailure::Timeout { .. })));
+                assert_eq!(geometry_runs[1].scale_method, "none");
+                assert!(fill_runs.is_empty());
+            }
+            other => panic!("expected Timeout, got {other:?}"),
+        }
+    }
+
+    /// Records the stage of every call in order
+    struct OrderBackend {
+        calls: std::cell::RefCell<Vec<&'static str>>,
+    }
+
+    impl AiBackend for OrderBackend {
+        fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            if prompt.contains("tailgateTopY") {
+                self.calls.borrow_mut().push("geometry");
+                Ok(r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#.to_string())
+            } else {
+                self.calls.borrow_mut().push("fill");
+                Ok(r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#.to_string())
+            }
+        }
+    }
+
+    #[test]
+    fn test_interleaved_schedule_alternates_stages() {
+        let backend = OrderBackend {
+            calls: Default::default(),
+        };
+        let sequential = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert_eq!(*backend.calls.borrow(), ["geometry", "geometry", "fill", "fill"]);
+
+        backend.calls.borrow_mut().clear();
+        let config = BoxOverlayConfig {
+            schedule: StageSchedule::Interleaved,
+            ..Default::default()
+        };
+        let interleaved = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(*backend.calls.borrow(), ["geometry", "fill", "geometry", "fill"]);
+        assert_eq!(interleaved.tonnage, sequential.tonnage);
+
+        let plan = plan_box_overlay(&config, 1);
+        let stages: Vec<PromptStage> = plan.calls.iter().map(|c| c.stage).collect();
+        assert_eq!(
+            stages,
+            [PromptStage::Geometry, PromptStage::Fill, PromptStage::Geometry, PromptStage::Fill]
+        );
+    }
+
+    /// Thread-safe backend tracking how many calls overlap
+    struct ConcurrencyProbe {
+        in_flight: std::sync::atomic::AtomicUsize,
+        max_in_flight: std::sync::atomic::AtomicUsize,
+    }
+
+    impl AiBackend for ConcurrencyProbe {
+        fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            use std::sync::atomic::Ordering;
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(30));
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            if prompt.contains("tailgateTopY") {
+                Ok(r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#.to_string())
+            } else {
+                Ok(r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#.to_string())
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_runs_stages_in_parallel() {
+        let backend = ConcurrencyProbe {
+            in_flight: Default::default(),
+            max_in_flight: Default::default(),
+        };
+        let config = BoxOverlayConfig {
+            schedule: StageSchedule::Interleaved,
+            ..Default::default()
+        };
+        let result = analyze_box_overlay_concurrent(&backend, &[], &config).unwrap();
+        assert_eq!(result.geometry_runs.len(), 2);
+        assert_eq!(result.fill_runs.len(), 2);
+        assert_eq!(backend.max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Streams a reply in small chunks, counting how many were generated
+    struct StreamingBackend {
+        chunks_sent: std::cell::Cell<usize>,
+    }
+
+    impl AiBackend for StreamingBackend {
+        fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            unreachable!("pipeline should use the streaming path")
+        }
+
+        fn send_prompt_streaming(
+            &self,
+            prompt: &str,
+            _images: &[InputImage],
+            on_chunk: &mut dyn FnMut(&str) -> ControlFlow<()>,
+        ) -> Result<String, PipelineError> {
+            let reply = if prompt.contains("tailgateTopY") {
+                r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2} Explanation follows..."#
+            } else {
+                r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8} Explanation follows..."#
+            };
+            let mut received = String::new();
+            for chunk in reply.as_bytes().chunks(8) {
+                let chunk = std::str::from_utf8(chunk).unwrap();
+                received.push_str(chunk);
+                self.chunks_sent.set(self.chunks_sent.get() + 1);
+                if on_chunk(chunk).is_break() {
+                    break;
+                }
+            }
+            Ok(received)
+        }
+    }
+
+    #[test]
+    fn test_streaming_stops_at_closing_brace() {
+        let backend = StreamingBackend {
+            chunks_sent: std::cell::Cell::new(0),
+        };
+        let config = BoxOverlayConfig {
+            ensemble_count: 1,
+            ..Default::default()
+        };
+        let result = analyze_geometry(&backend, &[], &config).unwrap();
+        let raw = &result.runs[0].raw_response;
+        assert!(raw.starts_with('{') && !raw.contains("Explanation"));
+        assert_eq!(backend.chunks_sent.get(), raw.len().div_ceil(8));
+    }
+
+    #[test]
+    fn test_builder_defaults_and_validation() {
+        let config = BoxOverlayConfig::builder().truck_class("10t").build().unwrap();
+        assert_eq!(config.truck_class, "10t");
+        assert_eq!(config.material_type, "As殻");
+        assert_eq!(config.ensemble_count, 2);
+
+        let err = BoxOverlayConfig::builder().ensemble_count(0).build().unwrap_err();
+        assert_eq!(err, ConfigError::ZeroEnsembleCount);
+        let err = BoxOverlayConfig::builder().material_type("鉄くず").build().unwrap_err();
+        assert_eq!(err.code(), "UNKNOWN_MATERIAL");
+    }
+
+    #[test]
+    fn test_clamp_provenance_recorded() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_high = r#"{"fillRatioL":1.2,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let fill_ok = r#"{"fillRatioL":0.9,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![geo_json], vec![fill_high, fill_ok]);
+
+        let result = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert_eq!(result.clamps.len(), 1);
+        let c = &result.clamps[0];
+        assert_eq!(c.field, "fillRatioL");
+        assert!((c.original - 1.05).abs() < 1e-12);
+        assert!((c.delta - (0.9 - 1.05)).abs() < 1e-12);
+
+        assert_eq!(result.fill_runs[0].out_of_range.len(), 1);
+        assert!((result.fill_runs[0].out_of_range[0].original - 1.2).abs() < 1e-12);
+        assert!(result.fill_runs[1].out_of_range.is_empty());
+    }
+
+    #[test]
+    fn test_extreme_response_values_flagged() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_huge = r#"{"fillRatioL":1e308,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![geo_json], vec![fill_huge]);
+
+        let result = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert!(result.tonnage.is_finite());
+        assert!(result.warnings.contains(&AnalysisWarning::ResponseValueClamped {
+            field: "fillRatioL".into(),
+            value: 1e308,
+            clamped: crate::parse::RESPONSE_VALUE_MAX,
+        }));
+    }
+
+    #[test]
+    fn test_manual_height_clamp_recorded() {
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![], vec![fill_json]);
+        let result =
+            analyze_box_overlay_with_geometry(&backend, &[], &BoxOverlayConfig::default(), 5.0).unwrap();
+        assert_eq!(result.clamps[0].field, "height");
+        assert!((result.clamps[0].original - 5.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_fused_scale_in_pipeline() {
+        // Plate scale far from the tailgate scale
+        let geo_json = r#"{"plateBox":[0.4,0.6,0.6,0.63],"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let config = BoxOverlayConfig {
+            scale: ScaleOptions {
+                fuse_plate: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let backend = MockBackend::new(vec![geo_json], vec![]);
+        let result = analyze_geometry(&backend, &[], &config).unwrap();
+        assert!(result.runs.iter().all(|r| r.scale_method == "fused"));
+        assert!(result.warnings.contains(&AnalysisWarning::ScaleInconsistent { runs: 2 }));
+    }
+
+    #[test]
+    fn test_implausible_plate_rejected() {
+        // No tailgate; tall narrow "plate" in a 4:3 photo
+        let geo_json = r#"{"plateBox":[0.4,0.6,0.45,0.8],"tailgateTopY":0.3,"tailgateBottomY":0.0,"cargoTopY":0.2}"#;
+        let config = BoxOverlayConfig {
+            scale: ScaleOptions {
+                image_aspect: Some(4.0 / 3.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let backend = MockBackend::new(vec![geo_json], vec![]);
+        assert!(matches!(
+            analyze_geometry(&backend, &[], &config),
+            Err(PipelineError::NoValidGeometry)
+        ));
+
+        let plausible = r#"{"plateBox":[0.4,0.7,0.55,0.8],"tailgateTopY":0.3,"tailgateBottomY":0.0,"cargoTopY":0.2}"#;
+        let backend = MockBackend::new(vec![plausible], vec![]);
+        let result = analyze_geometry(&backend, &[], &config).unwrap();
+        assert!(result.runs.iter().all(|r| r.scale_method == "plate" && !r.plate_rejected));
+    }
+
+    #[test]
+    fn test_detected_plate_class_used_unless_overridden() {
+        let geo_json = r#"{"plateBox":[0.4,0.7,0.6,0.81],"plateClass":"中板","tailgateTopY":0.3,"tailgateBottomY":0.0,"cargoTopY":0.2}"#;
+        let detected = analyze_geometry(
+            &MockBackend::new(vec![geo_json], vec![]),
+            &[],
+            &BoxOverlayConfig::default(),
+        )
+        .unwrap();
+
+        let config = BoxOverlayConfig {
+            scale: ScaleOptions {
+                plate_class: Some("大板".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let overridden = analyze_geometry(&MockBackend::new(vec![geo_json], vec![]), &[], &config).unwrap();
+        assert!(detected.height_m < overridden.height_m);
+    }
+
+    #[test]
+    fn test_truck_class_alias_normalized() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let config = BoxOverlayConfig {
+            truck_class: "４トン".to_string(),
+            strict_truck_class: true,
+            ..Default::default()
+        };
+        let result = analyze_box_overlay(&MockBackend::new(vec![geo_json], vec![fill_json]), &[], &config).unwrap();
+        assert_eq!(result.truck_class, "4t");
+        assert!(result.warnings.iter().all(|w| !matches!(w, AnalysisWarning::UnknownTruckClass { .. })));
+    }
+
+    #[test]
+    fn test_fill_fallback_from_geometry() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let backend = MockBackend::new(vec![geo_json, geo_json], vec!["bad fill", "bad fill"]);
+        let config = BoxOverlayConfig::builder().fill_fallback(true).build().unwrap();
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.fill_source, FillSource::Fallback);
+        assert_eq!(result.reasoning, i18n::REASONING_FILL_FALLBACK.get(language()));
+        assert!(result.warnings.contains(&AnalysisWarning::FillFallback));
+        // height 0.48m over a 0.32m bed -> 37.5% into the As殻 range 0.6~0.85
+        assert!((result.fill_ratio_l - 0.694).abs() < 1e-9);
+        assert!(result.tonnage > 0.0);
+    }
+
+    #[test]
+    fn test_billed_tonnage_keeps_raw_value() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let result = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert_eq!(result.billed_tonnage, None);
+
+        let config = BoxOverlayConfig::builder()
+            .invoicing(InvoiceRounding::new(0.5, crate::billing::RoundingMode::Up))
+            .build()
+            .unwrap();
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let billed = result.billed_tonnage.unwrap();
+        assert!(billed >= result.tonnage && billed - result.tonnage < 0.5);
+        assert_eq!(billed % 0.5, 0.0);
+
+        // Rounded up from the raw value, not from the two-decimal one
+        let config = config
+            .into_builder()
+            .invoicing(InvoiceRounding::new(0.1, crate::billing::RoundingMode::Up))
+            .build()
+            .unwrap();
+        let params = CoreParams {
+            height: 0.1,
+            fill_ratio_l: 0.8,
+            fill_ratio_w: 0.85,
+            taper_ratio: 0.9,
+            packing_density: 0.8,
+            material_type: "As殻".into(),
+            formula: FormulaVersion::default(),
+        };
+        let mut calc = crate::calculation::calculate_tonnage(&params, Some("4t"));
+        (calc.tonnage, calc.raw_tonnage) = (1.0, 1.001);
+        assert_eq!(billed_tonnage(&config, &calc), Some(1.1));
+    }
+
+    #[test]
+    fn test_material_hint_appended_to_fill_prompt() {
+        let config = BoxOverlayConfig::default();
+        assert_eq!(config.fill_run_prompt(0).1, SPEC.fill_prompt);
+
+        let config = BoxOverlayConfig::builder()
+            .material_type("切削ガラ")
+            .material_hint(true)
+            .build()
+            .unwrap();
+        let (_, prompt) = config.fill_run_prompt(0);
+        assert!(prompt.starts_with(SPEC.fill_prompt.as_str()));
+        assert!(prompt.ends_with("packingDensity around 0.9."));
+    }
+
+    #[test]
+    fn test_partial_keeps_geometry_when_fill_fails() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let backend = MockBackend::new(vec![geo_json, geo_json], vec!["bad fill", "bad fill"]);
+        let config = BoxOverlayConfig::default();
+
+        let partial = analyze_box_overlay_partial(&backend, &[], &config).unwrap();
+        assert!(!partial.is_complete());
+        assert!((partial.geometry.as_ref().unwrap().height_m - 0.48).abs() < 1e-9);
+        assert!(partial.fill.is_none());
+        match &partial.missing[..] {
+            [MissingStage::Fill { error: PipelineError::NoValidFill, runs }] => assert_eq!(runs.len(), 2),
+            other => panic!("unexpected missing stages: {:?}", other),
+        }
+
+        let manual = ManualFill {
+            fill_ratio_l: 0.8,
+            fill_ratio_w: 0.85,
+            taper_ratio: 0.9,
+            packing_density: 0.99,
+        };
+        let result = partial.complete(&config, None, Some(manual)).unwrap();
+        assert_eq!(result.fill_source, FillSource::Manual);
+        assert_eq!(result.fill_runs.len(), 2);
+        assert!(result.clamps.iter().any(|c| c.field == "packingDensity" && c.clamped == 0.95));
+    }
+
+    #[test]
+    fn test_partial_complete_requires_missing_values() {
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec!["bad geometry"], vec![fill_json]);
+        let config = BoxOverlayConfig {
+            ensemble_count: 1,
+            ..Default::default()
+        };
+
+        let partial = analyze_box_overlay_partial(&backend, &[], &config).unwrap();
+        assert!(partial.geometry.is_none() && partial.fill.is_some());
+        assert!(matches!(
+            partial.complete(&config, None, None),
+            Err(PipelineError::NoValidGeometry)
+        ));
+
+        let partial = analyze_box_overlay_partial(&backend, &[], &config).unwrap();
+        let result = partial.complete(&config, Some(0.45), None).unwrap();
+        assert!((result.height_m - 0.45).abs() < 1e-9);
+        assert_eq!(result.fill_source, FillSource::Estimated);
+    }
+
+    #[test]
+    fn test_backend_options_reach_backend_per_run() {
+        struct Recording(std::sync::Mutex<Vec<BackendOptions>>);
+        impl AiBackend for Recording {
+            fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+                if prompt.contains("tailgateTopY") {
+                    Ok(r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#.to_string())
+                } else {
+                    Ok(r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#.to_string())
+                }
+            }
+
+            fn send_prompt_with_options(
+                &self,
+                prompt: &str,
+                images: &[InputImage],
+                options: &BackendOptions,
+            ) -> Result<(String, String), PipelineError> {
+                self.0.lock().unwrap().push(options.clone());
+                self.send_prompt_traced(prompt, images)
+            }
+        }
+
+        let backend = Recording(std::sync::Mutex::new(Vec::new()));
+        let config = BoxOverlayConfig::builder()
+            .ensemble_count(3)
+            .backend_options(BackendOptions {
+                model: Some("gemini-2.5-flash".into()),
+                seed: Some(42),
+                ..Default::default()
+            })
+            .temperature_schedule(vec![0.0, 0.7])
+            .build()
+            .unwrap();
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+
+        let sent = backend.0.lock().unwrap();
+        assert_eq!(sent.len(), 6);
+        assert!(sent.iter().all(|o| o.seed == Some(42) && o.model.as_deref() == Some("gemini-2.5-flash")));
+        let temps: Vec<_> = result.fill_runs.iter().map(|r| r.options.temperature).collect();
+        assert_eq!(temps, [Some(0.0), Some(0.7), Some(0.0)]);
+    }
+
+    #[test]
+    fn test_metrics_reported_to_sink() {
+        use crate::metrics::MetricsSink;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(Counter, Option<PromptStage>, u64)>>);
+        impl MetricsSink for Recorder {
+            fn increment(&self, counter: Counter, stage: Option<PromptStage>, by: u64) {
+                self.0.lock().unwrap().push((counter, stage, by));
+            }
+        }
+        let count = |r: &Recorder, counter, stage| {
+            r.0.lock()
+                .unwrap()
+                .iter()
+                .filter(|(c, s, _)| *c == counter && *s == stage)
+                .map(|(_, _, n)| n)
+                .sum::<u64>()
+        };
+
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.95,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![geo_json, geo_json], vec![fill_json, "bad fill"]);
+        let recorder = Arc::new(Recorder::default());
+        let config = BoxOverlayConfig::builder()
+            .metrics(Metrics::from_arc(recorder.clone()))
+            .build()
+            .unwrap();
+        analyze_box_overlay(&backend, &[], &config).unwrap();
+
+        assert_eq!(count(&recorder, Counter::Runs, Some(PromptStage::Geometry)), 2);
+        assert_eq!(count(&recorder, Counter::Runs, Some(PromptStage::Fill)), 2);
+        assert_eq!(count(&recorder, Counter::ParseFailures, Some(PromptStage::Fill)), 1);
+        assert_eq!(count(&recorder, Counter::Clamps, None), 1);
+        assert_eq!(count(&recorder, Counter::Analyses, None), 1);
     }
 
     #[test]
-    fn test_pipeline_geometry_all_fail() {
+    fn test_redaction_masks_plates_in_logs_and_reasoning() {
+        use crate::redact::RedactionConfig;
+
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2,"plateText":"品川 500 あ 12-34"}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,"reasoning":"品川 500 あ 12-34 の荷台は山盛り"}"#;
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let config = BoxOverlayConfig::builder()
+            .ensemble_count(1)
+            .redaction(Redactor::new(&RedactionConfig::default()).unwrap())
+            .build()
+            .unwrap();
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!((result.height_m - 0.48).abs() < 1e-9);
+        assert!(!result.geometry_runs[0].raw_response.contains("12-34"));
+        assert!(!result.fill_runs[0].raw_response.contains("12-34"));
+        assert_eq!(result.reasoning, "*** の荷台は山盛り");
+    }
+
+    #[test]
+    fn test_plate_region_union_of_runs() {
+        let a = r#"{"plateBox":[0.4,0.7,0.6,0.8],"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let b = r#"{"plateBox":[0.42,0.68,0.61,0.79],"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![a, b], vec![fill_json, fill_json]);
+
+        let result = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        let region = result.plate_region.unwrap();
+        assert_eq!(region.normalized, [0.4, 0.68, 0.61, 0.8]);
+        assert_eq!(region.pixels, None);
+        assert_eq!(region.to_pixels(1000, 500), [400, 340, 610, 400]);
+
         let backend = MockBackend::new(
-            vec!["not json at all", "also bad"],
-            vec![r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#],
+            vec![r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#],
+            vec![fill_json],
         );
         let config = BoxOverlayConfig {
-            truck_class: "4t".to_string(),
-            material_type: "As殻".to_string(),
-            ensemble_count: 2,
+            ensemble_count: 1,
+            ..Default::default()
         };
-
-        let result = analyze_box_overlay(&backend, &[], &config);
-        assert!(matches!(result, Err(PipelineError::NoValidGeometry)));
+        assert!(analyze_box_overlay(&backend, &[], &config).unwrap().plate_region.is_none());
     }
 
     #[test]
-    fn test_pipeline_fill_all_fail() {
-        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
-        let backend = MockBackend::new(vec![geo_json], vec!["bad fill"]);
+    fn test_overlay_from_representative_run() {
+        let low = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.25}"#;
+        let mid = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let high = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.1}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(vec![low, mid, high], vec![fill_json; 3]);
         let config = BoxOverlayConfig {
-            truck_class: "4t".to_string(),
-            material_type: "As殻".to_string(),
-            ensemble_count: 1,
+            ensemble_count: 3,
+            ..Default::default()
         };
 
-        let result = analyze_box_overlay(&backend, &[], &config);
-        assert!(matches!(result, Err(PipelineError::NoValidFill)));
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let overlay = result.overlay.unwrap();
+        let cargo = overlay.find(crate::overlay::OverlayKind::CargoTop).unwrap();
+        assert_eq!(cargo.shape, crate::overlay::OverlayShape::HLine { y: 0.2, x0: 0.0, x1: 1.0 });
+        assert_eq!(cargo.label, "荷山頂部 0.48m");
+
+        let backend = MockBackend::new(vec![], vec![fill_json]);
+        let manual = analyze_box_overlay_with_geometry(&backend, &[], &config, 0.4).unwrap();
+        assert!(manual.overlay.is_none());
     }
 
     #[test]
-    fn test_pipeline_partial_geometry_success() {
-        // First run fails, second succeeds
-        let good_geo = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+    fn test_multi_frame_median_over_frames() {
+        let bad_angle = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.05}"#;
+        let good = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
         let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let backend = MockBackend::new(
+            vec![bad_angle, bad_angle, good, good, "garbage", good],
+            vec![fill_json, fill_json],
+        );
+        let frames: Vec<_> =
+            [b"f0", b"f1", b"f2"].map(|f| InputImage::with_format(f.to_vec(), ImageFormat::Jpeg)).into();
 
-        let backend = MockBackend::new(vec!["bad json", good_geo], vec![fill_json, fill_json]);
-        let config = BoxOverlayConfig {
-            truck_class: "4t".to_string(),
-            material_type: "As殻".to_string(),
-            ensemble_count: 2,
+        let multi = analyze_box_overlay_frames(&backend, &frames, &BoxOverlayConfig::default()).unwrap();
+        assert!((multi.result.height_m - 0.48).abs() < 1e-9);
+        assert_eq!(multi.result.geometry_runs.len(), 6);
+        assert_eq!(multi.result.image_hashes.len(), 3);
+        assert_eq!(multi.best_frame, 1);
+        assert!((multi.frames[0].height_m.unwrap() - 0.72).abs() < 1e-9);
+        assert_eq!(multi.frames[2].heights.len(), 1);
+
+        let backend = MockBackend::new(vec!["garbage"], vec![fill_json]);
+        assert!(matches!(
+            analyze_box_overlay_frames(&backend, &frames, &BoxOverlayConfig::default()),
+            Err(PipelineError::NoValidGeometry)
+        ));
+    }
+
+    /// Answers the landmark, geometry and fill prompts with fixed replies
+    struct LandmarkBackend {
+        landmark: &'static str,
+        geometry: &'static str,
+    }
+
+    impl AiBackend for LandmarkBackend {
+        fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            Ok(if prompt.contains("\"landmark\"") {
+                self.landmark
+            } else if prompt.contains("tailgateTopY") {
+                self.geometry
+            } else {
+                r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#
+            }
+            .to_string())
+        }
+    }
+
+    #[test]
+    fn test_landmark_geometry_mode() {
+        let backend = LandmarkBackend {
+            landmark: r#"{"landmark":"後板とヒンジの間"}"#,
+            geometry: r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#,
+        };
+        let config = BoxOverlayConfig::builder().geometry_mode(GeometryMode::Landmark).build().unwrap();
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!((result.height_m - 0.45).abs() < 1e-9);
+        assert!(result.geometry_runs.iter().all(|r| r.landmark == Some(Landmark::BetweenBackPanelAndHinge)));
+        assert!(result.warnings.iter().all(|w| !matches!(w, AnalysisWarning::LandmarkFallback { .. })));
+
+        let plan = plan_box_overlay(&config, 1);
+        assert_eq!(plan.calls[0].stage, PromptStage::Landmark);
+        assert_eq!(plan.calls[0].prompt, SPEC.landmark_prompt);
+    }
+
+    #[test]
+    fn test_landmark_fallback() {
+        let config = BoxOverlayConfig::builder()
+            .geometry_mode(GeometryMode::CoordinatesWithLandmarkFallback)
+            .build()
+            .unwrap();
+
+        // Coordinates usable: no landmark call
+        let backend = LandmarkBackend {
+            landmark: r#"{"landmark":"ヒンジ超え"}"#,
+            geometry: r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#,
         };
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!((result.height_m - 0.48).abs() < 1e-9);
+        assert_eq!(result.geometry_runs.len(), 2);
 
+        let backend = LandmarkBackend { landmark: r#"{"landmark":"ヒンジ超え"}"#, geometry: "garbage" };
         let result = analyze_box_overlay(&backend, &[], &config).unwrap();
-        assert!(result.height_m > 0.0);
-        // One failed, one succeeded
-        assert!(result.geometry_runs[0].parsed.is_none());
-        assert!(result.geometry_runs[1].parsed.is_some());
+        assert!((result.height_m - 0.75).abs() < 1e-9);
+        assert_eq!(result.geometry_runs.len(), 4);
+        assert!(result.warnings.contains(&AnalysisWarning::LandmarkFallback { runs: 2 }));
+
+        let backend = LandmarkBackend { landmark: "garbage", geometry: "garbage" };
+        assert!(matches!(analyze_box_overlay(&backend, &[], &config), Err(PipelineError::NoValidGeometry)));
     }
 
     #[test]
-    fn test_pipeline_clamps_fill_to_spec_ranges() {
+    fn test_run_failures_recorded_and_serialized() {
         let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
-        // AI returns out-of-range fill values
-        let fill_json = r#"{"fillRatioL":0.1,"fillRatioW":0.99,"taperRatio":0.2,"packingDensity":0.99}"#;
+        let no_tailgate = r#"{"tailgateTopY":0,"tailgateBottomY":0,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let fill_broken = r#"{"fillRatioL": oops}"#;
+        let backend = MockBackend::new(vec![geo_json, no_tailgate, "garbage"], vec![fill_json, fill_broken]);
+        let config = BoxOverlayConfig::builder().ensemble_count(3).build().unwrap();
+
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let codes: Vec<Option<&str>> =
+            result.geometry_runs.iter().map(|r| r.failure.as_ref().map(|f| f.code())).collect();
+        assert_eq!(codes, [None, Some("RUN_REJECTED"), Some("PARSE_NO_JSON")]);
+        assert!(result.geometry_runs[1..].iter().all(|r| r.scale_method == "none"));
+        assert_eq!(result.fill_runs[1].failure.as_ref().unwrap().code(), "PARSE_INVALID_JSON");
+        assert!(result.fill_runs[2].failure.is_some());
+
+        let json = serde_json::to_value(&result.geometry_runs[2]).unwrap();
+        assert_eq!(json["failure"]["kind"], "parse");
+        assert_eq!(json["failure"]["code"], "PARSE_NO_JSON");
+        assert_eq!(json["scaleMethod"], "none");
+        let json = serde_json::to_value(&result.geometry_runs[0]).unwrap();
+        assert!(json["failure"].is_null());
+        assert!(json["heightM"].as_f64().unwrap() > 0.0);
 
+        let timeout = RunFailure::from_call(&config, &PipelineError::CallTimeout(Duration::from_millis(800)));
+        assert_eq!(serde_json::to_value(&timeout).unwrap(), serde_json::json!({"kind": "timeout", "limitMs": 800}));
+    }
+
+    #[test]
+    fn test_log_retention() {
+        let reply = "結果: {\"x\":1}".to_string();
+        assert_eq!(LogRetention::Full.apply(reply.clone()), reply);
+        // "結" is 3 bytes: a 5-byte cut keeps one character
+        assert_eq!(LogRetention::Truncated(5).apply(reply.clone()), "結…");
+        assert_eq!(LogRetention::Truncated(100).apply(reply.clone()), reply);
+        assert_eq!(LogRetention::None.apply(reply.clone()), "");
+        let hashed = LogRetention::HashOnly.apply(reply.clone());
+        assert_eq!(hashed, format!("sha256:{}", sha256_hex(reply.as_bytes())));
+
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
         let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
-        let config = BoxOverlayConfig {
-            truck_class: "4t".to_string(),
-            material_type: "As殻".to_string(),
-            ensemble_count: 1,
+        let config = BoxOverlayConfig::builder().log_retention(LogRetention::HashOnly).build().unwrap();
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.geometry_runs[0].raw_response, format!("sha256:{}", sha256_hex(geo_json.as_bytes())));
+        assert!(result.fill_runs.iter().all(|r| r.raw_response.starts_with("sha256:") && r.parsed.is_some()));
+    }
+
+    #[test]
+    fn test_reasonings_kept_per_run_and_merged() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill = |reasoning: &str| {
+            let values = r#""fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8"#;
+            format!(r#"{{{values},"reasoning":"{reasoning}"}}"#)
         };
+        let (a, a_again, b) = (fill("山盛り"), fill(" 山盛り "), fill("前積み"));
+        let backend = MockBackend::new(vec![geo_json], vec![&a, &a_again, &b]);
+        let config = BoxOverlayConfig::builder().ensemble_count(3).build().unwrap();
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.reasoning, "山盛り\n前積み");
+        let runs: Vec<_> = result.reasonings.iter().map(|r| (r.run, r.text.as_str())).collect();
+        assert_eq!(runs, [(0, "山盛り"), (1, "山盛り"), (2, "前積み")]);
 
+        // The third non-geometry call is the summary
+        let summary = r#"{"summary":"山盛りだが前積みとの見方もある"}"#;
+        let backend = MockBackend::new(vec![geo_json], vec![&a, &b, summary]);
+        let config = BoxOverlayConfig::builder().reasoning_summary(ReasoningSummary::Model).build().unwrap();
         let result = analyze_box_overlay(&backend, &[], &config).unwrap();
-        let r = &SPEC.ranges;
-        assert!(result.fill_ratio_l >= r.fill_ratio_l.min);
-        assert!(result.fill_ratio_w <= r.fill_ratio_w.max);
-        assert!(result.taper_ratio >= r.taper_ratio.min);
+        assert_eq!(result.reasoning, "山盛りだが前積みとの見方もある");
+        assert_eq!((result.reasonings.len(), result.fill_runs.len()), (2, 2));
+        assert_eq!(plan_box_overlay(&config, 1).calls.last().unwrap().stage, PromptStage::Reasoning);
+
+        // Identical reasonings need no summary call; a failed summary keeps the merge
+        let backend = MockBackend::new(vec![geo_json], vec![&a, &a_again, "garbage"]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.reasoning, "山盛り");
+        let backend = MockBackend::new(vec![geo_json], vec![&a, &b, "garbage"]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.reasoning, "山盛り\n前積み");
     }
 
     #[test]
-    fn test_pipeline_result_matches_ts_calculation() {
-        // Use known geometry: tailgate top=0.3, bot=0.5, cargo_top=0.2, bed_height=0.32
-        // height_from_geometry: tg_height_norm=0.2, m_per_norm=1.6, h=(0.5-0.2)*1.6=0.48
+    fn test_ensemble_escalation() {
         let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let geo_low = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.4}"#;
         let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let fill_low = r#"{"fillRatioL":0.3,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
 
+        // Agreeing runs: no extra calls even with escalation allowed
+        let config = BoxOverlayConfig::builder().max_escalation_runs(3).build().unwrap();
         let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
-        let config = BoxOverlayConfig {
-            truck_class: "4t".to_string(),
-            material_type: "As殻".to_string(),
-            ensemble_count: 1,
-        };
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!((result.geometry_runs.len(), result.fill_runs.len()), (2, 2));
 
+        // Disagreeing heights: capped at the configured extra runs
+        let config = BoxOverlayConfig::builder().max_escalation_runs(2).build().unwrap();
+        let backend = MockBackend::new(vec![geo_json, geo_low], vec![fill_json]);
         let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!((result.geometry_runs.len(), result.fill_runs.len()), (4, 2));
+        let escalated: Vec<bool> = result.geometry_runs.iter().map(|r| r.escalated).collect();
+        assert_eq!(escalated, [false, false, true, true]);
+        assert!(result.warnings.contains(&AnalysisWarning::GeometryEscalated { runs: 2 }));
 
-        // height should be 0.48
-        assert!(
-            (result.height_m - 0.48).abs() < 0.01,
-            "height={}, expected ~0.48",
-            result.height_m
-        );
+        // Disagreeing fill, escalation off: unchanged
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json, fill_low]);
+        let result = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert_eq!(result.fill_runs.len(), 2);
+        assert!(!result.warnings.iter().any(|w| matches!(w, AnalysisWarning::FillEscalated { .. })));
 
-        // Manual: bedL=3.4, bedW=2.06
-        // effectiveL = 0.8 * 0.9 = 0.72
-        // effectiveW = (0.9 + 0.85) / 2 = 0.875
-        // volume = 3.4 * 2.06 * 0.48 * 0.72 * 0.875 = ~2.117
-        assert!(result.volume > 2.0 && result.volume < 2.3, "volume={}", result.volume);
-        // tonnage should be in a reasonable range
-        assert!(result.tonnage > 3.0 && result.tonnage < 5.0, "tonnage={}", result.tonnage);
+        let config = BoxOverlayConfig::builder().max_escalation_runs(1).build().unwrap();
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json, fill_low]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.fill_runs.len(), 3);
+        assert!(result.fill_runs[2].escalated);
+        assert!(result.warnings.contains(&AnalysisWarning::FillEscalated { runs: 1 }));
+    }
+
+    #[test]
+    fn test_occlusion_check() {
+        let clear = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2,"occludedRatio":0.2}"#;
+        let hidden = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.4,"occludedRatio":0.7}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        // Not configured: not asked for, reported ratios ignored
+        let plain = BoxOverlayConfig::builder().ensemble_count(3).build().unwrap();
+        assert!(!plain.geometry_run_prompt(0).1.contains("occludedRatio"));
+        let backend = MockBackend::new(vec![clear, hidden, clear], vec![fill_json]);
+        let baseline = analyze_box_overlay(&backend, &[], &plain).unwrap();
+        assert_eq!(baseline.occluded_ratio, None);
+        assert_eq!(baseline.statistics.height.values.len(), 3);
+
+        let config = plain.into_builder().occlusion(OcclusionCheck::default()).build().unwrap();
+        assert!(config.geometry_run_prompt(0).1.contains("occludedRatio"));
+        let backend = MockBackend::new(vec![clear, hidden, clear], vec![fill_json]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        let reason = i18n::REJECT_OCCLUDED.format(language(), &[&70]);
+        assert_eq!(result.geometry_runs[1].failure, Some(RunFailure::Rejected { reason }));
+        assert_eq!(result.statistics.height.values.len(), 2);
+        assert_eq!(result.occluded_ratio, Some(0.2));
+        assert!(result.warnings.contains(&AnalysisWarning::OcclusionRejected { runs: 1 }));
+        assert!(result.warnings.contains(&AnalysisWarning::Occluded { ratio: 0.2 }));
+        assert!(result.tonnage_min < result.tonnage * 0.8);
+        assert!(result.tonnage_max > result.tonnage);
+
+        // Every run occluded: no height rather than a confident wrong one
+        let backend = MockBackend::new(vec![hidden], vec![fill_json]);
+        let err = analyze_box_overlay(&backend, &[], &config).unwrap_err();
+        assert!(matches!(err, PipelineError::NoValidGeometry));
     }
 
     #[test]
-    fn test_median_odd() {
-        assert!((median(&[3.0, 1.0, 2.0]) - 2.0).abs() < f64::EPSILON);
+    fn test_tarp_mode() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json =
+            r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,"materialType":"土砂"}"#;
+
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let open = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert!(!open.covered);
+        assert_eq!(open.material_type, "土砂");
+
+        let config = BoxOverlayConfig::builder().tarp(TarpMode { deflation: 0.8 }).build().unwrap();
+        assert!(config.geometry_run_prompt(0).1.contains("tarp profile"));
+        assert!(config.fill_run_prompt(0).1.contains("covered by a tarp"));
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let covered = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert!(covered.covered);
+        assert!((covered.height_m - round3(open.height_m * 0.8)).abs() < 1e-9);
+        assert!(covered.volume < open.volume);
+        // The material under the sheet is not trusted
+        assert_eq!(covered.material_type, "As殻");
+
+        for deflation in [0.0, 1.5] {
+            let err = BoxOverlayConfig::builder().tarp(TarpMode { deflation }).build().unwrap_err();
+            assert_eq!(err.code(), "INVALID_TARP_DEFLATION");
+        }
     }
 
     #[test]
-    fn test_median_even() {
-        // Our median takes sorted[len/2], so for [1,2,3,4] -> sorted[2] = 3
-        assert!((median(&[4.0, 1.0, 3.0, 2.0]) - 3.0).abs() < f64::EPSILON);
+    fn test_empty_bed() {
+        let floor = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.5}"#;
+        let noise = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.495}"#;
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        // Fill replies that would fail the stage are never requested
+        let config = BoxOverlayConfig::builder().ensemble_count(3).build().unwrap();
+        let backend = MockBackend::new(vec![floor, noise, floor], vec!["no json"]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.load_state, LoadState::Empty);
+        assert_eq!(result.fill_source, FillSource::Skipped);
+        assert_eq!(result.reasoning, i18n::REASONING_EMPTY_BED.get(language()));
+        assert!(result.fill_runs.is_empty());
+        assert_eq!((result.tonnage, result.tonnage_min, result.tonnage_max), (0.0, 0.0, 0.0));
+        assert!(!result.needs_review && result.disagreement.is_none());
+
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (3, 0));
+
+        // A manual height of an empty bed makes no call at all
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let result = analyze_box_overlay_with_geometry(&backend, &[], &config, 0.0).unwrap();
+        assert_eq!(result.load_state, LoadState::Empty);
+        assert_eq!(result.tonnage, 0.0);
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (0, 0));
+        analyze_box_overlay_with_geometry(&backend, &[], &config, 0.3).unwrap();
+        assert_eq!(backend.with(|m| m.fill_call), 3);
+
+        // Interleaved fill runs are made but not used, and never escalated
+        let fill_low = r#"{"fillRatioL":0.3,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let config = config.into_builder().schedule(StageSchedule::Interleaved).max_escalation_runs(2).build().unwrap();
+        let backend = MockBackend::new(vec![floor], vec![fill_json, fill_low]);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.load_state, LoadState::Empty);
+        assert_eq!(result.fill_runs.len(), 3);
+        assert_eq!(result.tonnage, 0.0);
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (3, 3));
+        let concurrent = MockBackend::new(vec![floor], vec![fill_json, fill_low]);
+        assert_eq!(analyze_box_overlay_concurrent(&concurrent, &[], &config).unwrap().tonnage, 0.0);
+        assert_eq!(concurrent.with(|m| m.fill_call), 5);
+
+        // Loaded: the disagreeing fill runs are escalated as usual
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json, fill_low]);
+        analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(backend.with(|m| m.fill_call), 5);
+
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let result = analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert_eq!(result.load_state, LoadState::Loaded);
+        assert!(result.tonnage > 0.0);
     }
 
+    const FLOOR: &str = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.5}"#;
+
     #[test]
-    fn test_average() {
-        assert!((average(&[1.0, 2.0, 3.0]) - 2.0).abs() < f64::EPSILON);
+    fn test_empty_bed_partial() {
+        let backend = MockBackend::new(vec![FLOOR], vec!["no json"]);
+        let config = BoxOverlayConfig::default();
+        let partial = analyze_box_overlay_partial(&backend, &[], &config).unwrap();
+        assert!(partial.is_complete());
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (2, 0));
+        let result = partial.complete(&config, None, None).unwrap();
+        assert_eq!(result.load_state, LoadState::Empty);
+        assert_eq!(result.fill_source, FillSource::Skipped);
+        assert_eq!(result.tonnage, 0.0);
     }
 
     #[test]
-    fn test_pipeline_invalid_tailgate_top_skipped() {
-        // tailgateTopY = 0 should be skipped (invalid)
-        let bad_geo = r#"{"tailgateTopY":0.0,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
-        let good_geo = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+    fn test_empty_bed_frames() {
+        let backend = MockBackend::new(vec![FLOOR], vec!["no json"]);
+        let frames: Vec<_> = [b"f0", b"f1"].map(|f| InputImage::with_format(f.to_vec(), ImageFormat::Jpeg)).into();
+        let multi = analyze_box_overlay_frames(&backend, &frames, &BoxOverlayConfig::default()).unwrap();
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (4, 0));
+        assert_eq!(multi.result.load_state, LoadState::Empty);
+        assert_eq!(multi.result.tonnage, 0.0);
+    }
+
+    #[test]
+    fn test_empty_bed_strategy() {
+        let backend = MockBackend::new(vec![FLOOR], vec!["no json"]);
+        let result = analyze_with(&BoxOverlayStrategy, &backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (2, 0));
+        assert_eq!(result.load_state, LoadState::Empty);
+        assert_eq!(result.fill_source, FillSource::Skipped);
+        assert_eq!(result.tonnage, 0.0);
+    }
+
+    #[test]
+    fn test_analyze_delta() {
+        let full = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2,"plateClass":"大板"}"#;
+        let partial = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.35}"#;
+        let fill_json =
+            r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,"materialType":"土砂"}"#;
+        let fill_after = r#"{"fillRatioL":0.7,"fillRatioW":0.8,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        // Two runs per stage: before, then after
+        let backend = MockBackend::new(vec![full, full, partial], vec![fill_json, fill_json, fill_after]);
+        let delta = analyze_delta(&backend, &[], &[], &BoxOverlayConfig::default()).unwrap();
+        assert!(delta.after.height_m < delta.before.height_m);
+        assert_eq!(delta.tonnage_delta(), round2(delta.after.tonnage - delta.before.tonnage));
+        assert!(delta.removed() > 0.0);
+        assert_eq!(delta.added(), 0.0);
+        // Material detected before is kept for the after photos
+        assert_eq!(delta.after.material_type, "土砂");
+        assert!(delta.diff.material_type.is_none());
+    }
+
+    #[test]
+    fn test_analyze_delta_reuses_calibration() {
+        let before = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2,"plateClass":"大板"}"#;
+        // Tailgate bottom hidden on the after photos: no scale reference of their own
+        let after = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.0,"cargoTopY":0.35}"#;
         let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let config = BoxOverlayConfig::builder()
+            .geometry_mode(GeometryMode::CoordinatesWithLandmarkFallback)
+            .keep_run_logs(false)
+            .build()
+            .unwrap();
 
-        let backend = MockBackend::new(vec![bad_geo, good_geo], vec![fill_json, fill_json]);
-        let config = BoxOverlayConfig {
-            truck_class: "4t".to_string(),
-            material_type: "As殻".to_string(),
-            ensemble_count: 2,
+        // Measured alone, the after photos fall back to landmark calls (served
+        // from the fill replies here, so they fail)
+        let backend = MockBackend::new(vec![after], vec![fill_json]);
+        assert!(matches!(analyze_box_overlay(&backend, &[], &config), Err(PipelineError::NoValidGeometry)));
+        assert_eq!(backend.with(|m| m.fill_call), 2);
+
+        let backend = MockBackend::new(vec![before, before, after], vec![fill_json]);
+        let delta = analyze_delta(&backend, &[], &[], &config).unwrap();
+        assert!(delta.before.geometry_runs.is_empty());
+        assert_eq!(delta.before.plate_class.as_deref(), Some("大板"));
+        let calibration = delta.before.calibration.clone().unwrap();
+        assert_eq!(calibration.method, "tailgate");
+        assert!((calibration.m_per_norm - 1.6).abs() < 1e-9);
+        // 0.32 m tailgate + (0.3 - 0.35) × 1.6, with no landmark call
+        assert!((delta.after.height_m - 0.24).abs() < 1e-9);
+        assert_eq!(delta.after.calibration, Some(calibration));
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (4, 4));
+    }
+
+    #[test]
+    fn test_analyze_delta_from_empty_bed() {
+        let floor = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.5,"plateClass":"大板"}"#;
+        let loaded = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        let config = BoxOverlayConfig::builder().material_type("Co殻").build().unwrap();
+        let backend = MockBackend::new(vec![floor, floor, loaded], vec![fill_json]);
+        let delta = analyze_delta(&backend, &[], &[], &config).unwrap();
+        assert_eq!(delta.before.load_state, LoadState::Empty);
+        assert_eq!(delta.after.load_state, LoadState::Loaded);
+        assert_eq!(delta.before.tonnage, 0.0);
+        assert_eq!(delta.added(), delta.after.tonnage);
+        assert_eq!(delta.removed(), 0.0);
+        // The empty bed made no fill calls; the after photos keep the configured material
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (4, 2));
+        assert_eq!(delta.after.material_type, "Co殻");
+    }
+
+    #[test]
+    fn test_mode_string_tie_goes_to_first_seen() {
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(mode_string(&strings(&["中板", "大板", "大板", "中板"])).as_deref(), Some("中板"));
+        assert_eq!(mode_string(&strings(&["大板", "中板"])).as_deref(), Some("大板"));
+        assert_eq!(mode_string(&strings(&["中板", "大板", "大板"])).as_deref(), Some("大板"));
+        assert!(mode_string(&[]).is_none());
+    }
+
+    #[test]
+    fn test_weighted_material_vote() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill = |material: &str, confidence: f64| {
+            format!(
+                r#"{{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8,{}}}"#,
+                format_args!(r#""materialType":"{}","confidenceScore":{}"#, material, confidence)
+            )
         };
+        let fills = [fill("土砂", 0.4), fill("Co殻", 0.9), fill("土砂", 0.4)];
+        let fills: Vec<&str> = fills.iter().map(String::as_str).collect();
 
+        // One confident run outweighs two unsure ones
+        let config = BoxOverlayConfig::builder().ensemble_count(3).build().unwrap();
+        let backend = MockBackend::new(vec![geo_json], fills.clone());
         let result = analyze_box_overlay(&backend, &[], &config).unwrap();
-        assert!(result.height_m > 0.0);
-        assert_eq!(result.geometry_runs[0].scale_method, "none");
-        assert_ne!(result.geometry_runs[1].scale_method, "none");
+        assert_eq!(result.material_type, "Co殻");
+        let counts: Vec<_> = result.material_votes.votes.iter().map(|v| (v.material.as_str(), v.count)).collect();
+        assert_eq!(counts, [("Co殻", 1), ("土砂", 2)]);
+
+        // 0.9 / 1.7 of the weight is not enough agreement to override
+        let config = config.into_builder().material_agreement(0.6).build().unwrap();
+        let backend = MockBackend::new(vec![geo_json], fills);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.material_type, "As殻");
+    }
+
+    #[test]
+    fn test_per_stage_run_counts() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+
+        let config = BoxOverlayConfig::builder().geometry_runs(3).fill_runs(1).build().unwrap();
+        assert_eq!((config.geometry_run_count(), config.fill_run_count()), (3, 1));
+        let result = analyze_box_overlay(&MockBackend::new(vec![geo_json], vec![fill_json]), &[], &config).unwrap();
+        assert_eq!((result.geometry_runs.len(), result.fill_runs.len()), (3, 1));
+
+        // Interleaved: pairs while both stages have runs left, then the rest
+        let config = config.into_builder().schedule(StageSchedule::Interleaved).build().unwrap();
+        let plan = plan_box_overlay(&config, 1);
+        let stages: Vec<PromptStage> = plan.calls.iter().map(|c| c.stage).collect();
+        assert_eq!(stages, [PromptStage::Geometry, PromptStage::Fill, PromptStage::Geometry, PromptStage::Geometry]);
+        let result = analyze_box_overlay(&MockBackend::new(vec![geo_json], vec![fill_json]), &[], &config).unwrap();
+        assert_eq!((result.geometry_runs.len(), result.fill_runs.len()), (3, 1));
+
+        // The legacy shorthand still covers the stage without an override
+        let config = BoxOverlayConfig::builder().ensemble_count(3).fill_runs(1).build().unwrap();
+        assert_eq!((config.geometry_run_count(), config.fill_run_count()), (3, 1));
+        let err = BoxOverlayConfig::builder().fill_runs(0).build().unwrap_err();
+        assert_eq!(err, ConfigError::ZeroEnsembleCount);
+    }
+
+    /// Reports provider limits and records what each call sent
+    struct LimitedBackend {
+        sent: std::sync::Mutex<Vec<(Vec<u8>, bool)>>,
+    }
+
+    impl AiBackend for LimitedBackend {
+        fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            unreachable!("pipeline should pass options")
+        }
+
+        fn capabilities(&self) -> BackendCapabilities {
+            BackendCapabilities {
+                max_images: Some(2),
+                max_image_bytes: Some(8),
+                structured_output: true,
+                streaming: false,
+            }
+        }
+
+        fn send_prompt_with_options(
+            &self,
+            prompt: &str,
+            images: &[InputImage],
+            options: &BackendOptions,
+        ) -> Result<(String, String), PipelineError> {
+            let first_bytes = images.iter().map(|i| i.bytes[0]).collect();
+            self.sent.lock().unwrap().push((first_bytes, options.json_output));
+            let reply = if prompt.contains("tailgateTopY") {
+                r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#
+            } else {
+                r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#
+            };
+            Ok((reply.to_string(), self.name().to_string()))
+        }
+    }
+
+    #[test]
+    fn test_backend_capabilities_adapt_calls() {
+        let backend = LimitedBackend { sent: Default::default() };
+        let images: Vec<InputImage> =
+            (0..3).map(|i| InputImage::with_format(vec![i, 0, 0], ImageFormat::Jpeg)).collect();
+        let config = BoxOverlayConfig::builder().geometry_runs(3).fill_runs(1).build().unwrap();
+        let result = analyze_box_overlay(&backend, &images, &config).unwrap();
+        assert!(result.geometry_runs.iter().all(|r| !r.options.json_output), "logs keep the configured options");
+
+        // Three images in batches of two, rotated across runs; JSON mode on every call
+        let sent = backend.sent.lock().unwrap().clone();
+        let batches: Vec<Vec<u8>> = sent.iter().map(|(b, _)| b.clone()).collect();
+        assert_eq!(batches, [vec![0, 1], vec![2], vec![0, 1], vec![0, 1]]);
+        assert!(sent.iter().all(|(_, json)| *json));
+
+        let too_big = [InputImage::with_format(vec![0; 9], ImageFormat::Jpeg)];
+        let err = analyze_box_overlay(&backend, &too_big, &config).unwrap_err();
+        assert!(matches!(err, PipelineError::ImageTooLarge { index: 0, bytes: 9, max: 8 }));
+        assert_eq!(backend.sent.lock().unwrap().len(), 4, "rejected before any call");
+
+        // A fallback chain only promises what every backend accepts
+        let chain = crate::backend::FallbackBackend::new(Box::new(LimitedBackend { sent: Default::default() }))
+            .with_fallback(Box::new(MockBackend::new(vec![], vec![])));
+        let caps = chain.capabilities();
+        assert_eq!((caps.max_images, caps.max_image_bytes, caps.structured_output), (Some(2), Some(8), false));
+    }
+
+    #[test]
+    fn test_batch_fans_out_in_job_order() {
+        let backend = ConcurrencyProbe {
+            in_flight: Default::default(),
+            max_in_flight: Default::default(),
+        };
+        let jobs: Vec<Vec<InputImage>> =
+            (0..6u8).map(|i| vec![InputImage::with_format(vec![i], ImageFormat::Jpeg)]).collect();
+        let results = analyze_box_overlay_batch(&backend, &jobs, &BoxOverlayConfig::default(), 3);
+        assert_eq!(results.len(), 6);
+        for (job, result) in jobs.iter().zip(&results) {
+            let result = result.as_ref().unwrap();
+            assert_eq!(result.image_hashes, hash_images(job), "job order kept");
+        }
+        assert_eq!(backend.max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let none: [Vec<InputImage>; 0] = [];
+        assert!(analyze_box_overlay_batch(&backend, &none, &BoxOverlayConfig::default(), 0).is_empty());
+    }
+
+    /// Answers the multi-param, geometry and fill prompts of one load
+    struct StrategyBackend;
+    impl AiBackend for StrategyBackend {
+        fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            let reply = if prompt.contains("fillRatioZ") {
+                r#"{"isTargetDetected":true,"materialType":"As殻","height":0.4,"packingDensity":0.8,
+                    "fillRatioL":0.8,"fillRatioW":0.85,"fillRatioZ":0.9,"confidenceScore":0.7,"reasoning":"山形"}"#
+            } else if prompt.contains("tailgateTopY") {
+                r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#
+            } else {
+                r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#
+            };
+            Ok(reply.to_string())
+        }
+    }
+
+    #[test]
+    fn test_multi_param_strategy() {
+        let config = BoxOverlayConfig::builder()
+            .truck_class("4t")
+            .ensemble_count(2)
+            .strategy(Strategy::MultiParam)
+            .build()
+            .unwrap();
+        let result = analyze(&StrategyBackend, &[], &config).unwrap();
+        assert_eq!(result.strategy(), Strategy::MultiParam);
+        let StrategyResult::MultiParam(multi) = result else {
+            panic!("multi-param result expected");
+        };
+        assert_eq!((multi.height_m, multi.fill_ratio_z, multi.confidence), (0.4, 0.9, 0.7));
+        assert_eq!(multi.runs.len(), 2);
+        assert_eq!(multi.reasoning, "山形");
+        let params = MultiParams {
+            height: 0.4,
+            fill_ratio_l: 0.8,
+            fill_ratio_w: 0.85,
+            fill_ratio_z: 0.9,
+            packing_density: 0.8,
+            material_type: "As殻".into(),
+        };
+        assert_eq!(multi.tonnage, crate::calculation::calculate_multi_param(&params, Some("4t")).tonnage);
+
+        let comparison = compare_strategies(&StrategyBackend, &[], &config).unwrap();
+        assert_eq!(comparison.multi_param.tonnage, multi.tonnage);
+        assert_eq!(
+            comparison.tonnage_difference(),
+            round2(multi.tonnage - comparison.box_overlay.tonnage)
+        );
+
+        // Runs that saw no truck are rejected
+        let backend = MockBackend::new(vec![], vec![r#"{"isTargetDetected":false,"height":0.0}"#]);
+        let err = analyze_multi_param(&backend, &[], &config).unwrap_err();
+        assert_eq!(err.code(), "NO_VALID_MULTI_PARAM");
+        assert_eq!(backend.with(|m| m.fill_call), 2);
+    }
+
+    /// Plugin strategy: the height from landmark answers alone, as a tonnage
+    /// over the full bed at the default packing
+    struct LandmarkOnly;
+    impl EstimationStrategy for LandmarkOnly {
+        type Run = GeometryRunLog;
+        type Estimate = f64;
+        type Output = f64;
+
+        fn name(&self) -> &str {
+            "landmark-only"
+        }
+
+        fn prompts(&self, _cx: &StrategyContext<'_>) -> Vec<StrategyCall> {
+            let call = |run| StrategyCall {
+                stage: PromptStage::Landmark,
+                run,
+                variant: "landmark".into(),
+                prompt: SPEC.landmark_prompt.clone(),
+            };
+            vec![call(0), call(1)]
+        }
+
+        fn parse(&self, cx: &StrategyContext<'_>, reply: StrategyReply) -> GeometryRunLog {
+            landmark_run_log(cx.config, reply.backend, reply.options, reply.reply)
+        }
+
+        fn aggregate(&self, _cx: &StrategyContext<'_>, runs: Vec<GeometryRunLog>) -> Result<f64, PipelineError> {
+            let heights: Vec<f64> = runs.iter().filter(|r| is_valid_geometry_run(r)).map(|r| r.height_m).collect();
+            if heights.is_empty() {
+                return Err(PipelineError::NoValidGeometry);
+            }
+            Ok(crate::ensemble::median(&heights))
+        }
+
+        fn calculate(&self, cx: &StrategyContext<'_>, height: f64) -> f64 {
+            let (l, w) = bed_for(cx.config);
+            round2(l * w * height * density_for(cx.config, &cx.config.material_type).0 * 0.7)
+        }
+    }
+
+    #[test]
+    fn test_estimation_strategies() {
+        let geo_json = r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#;
+        let fill_json = r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#;
+        let config = BoxOverlayConfig::builder().truck_class("4t").ensemble_count(2).build().unwrap();
+
+        let direct = analyze_box_overlay(&MockBackend::new(vec![geo_json], vec![fill_json]), &[], &config).unwrap();
+        let backend = MockBackend::new(vec![geo_json], vec![fill_json]);
+        let plugged = analyze_with(&BoxOverlayStrategy, &backend, &[], &config).unwrap();
+        assert_eq!((plugged.height_m, plugged.tonnage), (direct.height_m, direct.tonnage));
+        assert_eq!((plugged.geometry_runs.len(), plugged.fill_runs.len()), (2, 2));
+        assert_eq!(backend.with(|m| (m.geo_call, m.fill_call)), (2, 2));
+
+        // A strategy added without touching the pipeline
+        let backend = MockBackend::new(vec![], vec![r#"{"landmark":"後板と同じ"}"#]);
+        let tonnage = analyze_with(&LandmarkOnly, &backend, &[], &config).unwrap();
+        let (l, w) = bed_for(&config);
+        assert_eq!(tonnage, round2(l * w * landmark_height(Landmark::AtBackPanel) * 2.5 * 0.7));
+        assert_eq!(LandmarkOnly.name(), "landmark-only");
     }
 }
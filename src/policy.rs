@@ -0,0 +1,228 @@
+//! Site acceptance rules
+//!
+//! A `TolerancePolicy` classifies each result as OK / Review / Reject, so
+//! site-specific acceptance rules live in configuration instead of
+//! application code. The pipeline consults it through
+//! `BoxOverlayConfig::policy`, reconciliation through `TolerancePolicy::reconcile`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::pipeline::{BoxOverlayResult, FillSource};
+use crate::reconcile::{reconcile, BatchReconciliation, ManifestEntry, Reconciliation, Tolerance};
+use crate::spec::{get_truck_spec, TruckSpec};
+
+/// Classification of a result, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    #[default]
+    Ok,
+    /// Needs a person to look at it
+    Review,
+    Reject,
+}
+
+impl Verdict {
+    /// Stable machine-readable code ("ok", "review", "reject")
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Review => "review",
+            Self::Reject => "reject",
+        }
+    }
+
+    /// Verdict of a `code`
+    pub fn from_code(code: &str) -> Option<Self> {
+        [Self::Ok, Self::Review, Self::Reject].into_iter().find(|v| v.code() == code)
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "OK",
+            Self::Review => "要確認",
+            Self::Reject => "不合格",
+        })
+    }
+}
+
+/// Deviation thresholds.
+///
+/// A deviation is OK up to `review_deviation` of the reference weight,
+/// Review up to `reject_deviation`, Reject beyond. Neither threshold is ever
+/// below `absolute_min` tonnes, so light loads are not flagged for small
+/// differences.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToleranceRule {
+    pub review_deviation: f64,
+    pub reject_deviation: f64,
+    pub absolute_min: f64,
+}
+
+impl Default for ToleranceRule {
+    fn default() -> Self {
+        Self {
+            review_deviation: 0.15,
+            reject_deviation: 0.3,
+            absolute_min: 0.3,
+        }
+    }
+}
+
+impl ToleranceRule {
+    /// Verdict for a deviation (tonnes) from a reference weight
+    pub fn classify(&self, deviation: f64, reference: f64) -> Verdict {
+        let allowed = |fraction: f64| (fraction * reference.abs()).max(self.absolute_min);
+        if deviation <= allowed(self.review_deviation) {
+            Verdict::Ok
+        } else if deviation <= allowed(self.reject_deviation) {
+            Verdict::Review
+        } else {
+            Verdict::Reject
+        }
+    }
+
+    /// The OK band as a reconciliation tolerance
+    pub fn tolerance(&self) -> Tolerance {
+        Tolerance {
+            relative: self.review_deviation,
+            absolute: self.absolute_min,
+        }
+    }
+}
+
+/// Site acceptance policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct TolerancePolicy {
+    /// Rule for materials without an entry in `materials`
+    pub default_rule: ToleranceRule,
+    /// Per-material rules keyed by material name
+    pub materials: HashMap<String, ToleranceRule>,
+    /// Send results with diverging ensemble runs to review
+    pub review_disagreement: bool,
+    /// Send results whose fill did not come from the fill ensemble to review
+    pub review_fill_fallback: bool,
+}
+
+impl Default for TolerancePolicy {
+    fn default() -> Self {
+        Self {
+            default_rule: ToleranceRule::default(),
+            materials: HashMap::new(),
+            review_disagreement: true,
+            review_fill_fallback: true,
+        }
+    }
+}
+
+impl TolerancePolicy {
+    /// Rule that applies to a material
+    pub fn rule_for(&self, material: &str) -> &ToleranceRule {
+        self.materials.get(material).unwrap_or(&self.default_rule)
+    }
+
+    /// Verdict for a pipeline result: excess over the truck's maximum
+    /// capacity is judged by the material rule, plus the review flags
+    pub fn classify(&self, result: &BoxOverlayResult) -> Verdict {
+        self.classify_for(result, get_truck_spec(&result.truck_class))
+    }
+
+    /// `classify` against a given truck spec (None = capacity not checked)
+    pub fn classify_for(&self, result: &BoxOverlayResult, truck: Option<&TruckSpec>) -> Verdict {
+        let mut verdict = match truck {
+            Some(truck) => self
+                .rule_for(&result.material_type)
+                .classify((result.tonnage - truck.max_capacity).max(0.0), truck.max_capacity),
+            None => Verdict::Ok,
+        };
+        if self.review_disagreement && result.disagreement.is_some() {
+            verdict = verdict.max(Verdict::Review);
+        }
+        if self.review_fill_fallback && result.fill_source != FillSource::Estimated {
+            verdict = verdict.max(Verdict::Review);
+        }
+        verdict
+    }
+
+    /// Reconcile against a manifest entry using the material rule
+    pub fn reconcile(&self, estimate: &BoxOverlayResult, declared: &ManifestEntry) -> Reconciliation {
+        let rule = self.rule_for(&estimate.material_type);
+        let mut r = reconcile(estimate, declared, &rule.tolerance());
+        r.verdict = r.verdict.max(rule.classify(r.difference.abs(), r.declared));
+        r
+    }
+
+    /// `reconcile` over a batch of loads
+    pub fn reconcile_batch<'a>(
+        &self,
+        loads: impl IntoIterator<Item = (&'a BoxOverlayResult, &'a ManifestEntry)>,
+    ) -> BatchReconciliation {
+        BatchReconciliation::from_loads(loads.into_iter().map(|(e, d)| self.reconcile(e, d)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    #[test]
+    fn test_pipeline_sets_verdict() {
+        let config = BoxOverlayConfig::builder().policy(TolerancePolicy::default()).build().unwrap();
+        let mut result = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        // 4.3t on a 4t truck is within the 0.6t review band
+        assert_eq!(result.verdict, Some(Verdict::Ok));
+
+        let policy = TolerancePolicy::default();
+        result.tonnage = 5.5;
+        assert_eq!(policy.classify(&result), Verdict::Reject);
+        result.tonnage = 4.0;
+        result.fill_source = FillSource::Manual;
+        assert_eq!(policy.classify(&result), Verdict::Review);
+
+        let declared = ManifestEntry {
+            id: "1".into(),
+            declared_tonnage: 3.4,
+            material_type: None,
+        };
+        let r = policy.reconcile(&result, &declared);
+        assert_eq!(r.verdict, Verdict::Review);
+        assert!(r.is_discrepancy());
+        let declared = ManifestEntry {
+            declared_tonnage: 2.5,
+            ..declared
+        };
+        assert_eq!(policy.reconcile(&result, &declared).verdict, Verdict::Reject);
+    }
+
+    #[test]
+    fn test_rule_bands() {
+        let rule = ToleranceRule::default();
+        assert_eq!(rule.classify(0.5, 4.0), Verdict::Ok);
+        assert_eq!(rule.classify(1.0, 4.0), Verdict::Review);
+        assert_eq!(rule.classify(1.5, 4.0), Verdict::Reject);
+        // Absolute floor on a light load
+        assert_eq!(rule.classify(0.3, 0.5), Verdict::Ok);
+    }
+
+    #[test]
+    fn test_per_material_rule() {
+        let mut policy = TolerancePolicy::default();
+        policy.materials.insert(
+            "土砂".into(),
+            ToleranceRule {
+                review_deviation: 0.05,
+                ..Default::default()
+            },
+        );
+        assert_eq!(policy.rule_for("土砂").review_deviation, 0.05);
+        assert_eq!(policy.rule_for("As殻"), &ToleranceRule::default());
+        assert!(Verdict::Reject > Verdict::Review && Verdict::Review > Verdict::Ok);
+        assert_eq!(Verdict::from_code(Verdict::Review.code()), Some(Verdict::Review));
+        assert_eq!(Verdict::from_code("要確認"), None);
+    }
+}
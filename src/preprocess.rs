@@ -0,0 +1,181 @@
+//! Image preprocessing before backend calls (feature `image`)
+//!
+//! Applies the EXIF orientation, downscales oversized phone photos to a
+//! maximum dimension and re-encodes everything to JPEG, so uploads stay within
+//! model payload limits and portrait shots reach the model upright.
+
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+
+use crate::i18n::{self, language, Language, Localize};
+use crate::input::{ImageFormat, InputImage};
+
+/// Preprocessing error, with the message of the underlying I/O or image error
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{}", self.localize(language()))]
+#[non_exhaustive]
+pub enum PreprocessError {
+    /// The bytes could not be read as an image
+    Read(String),
+    /// Decoding, orienting or encoding failed
+    Image(String),
+}
+
+impl PreprocessError {
+    /// Message of the underlying error
+    pub fn detail(&self) -> &str {
+        match self {
+            Self::Read(detail) | Self::Image(detail) => detail,
+        }
+    }
+}
+
+impl Localize for PreprocessError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Read(detail) => i18n::IMAGE_UNREADABLE.format(lang, &[detail]),
+            Self::Image(detail) => i18n::IMAGE_PROCESSING_FAILED.format(lang, &[detail]),
+        }
+    }
+}
+
+impl From<image::ImageError> for PreprocessError {
+    fn from(e: image::ImageError) -> Self {
+        Self::Image(e.to_string())
+    }
+}
+
+/// Preprocessing settings
+#[derive(Debug, Clone)]
+pub struct PreprocessConfig {
+    /// Longest edge in pixels after downscaling
+    pub max_dimension: u32,
+    /// JPEG quality (1-100)
+    pub jpeg_quality: u8,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            max_dimension: 1600,
+            jpeg_quality: 85,
+        }
+    }
+}
+
+/// Orient, downscale and re-encode a single image to JPEG
+pub fn preprocess_image(bytes: &[u8], config: &PreprocessConfig) -> Result<Vec<u8>, PreprocessError> {
+    let reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| PreprocessError::Read(e.to_string()))?;
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    img.apply_orientation(orientation);
+
+    let max = config.max_dimension.max(1);
+    if img.width() > max || img.height() > max {
+        img = img.resize(max, max, FilterType::Triangle);
+    }
+
+    // JPEG has no alpha channel
+    let rgb = DynamicImage::ImageRgb8(img.to_rgb8());
+    let mut out = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(&mut out, config.jpeg_quality.clamp(1, 100));
+    rgb.write_with_encoder(encoder)?;
+    Ok(out)
+}
+
+/// Width / height of an image as displayed (EXIF orientation applied),
+/// read from the header without decoding the pixels
+pub fn image_aspect(bytes: &[u8]) -> Option<f64> {
+    let (w, h) = image_dimensions(bytes)?;
+    (h > 0).then(|| w as f64 / h as f64)
+}
+
+/// (width, height) in pixels as displayed (EXIF orientation applied),
+/// read from the header without decoding the pixels
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    use image::metadata::Orientation;
+
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (w, h) = decoder.dimensions();
+    let (w, h) = match decoder.orientation().ok()? {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => (h, w),
+        _ => (w, h),
+    };
+    Some((w, h))
+}
+
+/// Preprocess every image, failing on the first undecodable one; name and
+/// capture time carry over to the JPEG copies
+pub fn preprocess_images(
+    images: &[InputImage],
+    config: &PreprocessConfig,
+) -> Result<Vec<InputImage>, PreprocessError> {
+    images
+        .iter()
+        .map(|img| {
+            Ok(InputImage {
+                bytes: preprocess_image(&img.bytes, config)?,
+                mime: ImageFormat::Jpeg,
+                ..img.clone()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbaImage};
+
+    fn png_bytes(w: u32, h: u32) -> Vec<u8> {
+        let img = DynamicImage::ImageRgba8(RgbaImage::new(w, h));
+        let mut out = Vec::new();
+        img.write_to(&mut Cursor::new(&mut out), ImageFormat::Png).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_downscale_and_reencode_to_jpeg() {
+        let config = PreprocessConfig {
+            max_dimension: 100,
+            jpeg_quality: 80,
+        };
+        let out = preprocess_image(&png_bytes(400, 200), &config).unwrap();
+        assert_eq!(&out[..2], &[0xFF, 0xD8], "output must be JPEG");
+
+        let img = image::load_from_memory(&out).unwrap();
+        assert_eq!((img.width(), img.height()), (100, 50));
+    }
+
+    #[test]
+    fn test_small_image_not_upscaled() {
+        let out = preprocess_image(&png_bytes(40, 30), &PreprocessConfig::default()).unwrap();
+        let img = image::load_from_memory(&out).unwrap();
+        assert_eq!((img.width(), img.height()), (40, 30));
+    }
+
+    #[test]
+    fn test_garbage_bytes_rejected() {
+        assert!(preprocess_image(b"not an image", &PreprocessConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_image_aspect_from_header() {
+        assert_eq!(image_aspect(&png_bytes(400, 200)), Some(2.0));
+        assert_eq!(image_dimensions(&png_bytes(400, 200)), Some((400, 200)));
+        assert_eq!(image_aspect(b"not an image"), None);
+    }
+}
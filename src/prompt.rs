@@ -1,8 +1,88 @@
-//! Prompt access (deprecated)
+//! Prompt access and versioned registry
 //!
-//! Prompts are now stored in prompt-spec.json and read at runtime by each consumer
-//! (CLI reads from file, Web reads from JSON import).
-//! This module is kept for backward compatibility but will be removed in a future version.
+//! Prompts are stored in prompt-spec.json together with per-prompt version
+//! metadata (`promptVersions`). `registry()` enumerates every prompt generation
+//! known to the spec so that retired prompts stay reachable for reproducing
+//! old results while being clearly marked as deprecated.
+
+use crate::spec::SPEC;
+
+/// Pipeline stage a prompt belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptStage {
+    /// Box-overlay geometry detection
+    Geometry,
+    /// Box-overlay fill estimation
+    Fill,
+    /// Image usability pre-check
+    Quality,
+    /// License plate reading for the fleet lookup
+    Plate,
+    /// Cargo height relative to the calibration landmarks
+    Landmark,
+    /// Summary of the fill runs' reasonings (text only)
+    Reasoning,
+    /// Legacy single-shot multi-param estimation
+    MultiParam,
+}
+
+/// A named, versioned prompt from the spec
+#[derive(Debug, Clone)]
+pub struct PromptEntry {
+    /// Prompt name (key in `promptVersions`)
+    pub name: String,
+    pub stage: PromptStage,
+    pub version: String,
+    /// Deprecation note (None = current)
+    pub deprecated: Option<String>,
+    pub text: String,
+}
+
+impl PromptEntry {
+    /// `name@version` identifier, suitable for recording in results
+    pub fn id(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated.is_some()
+    }
+}
+
+/// Enumerate all prompts defined in the spec (current and deprecated)
+pub fn registry() -> Vec<PromptEntry> {
+    let spec = &*SPEC;
+    let prompts = [
+        ("geometry", PromptStage::Geometry, spec.geometry_prompt.clone()),
+        ("fill", PromptStage::Fill, spec.fill_prompt.clone()),
+        ("quality", PromptStage::Quality, spec.quality_prompt.clone()),
+        ("plate", PromptStage::Plate, spec.plate_prompt.clone()),
+        ("landmark", PromptStage::Landmark, spec.landmark_prompt.clone()),
+        ("reasoning", PromptStage::Reasoning, spec.reasoning_prompt.clone()),
+        ("multiParam", PromptStage::MultiParam, spec.multi_param_prompt.render()),
+    ];
+
+    prompts
+        .into_iter()
+        .map(|(name, stage, text)| {
+            let meta = spec.prompt_versions.get(name);
+            PromptEntry {
+                name: name.to_string(),
+                stage,
+                version: meta.map(|m| m.version.clone()).unwrap_or_else(|| spec.version.clone()),
+                deprecated: meta.and_then(|m| m.deprecated.clone()),
+                text,
+            }
+        })
+        .collect()
+}
+
+/// Look up a prompt by name (`"fill"`) or full id (`"fill@2.1.0"`)
+pub fn find_prompt(key: &str) -> Option<PromptEntry> {
+    registry()
+        .into_iter()
+        .find(|p| p.name == key || p.id() == key)
+}
 
 /// Deprecated: prompts are now in prompt-spec.json, read at runtime
 #[deprecated(note = "Use prompt-spec.json directly. Prompts are no longer compiled into Rust.")]
@@ -21,4 +101,27 @@ mod tests {
         let prompt = build_core_prompt();
         assert!(prompt.contains("DEPRECATED"));
     }
+
+    #[test]
+    fn test_registry_lists_current_and_deprecated() {
+        let reg = registry();
+        assert_eq!(reg.len(), 7);
+
+        let geo = reg.iter().find(|p| p.stage == PromptStage::Geometry).unwrap();
+        assert_eq!(geo.text, SPEC.geometry_prompt);
+        assert!(!geo.is_deprecated());
+
+        let multi = reg.iter().find(|p| p.stage == PromptStage::MultiParam).unwrap();
+        assert!(multi.is_deprecated());
+        assert!(multi.text.contains("fillRatioZ"));
+        assert!(!multi.text.contains("{rangeGuide}"));
+    }
+
+    #[test]
+    fn test_find_prompt_by_name_or_id() {
+        let fill = find_prompt("fill").unwrap();
+        assert_eq!(fill.id(), "fill@2.1.0");
+        assert!(find_prompt("fill@2.1.0").is_some());
+        assert!(find_prompt("fill@0.0.1").is_none());
+    }
 }
@@ -0,0 +1,214 @@
+//! Image quality pre-check
+//!
+//! Cheap gate run before the ensemble: local brightness/blur heuristics
+//! (with the `image` feature) and an optional single AI call asking whether a
+//! truck bed is visible at all. Rejected photos surface as
+//! `PipelineError::UnusableImage` without spending the ensemble budget.
+
+use std::fmt;
+
+use crate::i18n::{self, language, Language, Localize};
+use crate::input::InputImage;
+use crate::parse::parse_quality;
+use crate::pipeline::{AiBackend, BackendOptions, PipelineError};
+use crate::spec::SPEC;
+
+/// Quality pre-check settings
+#[derive(Debug, Clone)]
+pub struct QualityCheckConfig {
+    /// Minimum mean luminance (0-255); heuristic, `image` feature only
+    pub min_brightness: f64,
+    /// Minimum Laplacian variance (blur measure); heuristic, `image` feature only
+    pub min_sharpness: f64,
+    /// Additionally ask the backend whether a loaded truck bed is visible
+    pub ai_check: bool,
+}
+
+impl Default for QualityCheckConfig {
+    fn default() -> Self {
+        Self {
+            min_brightness: 40.0,
+            min_sharpness: 15.0,
+            ai_check: false,
+        }
+    }
+}
+
+/// Measured quality of a decoded image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageQuality {
+    /// Mean luminance (0-255)
+    pub brightness: f64,
+    /// Variance of the 4-neighbour Laplacian over the luminance channel
+    pub sharpness: f64,
+}
+
+/// Why `check_images` rejected the photos (image numbers are 1-based)
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ImageIssue {
+    Undecodable { image: usize },
+    TooDark { image: usize, brightness: f64, min: f64 },
+    Blurry { image: usize, sharpness: f64, min: f64 },
+    /// The AI check saw no truck bed; the model's reason when it gave one
+    NoTruckBed(Option<String>),
+}
+
+impl Localize for ImageIssue {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Undecodable { image } => i18n::IMAGE_UNDECODABLE.format(lang, &[image]),
+            Self::TooDark { image, brightness, min } => i18n::IMAGE_TOO_DARK.format(
+                lang,
+                &[image, &format!("{:.0}", brightness), &format!("{:.0}", min)],
+            ),
+            Self::Blurry { image, sharpness, min } => i18n::IMAGE_BLURRY.format(
+                lang,
+                &[image, &format!("{:.1}", sharpness), &format!("{:.1}", min)],
+            ),
+            Self::NoTruckBed(Some(reason)) => reason.clone(),
+            Self::NoTruckBed(None) => i18n::IMAGE_NO_TRUCK_BED.get(lang).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ImageIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// Measure brightness and sharpness (None if the bytes cannot be decoded)
+#[cfg(feature = "image")]
+pub fn assess_image(bytes: &[u8]) -> Option<ImageQuality> {
+    let luma = image::load_from_memory(bytes).ok()?.to_luma8();
+    let (w, h) = luma.dimensions();
+    if w == 0 || h == 0 {
+        return None;
+    }
+
+    let px = |x: u32, y: u32| f64::from(luma.get_pixel(x, y)[0]);
+    let brightness = luma.pixels().map(|p| f64::from(p[0])).sum::<f64>() / f64::from(w * h);
+
+    let mut lap = Vec::new();
+    for y in 1..h.saturating_sub(1) {
+        for x in 1..w.saturating_sub(1) {
+            lap.push(px(x - 1, y) + px(x + 1, y) + px(x, y - 1) + px(x, y + 1) - 4.0 * px(x, y));
+        }
+    }
+    let sharpness = if lap.is_empty() {
+        0.0
+    } else {
+        let mean = lap.iter().sum::<f64>() / lap.len() as f64;
+        lap.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / lap.len() as f64
+    };
+
+    Some(ImageQuality { brightness, sharpness })
+}
+
+/// Run the configured checks, returning `UnusableImage` on the first failure;
+/// the AI check is sent with `options`
+pub fn check_images(
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &QualityCheckConfig,
+    options: &BackendOptions,
+) -> Result<(), PipelineError> {
+    #[cfg(feature = "image")]
+    for (i, image) in images.iter().enumerate() {
+        let q = assess_image(&image.bytes)
+            .ok_or(PipelineError::UnusableImage(ImageIssue::Undecodable { image: i + 1 }))?;
+        if q.brightness < config.min_brightness {
+            return Err(PipelineError::UnusableImage(ImageIssue::TooDark {
+                image: i + 1,
+                brightness: q.brightness,
+                min: config.min_brightness,
+            }));
+        }
+        if q.sharpness < config.min_sharpness {
+            return Err(PipelineError::UnusableImage(ImageIssue::Blurry {
+                image: i + 1,
+                sharpness: q.sharpness,
+                min: config.min_sharpness,
+            }));
+        }
+    }
+
+    if config.ai_check {
+        let (response, _) = backend.send_prompt_with_options(&SPEC.quality_prompt, images, options)?;
+        let verdict = parse_quality(&response)?;
+        if !verdict.usable {
+            return Err(PipelineError::UnusableImage(ImageIssue::NoTruckBed(verdict.reason)));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct VerdictBackend(&'static str);
+    impl AiBackend for VerdictBackend {
+        fn send_prompt(&self, _prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    fn ai_only() -> QualityCheckConfig {
+        QualityCheckConfig {
+            min_brightness: 0.0,
+            min_sharpness: 0.0,
+            ai_check: true,
+        }
+    }
+
+    #[test]
+    fn test_ai_check_rejects_with_reason() {
+        let backend = VerdictBackend(r#"{"usable":false,"reason":"トラックが写っていません"}"#);
+        let err = check_images(&backend, &[], &ai_only(), &BackendOptions::default()).unwrap_err();
+        match err {
+            PipelineError::UnusableImage(ImageIssue::NoTruckBed(Some(reason))) => assert!(reason.contains("トラック")),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ai_check_accepts() {
+        let backend = VerdictBackend(r#"{"usable":true}"#);
+        assert!(check_images(&backend, &[], &ai_only(), &BackendOptions::default()).is_ok());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_dark_and_flat_images() {
+        use image::{DynamicImage, GrayImage, ImageFormat};
+
+        let encode = |img: GrayImage| {
+            let mut out = Vec::new();
+            DynamicImage::ImageLuma8(img)
+                .write_to(&mut std::io::Cursor::new(&mut out), ImageFormat::Png)
+                .unwrap();
+            InputImage::new(out).unwrap()
+        };
+        let backend = VerdictBackend("unused");
+        let config = QualityCheckConfig::default();
+
+        let dark = encode(GrayImage::from_pixel(32, 32, image::Luma([5])));
+        assert!(matches!(
+            check_images(&backend, &[dark], &config, &BackendOptions::default()),
+            Err(PipelineError::UnusableImage(_))
+        ));
+
+        let flat = encode(GrayImage::from_pixel(32, 32, image::Luma([128])));
+        let q = assess_image(&flat.bytes).unwrap();
+        assert!(q.sharpness < 1e-9);
+        assert!(check_images(&backend, &[flat], &config, &BackendOptions::default()).is_err());
+
+        let checker = encode(GrayImage::from_fn(32, 32, |x, y| {
+            image::Luma([if (x + y) % 2 == 0 { 30 } else { 220 }])
+        }));
+        assert!(check_images(&backend, &[checker], &config, &BackendOptions::default()).is_ok());
+    }
+}
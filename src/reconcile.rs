@@ -0,0 +1,224 @@
+//! Manifest (伝票) reconciliation
+//!
+//! Compares estimated tonnage against the weight declared on the manifest,
+//! one load at a time or over a day's loads, and flags loads whose difference
+//! exceeds the tolerance.
+
+use std::fmt;
+
+use crate::pipeline::BoxOverlayResult;
+use crate::policy::Verdict;
+
+/// One load as declared on the manifest
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    /// Manifest (伝票) number
+    pub id: String,
+    /// Declared weight in tonnes
+    pub declared_tonnage: f64,
+    /// Declared material (None = not compared)
+    pub material_type: Option<String>,
+}
+
+/// Allowed difference between estimate and declaration.
+///
+/// A load is within tolerance when |estimated - declared| is at most
+/// `relative × declared` or `absolute`, whichever is larger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tolerance {
+    /// Fraction of the declared weight (0.15 = 15%)
+    pub relative: f64,
+    /// Floor in tonnes, so light loads are not flagged for small differences
+    pub absolute: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            relative: 0.15,
+            absolute: 0.3,
+        }
+    }
+}
+
+impl Tolerance {
+    /// Largest allowed |estimated - declared| for a declared weight
+    pub fn allowed(&self, declared: f64) -> f64 {
+        (self.relative * declared.abs()).max(self.absolute)
+    }
+}
+
+/// Outcome of comparing one load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcileStatus {
+    Within,
+    /// Estimate exceeds the declaration beyond tolerance (possible under-declaration)
+    Above,
+    /// Estimate falls short of the declaration beyond tolerance
+    Below,
+}
+
+impl fmt::Display for ReconcileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Within => "許容範囲内",
+            Self::Above => "推定重量が申告を上回っています",
+            Self::Below => "推定重量が申告を下回っています",
+        })
+    }
+}
+
+/// Comparison of one estimate against its manifest entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reconciliation {
+    pub id: String,
+    pub estimated: f64,
+    pub declared: f64,
+    /// estimated - declared
+    pub difference: f64,
+    /// difference / declared (0 when nothing was declared)
+    pub relative: f64,
+    pub status: ReconcileStatus,
+    /// Declared and estimated materials differ
+    pub material_mismatch: bool,
+    /// Review when out of tolerance or mismatched; a `TolerancePolicy` can
+    /// also reject
+    pub verdict: Verdict,
+}
+
+impl Reconciliation {
+    /// True when the load needs attention
+    pub fn is_discrepancy(&self) -> bool {
+        self.verdict != Verdict::Ok
+    }
+}
+
+/// Compare an estimate against the declared manifest weight
+pub fn reconcile(estimate: &BoxOverlayResult, declared: &ManifestEntry, tolerance: &Tolerance) -> Reconciliation {
+    let difference = estimate.tonnage - declared.declared_tonnage;
+    let status = if difference.abs() <= tolerance.allowed(declared.declared_tonnage) {
+        ReconcileStatus::Within
+    } else if difference > 0.0 {
+        ReconcileStatus::Above
+    } else {
+        ReconcileStatus::Below
+    };
+    let material_mismatch = declared
+        .material_type
+        .as_ref()
+        .is_some_and(|m| *m != estimate.material_type);
+    Reconciliation {
+        id: declared.id.clone(),
+        estimated: estimate.tonnage,
+        declared: declared.declared_tonnage,
+        difference,
+        relative: if declared.declared_tonnage > 0.0 {
+            difference / declared.declared_tonnage
+        } else {
+            0.0
+        },
+        status,
+        material_mismatch,
+        verdict: if status == ReconcileStatus::Within && !material_mismatch {
+            Verdict::Ok
+        } else {
+            Verdict::Review
+        },
+    }
+}
+
+/// Reconciliation of a batch of loads (e.g. one day)
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchReconciliation {
+    pub loads: Vec<Reconciliation>,
+    pub total_estimated: f64,
+    pub total_declared: f64,
+}
+
+impl BatchReconciliation {
+    /// Batch over already reconciled loads
+    pub fn from_loads(loads: Vec<Reconciliation>) -> Self {
+        Self {
+            total_estimated: loads.iter().map(|r| r.estimated).sum(),
+            total_declared: loads.iter().map(|r| r.declared).sum(),
+            loads,
+        }
+    }
+
+    /// Loads that need attention, in input order
+    pub fn discrepancies(&self) -> impl Iterator<Item = &Reconciliation> {
+        self.loads.iter().filter(|r| r.is_discrepancy())
+    }
+
+    /// total_estimated - total_declared
+    pub fn total_difference(&self) -> f64 {
+        self.total_estimated - self.total_declared
+    }
+}
+
+/// Reconcile every (estimate, manifest entry) pair
+pub fn reconcile_batch<'a>(
+    loads: impl IntoIterator<Item = (&'a BoxOverlayResult, &'a ManifestEntry)>,
+    tolerance: &Tolerance,
+) -> BatchReconciliation {
+    BatchReconciliation::from_loads(
+        loads
+            .into_iter()
+            .map(|(estimate, declared)| reconcile(estimate, declared, tolerance))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    fn estimate(tonnage: f64) -> BoxOverlayResult {
+        let mut r = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        r.tonnage = tonnage;
+        r
+    }
+
+    fn entry(id: &str, declared: f64) -> ManifestEntry {
+        ManifestEntry {
+            id: id.into(),
+            declared_tonnage: declared,
+            material_type: None,
+        }
+    }
+
+    #[test]
+    fn test_reconcile_tolerance() {
+        let t = Tolerance::default();
+        assert_eq!(reconcile(&estimate(4.3), &entry("A", 4.0), &t).status, ReconcileStatus::Within);
+        let r = reconcile(&estimate(5.0), &entry("B", 4.0), &t);
+        assert_eq!(r.status, ReconcileStatus::Above);
+        assert!((r.relative - 0.25).abs() < 1e-9);
+        // Absolute floor: 0.25t off a 1t declaration is still fine
+        assert_eq!(reconcile(&estimate(0.75), &entry("C", 1.0), &t).status, ReconcileStatus::Within);
+        assert_eq!(reconcile(&estimate(0.5), &entry("D", 1.0), &t).status, ReconcileStatus::Below);
+    }
+
+    #[test]
+    fn test_material_mismatch_is_discrepancy() {
+        let mut e = entry("A", 4.0);
+        e.material_type = Some("土砂".into());
+        let r = reconcile(&estimate(4.0), &e, &Tolerance::default());
+        assert_eq!(r.status, ReconcileStatus::Within);
+        assert!(r.material_mismatch && r.is_discrepancy());
+    }
+
+    #[test]
+    fn test_batch_totals_and_discrepancies() {
+        let estimates = [estimate(4.0), estimate(5.2), estimate(3.9)];
+        let entries = [entry("1", 4.1), entry("2", 4.0), entry("3", 4.0)];
+        let batch = reconcile_batch(estimates.iter().zip(&entries), &Tolerance::default());
+        assert_eq!(batch.loads.len(), 3);
+        assert!((batch.total_estimated - 13.1).abs() < 1e-9);
+        assert!((batch.total_difference() - 1.0).abs() < 1e-9);
+        let flagged: Vec<&str> = batch.discrepancies().map(|r| r.id.as_str()).collect();
+        assert_eq!(flagged, ["2"]);
+    }
+}
@@ -0,0 +1,171 @@
+//! Redaction of personal data in model responses
+//!
+//! Run logs keep the raw model output, which can quote license plate numbers
+//! or names seen in the photo. Plates are personal data under our compliance
+//! rules, so a `Redactor` set on `BoxOverlayConfig::redaction` masks them
+//! before responses enter run logs, results or exports. Parsing always sees
+//! the unredacted response.
+
+use std::fmt;
+
+use regex::Regex;
+
+use crate::i18n::{self, language, Language, Localize};
+
+/// Japanese plate: region, class number, kana, serial ("品川 500 あ 12-34")
+const PLATE_PATTERN: &str =
+    r"[\p{Han}\p{Katakana}]{1,4}\s*\d{1,3}\s*[\p{Hiragana}A-Z]\s*(?:[\d・･.]{1,2}\s*[-‐－]\s*\d{2}|[・･.]{0,3}\d{1,4})";
+
+/// JSON string value of a plate field ("licensePlate": "...")
+const PLATE_FIELD_PATTERN: &str = r#"("(?:licensePlate|plateNumber|plate)"\s*:\s*")[^"]*(")"#;
+
+/// What to mask
+#[derive(Debug, Clone)]
+pub struct RedactionConfig {
+    /// Mask license plate numbers and plate fields
+    pub plates: bool,
+    /// Literal strings to mask (driver, customer or site names)
+    pub names: Vec<String>,
+    /// Additional regular expressions to mask
+    pub patterns: Vec<String>,
+    /// Replacement text
+    pub mask: String,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            plates: true,
+            names: Vec::new(),
+            patterns: Vec::new(),
+            mask: "***".to_string(),
+        }
+    }
+}
+
+/// Invalid user-supplied redaction pattern
+#[derive(Debug, Clone, thiserror::Error)]
+pub struct RedactionError {
+    pub pattern: String,
+    pub message: String,
+}
+
+impl RedactionError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        "REDACTION_PATTERN_INVALID"
+    }
+}
+
+impl Localize for RedactionError {
+    fn localize(&self, lang: Language) -> String {
+        i18n::REDACTION_PATTERN_INVALID.format(lang, &[&self.pattern, &self.message])
+    }
+}
+
+impl fmt::Display for RedactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+/// Compiled redaction rules
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    plate_field: Option<Regex>,
+    rules: Vec<Regex>,
+    mask: String,
+}
+
+impl Redactor {
+    /// Compile the configured rules
+    pub fn new(config: &RedactionConfig) -> Result<Self, RedactionError> {
+        let compile = |pattern: &str| {
+            Regex::new(pattern).map_err(|e| RedactionError {
+                pattern: pattern.to_string(),
+                message: e.to_string(),
+            })
+        };
+
+        let mut rules = Vec::new();
+        if config.plates {
+            rules.push(compile(PLATE_PATTERN)?);
+        }
+        let names: Vec<String> = config
+            .names
+            .iter()
+            .filter(|n| !n.is_empty())
+            .map(|n| regex::escape(n))
+            .collect();
+        if !names.is_empty() {
+            rules.push(compile(&names.join("|"))?);
+        }
+        for pattern in &config.patterns {
+            rules.push(compile(pattern)?);
+        }
+
+        Ok(Self {
+            plate_field: config.plates.then(|| compile(PLATE_FIELD_PATTERN)).transpose()?,
+            rules,
+            mask: config.mask.clone(),
+        })
+    }
+
+    /// Text with every match replaced by the mask
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = match &self.plate_field {
+            Some(re) => re
+                .replace_all(text, |c: &regex::Captures| format!("{}{}{}", &c[1], self.mask, &c[2]))
+                .into_owned(),
+            None => text.to_string(),
+        };
+        for re in &self.rules {
+            out = re.replace_all(&out, regex::NoExpand(&self.mask)).into_owned();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plate_numbers_masked() {
+        let r = Redactor::new(&RedactionConfig::default()).unwrap();
+        assert_eq!(
+            r.redact("後方に品川 500 あ 12-34 のダンプ"),
+            "後方に*** のダンプ"
+        );
+        assert_eq!(
+            r.redact(r#"{"licensePlate":"練馬100さ・・・5","height":0.4}"#),
+            r#"{"licensePlate":"***","height":0.4}"#
+        );
+        assert_eq!(r.redact(r#"{"fillRatioL":0.8}"#), r#"{"fillRatioL":0.8}"#);
+    }
+
+    #[test]
+    fn test_names_and_custom_patterns() {
+        let config = RedactionConfig {
+            plates: false,
+            names: vec!["山田運送".into()],
+            patterns: vec![r"\d{3}-\d{4}-\d{4}".into()],
+            mask: "[伏字]".into(),
+        };
+        let r = Redactor::new(&config).unwrap();
+        assert_eq!(
+            r.redact("山田運送 (090-1234-5678) の車両"),
+            "[伏字] ([伏字]) の車両"
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern_rejected() {
+        let config = RedactionConfig {
+            patterns: vec!["(".into()],
+            ..Default::default()
+        };
+        let err = Redactor::new(&config).unwrap_err();
+        assert_eq!(err.code(), "REDACTION_PATTERN_INVALID");
+    }
+}
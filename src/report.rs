@@ -0,0 +1,581 @@
+//! Human-readable reporting of analysis results
+//!
+//! Text output shared by the CLI and Web so both emit identical wording.
+
+use crate::i18n::{self, Message};
+pub use crate::i18n::Language;
+use crate::overlay::{OverlayData, OverlayShape};
+use crate::parse::FillResponse;
+use crate::pipeline::{BoxOverlayResult, FillRunLog, GeometryRunLog};
+use crate::spec::{get_truck_spec, SPEC};
+
+/// Quantity a report leads with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportingMode {
+    #[default]
+    Weight,
+    /// Loose m³ first (and bank m³ when the material has a swell factor),
+    /// tonnage secondary, for contracts billed by volume
+    Volume,
+}
+
+impl BoxOverlayResult {
+    /// (value, unit) of the quantity the result is reported in
+    pub fn primary_quantity(&self) -> (f64, &'static str) {
+        match self.reporting {
+            ReportingMode::Weight => (self.tonnage, "t"),
+            ReportingMode::Volume => (self.volume, "m³"),
+        }
+    }
+
+    /// Most common scale method among the valid geometry runs
+    /// ("manual" when the height was supplied without a geometry stage).
+    pub fn scale_method(&self) -> &str {
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for run in self.geometry_runs.iter().filter(|r| r.height_m > 0.0) {
+            match counts.iter_mut().find(|(m, _)| *m == run.scale_method) {
+                Some((_, c)) => *c += 1,
+                None => counts.push((&run.scale_method, 1)),
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, c)| *c)
+            .map(|(m, _)| m)
+            .unwrap_or("manual")
+    }
+
+    /// Step-by-step derivation of the tonnage, one numbered line per step
+    pub fn explain(&self, lang: Language) -> String {
+        let c = &SPEC.constants;
+        let b = &self.breakdown;
+        let (bed_l, bed_w) = (b.bed_length, b.bed_width);
+        let (effective_l, effective_w) = (b.effective_l, b.effective_w);
+        let compression = b.compression_factor;
+        let valid_runs = self.geometry_runs.iter().filter(|r| r.height_m > 0.0).count();
+
+        let method = match self.scale_method() {
+            "tailgate" => i18n::SCALE_TAILGATE.get(lang),
+            "plate" => i18n::SCALE_PLATE.get(lang),
+            "fused" => i18n::SCALE_FUSED.get(lang),
+            "manual" => i18n::SCALE_MANUAL.get(lang),
+            other => other,
+        };
+        let step = |message: Message, args: &[&dyn std::fmt::Display]| message.format(lang, args);
+        let n = |value: f64, decimals: usize| format!("{:.*}", decimals, value);
+        let mut steps = vec![
+            step(i18n::EXPLAIN_SCALE, &[&method, &valid_runs, &self.geometry_runs.len()]),
+            step(i18n::EXPLAIN_HEIGHT, &[&n(self.height_m, 2)]),
+            step(
+                i18n::EXPLAIN_FILL,
+                &[
+                    &n(self.fill_ratio_l, 2),
+                    &n(self.taper_ratio, 2),
+                    &n(effective_l, 3),
+                    &n(c.bottom_fill, 2),
+                    &n(self.fill_ratio_w, 2),
+                    &n(effective_w, 3),
+                ],
+            ),
+            step(
+                i18n::EXPLAIN_VOLUME,
+                &[
+                    &n(bed_l, 2),
+                    &n(bed_w, 2),
+                    &n(self.height_m, 2),
+                    &n(effective_l, 3),
+                    &n(effective_w, 3),
+                    &n(self.volume, 3),
+                ],
+            ),
+            step(i18n::EXPLAIN_COMPRESSION, &[&n(compression, 3), &n(self.effective_packing, 3)]),
+            step(i18n::EXPLAIN_DENSITY, &[&self.material_type, &n(self.density, 2)]),
+            step(
+                i18n::EXPLAIN_WEIGHT,
+                &[
+                    &n(self.volume, 3),
+                    &n(self.density, 2),
+                    &n(self.effective_packing, 3),
+                    &n(self.tonnage, 2),
+                    &n(self.tonnage_min, 2),
+                    &n(self.tonnage_max, 2),
+                ],
+            ),
+        ];
+
+        if let (ReportingMode::Volume, Some(bank)) = (self.reporting, self.bank_volume) {
+            let swell = self.volume / bank;
+            steps.push(step(i18n::EXPLAIN_BANK_VOLUME, &[&n(self.volume, 3), &n(swell, 2), &n(bank, 3)]));
+        }
+
+        steps
+            .iter()
+            .enumerate()
+            .map(|(i, s)| format!("{}. {}", i + 1, s))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl GeometryRunLog {
+    /// Aligned `label  value` lines describing the run, for terminal debugging
+    pub fn render(&self) -> String {
+        let parsed = self.parsed.as_ref().map(|g| {
+            format!(
+                "tailgate {:.3}..{:.3}, cargo top {:.3}, plate {}",
+                g.tailgate_top_y,
+                g.tailgate_bottom_y,
+                g.cargo_top_y,
+                g.plate_box.map_or("-".to_string(), |b| format!("{:.3?}", b))
+            )
+        });
+        let parsed = parsed.or_else(|| self.landmark.map(|l| format!("{:?}", l)));
+        render_fields(&[
+            ("variant", self.variant.clone()),
+            ("backend", self.backend.clone()),
+            ("scale", self.scale_method.clone()),
+            ("height", format!("{:.3} m", self.height_m)),
+            ("disagreement", opt(self.scale_disagreement.map(|d| format!("{:.3}", d)))),
+            ("plate rejected", self.plate_rejected.to_string()),
+            ("parsed", opt(parsed)),
+            ("failure", opt(self.failure.as_ref().map(|f| f.to_string()))),
+            ("response", self.raw_response.clone()),
+        ])
+    }
+
+    fn table_row(&self, run: usize) -> Vec<String> {
+        vec![
+            run.to_string(),
+            self.variant.clone(),
+            self.backend.clone(),
+            self.scale_method.clone(),
+            format!("{:.3}", self.height_m),
+            opt(self.scale_disagreement.map(|d| format!("{:.3}", d))),
+            opt(self.failure.as_ref().map(|f| f.code().to_string())),
+        ]
+    }
+}
+
+impl FillRunLog {
+    /// Aligned `label  value` lines describing the run, for terminal debugging
+    pub fn render(&self) -> String {
+        let parsed = self.parsed.as_ref().map(|f| {
+            format!(
+                "L {:.3}, W {:.3}, taper {:.3}, packing {:.3}, material {}",
+                f.fill_ratio_l,
+                f.fill_ratio_w,
+                f.taper_ratio,
+                f.packing_density,
+                f.material_type.as_deref().unwrap_or("-")
+            )
+        });
+        let out_of_range = self
+            .out_of_range
+            .iter()
+            .map(|c| format!("{} {:.3} -> {:.3}", c.field, c.original, c.clamped))
+            .collect::<Vec<_>>()
+            .join(", ");
+        render_fields(&[
+            ("variant", self.variant.clone()),
+            ("backend", self.backend.clone()),
+            ("parsed", opt(parsed)),
+            ("out of range", if out_of_range.is_empty() { "-".to_string() } else { out_of_range }),
+            ("failure", opt(self.failure.as_ref().map(|f| f.to_string()))),
+            ("response", self.raw_response.clone()),
+        ])
+    }
+
+    fn table_row(&self, run: usize) -> Vec<String> {
+        let value = |f: fn(&FillResponse) -> f64| opt(self.parsed.as_ref().map(|p| format!("{:.3}", f(p))));
+        vec![
+            run.to_string(),
+            self.variant.clone(),
+            self.backend.clone(),
+            value(|p| p.fill_ratio_l),
+            value(|p| p.fill_ratio_w),
+            value(|p| p.taper_ratio),
+            value(|p| p.packing_density),
+            opt(self.parsed.as_ref().and_then(|p| p.material_type.clone())),
+            opt(self.failure.as_ref().map(|f| f.code().to_string())),
+        ]
+    }
+}
+
+impl BoxOverlayResult {
+    /// Run tables of both stages followed by the aggregated values and
+    /// warnings, aligned for a monospace terminal
+    pub fn debug_report(&self) -> String {
+        let geometry: Vec<Vec<String>> =
+            self.geometry_runs.iter().enumerate().map(|(i, r)| r.table_row(i)).collect();
+        let fill: Vec<Vec<String>> = self.fill_runs.iter().enumerate().map(|(i, r)| r.table_row(i)).collect();
+
+        let mut out = String::new();
+        out.push_str(&format!("geometry runs ({})\n", self.geometry_runs.len()));
+        out.push_str(&render_table(
+            &["#", "variant", "backend", "scale", "height", "disagree", "failure"],
+            &geometry,
+        ));
+        out.push_str(&format!("\nfill runs ({}, {:?})\n", self.fill_runs.len(), self.fill_source));
+        out.push_str(&render_table(
+            &["#", "variant", "backend", "L", "W", "taper", "packing", "material", "failure"],
+            &fill,
+        ));
+        out.push('\n');
+        out.push_str(&render_fields(&[
+            ("truck", self.truck_class.clone()),
+            ("material", self.material_type.clone()),
+            ("height", format!("{:.3} m", self.height_m)),
+            (
+                "fill",
+                format!(
+                    "L {:.3}, W {:.3}, taper {:.3}, packing {:.3}",
+                    self.fill_ratio_l, self.fill_ratio_w, self.taper_ratio, self.packing_density
+                ),
+            ),
+            ("volume", format!("{:.3} m³", self.volume)),
+            (
+                "tonnage",
+                format!("{:.3} t ({:.3}..{:.3})", self.tonnage, self.tonnage_min, self.tonnage_max),
+            ),
+        ]));
+        for warning in &self.warnings {
+            out.push_str(&format!("\nwarning  {}", warning));
+        }
+        out
+    }
+}
+
+fn opt(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "-".to_string())
+}
+
+/// Terminal column width: 2 for East Asian wide characters, 1 otherwise
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x1100..=0x115F
+            | 0x2E80..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6 => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+fn pad(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(display_width(text))))
+}
+
+/// Labels padded to a common width, one field per line
+fn render_fields(fields: &[(&str, String)]) -> String {
+    let width = fields.iter().map(|(label, _)| display_width(label)).max().unwrap_or(0);
+    fields
+        .iter()
+        .map(|(label, value)| format!("{}  {}", pad(label, width), value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Columns padded to their widest cell, with a rule under the header
+fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| rows.iter().map(|r| display_width(&r[i])).chain([display_width(h)]).max().unwrap_or(0))
+        .collect();
+    let line = |cells: Vec<&str>| {
+        let padded: Vec<String> = cells.iter().zip(&widths).map(|(c, w)| pad(c, *w)).collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    let rules: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    let mut lines = vec![line(headers.to_vec()), line(rules.iter().map(String::as_str).collect())];
+    lines.extend(rows.iter().map(|r| line(r.iter().map(String::as_str).collect())));
+    lines.join("\n") + "\n"
+}
+
+/// Standard one-paragraph field report (現場報告) used on daily sheets,
+/// e.g. `4t車 As殻 積載高さ0.48m 体積2.35m³ 推定3.4t(過積載なし) 工事番号:J-1`
+pub fn field_summary(result: &BoxOverlayResult, lang: Language) -> String {
+    let overload = match get_truck_spec(&result.truck_class) {
+        Some(truck) if result.tonnage > truck.max_capacity => {
+            i18n::SUMMARY_OVERLOAD.format(lang, &[&format!("{:.1}", truck.max_capacity)])
+        }
+        Some(_) => i18n::SUMMARY_NOT_OVERLOADED.get(lang).to_string(),
+        None => i18n::SUMMARY_CAPACITY_UNKNOWN.get(lang).to_string(),
+    };
+    let (height, volume, tonnage) = (
+        format!("{:.2}", result.height_m),
+        format!("{:.2}", result.volume),
+        format!("{:.1}", result.tonnage),
+    );
+    let (class, material) = (&result.truck_class, &result.material_type);
+    let mut summary = match result.reporting {
+        ReportingMode::Weight => {
+            i18n::SUMMARY_WEIGHT.format(lang, &[class, material, &height, &volume, &tonnage, &overload])
+        }
+        ReportingMode::Volume => {
+            let bank = result
+                .bank_volume
+                .map(|b| i18n::SUMMARY_BANK_VOLUME.format(lang, &[&format!("{:.2}", b)]))
+                .unwrap_or_default();
+            i18n::SUMMARY_VOLUME.format(lang, &[class, material, &height, &volume, &bank, &tonnage, &overload])
+        }
+    };
+    for (label, value) in result.metadata.entries(lang) {
+        summary.push_str(&format!(" {}:{}", label, value));
+    }
+    if result.disagreement.is_some() || result.needs_review {
+        summary.push_str(i18n::SUMMARY_NEEDS_REVIEW.get(lang));
+    }
+    summary
+}
+
+/// SVG layer drawing the overlay over a photo of `img_w` × `img_h` pixels
+/// (as displayed), for compositing in reports and the web viewer
+pub fn overlay_svg(overlay: &OverlayData, img_w: u32, img_h: u32) -> String {
+    let px = overlay.to_pixels(img_w, img_h);
+    let stroke = (img_w.max(img_h) as f64 / 400.0).max(1.0);
+    let font = (img_h as f64 / 40.0).max(10.0);
+
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = img_w,
+        h = img_h
+    );
+    for item in &px.items {
+        // Labels sit just above the shape's top-left corner
+        let (lx, ly) = match item.shape {
+            OverlayShape::Rect { x0, y0, x1, y1 } => {
+                svg.push_str(&format!(
+                    r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="none" stroke="{}" stroke-width="{:.1}"/>"#,
+                    x0,
+                    y0,
+                    x1 - x0,
+                    y1 - y0,
+                    item.color,
+                    stroke
+                ));
+                (x0, y0)
+            }
+            OverlayShape::HLine { y, x0, x1 } => {
+                svg.push_str(&format!(
+                    r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="{:.1}"/>"#,
+                    x0, y, x1, y, item.color, stroke
+                ));
+                (x0, y)
+            }
+        };
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{:.1}" fill="{}" font-size="{:.0}">{}</text>"#,
+            lx + stroke * 2.0,
+            (ly - stroke * 2.0).max(font),
+            item.color,
+            font,
+            escape_xml(&item.label)
+        ));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// JSON body of a result, as returned by the server's `/analyze` and the
+/// WASM `analyzeBoxOverlay`
+pub fn result_json(result: &BoxOverlayResult) -> serde_json::Value {
+    serde_json::json!({
+        "truckClass": result.truck_class,
+        "materialType": result.material_type,
+        "heightM": result.height_m,
+        "fillRatioL": result.fill_ratio_l,
+        "fillRatioW": result.fill_ratio_w,
+        "taperRatio": result.taper_ratio,
+        "packingDensity": result.packing_density,
+        "volume": result.volume,
+        "tonnage": result.tonnage,
+        "tonnageMin": result.tonnage_min,
+        "tonnageMax": result.tonnage_max,
+        "density": result.density,
+        "formula": result.formula,
+        "occludedRatio": result.occluded_ratio,
+        "covered": result.covered,
+        "loadState": result.load_state,
+        "materialVotes": result.material_votes,
+        "bankVolume": result.bank_volume,
+        "billedTonnage": result.billed_tonnage,
+        "verdict": result.verdict,
+        "disagreement": result.disagreement.is_some(),
+        "needsReview": result.needs_review,
+        "reviewFlags": result.review_flags,
+        "vehicleId": result.vehicle_id,
+        "plateNumber": result.plate_number,
+        "metadata": result.metadata,
+        "warnings": result.warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>(),
+        "breakdown": result.breakdown,
+        "overlay": result.overlay,
+        "reasoning": result.reasoning,
+        "reasonings": result.reasonings,
+        "summary": field_summary(result, i18n::language()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, AnalysisWarning, BoxOverlayConfig};
+    use crate::metadata::LoadMetadata;
+    use crate::testing::fixed_backend;
+
+    fn sample_result() -> BoxOverlayResult {
+        analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn test_explain_japanese() {
+        let text = sample_result().explain(Language::Ja);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].contains("後板") && lines[0].contains("2/2"));
+        assert!(lines[1].contains("0.48 m"));
+        assert!(lines[5].contains("As殻 2.50"));
+        assert!(lines[6].starts_with("7. 推定重量"));
+    }
+
+    #[test]
+    fn test_explain_english_matches_values() {
+        let r = sample_result();
+        let text = r.explain(Language::En);
+        assert!(text.contains("Scale reference: tailgate"));
+        assert!(text.contains("3.40 m × 2.06 m"));
+        assert!(text.contains(&format!("= {:.2} t", r.tonnage)));
+    }
+
+    #[test]
+    fn test_scale_method_manual_without_geometry_runs() {
+        let mut r = sample_result();
+        r.geometry_runs.clear();
+        assert_eq!(r.scale_method(), "manual");
+        assert!(r.explain(Language::Ja).contains("手入力"));
+    }
+
+    #[test]
+    fn test_field_summary() {
+        let r = sample_result();
+        assert_eq!(
+            field_summary(&r, Language::Ja),
+            format!(
+                "4t車 As殻 積載高さ0.48m 体積{:.2}m³ 推定{:.1}t(過積載の疑い: 最大積載量4.0t)",
+                r.volume, r.tonnage
+            )
+        );
+
+        let mut light = r.clone();
+        light.tonnage = 3.4;
+        assert!(field_summary(&light, Language::Ja).ends_with("推定3.4t(過積載なし)"));
+        light.truck_class = "3t".into();
+        assert!(field_summary(&light, Language::Ja).ends_with("(最大積載量不明)"));
+        light.metadata = LoadMetadata::new().job_number("J-1").driver_id("D-7");
+        assert!(field_summary(&light, Language::Ja).ends_with("(最大積載量不明) 工事番号:J-1 運転者:D-7"));
+    }
+
+    #[test]
+    fn test_result_json() {
+        let r = sample_result();
+        let json = result_json(&r);
+        assert_eq!(json["truckClass"], "4t");
+        assert_eq!(json["materialType"], "As殻");
+        assert_eq!(json["heightM"], r.height_m);
+        assert_eq!(json["tonnage"], r.tonnage);
+        assert_eq!(json["loadState"], "loaded");
+        assert_eq!(json["disagreement"], false);
+        assert_eq!(json["summary"], field_summary(&r, i18n::language()));
+        assert_eq!(json["breakdown"]["bedLength"], r.breakdown.bed_length);
+        assert!(json["vehicleId"].is_null() && json["verdict"].is_null());
+
+        let mut warned = r.clone();
+        warned.warnings.push(AnalysisWarning::PlateUnread);
+        warned.plate_number = Some("品川500あ1234".into());
+        let json = result_json(&warned);
+        assert_eq!(json["warnings"][0], AnalysisWarning::PlateUnread.to_string());
+        assert_eq!(json["plateNumber"], "品川500あ1234");
+        // Run logs and input details stay out of the response body
+        assert!(json.get("geometryRuns").is_none() && json.get("imageHashes").is_none());
+    }
+
+    #[test]
+    fn test_volume_reporting_mode() {
+        let backend = fixed_backend();
+        let config = BoxOverlayConfig::builder()
+            .material_type("土砂")
+            .reporting(ReportingMode::Volume)
+            .build()
+            .unwrap();
+        let r = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(r.primary_quantity(), (r.volume, "m³"));
+        let bank = r.bank_volume.unwrap();
+        assert!((bank - r.volume / 1.2).abs() < 1e-3);
+        assert!(field_summary(&r, Language::Ja).contains(&format!("ほぐし{:.2}m³(地山{:.2}m³) 参考重量", r.volume, bank)));
+        let text = r.explain(Language::Ja);
+        assert!(text.lines().last().unwrap().starts_with("8. 地山換算"));
+
+        // No swell factor: loose volume only
+        let mut as_gara = r.clone();
+        as_gara.bank_volume = None;
+        assert!(field_summary(&as_gara, Language::Ja).contains("m³ 参考重量"));
+        assert_eq!(sample_result().primary_quantity().1, "t");
+    }
+
+    #[test]
+    fn test_overlay_svg() {
+        let overlay = sample_result().overlay.unwrap();
+        let svg = overlay_svg(&overlay, 800, 600);
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="800" height="600""#));
+        assert!(svg.ends_with("</svg>"));
+        // Cargo top at y = 0.2 of 600px, labelled with the measured height
+        assert!(svg.contains(r##"<line x1="0.0" y1="120.0" x2="800.0" y2="120.0" stroke="#ef4444""##));
+        assert!(svg.contains(">荷山頂部 0.48m</text>"));
+        assert_eq!(svg.matches("<text").count(), overlay.items.len());
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(escape_xml(r#"a<b & "c">"#), "a&lt;b &amp; &quot;c&quot;&gt;");
+    }
+
+    #[test]
+    fn test_debug_report_tables() {
+        let r = sample_result();
+        let report = r.debug_report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "geometry runs (2)");
+        assert!(lines[1].starts_with("#  variant  backend"));
+        // Header, rule and rows share column positions
+        let height_col = lines[1].find("height").unwrap();
+        assert_eq!(&lines[3][height_col..height_col + 5], "0.480");
+        assert!(lines[2].starts_with("-  -------"));
+        assert!(report.contains("fill runs (2, Estimated)"));
+        assert!(report.contains("material  As殻"));
+
+        let run = r.geometry_runs[0].render();
+        assert!(run.lines().any(|l| l == "scale           tailgate"));
+        assert!(r.fill_runs[1].render().contains("failure       -"));
+    }
+
+    #[test]
+    fn test_display_width_aligns_wide_text() {
+        let rows = [vec!["As殻".to_string(), "1".to_string()], vec!["土砂".to_string(), "2".to_string()]];
+        let table = render_table(&["material", "n"], &rows);
+        let rows: Vec<&str> = table.lines().collect();
+        assert_eq!(rows[2], "As殻      1");
+        assert_eq!(rows[3], "土砂      2");
+        assert_eq!(display_width(rows[2]), display_width(rows[0]));
+    }
+}
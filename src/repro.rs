@@ -0,0 +1,206 @@
+//! Reproducibility manifests
+//!
+//! Every `BoxOverlayResult` carries a `ReproManifest`: the spec hash, crate
+//! version, prompts, backend options and aggregation settings it was produced
+//! with. `reproduce` rebuilds the configuration from it, re-runs the analysis
+//! on the same images and reports what changed.
+//!
+//! Sites, fleets, preprocessing and quality checks are not captured; apply
+//! them to a base configuration with `ReproManifest::apply` when needed.
+
+use crate::calculation::FormulaVersion;
+use crate::diff::ResultDiff;
+use crate::ensemble::{DisagreementThresholds, MedianMode};
+use crate::hashing::sha256_hex;
+use crate::input::InputImage;
+use crate::pipeline::{
+    analyze_box_overlay, AiBackend, BackendOptions, BoxOverlayConfig, BoxOverlayResult, GeometryMode, OcclusionCheck,
+    PipelineError, PromptVariant, StageSchedule, TarpMode,
+};
+use crate::spec::spec_json;
+
+/// Settings an analysis was run with
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReproManifest {
+    /// `CARGO_PKG_VERSION` of the crate that ran the analysis
+    pub crate_version: String,
+    /// Lowercase hex SHA-256 of the prompt-spec.json in use
+    pub spec_hash: String,
+    pub truck_class: String,
+    pub material_type: String,
+    pub geometry_runs: usize,
+    pub fill_runs: usize,
+    /// Prompt overrides (None = spec prompt, covered by `spec_hash`)
+    pub geometry_prompt: Option<String>,
+    pub fill_prompt: Option<String>,
+    pub geometry_variants: Vec<PromptVariant>,
+    pub fill_variants: Vec<PromptVariant>,
+    pub geometry_mode: GeometryMode,
+    pub schedule: StageSchedule,
+    pub fill_fallback: bool,
+    pub material_hint: bool,
+    /// Options of every call, including the seed
+    pub backend_options: BackendOptions,
+    /// Per-run temperatures overriding `backend_options.temperature`
+    pub temperature_schedule: Vec<f64>,
+    pub disagreement: DisagreementThresholds,
+    pub max_escalation_runs: usize,
+    pub median: MedianMode,
+    pub formula: FormulaVersion,
+    #[serde(default)]
+    pub occlusion: Option<OcclusionCheck>,
+    #[serde(default)]
+    pub tarp: Option<TarpMode>,
+    #[serde(default)]
+    pub material_agreement: f64,
+}
+
+impl ReproManifest {
+    /// Capture the reproducible settings of `config`
+    pub fn capture(config: &BoxOverlayConfig) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            spec_hash: spec_hash(),
+            truck_class: config.truck_class.clone(),
+            material_type: config.material_type.clone(),
+            geometry_runs: config.geometry_run_count(),
+            fill_runs: config.fill_run_count(),
+            geometry_prompt: config.geometry_prompt.clone(),
+            fill_prompt: config.fill_prompt.clone(),
+            geometry_variants: config.geometry_variants.clone(),
+            fill_variants: config.fill_variants.clone(),
+            geometry_mode: config.geometry_mode,
+            schedule: config.schedule,
+            fill_fallback: config.fill_fallback,
+            material_hint: config.material_hint,
+            backend_options: config.backend_options.clone(),
+            temperature_schedule: config.temperature_schedule.clone(),
+            disagreement: config.disagreement.clone(),
+            max_escalation_runs: config.max_escalation_runs,
+            median: config.median,
+            formula: config.formula,
+            occlusion: config.occlusion,
+            tarp: config.tarp,
+            material_agreement: config.material_agreement,
+        }
+    }
+
+    /// `base` with the captured settings applied
+    pub fn apply(&self, mut base: BoxOverlayConfig) -> BoxOverlayConfig {
+        base.truck_class = self.truck_class.clone();
+        base.material_type = self.material_type.clone();
+        base.geometry_runs = Some(self.geometry_runs);
+        base.fill_runs = Some(self.fill_runs);
+        base.geometry_prompt = self.geometry_prompt.clone();
+        base.fill_prompt = self.fill_prompt.clone();
+        base.geometry_variants = self.geometry_variants.clone();
+        base.fill_variants = self.fill_variants.clone();
+        base.geometry_mode = self.geometry_mode;
+        base.schedule = self.schedule;
+        base.fill_fallback = self.fill_fallback;
+        base.material_hint = self.material_hint;
+        base.backend_options = self.backend_options.clone();
+        base.temperature_schedule = self.temperature_schedule.clone();
+        base.disagreement = self.disagreement.clone();
+        base.max_escalation_runs = self.max_escalation_runs;
+        base.median = self.median;
+        base.formula = self.formula;
+        base.occlusion = self.occlusion;
+        base.tarp = self.tarp;
+        base.material_agreement = self.material_agreement;
+        base
+    }
+
+    /// Default configuration with the captured settings applied
+    pub fn to_config(&self) -> BoxOverlayConfig {
+        self.apply(BoxOverlayConfig::default())
+    }
+
+    /// True when this build and spec match the ones that produced the manifest
+    pub fn matches_environment(&self) -> bool {
+        self.crate_version == env!("CARGO_PKG_VERSION") && self.spec_hash == spec_hash()
+    }
+}
+
+fn spec_hash() -> String {
+    sha256_hex(spec_json().as_bytes())
+}
+
+/// Outcome of `reproduce`
+#[derive(Debug, Clone)]
+pub struct Reproduction {
+    pub result: BoxOverlayResult,
+    /// `original.diff(&result)`
+    pub diff: ResultDiff,
+    /// The spec differs from the one recorded in the manifest
+    pub spec_changed: bool,
+    /// The crate version differs from the one recorded in the manifest
+    pub version_changed: bool,
+    /// The images differ (by SHA-256) from the original ones
+    pub images_changed: bool,
+}
+
+impl Reproduction {
+    /// Same environment, same images and no difference in the outcome
+    pub fn is_exact(&self) -> bool {
+        !self.spec_changed && !self.version_changed && !self.images_changed && self.diff.is_empty()
+    }
+}
+
+/// Re-run the analysis that produced `original` on `images` with the settings
+/// of its manifest, and diff the outcome against it
+pub fn reproduce(
+    original: &BoxOverlayResult,
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+) -> Result<Reproduction, PipelineError> {
+    let manifest = &original.repro;
+    let result = analyze_box_overlay(backend, images, &manifest.to_config())?;
+    let sha256s = |r: &BoxOverlayResult| r.image_hashes.iter().map(|h| h.sha256.clone()).collect::<Vec<_>>();
+    Ok(Reproduction {
+        diff: original.diff(&result),
+        spec_changed: manifest.spec_hash != spec_hash(),
+        version_changed: manifest.crate_version != env!("CARGO_PKG_VERSION"),
+        images_changed: sha256s(original) != sha256s(&result),
+        result,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixed_backend;
+
+    #[test]
+    fn test_reproduce() {
+        let config = BoxOverlayConfig::builder()
+            .ensemble_count(3)
+            .median(MedianMode::Upper)
+            .backend_options(BackendOptions { seed: Some(42), ..Default::default() })
+            .temperature_schedule(vec![0.0, 0.4])
+            .build()
+            .unwrap();
+        let original = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        let manifest = &original.repro;
+        assert!(manifest.matches_environment());
+        assert_eq!(manifest.geometry_runs, 3);
+        assert_eq!(manifest.backend_options.seed, Some(42));
+
+        let json = serde_json::to_string(manifest).unwrap();
+        assert!(json.contains("\"specHash\""));
+        assert_eq!(&serde_json::from_str::<ReproManifest>(&json).unwrap(), manifest);
+
+        let rebuilt = manifest.to_config();
+        assert_eq!(rebuilt.median, MedianMode::Upper);
+        assert_eq!(rebuilt.run_backend_options(1), config.run_backend_options(1));
+
+        let same = reproduce(&original, &fixed_backend(), &[]).unwrap();
+        assert!(same.is_exact(), "{:?}", same.diff);
+
+        let drifted = reproduce(&original, &fixed_backend().with_cargo_top(0.25), &[]).unwrap();
+        assert!(!drifted.is_exact());
+        assert!(drifted.diff.height_m < 0.0);
+        assert_eq!(drifted.diff.geometry_runs, [0, 1, 2]);
+    }
+}
@@ -0,0 +1,199 @@
+//! Streaming of run logs
+//!
+//! Run logs are collected into `BoxOverlayResult`; a `LogSink` set on
+//! `BoxOverlayConfig::log_sink` also receives each run as soon as it
+//! completes, for live progress in a UI or for persisting very large
+//! ensembles. With `BoxOverlayConfig::keep_run_logs` off the result then
+//! carries no run logs at all, and the pipeline drops each raw response as
+//! soon as the sink has it.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use crate::pipeline::{FillRunLog, GeometryRunLog};
+
+/// A completed ensemble run
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "stage", rename_all = "camelCase")]
+pub enum RunEvent {
+    /// Geometry (or landmark) run; `run` is its index in `geometry_runs`
+    Geometry { run: usize, log: GeometryRunLog },
+    /// Fill run; `run` is its index in `fill_runs`
+    Fill { run: usize, log: FillRunLog },
+}
+
+/// Receiver of run logs as they complete.
+///
+/// Called on the thread that issued the call, so with
+/// `analyze_box_overlay_concurrent` geometry and fill events interleave.
+pub trait LogSink: Send + Sync {
+    fn record(&self, event: &RunEvent);
+}
+
+impl<F: Fn(&RunEvent) + Send + Sync> LogSink for F {
+    fn record(&self, event: &RunEvent) {
+        self(event)
+    }
+}
+
+/// Forwards a copy of every event; a dropped receiver is ignored
+impl LogSink for Sender<RunEvent> {
+    fn record(&self, event: &RunEvent) {
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Writes every event as one JSON line
+pub struct JsonlLogSink<W: Write + Send> {
+    state: Mutex<(W, Option<io::Error>)>,
+}
+
+impl<W: Write + Send> JsonlLogSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            state: Mutex::new((writer, None)),
+        }
+    }
+
+    /// Flush and return the writer, or the first write error
+    pub fn finish(self) -> io::Result<W> {
+        let (mut writer, error) = self.state.into_inner().unwrap_or_else(|p| p.into_inner());
+        match error {
+            Some(e) => Err(e),
+            None => writer.flush().map(|_| writer),
+        }
+    }
+}
+
+impl<W: Write + Send> LogSink for JsonlLogSink<W> {
+    fn record(&self, event: &RunEvent) {
+        let mut state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+        let (writer, error) = &mut *state;
+        if error.is_some() {
+            return;
+        }
+        let line = serde_json::to_string(event).map_err(io::Error::from);
+        if let Err(e) = line.and_then(|line| writeln!(writer, "{}", line)) {
+            *error = Some(e);
+        }
+    }
+}
+
+/// Optional shared sink held by `BoxOverlayConfig` (default: none)
+#[derive(Clone, Default)]
+pub struct RunLogs(Option<Arc<dyn LogSink>>);
+
+impl RunLogs {
+    pub fn new(sink: impl LogSink + 'static) -> Self {
+        Self(Some(Arc::new(sink)))
+    }
+
+    pub fn from_arc(sink: Arc<dyn LogSink>) -> Self {
+        Self(Some(sink))
+    }
+
+    /// True when a sink is set
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Report a geometry run and append it to `runs`; unless `keep` (the
+    /// logs go into the result) its raw response is dropped after reporting
+    pub(crate) fn push_geometry(&self, runs: &mut Vec<GeometryRunLog>, log: GeometryRunLog, keep: bool) {
+        let event = RunEvent::Geometry { run: runs.len(), log };
+        self.record(&event);
+        if let RunEvent::Geometry { mut log, .. } = event {
+            if !keep {
+                log.raw_response = String::new();
+            }
+            runs.push(log);
+        }
+    }
+
+    /// Report a fill run and append it to `runs`, as `push_geometry`
+    pub(crate) fn push_fill(&self, runs: &mut Vec<FillRunLog>, log: FillRunLog, keep: bool) {
+        let event = RunEvent::Fill { run: runs.len(), log };
+        self.record(&event);
+        if let RunEvent::Fill { mut log, .. } = event {
+            if !keep {
+                log.raw_response = String::new();
+            }
+            runs.push(log);
+        }
+    }
+
+    fn record(&self, event: &RunEvent) {
+        if let Some(sink) = &self.0 {
+            sink.record(event);
+        }
+    }
+}
+
+impl fmt::Debug for RunLogs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.is_enabled() { "RunLogs(enabled)" } else { "RunLogs(none)" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    #[test]
+    fn test_runs_streamed_to_channel() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let config = BoxOverlayConfig::builder()
+            .log_sink(RunLogs::new(tx))
+            .keep_run_logs(false)
+            .build()
+            .unwrap();
+        let result = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        assert!(result.geometry_runs.is_empty() && result.fill_runs.is_empty());
+        assert!((result.height_m - 0.48).abs() < 1e-9);
+
+        let events: Vec<RunEvent> = rx.try_iter().collect();
+        assert!(events.iter().all(|e| match e {
+            RunEvent::Geometry { log, .. } => log.raw_response.contains("cargoTopY"),
+            RunEvent::Fill { log, .. } => log.raw_response.contains("fillRatioL"),
+        }));
+        let order: Vec<(&str, usize)> = events
+            .iter()
+            .map(|e| match e {
+                RunEvent::Geometry { run, .. } => ("geometry", *run),
+                RunEvent::Fill { run, .. } => ("fill", *run),
+            })
+            .collect();
+        assert_eq!(order, [("geometry", 0), ("geometry", 1), ("fill", 0), ("fill", 1)]);
+    }
+
+    #[test]
+    fn test_jsonl_sink_and_callback() {
+        let sink = Arc::new(JsonlLogSink::new(Vec::new()));
+        let config = BoxOverlayConfig::builder().log_sink(RunLogs::from_arc(sink.clone())).build().unwrap();
+        let result = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        assert_eq!(result.geometry_runs.len(), 2);
+        drop(config);
+
+        let bytes = Arc::into_inner(sink).unwrap().finish().unwrap();
+        let lines: Vec<serde_json::Value> =
+            String::from_utf8(bytes).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["stage"], "geometry");
+        assert_eq!(lines[0]["log"]["scaleMethod"], "tailgate");
+        assert_eq!(lines[3]["stage"], "fill");
+        assert_eq!(lines[3]["run"], 1);
+
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let seen = count.clone();
+        let callback = move |_: &RunEvent| {
+            seen.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        };
+        let config = BoxOverlayConfig::builder().log_sink(RunLogs::new(callback)).build().unwrap();
+        analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 4);
+    }
+}
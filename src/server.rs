@@ -0,0 +1,331 @@
+//! HTTP service (feature `server`)
+//!
+//! `router` exposes the pipeline as an axum `Router`, so a small deployment
+//! only has to bind it to a listener:
+//!
+//! - `POST /analyze`: multipart form with one or more `image` parts and an
+//!   optional `config` part (JSON, see `AnalyzeOptions`)
+//! - `POST /validate`: JSON parameters, answered with their range errors
+//! - `GET /spec`: the prompt-spec.json in use
+//!
+//! Backends block, so analyses run on tokio's blocking thread pool.
+
+use std::sync::Arc;
+
+use axum::extract::{DefaultBodyLimit, Multipart, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::i18n::{self, language};
+use crate::input::{InputError, InputImage};
+use crate::metadata::LoadMetadata;
+use crate::pipeline::{analyze_box_overlay, AiBackend, BoxOverlayConfig, PipelineError};
+use crate::report::result_json;
+use crate::spec::spec_json;
+use crate::validation::{validate_params, EstimationParams, ValidationError};
+
+/// Default limit of an `/analyze` request body (truck photos run to a few MB)
+pub const DEFAULT_MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// Backend and base configuration shared by all requests
+#[derive(Clone)]
+pub struct ServerState {
+    backend: Arc<dyn AiBackend + Send + Sync>,
+    config: BoxOverlayConfig,
+    max_body_bytes: usize,
+}
+
+impl ServerState {
+    pub fn new(backend: impl AiBackend + Send + Sync + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            config: BoxOverlayConfig::default(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    /// Configuration requests start from; their `config` part overrides it
+    pub fn config(mut self, config: BoxOverlayConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn max_body_bytes(mut self, bytes: usize) -> Self {
+        self.max_body_bytes = bytes;
+        self
+    }
+}
+
+/// Router with `/analyze`, `/validate` and `/spec`
+pub fn router(state: ServerState) -> Router {
+    let limit = DefaultBodyLimit::max(state.max_body_bytes);
+    Router::new()
+        .route("/analyze", post(analyze))
+        .route("/validate", post(validate))
+        .route("/spec", get(spec))
+        .layer(limit)
+        .with_state(state)
+}
+
+/// `config` part of an `/analyze` request; unset fields keep the server's
+/// base configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AnalyzeOptions {
+    pub truck_class: Option<String>,
+    pub material_type: Option<String>,
+    pub ensemble_count: Option<usize>,
+    pub geometry_runs: Option<usize>,
+    pub fill_runs: Option<usize>,
+    pub metadata: Option<LoadMetadata>,
+}
+
+impl AnalyzeOptions {
+    fn apply(self, base: BoxOverlayConfig) -> Result<BoxOverlayConfig, ApiError> {
+        let mut builder = base.into_builder();
+        if let Some(truck_class) = self.truck_class {
+            builder = builder.truck_class(truck_class);
+        }
+        if let Some(material_type) = self.material_type {
+            builder = builder.material_type(material_type);
+        }
+        if let Some(count) = self.ensemble_count {
+            builder = builder.ensemble_count(count);
+        }
+        if let Some(count) = self.geometry_runs {
+            builder = builder.geometry_runs(count);
+        }
+        if let Some(count) = self.fill_runs {
+            builder = builder.fill_runs(count);
+        }
+        if let Some(metadata) = self.metadata {
+            builder = builder.metadata(metadata);
+        }
+        builder
+            .build()
+            .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.code(), e.to_string()))
+    }
+}
+
+/// Error body `{"code": ..., "error": ...}` with its status
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+}
+
+impl From<PipelineError> for ApiError {
+    fn from(e: PipelineError) -> Self {
+        // Problems with the request itself are the client's; everything else
+        // is a failure of the AI backend behind this service
+        let status = match e {
+            PipelineError::UnusableImage(_)
+            | PipelineError::UnknownTruckClass(_)
+            | PipelineError::Preprocess(_)
+            | PipelineError::ImageTooLarge { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            PipelineError::CallTimeout(_) | PipelineError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            PipelineError::CircuitOpen { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_GATEWAY,
+        };
+        Self::new(status, e.code(), e.to_string())
+    }
+}
+
+impl From<InputError> for ApiError {
+    fn from(e: InputError) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, e.code(), e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({"code": self.code, "error": self.message}))).into_response()
+    }
+}
+
+async fn analyze(State(state): State<ServerState>, mut multipart: Multipart) -> Result<Json<Value>, ApiError> {
+    let mut images = Vec::new();
+    let mut options = AnalyzeOptions::default();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::bad_request("INVALID_MULTIPART", e.body_text()))?
+    {
+        match field.name() {
+            Some("image") => {
+                let name = field.file_name().map(str::to_owned);
+                let bytes = field
+                    .bytes()
+                    .await
+                    .map_err(|e| ApiError::bad_request("INVALID_MULTIPART", e.body_text()))?;
+                let image = InputImage::new(bytes.to_vec())?;
+                images.push(match name {
+                    Some(name) => image.name(name),
+                    None => image,
+                });
+            }
+            Some("config") => {
+                let text = field
+                    .text()
+                    .await
+                    .map_err(|e| ApiError::bad_request("INVALID_MULTIPART", e.body_text()))?;
+                options = serde_json::from_str(&text).map_err(|e| {
+                    ApiError::bad_request("INVALID_CONFIG", i18n::INVALID_CONFIG.format(language(), &[&e]))
+                })?;
+            }
+            other => {
+                return Err(ApiError::bad_request(
+                    "UNKNOWN_FIELD",
+                    i18n::UNKNOWN_FIELD.format(language(), &[&other.unwrap_or("")]),
+                ))
+            }
+        }
+    }
+    if images.is_empty() {
+        return Err(ApiError::bad_request("NO_IMAGE", i18n::NO_IMAGE_FIELD.get(language())));
+    }
+    let config = options.apply(state.config.clone())?;
+
+    let backend = Arc::clone(&state.backend);
+    let result = tokio::task::spawn_blocking(move || analyze_box_overlay(backend.as_ref(), &images, &config))
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL", e.to_string()))??;
+    Ok(Json(result_json(&result)))
+}
+
+/// Body of `/validate`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ValidateRequest {
+    height: Option<f64>,
+    fill_ratio_l: Option<f64>,
+    fill_ratio_w: Option<f64>,
+    taper_ratio: Option<f64>,
+    packing_density: Option<f64>,
+}
+
+async fn validate(Json(request): Json<ValidateRequest>) -> Json<Value> {
+    let errors = validate_params(&EstimationParams {
+        height: request.height,
+        fill_ratio_l: request.fill_ratio_l,
+        fill_ratio_w: request.fill_ratio_w,
+        taper_ratio: request.taper_ratio,
+        packing_density: request.packing_density,
+    });
+    Json(json!({
+        "ok": errors.is_empty(),
+        "errors": errors.iter().map(validation_json).collect::<Vec<_>>(),
+    }))
+}
+
+fn validation_json(e: &ValidationError) -> Value {
+    json!({
+        "code": e.code(),
+        "field": e.field,
+        "value": e.value,
+        "min": e.min,
+        "max": e.max,
+        "message": e.message,
+    })
+}
+
+async fn spec() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], spec_json())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixed_backend;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    const BOUNDARY: &str = "tonsuu-boundary";
+    const JPEG: &[u8] = b"\xFF\xD8\xFFphoto";
+
+    fn multipart(parts: &[(&str, &[u8])]) -> Request<Body> {
+        let mut body = Vec::new();
+        for (name, data) in parts {
+            body.extend_from_slice(
+                format!("--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n").as_bytes(),
+            );
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+        Request::post("/analyze")
+            .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={BOUNDARY}"))
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn send(request: Request<Body>) -> (StatusCode, Value) {
+        let response = router(ServerState::new(fixed_backend())).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_analyze() {
+        let config = r#"{"materialType":"As殻","metadata":{"jobNumber":"J-1"}}"#;
+        let (status, body) = send(multipart(&[("image", JPEG), ("config", config.as_bytes())])).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["truckClass"], "4t");
+        assert_eq!(body["metadata"]["jobNumber"], "J-1");
+        assert!(body["tonnage"].as_f64().unwrap() > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_rejects_bad_requests() {
+        let (status, body) = send(multipart(&[("config", b"{}")])).await;
+        assert_eq!((status, body["code"].as_str()), (StatusCode::BAD_REQUEST, Some("NO_IMAGE")));
+
+        let (status, body) = send(multipart(&[("image", JPEG), ("config", br#"{"ensembleCount":0}"#)])).await;
+        assert_eq!((status, body["code"].as_str()), (StatusCode::BAD_REQUEST, Some("ZERO_ENSEMBLE_COUNT")));
+
+        let (_, body) = send(multipart(&[("image", JPEG), ("config", br#"{"truck":"4t"}"#)])).await;
+        assert_eq!(body["code"], "INVALID_CONFIG");
+
+        let (status, body) = send(multipart(&[("image", b"GIF89a")])).await;
+        assert_eq!((status, body["code"].as_str()), (StatusCode::UNPROCESSABLE_ENTITY, Some("UNKNOWN_IMAGE_FORMAT")));
+    }
+
+    #[tokio::test]
+    async fn test_validate_and_spec() {
+        let request = Request::post("/validate")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"height":0.4,"fillRatioL":5.0}"#))
+            .unwrap();
+        let (status, body) = send(request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ok"], false);
+        assert_eq!(body["errors"][0]["field"], "fillRatioL");
+
+        let (status, body) = send(Request::get("/spec").body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["version"], "2.1.0");
+    }
+}
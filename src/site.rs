@@ -0,0 +1,133 @@
+//! Per-site configuration
+//!
+//! One deployment can serve several construction sites with their own
+//! trucks, accepted materials and acceptance rules. A `SiteProfile` bundles
+//! them and is selected per analysis with `BoxOverlayConfigBuilder::site`,
+//! so nothing global changes between requests.
+
+use std::collections::HashMap;
+
+use crate::policy::TolerancePolicy;
+use crate::spec::{get_material, get_truck_spec, MaterialEntry, TruckSpec};
+
+/// Site entries layered over the prompt spec.
+///
+/// Lookups try the site entries first (exact key), then the spec.
+#[derive(Debug, Clone, Default)]
+pub struct SpecOverlay {
+    /// Truck classes added or replaced for the site
+    pub truck_specs: HashMap<String, TruckSpec>,
+    /// Materials added or replaced for the site
+    pub materials: HashMap<String, MaterialEntry>,
+}
+
+impl SpecOverlay {
+    /// Truck spec by class (spec aliases normalized)
+    pub fn truck_spec<'a>(&'a self, truck_class: &'a str) -> Option<&'a TruckSpec> {
+        self.truck_specs.get(truck_class).or_else(|| get_truck_spec(truck_class))
+    }
+
+    /// Material entry by name
+    pub fn material(&self, name: &str) -> Option<&MaterialEntry> {
+        self.materials.get(name).or_else(|| get_material(name))
+    }
+}
+
+/// Configuration of one construction site
+#[derive(Debug, Clone)]
+pub struct SiteProfile {
+    pub name: String,
+    /// Truck classes operating at the site; the first is the default
+    pub trucks: Vec<String>,
+    /// Materials accepted at the site (empty = any)
+    pub allowed_materials: Vec<String>,
+    pub spec: SpecOverlay,
+    /// Acceptance policy for the site's results (None = keep the config's)
+    pub policy: Option<TolerancePolicy>,
+    /// Locale for user-facing text (BCP 47, e.g. "ja-JP")
+    pub locale: String,
+}
+
+impl SiteProfile {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            trucks: Vec::new(),
+            allowed_materials: Vec::new(),
+            spec: SpecOverlay::default(),
+            policy: None,
+            locale: "ja-JP".to_string(),
+        }
+    }
+
+    /// Default truck class of the site
+    pub fn default_truck_class(&self) -> Option<&str> {
+        self.trucks.first().map(String::as_str)
+    }
+
+    /// True when the material is accepted at the site
+    pub fn allows_material(&self, name: &str) -> bool {
+        self.allowed_materials.is_empty() || self.allowed_materials.iter().any(|m| m == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig, ConfigError};
+    use crate::policy::Verdict;
+    use crate::testing::fixed_backend;
+
+    fn site() -> SiteProfile {
+        let mut site = SiteProfile::new("北工区");
+        site.trucks = vec!["8t".into(), "4t".into()];
+        site.allowed_materials = vec!["As殻".into(), "土砂".into()];
+        site.spec.truck_specs.insert(
+            "8t".into(),
+            TruckSpec {
+                bed_length: 4.6,
+                bed_width: 2.2,
+                bed_height: 0.4,
+                level_volume: 5.0,
+                heap_volume: 6.0,
+                max_capacity: 8.0,
+            },
+        );
+        site.policy = Some(TolerancePolicy::default());
+        site
+    }
+
+    #[test]
+    fn test_site_defaults_apply_to_analysis() {
+        let config = BoxOverlayConfig::builder().site(site()).build().unwrap();
+        assert_eq!(config.truck_class, "8t");
+        let backend = fixed_backend().with_fill_fields(r#""materialType":"Co殻""#);
+        let result = analyze_box_overlay(&backend, &[], &config).unwrap();
+        assert_eq!(result.truck_class, "8t");
+        assert_eq!(result.breakdown.bed_length, 4.6);
+        // Co殻 is not accepted at the site, so the configured material is kept
+        assert_eq!(result.material_type, "As殻");
+        assert_eq!(result.verdict, Some(Verdict::Ok));
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_material_outside_site_rejected() {
+        let err = BoxOverlayConfig::builder()
+            .site(site())
+            .material_type("Co殻")
+            .build()
+            .unwrap_err();
+        assert_eq!(err, ConfigError::MaterialNotAllowed("Co殻".into()));
+        assert_eq!(err.code(), "MATERIAL_NOT_ALLOWED");
+    }
+
+    #[test]
+    fn test_overlay_falls_back_to_spec() {
+        let site = site();
+        assert_eq!(site.default_truck_class(), Some("8t"));
+        assert!(site.spec.truck_spec("４ｔ").is_some());
+        assert!(site.spec.material("土砂").is_some());
+        assert!(site.allows_material("土砂") && !site.allows_material("Co殻"));
+    }
+}
@@ -0,0 +1,155 @@
+//! Site geofencing
+//!
+//! Registers each `SiteProfile` with a geofence polygon so an analysis can
+//! pick its site from where the photos were taken. Set the registry with
+//! `BoxOverlayConfigBuilder::sites`; when no site is selected explicitly,
+//! the position of the first input that has one (explicit `InputImage::gps`
+//! or, with the `image` feature, photo EXIF) selects the site whose fence
+//! contains it.
+
+use std::sync::Arc;
+
+use crate::input::GpsPosition;
+use crate::site::SiteProfile;
+
+/// Closed polygon of (latitude, longitude) vertices in degrees
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geofence {
+    pub vertices: Vec<(f64, f64)>,
+}
+
+impl Geofence {
+    pub fn new(vertices: Vec<(f64, f64)>) -> Self {
+        Self { vertices }
+    }
+
+    /// True when the position lies inside the polygon (even-odd rule; fences
+    /// with fewer than three vertices contain nothing)
+    pub fn contains(&self, position: &GpsPosition) -> bool {
+        if self.vertices.len() < 3 {
+            return false;
+        }
+        let (y, x) = (position.latitude, position.longitude);
+        let mut inside = false;
+        let mut prev = self.vertices[self.vertices.len() - 1];
+        for &(lat, lon) in &self.vertices {
+            let (prev_lat, prev_lon) = prev;
+            if (lat > y) != (prev_lat > y) && x < (prev_lon - lon) * (y - lat) / (prev_lat - lat) + lon {
+                inside = !inside;
+            }
+            prev = (lat, lon);
+        }
+        inside
+    }
+}
+
+/// A site profile with its fence
+#[derive(Debug, Clone)]
+pub struct RegisteredSite {
+    pub profile: Arc<SiteProfile>,
+    pub fence: Geofence,
+}
+
+/// Sites looked up by position
+#[derive(Debug, Clone, Default)]
+pub struct SiteRegistry {
+    sites: Vec<RegisteredSite>,
+}
+
+impl SiteRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a site; where fences overlap the earlier registration wins
+    pub fn insert(&mut self, profile: impl Into<Arc<SiteProfile>>, fence: Geofence) {
+        self.sites.push(RegisteredSite {
+            profile: profile.into(),
+            fence,
+        });
+    }
+
+    /// Site whose fence contains the position
+    pub fn locate(&self, position: &GpsPosition) -> Option<&Arc<SiteProfile>> {
+        self.sites.iter().find(|s| s.fence.contains(position)).map(|s| &s.profile)
+    }
+
+    /// Site by name
+    pub fn get(&self, name: &str) -> Option<&Arc<SiteProfile>> {
+        self.sites.iter().find(|s| s.profile.name == name).map(|s| &s.profile)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredSite> {
+        self.sites.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{ImageFormat, InputImage};
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::policy::{TolerancePolicy, Verdict};
+    use crate::testing::fixed_backend;
+
+    fn at(latitude: f64, longitude: f64) -> GpsPosition {
+        GpsPosition {
+            latitude,
+            longitude,
+            altitude: None,
+        }
+    }
+
+    fn registry() -> SiteRegistry {
+        let mut north = SiteProfile::new("北工区");
+        north.policy = Some(TolerancePolicy::default());
+        let mut sites = SiteRegistry::new();
+        sites.insert(north, Geofence::new(vec![(35.70, 139.70), (35.70, 139.71), (35.71, 139.71), (35.71, 139.70)]));
+        sites.insert(
+            SiteProfile::new("南工区"),
+            Geofence::new(vec![(35.60, 139.70), (35.60, 139.72), (35.62, 139.71)]),
+        );
+        sites
+    }
+
+    #[test]
+    fn test_geofence_lookup() {
+        let sites = registry();
+        assert_eq!(sites.locate(&at(35.705, 139.705)).unwrap().name, "北工区");
+        assert_eq!(sites.locate(&at(35.605, 139.71)).unwrap().name, "南工区");
+        // Outside the triangle but inside its bounding box
+        assert!(sites.locate(&at(35.619, 139.701)).is_none());
+        assert!(sites.locate(&at(0.0, 0.0)).is_none());
+        assert!(!Geofence::new(vec![(0.0, 0.0), (1.0, 1.0)]).contains(&at(0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_result_tagged_with_located_site() {
+        let config = BoxOverlayConfig::builder().sites(registry()).build().unwrap();
+        let photo = |gps| InputImage::with_format(b"photo".to_vec(), ImageFormat::Jpeg).gps(gps);
+
+        let result = analyze_box_overlay(&fixed_backend(), &[photo(at(35.705, 139.705))], &config).unwrap();
+        assert_eq!(result.site.as_deref(), Some("北工区"));
+        assert_eq!(result.verdict, Some(Verdict::Ok), "site policy applies");
+
+        let result = analyze_box_overlay(&fixed_backend(), &[photo(at(10.0, 10.0))], &config).unwrap();
+        assert_eq!((result.site, result.verdict), (None, None));
+
+        // An explicitly selected site is kept
+        let config = BoxOverlayConfig::builder()
+            .sites(registry())
+            .site(SiteProfile::new("本社ヤード"))
+            .build()
+            .unwrap();
+        let result = analyze_box_overlay(&fixed_backend(), &[photo(at(35.705, 139.705))], &config).unwrap();
+        assert_eq!(result.site.as_deref(), Some("本社ヤード"));
+    }
+}
@@ -4,30 +4,184 @@
 //! the single source of truth for all domain constants (ranges, trucks, materials).
 //!
 //! Prompts are NOT embedded here — they are read at runtime by each consumer.
+//!
+//! With the `wasm-minimal` feature nothing is embedded: the host, which ships
+//! prompt-spec.json next to the .wasm anyway, hands it over with `load_spec`
+//! (`loadSpec` in JS) before the first call that needs it.
 
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::LazyLock;
+#[cfg(any(feature = "wasm-minimal", test))]
+use std::sync::OnceLock;
 use serde::Deserialize;
 
+use crate::i18n::{self, language, Language, Localize};
+
 /// Raw JSON embedded at compile time
-const SPEC_JSON: &str = include_str!("../prompt-spec.json");
+#[cfg(not(feature = "wasm-minimal"))]
+pub const SPEC_JSON: &str = include_str!("../prompt-spec.json");
+
+/// Spec JSON supplied by the host
+#[cfg(feature = "wasm-minimal")]
+static HOST_SPEC_JSON: OnceLock<String> = OnceLock::new();
+
+/// `HOST_SPEC_JSON`; unit tests run as if the host had loaded the spec
+/// shipped with the crate
+#[cfg(feature = "wasm-minimal")]
+fn host_spec() -> &'static OnceLock<String> {
+    #[cfg(test)]
+    HOST_SPEC_JSON.get_or_init(|| include_str!("../prompt-spec.json").to_string());
+    &HOST_SPEC_JSON
+}
 
 /// Parsed prompt-spec.json (singleton)
 pub static SPEC: LazyLock<PromptSpec> = LazyLock::new(|| {
-    serde_json::from_str(SPEC_JSON).expect("Failed to parse embedded prompt-spec.json")
+    PromptSpec::from_json(spec_json()).expect("Failed to parse prompt-spec.json")
 });
 
+/// Raw prompt-spec.json in use: the embedded one, or with `wasm-minimal`
+/// the one given to `load_spec`.
+///
+/// Panics in a `wasm-minimal` build before `load_spec`; exports check
+/// `check_spec_loaded` first.
+pub fn spec_json() -> &'static str {
+    #[cfg(not(feature = "wasm-minimal"))]
+    {
+        SPEC_JSON
+    }
+    #[cfg(feature = "wasm-minimal")]
+    {
+        loaded(host_spec()).expect("prompt-spec.json is not loaded: call load_spec first")
+    }
+}
+
+/// `Err(SpecError::NotLoaded)` until a `wasm-minimal` build has been given
+/// its spec; always Ok when the spec is embedded
+pub fn check_spec_loaded() -> Result<(), SpecError> {
+    #[cfg(feature = "wasm-minimal")]
+    loaded(host_spec())?;
+    Ok(())
+}
+
+/// Supply prompt-spec.json (`wasm-minimal` builds only). The first valid
+/// spec wins; returns false when one was already loaded.
+#[cfg(feature = "wasm-minimal")]
+pub fn load_spec(json: String) -> Result<bool, SpecError> {
+    load_into(host_spec(), json)
+}
+
+#[cfg(any(feature = "wasm-minimal", test))]
+fn load_into(cell: &OnceLock<String>, json: String) -> Result<bool, SpecError> {
+    PromptSpec::from_json(&json)?;
+    Ok(cell.set(json).is_ok())
+}
+
+#[cfg(any(feature = "wasm-minimal", test))]
+fn loaded(cell: &OnceLock<String>) -> Result<&str, SpecError> {
+    cell.get().map(String::as_str).ok_or(SpecError::NotLoaded)
+}
+
+/// prompt-spec.json could not be loaded
+#[derive(Debug, thiserror::Error)]
+pub enum SpecError {
+    /// The document is not a valid prompt spec
+    Invalid(#[source] serde_json::Error),
+    /// No spec was supplied yet (`wasm-minimal` builds before `load_spec`)
+    NotLoaded,
+}
+
+impl SpecError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Invalid(_) => "SPEC_INVALID",
+            Self::NotLoaded => "SPEC_NOT_LOADED",
+        }
+    }
+}
+
+impl Localize for SpecError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Invalid(source) => i18n::SPEC_INVALID.format(lang, &[source]),
+            Self::NotLoaded => i18n::SPEC_NOT_LOADED.get(lang).to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
 /// Top-level prompt specification (v2.1.0)
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptSpec {
     pub version: String,
     pub materials: HashMap<String, MaterialEntry>,
+    /// Material categories keyed by name (アスファルト殻, 土砂系, ...)
+    #[serde(default)]
+    pub material_categories: HashMap<String, MaterialCategory>,
     pub truck_specs: HashMap<String, TruckSpec>,
     pub ranges: Ranges,
     pub constants: Constants,
     pub geometry_prompt: String,
     pub fill_prompt: String,
+    /// Image usability pre-check prompt
+    pub quality_prompt: String,
+    /// License plate reading prompt (empty in specs that predate it)
+    #[serde(default)]
+    pub plate_prompt: String,
+    /// Landmark height prompt (empty in specs that predate it)
+    #[serde(default)]
+    pub landmark_prompt: String,
+    /// Reasoning summary prompt; `{reasonings}` is replaced by the run
+    /// reasonings (empty in specs that predate it)
+    #[serde(default)]
+    pub reasoning_prompt: String,
+    /// Legacy multi-param prompt template
+    pub multi_param_prompt: MultiParamPrompt,
+    /// Version and deprecation metadata keyed by prompt name
+    pub prompt_versions: HashMap<String, PromptVersion>,
+    /// License plate sizes keyed by class (中板, 大板)
+    pub plate_classes: HashMap<String, PlateClass>,
+}
+
+impl PromptSpec {
+    /// Parse a prompt-spec.json document (e.g. a site-specific override)
+    pub fn from_json(json: &str) -> Result<Self, SpecError> {
+        serde_json::from_str(json).map_err(SpecError::Invalid)
+    }
+}
+
+/// Legacy multi-param prompt (template + range guide)
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiParamPrompt {
+    pub prompt_format: String,
+    pub json_template: serde_json::Value,
+    pub range_guide: String,
+}
+
+impl MultiParamPrompt {
+    /// Expand `{jsonTemplate}` and `{rangeGuide}` into the final prompt text
+    pub fn render(&self) -> String {
+        self.prompt_format
+            .replace("{jsonTemplate}", &self.json_template.to_string())
+            .replace("{rangeGuide}", &self.range_guide)
+    }
+}
+
+/// Version metadata for a named prompt
+#[derive(Debug, Deserialize, Clone)]
+pub struct PromptVersion {
+    pub version: String,
+    /// Deprecation note (None = current)
+    #[serde(default)]
+    pub deprecated: Option<String>,
 }
 
 /// Parameter ranges for box-overlay strategy
@@ -81,10 +235,101 @@ pub struct Constants {
     pub effective_packing_max: f64,
 }
 
-/// Material density entry
+/// Physical license plate size
 #[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlateClass {
+    pub width_m: f64,
+    pub height_m: f64,
+}
+
+/// Material density and typical fill profile
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct MaterialEntry {
+    /// Key in `materialCategories` (None = uncategorized)
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Nominal density (t/m³)
     pub density: f64,
+    /// Plausible density range (None = the nominal value)
+    #[serde(default)]
+    pub density_min: Option<f64>,
+    #[serde(default)]
+    pub density_max: Option<f64>,
+    /// Swell factor (土量変化率 L): loose volume / bank volume
+    /// (None = no bank volume for this material)
+    #[serde(default)]
+    pub swell_factor: Option<f64>,
+    /// Typical packing density (None = lower end of the spec range)
+    #[serde(default)]
+    pub packing_density: Option<f64>,
+    /// Typical fill ratio ranges (None = spec range)
+    #[serde(default)]
+    pub fill_ratio_l: Option<Range>,
+    #[serde(default)]
+    pub fill_ratio_w: Option<Range>,
+    #[serde(default)]
+    pub taper_ratio: Option<Range>,
+}
+
+impl MaterialEntry {
+    /// Plausible density range, falling back to the nominal value
+    pub fn density_range(&self) -> Range {
+        Range {
+            min: self.density_min.unwrap_or(self.density),
+            max: self.density_max.unwrap_or(self.density),
+        }
+    }
+
+    /// Typical packing density, falling back to the spec range minimum
+    pub fn default_packing(&self) -> f64 {
+        self.packing_density.unwrap_or(SPEC.ranges.packing_density.min)
+    }
+
+    /// Typical fillRatioL range, falling back to the spec range
+    pub fn fill_ratio_l_range(&self) -> &Range {
+        self.fill_ratio_l.as_ref().unwrap_or(&SPEC.ranges.fill_ratio_l)
+    }
+
+    /// Typical fillRatioW range, falling back to the spec range
+    pub fn fill_ratio_w_range(&self) -> &Range {
+        self.fill_ratio_w.as_ref().unwrap_or(&SPEC.ranges.fill_ratio_w)
+    }
+
+    /// Typical taperRatio range, falling back to the spec range
+    pub fn taper_ratio_range(&self) -> &Range {
+        self.taper_ratio.as_ref().unwrap_or(&SPEC.ranges.taper_ratio)
+    }
+
+    /// Prompt sentence describing the typical values of this material
+    pub fn prompt_hint(&self, name: &str) -> String {
+        let (l, w, t) = (self.fill_ratio_l_range(), self.fill_ratio_w_range(), self.taper_ratio_range());
+        format!(
+            "The cargo is expected to be {}. Typical values for this material: fillRatioL {}~{}, fillRatioW {}~{}, taperRatio {}~{}, packingDensity around {}.",
+            name,
+            l.min,
+            l.max,
+            w.min,
+            w.max,
+            t.min,
+            t.max,
+            self.default_packing()
+        )
+    }
+}
+
+/// Material category with its legal waste classification
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MaterialCategory {
+    /// Classification for the manifest (がれき類, 建設発生土, ...)
+    pub legal_class: String,
+    /// JWNET waste type code (None = not waste, or assigned per load)
+    #[serde(default)]
+    pub waste_code: Option<String>,
+    /// Typical density range of the category (t/m³)
+    pub density_range: Range,
 }
 
 /// Truck bed specification
@@ -101,6 +346,34 @@ pub struct TruckSpec {
 
 // === Accessor functions ===
 
+/// Material entry by name
+pub fn get_material(name: &str) -> Option<&'static MaterialEntry> {
+    SPEC.materials.get(name)
+}
+
+/// Category entry by name
+pub fn get_category(name: &str) -> Option<&'static MaterialCategory> {
+    SPEC.material_categories.get(name)
+}
+
+/// (category name, entry) of a material
+pub fn material_category(material: &str) -> Option<(&'static str, &'static MaterialCategory)> {
+    let name = SPEC.materials.get(material)?.category.as_deref()?;
+    SPEC.material_categories.get_key_value(name).map(|(k, v)| (k.as_str(), v))
+}
+
+/// Materials of a category, sorted by name
+pub fn materials_in_category(category: &str) -> Vec<&'static str> {
+    let mut names: Vec<&str> = SPEC
+        .materials
+        .iter()
+        .filter(|(_, m)| m.category.as_deref() == Some(category))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    names.sort_unstable();
+    names
+}
+
 /// Get material density by name, default to As殻 density
 pub fn get_material_density(name: &str) -> f64 {
     SPEC.materials
@@ -111,9 +384,88 @@ pub fn get_material_density(name: &str) -> f64 {
         })
 }
 
-/// Get truck spec by class
+/// Get material density range by name, default to the As殻 range
+pub fn get_material_density_range(name: &str) -> Range {
+    SPEC.materials
+        .get(name)
+        .or_else(|| SPEC.materials.get("As殻"))
+        .map(|m| m.density_range())
+        .unwrap_or(Range { min: 2.5, max: 2.5 })
+}
+
+/// Physical plate height for a plate class, `PLATE_HEIGHT_M` when unknown or unset
+pub fn plate_height_m(plate_class: Option<&str>) -> f64 {
+    plate_class
+        .and_then(|c| SPEC.plate_classes.get(c))
+        .map(|p| p.height_m)
+        .unwrap_or(SPEC.constants.plate_height_m)
+}
+
+/// Outcome of truck-class normalization
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TruckClassMatch {
+    /// Canonical `truckSpecs` key
+    Known(String),
+    /// Input (as given) that matches no truck class in the spec
+    Unrecognized(String),
+}
+
+impl TruckClassMatch {
+    /// Canonical class, None when unrecognized
+    pub fn canonical(&self) -> Option<&str> {
+        match self {
+            Self::Known(class) => Some(class),
+            Self::Unrecognized(_) => None,
+        }
+    }
+}
+
+/// Map free-form truck-class input ("４ｔ", "4トン", "4 ton", "10t dump")
+/// to a canonical `truckSpecs` key
+pub fn normalize_truck_class(input: &str) -> TruckClassMatch {
+    if SPEC.truck_specs.contains_key(input) {
+        return TruckClassMatch::Known(input.to_string());
+    }
+
+    // Full-width -> half-width, drop whitespace, lowercase
+    let folded: String = input
+        .chars()
+        .map(|ch| match ch {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(ch as u32 - 0xFEE0).unwrap_or(ch),
+            _ => ch,
+        })
+        .filter(|ch| !ch.is_whitespace())
+        .map(|ch| ch.to_ascii_lowercase())
+        .collect();
+
+    let mut rest = folded.as_str();
+    for suffix in ["車", "ダンプ", "dumptruck", "dump", "truck"] {
+        rest = rest.strip_suffix(suffix).unwrap_or(rest);
+    }
+    for unit in ["トン", "tons", "ton", "t"] {
+        if let Some(n) = rest.strip_suffix(unit) {
+            rest = n;
+            break;
+        }
+    }
+
+    let candidate = if rest == "増" {
+        Some("増トン".to_string())
+    } else {
+        rest.parse::<f64>().ok().map(|n| format!("{}t", n))
+    };
+    match candidate {
+        Some(class) if SPEC.truck_specs.contains_key(&class) => TruckClassMatch::Known(class),
+        _ => TruckClassMatch::Unrecognized(input.to_string()),
+    }
+}
+
+/// Get truck spec by class (aliases normalized)
 pub fn get_truck_spec(truck_class: &str) -> Option<&TruckSpec> {
-    SPEC.truck_specs.get(truck_class)
+    match normalize_truck_class(truck_class) {
+        TruckClassMatch::Known(class) => SPEC.truck_specs.get(&class),
+        TruckClassMatch::Unrecognized(_) => None,
+    }
 }
 
 /// Get truck bed area (length * width)
@@ -146,6 +498,22 @@ pub fn hinge_height() -> f64 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_load_spec_first_valid_wins() {
+        let cell = OnceLock::new();
+        assert_eq!(loaded(&cell).unwrap_err().code(), "SPEC_NOT_LOADED");
+
+        assert_eq!(load_into(&cell, "{".into()).unwrap_err().code(), "SPEC_INVALID");
+        assert_eq!(load_into(&cell, "{}".into()).unwrap_err().code(), "SPEC_INVALID");
+        assert!(loaded(&cell).is_err());
+
+        assert!(load_into(&cell, spec_json().to_string()).unwrap());
+        let mut other: serde_json::Value = serde_json::from_str(spec_json()).unwrap();
+        other["version"] = "9.9.9".into();
+        assert!(!load_into(&cell, other.to_string()).unwrap());
+        assert_eq!(loaded(&cell).unwrap(), spec_json());
+    }
+
     #[test]
     fn test_spec_parses() {
         let spec = &*SPEC;
@@ -197,4 +565,70 @@ mod tests {
         assert!((c.bottom_fill - 0.9).abs() < f64::EPSILON);
         assert!((c.compression_ref_volume - 2.0).abs() < f64::EPSILON);
     }
+
+    #[test]
+    fn test_plate_height_by_class() {
+        assert!((plate_height_m(Some("中板")) - 0.165).abs() < f64::EPSILON);
+        assert!((plate_height_m(Some("大板")) - 0.22).abs() < f64::EPSILON);
+        assert!((plate_height_m(Some("字光式")) - SPEC.constants.plate_height_m).abs() < f64::EPSILON);
+        assert!((plate_height_m(None) - SPEC.constants.plate_height_m).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_normalize_truck_class_aliases() {
+        for (input, expected) in [
+            ("4t", "4t"),
+            ("４ｔ", "4t"),
+            ("4トン", "4t"),
+            ("4 ton", "4t"),
+            ("4T", "4t"),
+            ("10t dump", "10t"),
+            ("１０トンダンプ", "10t"),
+            ("増トン車", "増トン"),
+            ("増t", "増トン"),
+        ] {
+            assert_eq!(normalize_truck_class(input), TruckClassMatch::Known(expected.into()), "{input}");
+        }
+        assert_eq!(
+            normalize_truck_class("3t"),
+            TruckClassMatch::Unrecognized("3t".into())
+        );
+        assert!(normalize_truck_class("トラック").canonical().is_none());
+        assert!(get_truck_spec("４トン").is_some());
+    }
+
+    #[test]
+    fn test_material_profiles_within_spec_ranges() {
+        let r = &SPEC.ranges;
+        for (name, m) in &SPEC.materials {
+            let p = m.default_packing();
+            assert!(p >= r.packing_density.min && p <= r.packing_density.max, "{}", name);
+            for (typical, spec) in [
+                (m.fill_ratio_l_range(), &r.fill_ratio_l),
+                (m.fill_ratio_w_range(), &r.fill_ratio_w),
+                (m.taper_ratio_range(), &r.taper_ratio),
+            ] {
+                assert!(typical.min >= spec.min && typical.max <= spec.max, "{}", name);
+            }
+        }
+        assert!(get_material("土砂").unwrap().default_packing() > get_material("As殻").unwrap().default_packing());
+    }
+
+    #[test]
+    fn test_material_categories() {
+        for (name, m) in &SPEC.materials {
+            let category = m.category.as_deref().unwrap_or_default();
+            let entry = get_category(category).unwrap_or_else(|| panic!("{} has no category", name));
+            let range = m.density_range();
+            assert!(range.min <= m.density && m.density <= range.max, "{}", name);
+            assert!(range.min >= entry.density_range.min && range.max <= entry.density_range.max, "{}", name);
+        }
+        let (name, category) = material_category("切削ガラ").unwrap();
+        assert_eq!(name, "アスファルト殻");
+        assert_eq!(category.legal_class, "がれき類");
+        assert_eq!(category.waste_code.as_deref(), Some("1520"));
+        assert_eq!(materials_in_category("アスファルト殻"), ["As殻", "切削ガラ", "開粒度As殻"]);
+        assert!(get_category("土砂系").unwrap().waste_code.is_none());
+        assert!(material_category("unknown").is_none());
+    }
 }
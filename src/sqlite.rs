@@ -0,0 +1,420 @@
+//! SQLite history (feature `sqlite`)
+//!
+//! Durable analysis history for the desktop CLI: `SqliteHistory` creates the
+//! schema on open and implements `ResultStore`, with queries filtered and
+//! daily totals aggregated in SQL.
+
+use std::path::Path;
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+
+use crate::fleet::normalize_plate;
+use crate::history::{DailyTotal, HistoryError, LoadRecord, RunRecord, JST_OFFSET_SECS, SCHEMA_VERSION};
+use crate::policy::Verdict;
+use crate::store::{LoadQuery, ResultStore};
+
+/// Tables created by `create_schema` (idempotent)
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS loads (
+    id             TEXT PRIMARY KEY,
+    recorded_at    INTEGER NOT NULL,
+    day            TEXT NOT NULL,
+    truck_class    TEXT NOT NULL,
+    vehicle_id     TEXT,
+    plate_number   TEXT,
+    -- plate_number folded by normalize_plate, for lookups
+    plate_key      TEXT,
+    material_type  TEXT NOT NULL,
+    height_m       REAL NOT NULL,
+    volume         REAL NOT NULL,
+    tonnage        REAL NOT NULL,
+    tonnage_min    REAL NOT NULL,
+    tonnage_max    REAL NOT NULL,
+    billed_tonnage REAL,
+    formula        TEXT NOT NULL DEFAULT '2.1',
+    verdict        TEXT,
+    disagreement   INTEGER NOT NULL,
+    metadata       TEXT NOT NULL,
+    warnings       TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS loads_day ON loads (day, material_type);
+CREATE TABLE IF NOT EXISTS runs (
+    load_id         TEXT NOT NULL REFERENCES loads (id) ON DELETE CASCADE,
+    stage           TEXT NOT NULL,
+    run             INTEGER NOT NULL,
+    variant         TEXT NOT NULL,
+    backend         TEXT NOT NULL,
+    raw_response    TEXT NOT NULL,
+    parsed          INTEGER NOT NULL,
+    height_m        REAL,
+    scale_method    TEXT,
+    fill_ratio_l    REAL,
+    fill_ratio_w    REAL,
+    taper_ratio     REAL,
+    packing_density REAL,
+    PRIMARY KEY (load_id, stage, run)
+);
+";
+
+/// Create the history tables if missing and stamp the database with
+/// `SCHEMA_VERSION` (`PRAGMA user_version`); databases written by a newer
+/// crate are refused
+pub fn create_schema(conn: &Connection) -> Result<(), HistoryError> {
+    let version: i64 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(storage)?;
+    if version > i64::from(SCHEMA_VERSION) {
+        return Err(HistoryError::UnsupportedSchema(version as u64));
+    }
+    conn.execute_batch(SCHEMA).map_err(storage)?;
+    // Tables created before schema 3 lack `formula`; their loads are 2.1
+    let has_formula: bool = conn
+        .query_row("SELECT COUNT(*) > 0 FROM pragma_table_info('loads') WHERE name = 'formula'", [], |row| row.get(0))
+        .map_err(storage)?;
+    if !has_formula {
+        conn.execute_batch("ALTER TABLE loads ADD COLUMN formula TEXT NOT NULL DEFAULT '2.1'")
+            .map_err(storage)?;
+    }
+    conn.execute_batch(&format!("PRAGMA user_version = {SCHEMA_VERSION}"))
+        .map_err(storage)
+}
+
+fn storage(e: rusqlite::Error) -> HistoryError {
+    HistoryError::Storage(e.to_string())
+}
+
+fn json_error(e: serde_json::Error) -> HistoryError {
+    HistoryError::Storage(e.to_string())
+}
+
+const LOAD_COLUMNS: &str = "id, recorded_at, day, truck_class, vehicle_id, plate_number, material_type, height_m, \
+     volume, tonnage, tonnage_min, tonnage_max, billed_tonnage, verdict, disagreement, metadata, warnings, formula";
+
+/// Analysis history in a SQLite database
+#[derive(Debug)]
+pub struct SqliteHistory {
+    conn: Connection,
+    /// UTC offset used for `LoadRecord::day` (default JST)
+    pub utc_offset_secs: i64,
+}
+
+impl SqliteHistory {
+    /// Open (or create) a database file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        Self::from_connection(Connection::open(path).map_err(storage)?)
+    }
+
+    /// Database that lives only as long as the value (tests, previews)
+    pub fn open_in_memory() -> Result<Self, HistoryError> {
+        Self::from_connection(Connection::open_in_memory().map_err(storage)?)
+    }
+
+    /// Use an existing connection; the schema is created if missing
+    pub fn from_connection(conn: Connection) -> Result<Self, HistoryError> {
+        conn.execute_batch("PRAGMA foreign_keys = ON").map_err(storage)?;
+        create_schema(&conn)?;
+        Ok(Self {
+            conn,
+            utc_offset_secs: JST_OFFSET_SECS,
+        })
+    }
+
+    /// Underlying connection, for queries this module does not cover
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Loads recorded in `[from, to)` (Unix seconds), oldest first
+    pub fn loads_between(&self, from: i64, to: i64) -> Result<Vec<LoadRecord>, HistoryError> {
+        self.query_loads(
+            &format!("SELECT {LOAD_COLUMNS} FROM loads WHERE recorded_at >= ?1 AND recorded_at < ?2 ORDER BY recorded_at, id"),
+            rusqlite::params_from_iter([from, to]),
+        )
+    }
+
+    fn query_loads(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<LoadRecord>, HistoryError> {
+        let mut stmt = self.conn.prepare(sql).map_err(storage)?;
+        let rows = stmt.query_map(params, load_row).map_err(storage)?;
+        rows.map(|r| r.map_err(storage)).collect()
+    }
+}
+
+/// WHERE clause and parameters of a `LoadQuery`
+fn where_clause(query: &LoadQuery) -> (String, Vec<String>) {
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+    if let Some(day) = &query.from_day {
+        values.push(day.clone());
+        conditions.push(format!("day >= ?{}", values.len()));
+    }
+    if let Some(day) = &query.to_day {
+        values.push(day.clone());
+        conditions.push(format!("day <= ?{}", values.len()));
+    }
+    if let Some(vehicle) = &query.vehicle {
+        values.push(vehicle.clone());
+        values.push(normalize_plate(vehicle));
+        conditions.push(format!("(vehicle_id = ?{} OR plate_key = ?{})", values.len() - 1, values.len()));
+    }
+    if let Some(truck_class) = &query.truck_class {
+        values.push(truck_class.clone());
+        conditions.push(format!("truck_class = ?{}", values.len()));
+    }
+    if let Some(material) = &query.material_type {
+        values.push(material.clone());
+        conditions.push(format!("material_type = ?{}", values.len()));
+    }
+    if conditions.is_empty() {
+        (String::new(), values)
+    } else {
+        (format!(" WHERE {}", conditions.join(" AND ")), values)
+    }
+}
+
+impl ResultStore for SqliteHistory {
+    fn save(&mut self, record: LoadRecord, runs: Vec<RunRecord>) -> Result<(), HistoryError> {
+        let tx = self.conn.transaction().map_err(storage)?;
+        let inserted = tx
+            .execute(
+                &format!(
+                    "INSERT OR IGNORE INTO loads ({LOAD_COLUMNS}, plate_key) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)"
+                ),
+                params![
+                    record.id,
+                    record.recorded_at,
+                    record.day,
+                    record.truck_class,
+                    record.vehicle_id,
+                    record.plate_number,
+                    record.material_type,
+                    record.height_m,
+                    record.volume,
+                    record.tonnage,
+                    record.tonnage_min,
+                    record.tonnage_max,
+                    record.billed_tonnage,
+                    record.verdict.map(|v| v.code()),
+                    record.disagreement,
+                    serde_json::to_string(&record.metadata).map_err(json_error)?,
+                    serde_json::to_string(&record.warnings).map_err(json_error)?,
+                    record.formula.as_str(),
+                    record.plate_number.as_deref().map(normalize_plate),
+                ],
+            )
+            .map_err(storage)?;
+        if inserted == 0 {
+            return Err(HistoryError::DuplicateId(record.id));
+        }
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO runs (load_id, stage, run, variant, backend, raw_response, parsed, height_m, \
+                     scale_method, fill_ratio_l, fill_ratio_w, taper_ratio, packing_density) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                )
+                .map_err(storage)?;
+            for r in &runs {
+                stmt.execute(params![
+                    r.load_id,
+                    r.stage,
+                    r.run,
+                    r.variant,
+                    r.backend,
+                    r.raw_response,
+                    r.parsed,
+                    r.height_m,
+                    r.scale_method,
+                    r.fill_ratio_l,
+                    r.fill_ratio_w,
+                    r.taper_ratio,
+                    r.packing_density,
+                ])
+                .map_err(storage)?;
+            }
+        }
+        tx.commit().map_err(storage)
+    }
+
+    fn load(&self, id: &str) -> Result<Option<LoadRecord>, HistoryError> {
+        self.conn
+            .query_row(&format!("SELECT {LOAD_COLUMNS} FROM loads WHERE id = ?1"), [id], load_row)
+            .optional()
+            .map_err(storage)
+    }
+
+    fn runs(&self, id: &str) -> Result<Vec<RunRecord>, HistoryError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT load_id, stage, run, variant, backend, raw_response, parsed, height_m, scale_method, \
+                 fill_ratio_l, fill_ratio_w, taper_ratio, packing_density FROM runs WHERE load_id = ?1 \
+                 ORDER BY stage = 'fill', run",
+            )
+            .map_err(storage)?;
+        let rows = stmt
+            .query_map([id], |row| {
+                Ok(RunRecord {
+                    load_id: row.get(0)?,
+                    stage: row.get(1)?,
+                    run: row.get(2)?,
+                    variant: row.get(3)?,
+                    backend: row.get(4)?,
+                    raw_response: row.get(5)?,
+                    parsed: row.get(6)?,
+                    height_m: row.get(7)?,
+                    scale_method: row.get(8)?,
+                    fill_ratio_l: row.get(9)?,
+                    fill_ratio_w: row.get(10)?,
+                    taper_ratio: row.get(11)?,
+                    packing_density: row.get(12)?,
+                })
+            })
+            .map_err(storage)?;
+        rows.map(|r| r.map_err(storage)).collect()
+    }
+
+    fn query(&self, query: &LoadQuery) -> Result<Vec<LoadRecord>, HistoryError> {
+        let (clause, values) = where_clause(query);
+        self.query_loads(
+            &format!("SELECT {LOAD_COLUMNS} FROM loads{clause} ORDER BY recorded_at, id"),
+            rusqlite::params_from_iter(values),
+        )
+    }
+
+    /// Aggregated in SQL
+    fn daily_totals(&self, query: &LoadQuery) -> Result<Vec<DailyTotal>, HistoryError> {
+        let (clause, values) = where_clause(query);
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT day, material_type, COUNT(*), SUM(tonnage), SUM(volume), \
+                 SUM(COALESCE(billed_tonnage, tonnage)) FROM loads{clause} \
+                 GROUP BY day, material_type ORDER BY day, material_type"
+            ))
+            .map_err(storage)?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(values), |row| {
+                Ok(DailyTotal {
+                    day: row.get(0)?,
+                    material_type: row.get(1)?,
+                    loads: row.get(2)?,
+                    tonnage: row.get(3)?,
+                    volume: row.get(4)?,
+                    billed_tonnage: row.get(5)?,
+                })
+            })
+            .map_err(storage)?;
+        rows.map(|r| r.map_err(storage)).collect()
+    }
+
+    fn utc_offset_secs(&self) -> i64 {
+        self.utc_offset_secs
+    }
+}
+
+fn load_row(row: &Row<'_>) -> rusqlite::Result<LoadRecord> {
+    let verdict: Option<String> = row.get(13)?;
+    Ok(LoadRecord {
+        schema_version: SCHEMA_VERSION,
+        id: row.get(0)?,
+        recorded_at: row.get(1)?,
+        day: row.get(2)?,
+        truck_class: row.get(3)?,
+        vehicle_id: row.get(4)?,
+        plate_number: row.get(5)?,
+        material_type: row.get(6)?,
+        height_m: row.get(7)?,
+        volume: row.get(8)?,
+        tonnage: row.get(9)?,
+        tonnage_min: row.get(10)?,
+        tonnage_max: row.get(11)?,
+        billed_tonnage: row.get(12)?,
+        formula: row
+            .get::<_, String>(17)?
+            .parse()
+            .map_err(|e: String| rusqlite::Error::FromSqlConversionFailure(17, Type::Text, e.into()))?,
+        verdict: verdict.as_deref().and_then(Verdict::from_code),
+        disagreement: row.get(14)?,
+        metadata: json_column(row, 15)?,
+        warnings: json_column(row, 16)?,
+    })
+}
+
+/// JSON-encoded text column
+fn json_column<T: DeserializeOwned>(row: &Row<'_>, idx: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(idx)?;
+    serde_json::from_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::run_records;
+    use crate::metadata::LoadMetadata;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    // 2026-10-16 00:00 JST
+    const DAY_START: i64 = 1_792_076_400;
+
+    #[test]
+    fn test_store() {
+        let mut db = SqliteHistory::open_in_memory().unwrap();
+        crate::store::tests::check_store(&mut db);
+    }
+
+    #[test]
+    fn test_duplicate_and_time_range() {
+        let config = BoxOverlayConfig::builder()
+            .metadata(LoadMetadata::new().job_number("J-1"))
+            .build()
+            .unwrap();
+        let result = analyze_box_overlay(&fixed_backend(), &[], &config).unwrap();
+        let mut db = SqliteHistory::open_in_memory().unwrap();
+        let stored = db.record("load-1", DAY_START + 3600, &result).unwrap();
+        db.record("load-2", DAY_START + 7200, &result).unwrap();
+        db.record("load-3", DAY_START - 60, &result).unwrap();
+
+        let err = db.record("load-1", DAY_START, &result).unwrap_err();
+        assert_eq!(err.code(), "HISTORY_DUPLICATE_ID");
+        // The failed insert left no runs behind
+        assert_eq!(db.runs("load-1").unwrap(), run_records("load-1", &result));
+
+        assert_eq!(db.load("load-1").unwrap(), Some(stored));
+        let ids: Vec<String> = db.query(&LoadQuery::new().day("2026-10-16")).unwrap().into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, ["load-1", "load-2"]);
+        assert_eq!(db.loads_between(DAY_START - 60, DAY_START + 3601).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_newer_schema_refused() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("PRAGMA user_version = 99").unwrap();
+        let err = SqliteHistory::from_connection(conn).unwrap_err();
+        assert_eq!(err, HistoryError::UnsupportedSchema(99));
+
+        let db = SqliteHistory::open_in_memory().unwrap();
+        let version: u32 = db.connection().query_row("PRAGMA user_version", [], |r| r.get(0)).unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_daily_totals_in_sql() {
+        let result = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        let mut db = SqliteHistory::open_in_memory().unwrap();
+        let mut billed = result.clone();
+        billed.billed_tonnage = Some(4.5);
+        db.record("a", DAY_START, &result).unwrap();
+        db.record("b", DAY_START + 60, &billed).unwrap();
+        db.record("c", DAY_START + 86_400, &result).unwrap();
+
+        let query = LoadQuery::new().days("2026-10-16", "2026-10-17");
+        let totals = db.daily_totals(&query).unwrap();
+        assert_eq!(totals, crate::history::daily_totals(&db.query(&query).unwrap()));
+        assert_eq!(totals[0].loads, 2);
+        assert!((totals[0].billed_tonnage - (result.tonnage + 4.5)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,354 @@
+//! Result storage
+//!
+//! `ResultStore` is the interface the history and aggregation code works
+//! against: save a load with its runs, load it by id, query by day range,
+//! truck and material. `MemoryStore` and the JSON Lines `FileStore` ship
+//! here, `SqliteHistory` with the `sqlite` feature; server deployments
+//! implement the trait over their own database.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fleet::normalize_plate;
+use crate::history::{daily_totals, run_records, DailyTotal, HistoryError, LoadRecord, RunRecord, JST_OFFSET_SECS};
+use crate::pipeline::BoxOverlayResult;
+
+/// Filter for `ResultStore::query`; unset fields match every load
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadQuery {
+    /// First local day ("YYYY-MM-DD"), inclusive
+    pub from_day: Option<String>,
+    /// Last local day, inclusive
+    pub to_day: Option<String>,
+    /// Vehicle ID or plate number (any spacing or width)
+    pub vehicle: Option<String>,
+    pub truck_class: Option<String>,
+    pub material_type: Option<String>,
+}
+
+impl LoadQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads of one day
+    pub fn day(self, day: impl Into<String>) -> Self {
+        let day = day.into();
+        self.days(day.clone(), day)
+    }
+
+    /// Loads of the days `from..=to`
+    pub fn days(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.from_day = Some(from.into());
+        self.to_day = Some(to.into());
+        self
+    }
+
+    pub fn vehicle(mut self, id_or_plate: impl Into<String>) -> Self {
+        self.vehicle = Some(id_or_plate.into());
+        self
+    }
+
+    pub fn truck_class(mut self, truck_class: impl Into<String>) -> Self {
+        self.truck_class = Some(truck_class.into());
+        self
+    }
+
+    pub fn material_type(mut self, material_type: impl Into<String>) -> Self {
+        self.material_type = Some(material_type.into());
+        self
+    }
+
+    /// True when the record passes every set filter
+    pub fn matches(&self, record: &LoadRecord) -> bool {
+        self.from_day.as_ref().is_none_or(|d| record.day >= *d)
+            && self.to_day.as_ref().is_none_or(|d| record.day <= *d)
+            && self.vehicle.as_ref().is_none_or(|v| {
+                record.vehicle_id.as_ref() == Some(v)
+                    || record
+                        .plate_number
+                        .as_ref()
+                        .is_some_and(|p| normalize_plate(p) == normalize_plate(v))
+            })
+            && self.truck_class.as_ref().is_none_or(|c| record.truck_class == *c)
+            && self.material_type.as_ref().is_none_or(|m| record.material_type == *m)
+    }
+}
+
+/// Storage of analysis history
+pub trait ResultStore {
+    /// Store a load and its runs; fails with `DuplicateId` when the id exists
+    fn save(&mut self, record: LoadRecord, runs: Vec<RunRecord>) -> Result<(), HistoryError>;
+
+    /// Load by id
+    fn load(&self, id: &str) -> Result<Option<LoadRecord>, HistoryError>;
+
+    /// Runs of a load, geometry runs first
+    fn runs(&self, id: &str) -> Result<Vec<RunRecord>, HistoryError>;
+
+    /// Matching loads, oldest first
+    fn query(&self, query: &LoadQuery) -> Result<Vec<LoadRecord>, HistoryError>;
+
+    /// Per-day, per-material totals of the matching loads
+    fn daily_totals(&self, query: &LoadQuery) -> Result<Vec<DailyTotal>, HistoryError> {
+        Ok(daily_totals(&self.query(query)?))
+    }
+
+    /// UTC offset used for `LoadRecord::day`
+    fn utc_offset_secs(&self) -> i64 {
+        JST_OFFSET_SECS
+    }
+
+    /// Store a result analyzed at `recorded_at` (Unix seconds) under `id`
+    fn record(&mut self, id: &str, recorded_at: i64, result: &BoxOverlayResult) -> Result<LoadRecord, HistoryError> {
+        let record = LoadRecord::new(id, recorded_at, self.utc_offset_secs(), result);
+        self.save(record.clone(), run_records(id, result))?;
+        Ok(record)
+    }
+}
+
+/// Order of `ResultStore::query`
+fn sort_loads(loads: &mut [LoadRecord]) {
+    loads.sort_by(|a, b| a.recorded_at.cmp(&b.recorded_at).then_with(|| a.id.cmp(&b.id)));
+}
+
+// ─── In-memory store ─────────────────────────────────────────────────
+
+/// History held in memory (tests, short-lived processes)
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    loads: HashMap<String, LoadRecord>,
+    runs: HashMap<String, Vec<RunRecord>>,
+    /// UTC offset used for `LoadRecord::day` (default JST)
+    pub utc_offset_secs: i64,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            loads: HashMap::new(),
+            runs: HashMap::new(),
+            utc_offset_secs: JST_OFFSET_SECS,
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.loads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.loads.is_empty()
+    }
+}
+
+impl ResultStore for MemoryStore {
+    fn save(&mut self, record: LoadRecord, runs: Vec<RunRecord>) -> Result<(), HistoryError> {
+        if self.loads.contains_key(&record.id) {
+            return Err(HistoryError::DuplicateId(record.id));
+        }
+        self.runs.insert(record.id.clone(), runs);
+        self.loads.insert(record.id.clone(), record);
+        Ok(())
+    }
+
+    fn load(&self, id: &str) -> Result<Option<LoadRecord>, HistoryError> {
+        Ok(self.loads.get(id).cloned())
+    }
+
+    fn runs(&self, id: &str) -> Result<Vec<RunRecord>, HistoryError> {
+        Ok(self.runs.get(id).cloned().unwrap_or_default())
+    }
+
+    fn query(&self, query: &LoadQuery) -> Result<Vec<LoadRecord>, HistoryError> {
+        let mut loads: Vec<LoadRecord> = self.loads.values().filter(|r| query.matches(r)).cloned().collect();
+        sort_loads(&mut loads);
+        Ok(loads)
+    }
+
+    fn utc_offset_secs(&self) -> i64 {
+        self.utc_offset_secs
+    }
+}
+
+// ─── File store ──────────────────────────────────────────────────────
+
+/// One line of a `FileStore` file; read with the load still unparsed so
+/// records of older schema versions can be upgraded
+#[derive(Serialize, Deserialize)]
+struct FileEntry<L = LoadRecord> {
+    load: L,
+    runs: Vec<RunRecord>,
+}
+
+/// History in an append-only JSON Lines file (one load with its runs per
+/// line), read into memory on open
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    memory: MemoryStore,
+}
+
+impl FileStore {
+    /// Open a history file, creating it when missing
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HistoryError> {
+        let path = path.as_ref().to_path_buf();
+        let mut memory = MemoryStore::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for (i, line) in BufReader::new(file).lines().enumerate() {
+                    let line = line.map_err(io_error)?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let entry: FileEntry<serde_json::Value> = serde_json::from_str(&line)
+                        .map_err(|e| HistoryError::InvalidLine { line: i + 1, message: e.to_string() })?;
+                    memory.save(LoadRecord::from_versioned_value(entry.load)?, entry.runs)?;
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(io_error(e)),
+        }
+        Ok(Self { path, memory })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// UTC offset used for `LoadRecord::day` (default JST)
+    pub fn set_utc_offset_secs(&mut self, secs: i64) {
+        self.memory.utc_offset_secs = secs;
+    }
+}
+
+fn io_error(e: std::io::Error) -> HistoryError {
+    HistoryError::Storage(e.to_string())
+}
+
+impl ResultStore for FileStore {
+    fn save(&mut self, record: LoadRecord, runs: Vec<RunRecord>) -> Result<(), HistoryError> {
+        if self.memory.loads.contains_key(&record.id) {
+            return Err(HistoryError::DuplicateId(record.id));
+        }
+        let entry = FileEntry { load: record, runs };
+        let mut line = serde_json::to_string(&entry).map_err(|e| HistoryError::Storage(e.to_string()))?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(io_error)?;
+        file.write_all(line.as_bytes()).map_err(io_error)?;
+        self.memory.save(entry.load, entry.runs)
+    }
+
+    fn load(&self, id: &str) -> Result<Option<LoadRecord>, HistoryError> {
+        self.memory.load(id)
+    }
+
+    fn runs(&self, id: &str) -> Result<Vec<RunRecord>, HistoryError> {
+        self.memory.runs(id)
+    }
+
+    fn query(&self, query: &LoadQuery) -> Result<Vec<LoadRecord>, HistoryError> {
+        self.memory.query(query)
+    }
+
+    fn utc_offset_secs(&self) -> i64 {
+        self.memory.utc_offset_secs()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    // 2026-10-16 00:00 JST
+    const DAY_START: i64 = 1_792_076_400;
+
+    /// Three loads over two days: a (As殻, D-12), b (土砂), c (As殻, next day)
+    fn fill(store: &mut dyn ResultStore) {
+        let result = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        let mut a = result.clone();
+        a.vehicle_id = Some("D-12".into());
+        a.plate_number = Some("品川500あ1234".into());
+        let mut b = result.clone();
+        b.material_type = "土砂".into();
+        store.record("a", DAY_START + 60, &a).unwrap();
+        store.record("b", DAY_START + 30, &b).unwrap();
+        store.record("c", DAY_START + 86_400, &result).unwrap();
+    }
+
+    fn ids(loads: Vec<LoadRecord>) -> Vec<String> {
+        loads.into_iter().map(|r| r.id).collect()
+    }
+
+    /// Behaviour every store must share
+    pub(crate) fn check_store(store: &mut dyn ResultStore) {
+        fill(store);
+        assert_eq!(ids(store.query(&LoadQuery::new()).unwrap()), ["b", "a", "c"]);
+        assert_eq!(ids(store.query(&LoadQuery::new().day("2026-10-16")).unwrap()), ["b", "a"]);
+        assert_eq!(ids(store.query(&LoadQuery::new().vehicle("品川 500 あ 12-34")).unwrap()), ["a"]);
+        assert_eq!(ids(store.query(&LoadQuery::new().vehicle("D-12")).unwrap()), ["a"]);
+        assert_eq!(ids(store.query(&LoadQuery::new().material_type("As殻").truck_class("4t")).unwrap()), ["a", "c"]);
+
+        let totals = store.daily_totals(&LoadQuery::new().days("2026-10-16", "2026-10-17")).unwrap();
+        let keys: Vec<(&str, &str, u32)> =
+            totals.iter().map(|t| (t.day.as_str(), t.material_type.as_str(), t.loads)).collect();
+        assert_eq!(
+            keys,
+            [("2026-10-16", "As殻", 1), ("2026-10-16", "土砂", 1), ("2026-10-17", "As殻", 1)]
+        );
+
+        assert_eq!(store.load("a").unwrap().unwrap().vehicle_id.as_deref(), Some("D-12"));
+        assert!(store.load("z").unwrap().is_none());
+        assert_eq!(store.runs("a").unwrap().len(), 4);
+        let err = store.save(store.load("a").unwrap().unwrap(), Vec::new()).unwrap_err();
+        assert_eq!(err, HistoryError::DuplicateId("a".into()));
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStore::new();
+        check_store(&mut store);
+        assert_eq!(store.len(), 3);
+    }
+
+    #[test]
+    fn test_file_store_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("tonsuu-store-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        check_store(&mut FileStore::open(&path).unwrap());
+
+        let reopened = FileStore::open(&path).unwrap();
+        assert_eq!(ids(reopened.query(&LoadQuery::new()).unwrap()), ["b", "a", "c"]);
+        assert_eq!(reopened.runs("a").unwrap().len(), 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_store_reads_version_1_lines() {
+        let path = std::env::temp_dir().join(format!("tonsuu-store-v1-{}.jsonl", std::process::id()));
+        let result = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        let record = LoadRecord::new("old", DAY_START, JST_OFFSET_SECS, &result);
+        let mut line = serde_json::json!({"load": record, "runs": []});
+        line["load"].as_object_mut().unwrap().remove("schemaVersion");
+        std::fs::write(&path, format!("{line}\n")).unwrap();
+
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.load("old").unwrap(), Some(record));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+//! Golden fixtures (feature `test-util`)
+//!
+//! `record_fixture` runs an analysis through a recording backend and keeps
+//! the configuration, every prompt with its raw response, and the result.
+//! Saved as JSON next to an app's tests, `GoldenFixture::assert_replay`
+//! answers the same prompts from the recording and fails when the crate now
+//! sends different prompts or computes a different result, so behavior is
+//! pinned across tonsuu-core upgrades.
+//!
+//! `scenario` generates synthetic, labeled loads for property tests, and
+//! `fixed_backend` answers every prompt with one canned reply per stage.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::calculation::FormulaVersion;
+use crate::ensemble::MedianMode;
+use crate::i18n::{self, language};
+use crate::input::InputImage;
+use crate::metadata::LoadMetadata;
+use crate::pipeline::{
+    analyze_box_overlay, AiBackend, BackendCapabilities, BackendOptions, BoxOverlayConfig, BoxOverlayResult,
+    ConfigError, PipelineError,
+};
+use crate::policy::Verdict;
+
+pub mod scenario;
+
+/// Absolute tolerance when comparing numbers with the fixture
+pub const GOLDEN_TOLERANCE: f64 = 1e-9;
+
+/// Configuration values a fixture replays with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureConfig {
+    pub truck_class: String,
+    pub material_type: String,
+    pub ensemble_count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geometry_runs: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fill_runs: Option<usize>,
+    #[serde(default, skip_serializing_if = "LoadMetadata::is_empty")]
+    pub metadata: LoadMetadata,
+    #[serde(default, skip_serializing_if = "FormulaVersion::is_current")]
+    pub formula: FormulaVersion,
+    /// Fixtures recorded before the setting took the upper median
+    #[serde(default = "legacy_median")]
+    pub median: MedianMode,
+}
+
+fn legacy_median() -> MedianMode {
+    MedianMode::Upper
+}
+
+impl From<&BoxOverlayConfig> for FixtureConfig {
+    fn from(config: &BoxOverlayConfig) -> Self {
+        Self {
+            truck_class: config.truck_class.clone(),
+            material_type: config.material_type.clone(),
+            ensemble_count: config.ensemble_count,
+            geometry_runs: config.geometry_runs,
+            fill_runs: config.fill_runs,
+            metadata: config.metadata.clone(),
+            formula: config.formula,
+            median: config.median,
+        }
+    }
+}
+
+impl FixtureConfig {
+    pub fn to_config(&self) -> Result<BoxOverlayConfig, ConfigError> {
+        let mut builder = BoxOverlayConfig::builder()
+            .truck_class(self.truck_class.as_str())
+            .material_type(self.material_type.as_str())
+            .ensemble_count(self.ensemble_count)
+            .metadata(self.metadata.clone())
+            .formula(self.formula)
+            .median(self.median);
+        if let Some(count) = self.geometry_runs {
+            builder = builder.geometry_runs(count);
+        }
+        if let Some(count) = self.fill_runs {
+            builder = builder.fill_runs(count);
+        }
+        builder.build()
+    }
+}
+
+/// One backend call in the order it was made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exchange {
+    pub prompt: String,
+    pub response: String,
+    pub backend: String,
+}
+
+/// Result values a fixture pins
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoldenResult {
+    pub truck_class: String,
+    pub material_type: String,
+    pub height_m: f64,
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub taper_ratio: f64,
+    pub packing_density: f64,
+    pub volume: f64,
+    pub tonnage: f64,
+    pub tonnage_min: f64,
+    pub tonnage_max: f64,
+    pub billed_tonnage: Option<f64>,
+    pub verdict: Option<Verdict>,
+    pub vehicle_id: Option<String>,
+    pub plate_number: Option<String>,
+    /// Warning texts as displayed
+    pub warnings: Vec<String>,
+}
+
+impl From<&BoxOverlayResult> for GoldenResult {
+    fn from(result: &BoxOverlayResult) -> Self {
+        Self {
+            truck_class: result.truck_class.clone(),
+            material_type: result.material_type.clone(),
+            height_m: result.height_m,
+            fill_ratio_l: result.fill_ratio_l,
+            fill_ratio_w: result.fill_ratio_w,
+            taper_ratio: result.taper_ratio,
+            packing_density: result.packing_density,
+            volume: result.volume,
+            tonnage: result.tonnage,
+            tonnage_min: result.tonnage_min,
+            tonnage_max: result.tonnage_max,
+            billed_tonnage: result.billed_tonnage,
+            verdict: result.verdict,
+            vehicle_id: result.vehicle_id.clone(),
+            plate_number: result.plate_number.clone(),
+            warnings: result.warnings.iter().map(|w| w.to_string()).collect(),
+        }
+    }
+}
+
+impl GoldenResult {
+    /// Differences from `actual`, one line per field ("tonnage: 4.3 -> 4.5")
+    pub fn diff(&self, actual: &GoldenResult) -> Vec<String> {
+        let mut diffs = Vec::new();
+        let numbers = [
+            ("heightM", self.height_m, actual.height_m),
+            ("fillRatioL", self.fill_ratio_l, actual.fill_ratio_l),
+            ("fillRatioW", self.fill_ratio_w, actual.fill_ratio_w),
+            ("taperRatio", self.taper_ratio, actual.taper_ratio),
+            ("packingDensity", self.packing_density, actual.packing_density),
+            ("volume", self.volume, actual.volume),
+            ("tonnage", self.tonnage, actual.tonnage),
+            ("tonnageMin", self.tonnage_min, actual.tonnage_min),
+            ("tonnageMax", self.tonnage_max, actual.tonnage_max),
+        ];
+        for (field, expected, got) in numbers {
+            if (expected - got).abs() > GOLDEN_TOLERANCE {
+                diffs.push(format!("{field}: {expected} -> {got}"));
+            }
+        }
+        let billed_differs = match (self.billed_tonnage, actual.billed_tonnage) {
+            (Some(a), Some(b)) => (a - b).abs() > GOLDEN_TOLERANCE,
+            (a, b) => a.is_some() != b.is_some(),
+        };
+        if billed_differs {
+            diffs.push(format!("billedTonnage: {:?} -> {:?}", self.billed_tonnage, actual.billed_tonnage));
+        }
+        let texts = [
+            ("truckClass", format!("{:?}", self.truck_class), format!("{:?}", actual.truck_class)),
+            ("materialType", format!("{:?}", self.material_type), format!("{:?}", actual.material_type)),
+            ("verdict", format!("{:?}", self.verdict), format!("{:?}", actual.verdict)),
+            ("vehicleId", format!("{:?}", self.vehicle_id), format!("{:?}", actual.vehicle_id)),
+            ("plateNumber", format!("{:?}", self.plate_number), format!("{:?}", actual.plate_number)),
+            ("warnings", format!("{:?}", self.warnings), format!("{:?}", actual.warnings)),
+        ];
+        for (field, expected, got) in texts {
+            if expected != got {
+                diffs.push(format!("{field}: {expected} -> {got}"));
+            }
+        }
+        diffs
+    }
+}
+
+/// A recorded analysis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoldenFixture {
+    pub name: String,
+    /// Crate version that recorded the fixture
+    pub crate_version: String,
+    pub config: FixtureConfig,
+    pub exchanges: Vec<Exchange>,
+    pub result: GoldenResult,
+}
+
+impl GoldenFixture {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("fixture serializes")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Backend answering each prompt with its recorded responses in order
+    pub fn replay_backend(&self) -> ReplayBackend {
+        let mut responses: HashMap<String, VecDeque<String>> = HashMap::new();
+        for exchange in &self.exchanges {
+            responses
+                .entry(exchange.prompt.clone())
+                .or_default()
+                .push_back(exchange.response.clone());
+        }
+        ReplayBackend {
+            responses: Mutex::new(responses),
+        }
+    }
+
+    /// Run the analysis again against the recording (no images)
+    pub fn replay(&self) -> Result<BoxOverlayResult, PipelineError> {
+        let config = self
+            .config
+            .to_config()
+            .map_err(|e| PipelineError::AiError(i18n::FIXTURE_INVALID.format(language(), &[&e])))?;
+        self.replay_with(&[], &config)
+    }
+
+    /// Run the analysis again with the caller's images and configuration,
+    /// for fixtures recorded with settings `FixtureConfig` does not cover
+    pub fn replay_with(
+        &self,
+        images: &[InputImage],
+        config: &BoxOverlayConfig,
+    ) -> Result<BoxOverlayResult, PipelineError> {
+        analyze_box_overlay(&self.replay_backend(), images, config)
+    }
+
+    /// Differences between the fixture's result and `result`
+    pub fn check(&self, result: &BoxOverlayResult) -> Vec<String> {
+        self.result.diff(&GoldenResult::from(result))
+    }
+
+    /// Panic with every difference unless `result` matches the fixture
+    pub fn assert_matches(&self, result: &BoxOverlayResult) {
+        let diffs = self.check(result);
+        assert!(
+            diffs.is_empty(),
+            "golden fixture '{}' mismatch:\n  {}",
+            self.name,
+            diffs.join("\n  ")
+        );
+    }
+
+    /// Replay and assert the result matches
+    pub fn assert_replay(&self) {
+        match self.replay() {
+            Ok(result) => self.assert_matches(&result),
+            Err(e) => panic!("golden fixture '{}' replay failed: {e}", self.name),
+        }
+    }
+}
+
+/// Analyze `images` with `backend` and record the run as a fixture
+pub fn record_fixture(
+    name: impl Into<String>,
+    backend: &dyn AiBackend,
+    images: &[InputImage],
+    config: &BoxOverlayConfig,
+) -> Result<GoldenFixture, PipelineError> {
+    let recorder = RecordingBackend::new(backend);
+    let result = analyze_box_overlay(&recorder, images, config)?;
+    Ok(GoldenFixture {
+        name: name.into(),
+        crate_version: env!("CARGO_PKG_VERSION").to_string(),
+        config: FixtureConfig::from(config),
+        exchanges: recorder.into_exchanges(),
+        result: GoldenResult::from(&result),
+    })
+}
+
+// ─── Backends ────────────────────────────────────────────────────────
+
+/// Passes calls through to another backend and keeps each exchange
+pub struct RecordingBackend<'a> {
+    inner: &'a dyn AiBackend,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl<'a> RecordingBackend<'a> {
+    pub fn new(inner: &'a dyn AiBackend) -> Self {
+        Self {
+            inner,
+            exchanges: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn into_exchanges(self) -> Vec<Exchange> {
+        self.exchanges.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, prompt: &str, response: &str, backend: &str) {
+        let mut exchanges = self.exchanges.lock().unwrap_or_else(|e| e.into_inner());
+        exchanges.push(Exchange {
+            prompt: prompt.to_string(),
+            response: response.to_string(),
+            backend: backend.to_string(),
+        });
+    }
+}
+
+impl AiBackend for RecordingBackend<'_> {
+    fn send_prompt(&self, prompt: &str, images: &[InputImage]) -> Result<String, PipelineError> {
+        let response = self.inner.send_prompt(prompt, images)?;
+        self.push(prompt, &response, self.inner.name());
+        Ok(response)
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn capabilities(&self) -> BackendCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn send_prompt_within(
+        &self,
+        prompt: &str,
+        images: &[InputImage],
+        limit: Option<Duration>,
+        options: &BackendOptions,
+    ) -> Result<(String, String), PipelineError> {
+        let (response, backend) = self.inner.send_prompt_within(prompt, images, limit, options)?;
+        self.push(prompt, &response, &backend);
+        Ok((response, backend))
+    }
+}
+
+/// Answers geometry prompts with `geometry` and every other prompt with `fill`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedBackend {
+    pub geometry: String,
+    pub fill: String,
+}
+
+/// `FixedBackend` with one plausible load: cargo top 0.2 over a 0.3–0.5
+/// tailgate, fill 0.8/0.85 with taper 0.9 and packing density 0.8
+pub fn fixed_backend() -> FixedBackend {
+    FixedBackend {
+        geometry: r#"{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":0.2}"#.to_string(),
+        fill: r#"{"fillRatioL":0.8,"fillRatioW":0.85,"taperRatio":0.9,"packingDensity":0.8}"#.to_string(),
+    }
+}
+
+impl FixedBackend {
+    /// Answer geometry prompts with `cargoTopY` = `y` over the same tailgate
+    pub fn with_cargo_top(mut self, y: f64) -> Self {
+        self.geometry = format!(r#"{{"tailgateTopY":0.3,"tailgateBottomY":0.5,"cargoTopY":{}}}"#, y);
+        self
+    }
+
+    /// Add JSON members (e.g. `"materialType":"Co殻"`) to the geometry reply
+    pub fn with_geometry_fields(mut self, fields: &str) -> Self {
+        self.geometry = with_fields(&self.geometry, fields);
+        self
+    }
+
+    /// Add JSON members (e.g. `"materialType":"Co殻"`) to the fill reply
+    pub fn with_fill_fields(mut self, fields: &str) -> Self {
+        self.fill = with_fields(&self.fill, fields);
+        self
+    }
+}
+
+fn with_fields(reply: &str, fields: &str) -> String {
+    format!("{},{}}}", reply.trim_end().trim_end_matches('}'), fields)
+}
+
+impl AiBackend for FixedBackend {
+    fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+        if prompt.contains("tailgateTopY") {
+            Ok(self.geometry.clone())
+        } else {
+            Ok(self.fill.clone())
+        }
+    }
+}
+
+/// Answers prompts from a fixture; an unrecorded prompt is an error
+pub struct ReplayBackend {
+    responses: Mutex<HashMap<String, VecDeque<String>>>,
+}
+
+impl AiBackend for ReplayBackend {
+    fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+        let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        responses
+            .get_mut(prompt)
+            .and_then(VecDeque::pop_front)
+            .ok_or_else(|| PipelineError::AiError("記録にないプロンプトです (プロンプトが変更された可能性があります)".into()))
+    }
+
+    fn name(&self) -> &str {
+        "replay"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> GoldenFixture {
+        let config = BoxOverlayConfig::builder()
+            .metadata(LoadMetadata::new().job_number("J-1"))
+            .build()
+            .unwrap();
+        record_fixture("4t-asphalt", &fixed_backend(), &[], &config).unwrap()
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let fixture = fixture();
+        assert_eq!(fixture.exchanges.len(), 4);
+        let loaded = GoldenFixture::from_json(&fixture.to_json()).unwrap();
+        assert_eq!(loaded, fixture);
+        loaded.assert_replay();
+    }
+
+    #[test]
+    fn test_changes_are_reported() {
+        let fixture = fixture();
+        let mut result = fixture.replay().unwrap();
+        result.tonnage += 0.5;
+        result.vehicle_id = Some("D-1".into());
+        let diffs = fixture.check(&result);
+        assert_eq!(diffs.len(), 2);
+        assert!(diffs[0].starts_with("tonnage: "));
+
+        // Changed prompts no longer find their recorded responses
+        let mut changed = fixture.clone();
+        for exchange in changed.exchanges.iter_mut().filter(|e| e.prompt.contains("tailgateTopY")) {
+            exchange.prompt.push('!');
+        }
+        assert!(changed.replay().is_err());
+    }
+
+    #[test]
+    fn test_fixed_backend_fields() {
+        let backend = fixed_backend().with_fill_fields(r#""materialType":"Co殻""#);
+        let fill = backend.send_prompt("fill", &[]).unwrap();
+        assert!(fill.ends_with(r#""packingDensity":0.8,"materialType":"Co殻"}"#));
+        let result = crate::pipeline::analyze_box_overlay(&backend, &[], &BoxOverlayConfig::default()).unwrap();
+        assert!((result.height_m - 0.48).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,218 @@
+//! Synthetic scenarios
+//!
+//! A `Scenario` is a load described by its height, fill level and material.
+//! It renders the geometry and fill replies a model would give for that load,
+//! consistent with the truck's bed so the pipeline recovers the same height,
+//! and the tonnage `calculate_tonnage` expects for it. `ScenarioGenerator`
+//! draws reproducible random scenarios across the spec ranges to seed
+//! property tests and to fuzz the parse/pipeline path with labeled inputs.
+
+use serde_json::json;
+
+use crate::calculation::{calculate_tonnage, CoreParams, FormulaVersion, TonnageResult};
+use crate::offline::{CannedResponse, OfflineBackend, ResponseLibrary};
+use crate::pipeline::BoxOverlayConfig;
+use crate::spec::SPEC;
+
+/// Normalized tailgate edges in the rendered geometry replies
+const TAILGATE_TOP_Y: f64 = 0.45;
+const TAILGATE_BOTTOM_Y: f64 = 0.6;
+
+/// A labeled synthetic load
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub truck_class: String,
+    pub material_type: String,
+    pub height_m: f64,
+    pub fill_ratio_l: f64,
+    pub fill_ratio_w: f64,
+    pub taper_ratio: f64,
+    pub packing_density: f64,
+}
+
+impl Scenario {
+    /// Load of `height_m` (clamped to the spec height range and rounded to
+    /// the millimetre, as results report it) with `fill_level` in 0..=1
+    /// spread over the fill length, width and packing ranges.
+    ///
+    /// A load at or below the tailgate lies flat (taper 1.0); a mound above
+    /// it tapers toward the spec minimum at the maximum height.
+    pub fn new(truck_class: &str, height_m: f64, fill_level: f64, material_type: &str) -> Self {
+        let r = &SPEC.ranges;
+        let level = fill_level.clamp(0.0, 1.0);
+        let lerp = |min: f64, max: f64| min + (max - min) * level;
+        let height_m = (height_m.clamp(r.height.min, r.height.max) * 1000.0).round() / 1000.0;
+        let bed_height = bed_height(truck_class);
+        let mound = ((height_m - bed_height) / (r.height.max - bed_height)).clamp(0.0, 1.0);
+        Self {
+            truck_class: truck_class.to_string(),
+            material_type: material_type.to_string(),
+            height_m,
+            fill_ratio_l: lerp(r.fill_ratio_l.min, r.fill_ratio_l.max),
+            fill_ratio_w: lerp(r.fill_ratio_w.min, r.fill_ratio_w.max),
+            taper_ratio: r.taper_ratio.max - (r.taper_ratio.max - r.taper_ratio.min) * mound,
+            packing_density: lerp(r.packing_density.min, r.packing_density.max),
+        }
+    }
+
+    pub fn params(&self) -> CoreParams {
+        CoreParams {
+            height: self.height_m,
+            fill_ratio_l: self.fill_ratio_l,
+            fill_ratio_w: self.fill_ratio_w,
+            taper_ratio: self.taper_ratio,
+            packing_density: self.packing_density,
+            material_type: self.material_type.clone(),
+            formula: FormulaVersion::default(),
+        }
+    }
+
+    /// The tonnage an analysis of this load should report
+    pub fn expected(&self) -> TonnageResult {
+        calculate_tonnage(&self.params(), Some(&self.truck_class))
+    }
+
+    /// Geometry reply: the tailgate scales to the bed height, the cargo top
+    /// sits `height_m` above the tailgate bottom
+    pub fn geometry_json(&self) -> String {
+        let norm_per_m = (TAILGATE_BOTTOM_Y - TAILGATE_TOP_Y) / bed_height(&self.truck_class);
+        json!({
+            "tailgateTopY": TAILGATE_TOP_Y,
+            "tailgateBottomY": TAILGATE_BOTTOM_Y,
+            "cargoTopY": TAILGATE_BOTTOM_Y - self.height_m * norm_per_m,
+        })
+        .to_string()
+    }
+
+    pub fn fill_json(&self) -> String {
+        json!({
+            "fillRatioL": self.fill_ratio_l,
+            "fillRatioW": self.fill_ratio_w,
+            "taperRatio": self.taper_ratio,
+            "packingDensity": self.packing_density,
+            "materialType": self.material_type,
+            "reasoning": "synthetic scenario",
+        })
+        .to_string()
+    }
+
+    /// Config analyzing this load's truck class and material
+    pub fn config(&self) -> BoxOverlayConfig {
+        BoxOverlayConfig {
+            truck_class: self.truck_class.clone(),
+            material_type: self.material_type.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Backend replying with this scenario to every geometry and fill call
+    pub fn backend(&self) -> OfflineBackend {
+        let mut library = ResponseLibrary::new();
+        library.insert(CannedResponse {
+            tags: Vec::new(),
+            geometry: serde_json::from_str(&self.geometry_json()).expect("rendered JSON"),
+            fill: serde_json::from_str(&self.fill_json()).expect("rendered JSON"),
+            landmark: None,
+            plate: None,
+        });
+        OfflineBackend::new(library)
+    }
+}
+
+fn bed_height(truck_class: &str) -> f64 {
+    SPEC.truck_specs.get(truck_class).map_or(0.32, |t| t.bed_height)
+}
+
+/// Reproducible stream of random scenarios over the spec's truck classes,
+/// materials, height range and fill levels
+#[derive(Debug, Clone)]
+pub struct ScenarioGenerator {
+    state: u64,
+    truck_classes: Vec<String>,
+    materials: Vec<String>,
+}
+
+impl ScenarioGenerator {
+    pub fn new(seed: u64) -> Self {
+        let mut truck_classes: Vec<String> = SPEC.truck_specs.keys().cloned().collect();
+        let mut materials: Vec<String> = SPEC.materials.keys().cloned().collect();
+        truck_classes.sort();
+        materials.sort();
+        Self {
+            state: seed,
+            truck_classes,
+            materials,
+        }
+    }
+
+    /// splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in 0..1
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform index below `len`
+    fn next_index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
+impl Iterator for ScenarioGenerator {
+    type Item = Scenario;
+
+    fn next(&mut self) -> Option<Scenario> {
+        let h = &SPEC.ranges.height;
+        let height_m = h.min + (h.max - h.min) * self.next_f64();
+        let fill_level = self.next_f64();
+        let truck = self.next_index(self.truck_classes.len());
+        let material = self.next_index(self.materials.len());
+        Some(Scenario::new(&self.truck_classes[truck], height_m, fill_level, &self.materials[material]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{parse_fill, parse_geometry};
+    use crate::pipeline::{analyze_box_overlay, LoadState, EMPTY_BED_HEIGHT_M};
+
+    #[test]
+    fn test_pipeline_recovers_generated_scenarios() {
+        let scenarios: Vec<Scenario> = ScenarioGenerator::new(7).take(40).collect();
+        assert_eq!(scenarios, ScenarioGenerator::new(7).take(40).collect::<Vec<_>>(), "reproducible");
+
+        for scenario in &scenarios {
+            assert!(scenario.params().validated().is_ok(), "{:?}", scenario);
+            assert!(parse_geometry(&scenario.geometry_json()).is_ok());
+            assert!(parse_fill(&scenario.fill_json()).is_ok());
+
+            let result = analyze_box_overlay(&scenario.backend(), &[], &scenario.config()).unwrap();
+            assert!((result.height_m - scenario.height_m).abs() < 1e-9, "{:?}", scenario);
+            if scenario.height_m <= EMPTY_BED_HEIGHT_M {
+                assert_eq!((result.load_state, result.tonnage), (LoadState::Empty, 0.0), "{:?}", scenario);
+                continue;
+            }
+            assert_eq!(result.material_type, scenario.material_type);
+            assert!((result.tonnage - scenario.expected().tonnage).abs() < 1e-9, "{:?}", scenario);
+        }
+    }
+
+    #[test]
+    fn test_scenario_shape() {
+        let flat = Scenario::new("4t", 0.2, 0.0, "As殻");
+        let heaped = Scenario::new("4t", 0.8, 1.0, "As殻");
+        assert_eq!(flat.taper_ratio, 1.0);
+        assert_eq!(heaped.taper_ratio, SPEC.ranges.taper_ratio.min);
+        assert_eq!(flat.fill_ratio_l, SPEC.ranges.fill_ratio_l.min);
+        assert_eq!(Scenario::new("4t", 5.0, 2.0, "As殻").height_m, SPEC.ranges.height.max);
+        assert!(heaped.expected().tonnage > flat.expected().tonnage);
+    }
+}
@@ -2,21 +2,53 @@
 //!
 //! Validates AI-estimated values fall within defined ranges.
 
+use std::fmt;
+
+use crate::i18n::{self, language, Language, Localize};
 use crate::spec::{SPEC, Range, HeightRange};
 
 /// A validation error with the parameter name and details
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
 pub struct ValidationError {
     pub field: String,
     pub value: f64,
     pub min: f64,
     pub max: f64,
+    /// Range description in the language set when the error was raised
     pub message: String,
 }
 
-impl std::fmt::Display for ValidationError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}: {} ({})", self.field, self.value, self.message)
+impl ValidationError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        "OUT_OF_RANGE"
+    }
+
+    fn out_of_range(field: &str, value: f64, min: f64, max: f64) -> Self {
+        Self {
+            field: field.to_string(),
+            value,
+            min,
+            max,
+            message: range_message(min, max, language()),
+        }
+    }
+}
+
+fn range_message(min: f64, max: f64, lang: Language) -> String {
+    i18n::OUT_OF_RANGE.format(lang, &[&format!("{:.2}", min), &format!("{:.2}", max)])
+}
+
+impl Localize for ValidationError {
+    fn localize(&self, lang: Language) -> String {
+        format!("{}: {} ({})", self.field, self.value, range_message(self.min, self.max, lang))
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
     }
 }
 
@@ -57,25 +89,13 @@ pub fn validate_params(params: &EstimationParams) -> Vec<ValidationError> {
 
 fn check_range(field: &str, value: f64, range: &Range, errors: &mut Vec<ValidationError>) {
     if value < range.min || value > range.max {
-        errors.push(ValidationError {
-            field: field.to_string(),
-            value,
-            min: range.min,
-            max: range.max,
-            message: format!("範囲外: {:.2}~{:.2}", range.min, range.max),
-        });
+        errors.push(ValidationError::out_of_range(field, value, range.min, range.max));
     }
 }
 
 fn check_height_range(field: &str, value: f64, range: &HeightRange, errors: &mut Vec<ValidationError>) {
     if value < range.min || value > range.max {
-        errors.push(ValidationError {
-            field: field.to_string(),
-            value,
-            min: range.min,
-            max: range.max,
-            message: format!("範囲外: {:.2}~{:.2}", range.min, range.max),
-        });
+        errors.push(ValidationError::out_of_range(field, value, range.min, range.max));
     }
 }
 
@@ -99,10 +119,12 @@ pub fn clamp_params(params: &EstimationParams) -> EstimationParams {
 /// WASM-friendly validation (takes JSON string, returns JSON error array)
 #[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "wasm")]
+use crate::wasm::{export, require_spec, ExportValue};
 
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = "validateParams")]
-pub fn validate_params_wasm(json: &str) -> String {
+pub fn validate_params_wasm(json: &str) -> Result<ExportValue, JsError> {
     #[derive(serde::Deserialize)]
     #[serde(rename_all = "camelCase")]
     struct WasmParams {
@@ -113,8 +135,9 @@ pub fn validate_params_wasm(json: &str) -> String {
         packing_density: Option<f64>,
     }
 
+    require_spec()?;
     let parsed: Result<WasmParams, _> = serde_json::from_str(json);
-    match parsed {
+    Ok(match parsed {
         Ok(p) => {
             let params = EstimationParams {
                 height: p.height,
@@ -133,10 +156,10 @@ pub fn validate_params_wasm(json: &str) -> String {
                     "message": e.message,
                 })
             }).collect();
-            serde_json::to_string(&json_errors).unwrap_or_else(|_| "[]".to_string())
+            export(&json_errors)
         }
-        Err(e) => format!("[{{\"field\":\"parse\",\"message\":\"{}\"}}]", e),
-    }
+        Err(e) => export(&serde_json::json!([{ "field": "parse", "message": e.to_string() }])),
+    })
 }
 
 #[cfg(test)]
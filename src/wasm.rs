@@ -0,0 +1,163 @@
+//! Async WASM exports
+//!
+//! The pipeline is synchronous, but a browser reaches a model only through
+//! Promises. `analyzeBoxOverlay` bridges the two: it issues every call of the
+//! config's `CallPlan` through the page's `sendPrompt(prompt, stage)` callback
+//! at once, awaits the replies and runs the pipeline over them. The export
+//! itself returns a Promise, so the page keeps rendering while the model calls
+//! are in flight. Exports that wait on the network belong here as `async fn`s;
+//! the pure helpers (parsing, calculation) stay synchronous in their modules.
+//!
+//! Exports return `ExportValue`: JSON text by default, the JS object itself
+//! with the `wasm-minimal` feature (see `export`). Exports that read
+//! prompt-spec.json throw a JS `Error` in a `wasm-minimal` build that has not
+//! been given one yet (see `require_spec`).
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+use crate::i18n::{self, language};
+use crate::input::InputImage;
+use crate::metrics::stage_label;
+use crate::pipeline::{analyze_box_overlay, plan_box_overlay, AiBackend, BoxOverlayConfig, PipelineError};
+use crate::report::result_json;
+
+/// What exports hand back to JS
+#[cfg(not(feature = "wasm-minimal"))]
+pub type ExportValue = String;
+/// What exports hand back to JS
+#[cfg(feature = "wasm-minimal")]
+pub type ExportValue = JsValue;
+
+/// Convert an export's return value. `wasm-minimal` builds it straight into
+/// a plain JS object (serde-wasm-bindgen), skipping the JSON text encoding on
+/// this side and the `JSON.parse` on the host's.
+pub(crate) fn export<T: serde::Serialize + ?Sized>(value: &T) -> ExportValue {
+    #[cfg(not(feature = "wasm-minimal"))]
+    {
+        serde_json::to_string(value).unwrap_or_default()
+    }
+    #[cfg(feature = "wasm-minimal")]
+    {
+        value
+            .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+            .unwrap_or(JsValue::NULL)
+    }
+}
+
+/// Fail an export that reads prompt-spec.json with a JS `Error` while a
+/// `wasm-minimal` build has not been given its spec, instead of aborting
+pub(crate) fn require_spec() -> Result<(), JsError> {
+    crate::spec::check_spec_loaded().map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Replies fetched ahead of the pipeline, handed out per prompt in call order.
+/// A call the plan did not foresee (an escalation run) fails like a refused one.
+struct PrefetchedBackend {
+    replies: Mutex<HashMap<String, VecDeque<Result<String, String>>>>,
+}
+
+impl AiBackend for PrefetchedBackend {
+    fn send_prompt(&self, prompt: &str, _images: &[InputImage]) -> Result<String, PipelineError> {
+        let mut replies = self.replies.lock().unwrap_or_else(|p| p.into_inner());
+        match replies.get_mut(prompt).and_then(VecDeque::pop_front) {
+            Some(reply) => reply.map_err(PipelineError::AiError),
+            None => Err(PipelineError::AiError(i18n::UNPLANNED_CALL.get(language()).to_string())),
+        }
+    }
+}
+
+/// Reply text of one `Promise.allSettled` outcome
+fn settled_reply(outcome: &JsValue) -> Result<String, String> {
+    let field = |name: &str| Reflect::get(outcome, &JsValue::from_str(name)).unwrap_or(JsValue::UNDEFINED);
+    if field("status").as_string().as_deref() == Some("fulfilled") {
+        field("value").as_string().ok_or_else(|| i18n::REPLY_NOT_STRING.get(language()).to_string())
+    } else {
+        let reason = field("reason");
+        Err(reason.as_string().unwrap_or_else(|| format!("{:?}", reason)))
+    }
+}
+
+fn error_json(code: &str, message: impl ToString) -> ExportValue {
+    export(&json!({
+        "ok": false,
+        "code": code,
+        "error": message.to_string(),
+    }))
+}
+
+/// Supply prompt-spec.json to a `wasm-minimal` build, which embeds none; call
+/// once before anything else. Returns `{"ok": true, "loaded": false}` when a
+/// spec was already loaded (the first one is kept).
+#[cfg(feature = "wasm-minimal")]
+#[wasm_bindgen(js_name = "loadSpec")]
+pub fn load_spec_wasm(json: String) -> ExportValue {
+    match crate::spec::load_spec(json) {
+        Ok(loaded) => export(&json!({ "ok": true, "loaded": loaded })),
+        Err(e) => error_json(e.code(), e),
+    }
+}
+
+/// Analyze a load, calling the model through `sendPrompt(prompt, stage)`,
+/// which returns (a Promise of) the reply text; the page attaches the images.
+///
+/// Resolves to the result JSON with `"ok": true`, or to
+/// `{"ok": false, "code", "error"}` (also when no spec is loaded). A rejected
+/// call fails only its run.
+#[wasm_bindgen(js_name = "analyzeBoxOverlay")]
+pub async fn analyze_box_overlay_wasm(
+    images: Vec<Uint8Array>,
+    truck_class: String,
+    material_type: String,
+    ensemble_count: Option<usize>,
+    send_prompt: Function,
+) -> ExportValue {
+    if let Err(e) = crate::spec::check_spec_loaded() {
+        return error_json(e.code(), e);
+    }
+    let images: Vec<InputImage> = match images.iter().map(|b| InputImage::new(b.to_vec())).collect() {
+        Ok(images) => images,
+        Err(e) => return error_json(e.code(), e),
+    };
+    let mut builder = BoxOverlayConfig::builder().truck_class(truck_class).material_type(material_type);
+    if let Some(count) = ensemble_count {
+        builder = builder.ensemble_count(count);
+    }
+    let config = match builder.build() {
+        Ok(config) => config,
+        Err(e) => return error_json(e.code(), e),
+    };
+
+    let plan = plan_box_overlay(&config, images.len());
+    let pending = Array::new();
+    for call in &plan.calls {
+        let stage = JsValue::from_str(stage_label(Some(call.stage)));
+        let reply = send_prompt
+            .call2(&JsValue::NULL, &JsValue::from_str(&call.prompt), &stage)
+            .unwrap_or_else(|thrown| Promise::reject(&thrown).into());
+        pending.push(&reply);
+    }
+    // allSettled never rejects
+    let settled = JsFuture::from(Promise::all_settled(&pending)).await.unwrap_or(JsValue::UNDEFINED);
+
+    let mut replies: HashMap<String, VecDeque<Result<String, String>>> = HashMap::new();
+    for (call, outcome) in plan.calls.iter().zip(Array::from(&settled).iter()) {
+        replies.entry(call.prompt.clone()).or_default().push_back(settled_reply(&outcome));
+    }
+    let backend = PrefetchedBackend {
+        replies: Mutex::new(replies),
+    };
+    match analyze_box_overlay(&backend, &images, &config) {
+        Ok(result) => {
+            let mut body = result_json(&result);
+            body["ok"] = json!(true);
+            export(&body)
+        }
+        Err(e) => error_json(e.code(), e),
+    }
+}
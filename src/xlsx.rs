@@ -0,0 +1,234 @@
+//! Excel export of batch results (feature `xlsx`)
+//!
+//! Clients keep their daily report (日報) as an Excel template, so a day's
+//! results are exported as a workbook rather than CSV: a summary sheet, one
+//! row per load, and one row per ensemble run for checking individual loads.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+
+use crate::i18n::{self, language, Language, Localize};
+use crate::pipeline::BoxOverlayResult;
+use crate::policy::Verdict;
+
+/// Workbook that could not be written
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[non_exhaustive]
+pub enum ExportError {
+    Xlsx(String),
+}
+
+impl ExportError {
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Xlsx(_) => "XLSX_WRITE",
+        }
+    }
+}
+
+impl Localize for ExportError {
+    fn localize(&self, lang: Language) -> String {
+        match self {
+            Self::Xlsx(message) => i18n::XLSX_FAILED.format(lang, &[message]),
+        }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.localize(language()))
+    }
+}
+
+impl From<XlsxError> for ExportError {
+    fn from(e: XlsxError) -> Self {
+        Self::Xlsx(e.to_string())
+    }
+}
+
+const SUMMARY_SHEET: &str = "集計";
+const LOADS_SHEET: &str = "積載明細";
+const RUNS_SHEET: &str = "試行明細";
+
+const LOAD_HEADERS: [&str; 17] = [
+    "No", "車両ID", "ナンバー", "車格", "材質", "積載高さ(m)", "体積(m³)", "推定重量(t)", "下限(t)", "上限(t)",
+    "請求重量(t)", "判定", "工事番号", "運転者", "搬出先", "要確認", "警告",
+];
+
+const RUN_HEADERS: [&str; 11] = [
+    "No", "段階", "試行", "プロンプト", "バックエンド", "高さ(m)", "スケール基準", "充填率L", "充填率W", "テーパー",
+    "充填密度",
+];
+
+/// Workbook (.xlsx bytes) with summary, per-load and per-run sheets.
+///
+/// Loads are numbered from 1 in input order; the same number links a load's
+/// runs on the run sheet.
+pub fn write_xlsx<'a>(results: impl IntoIterator<Item = &'a BoxOverlayResult>) -> Result<Vec<u8>, ExportError> {
+    let results: Vec<&BoxOverlayResult> = results.into_iter().collect();
+    let header = Format::new().set_bold();
+    let decimal = Format::new().set_num_format("0.00");
+
+    let mut workbook = Workbook::new();
+    write_summary(workbook.add_worksheet(), &results, &header, &decimal)?;
+    write_loads(workbook.add_worksheet(), &results, &header, &decimal)?;
+    write_runs(workbook.add_worksheet(), &results, &header, &decimal)?;
+    Ok(workbook.save_to_buffer()?)
+}
+
+fn write_headers(sheet: &mut Worksheet, headers: &[&str], format: &Format) -> Result<(), XlsxError> {
+    for (col, title) in headers.iter().enumerate() {
+        sheet.write_with_format(0, col as u16, *title, format)?;
+    }
+    sheet.set_freeze_panes(1, 0)?;
+    Ok(())
+}
+
+/// Needs review: a policy verdict other than OK, diverging runs or a high CV
+fn needs_review(result: &BoxOverlayResult) -> bool {
+    result.verdict.is_some_and(|v| v != Verdict::Ok) || result.disagreement.is_some() || result.needs_review
+}
+
+fn write_summary(
+    sheet: &mut Worksheet,
+    results: &[&BoxOverlayResult],
+    header: &Format,
+    decimal: &Format,
+) -> Result<(), XlsxError> {
+    sheet.set_name(SUMMARY_SHEET)?;
+    let totals = [
+        ("台数", results.len() as f64),
+        ("推定重量合計(t)", results.iter().map(|r| r.tonnage).sum()),
+        ("体積合計(m³)", results.iter().map(|r| r.volume).sum()),
+        (
+            "請求重量合計(t)",
+            results.iter().map(|r| r.billed_tonnage.unwrap_or(r.tonnage)).sum(),
+        ),
+        ("要確認", results.iter().filter(|r| needs_review(r)).count() as f64),
+    ];
+    for (row, (label, value)) in totals.iter().enumerate() {
+        sheet.write_with_format(row as u32, 0, *label, header)?;
+        sheet.write_with_format(row as u32, 1, *value, decimal)?;
+    }
+
+    // Per-material totals, by material name
+    let mut materials: BTreeMap<&str, (u32, f64, f64)> = BTreeMap::new();
+    for r in results {
+        let entry = materials.entry(r.material_type.as_str()).or_default();
+        entry.0 += 1;
+        entry.1 += r.tonnage;
+        entry.2 += r.volume;
+    }
+    let top = totals.len() as u32 + 1;
+    for (col, title) in ["材質", "台数", "推定重量(t)", "体積(m³)"].iter().enumerate() {
+        sheet.write_with_format(top, col as u16, *title, header)?;
+    }
+    for (i, (material, (count, tonnage, volume))) in materials.into_iter().enumerate() {
+        let row = top + 1 + i as u32;
+        sheet.write(row, 0, material)?;
+        sheet.write(row, 1, count)?;
+        sheet.write_with_format(row, 2, tonnage, decimal)?;
+        sheet.write_with_format(row, 3, volume, decimal)?;
+    }
+    sheet.set_column_width(0, 18)?;
+    Ok(())
+}
+
+fn write_loads(
+    sheet: &mut Worksheet,
+    results: &[&BoxOverlayResult],
+    header: &Format,
+    decimal: &Format,
+) -> Result<(), XlsxError> {
+    sheet.set_name(LOADS_SHEET)?;
+    write_headers(sheet, &LOAD_HEADERS, header)?;
+    for (i, r) in results.iter().enumerate() {
+        let row = i as u32 + 1;
+        let warnings: Vec<String> = r.warnings.iter().map(|w| w.to_string()).collect();
+        sheet.write(row, 0, row)?;
+        sheet.write(row, 1, r.vehicle_id.as_deref())?;
+        sheet.write(row, 2, r.plate_number.as_deref())?;
+        sheet.write(row, 3, &r.truck_class)?;
+        sheet.write(row, 4, &r.material_type)?;
+        sheet.write_with_format(row, 5, r.height_m, decimal)?;
+        sheet.write_with_format(row, 6, r.volume, decimal)?;
+        sheet.write_with_format(row, 7, r.tonnage, decimal)?;
+        sheet.write_with_format(row, 8, r.tonnage_min, decimal)?;
+        sheet.write_with_format(row, 9, r.tonnage_max, decimal)?;
+        sheet.write_with_format(row, 10, r.billed_tonnage, decimal)?;
+        sheet.write(row, 11, r.verdict.map(|v| v.to_string()))?;
+        sheet.write(row, 12, r.metadata.job_number.as_deref())?;
+        sheet.write(row, 13, r.metadata.driver_id.as_deref())?;
+        sheet.write(row, 14, r.metadata.destination.as_deref())?;
+        sheet.write(row, 15, if needs_review(r) { "要確認" } else { "" })?;
+        sheet.write(row, 16, warnings.join(" / "))?;
+    }
+    Ok(())
+}
+
+fn write_runs(
+    sheet: &mut Worksheet,
+    results: &[&BoxOverlayResult],
+    header: &Format,
+    decimal: &Format,
+) -> Result<(), XlsxError> {
+    sheet.set_name(RUNS_SHEET)?;
+    write_headers(sheet, &RUN_HEADERS, header)?;
+    let mut row = 1;
+    for (i, r) in results.iter().enumerate() {
+        let load = i as u32 + 1;
+        for (run, log) in r.geometry_runs.iter().enumerate() {
+            sheet.write(row, 0, load)?;
+            sheet.write(row, 1, "幾何")?;
+            sheet.write(row, 2, run as u32 + 1)?;
+            sheet.write(row, 3, &log.variant)?;
+            sheet.write(row, 4, &log.backend)?;
+            sheet.write_with_format(row, 5, log.height_m, decimal)?;
+            sheet.write(row, 6, &log.scale_method)?;
+            row += 1;
+        }
+        for (run, log) in r.fill_runs.iter().enumerate() {
+            sheet.write(row, 0, load)?;
+            sheet.write(row, 1, "充填")?;
+            sheet.write(row, 2, run as u32 + 1)?;
+            sheet.write(row, 3, &log.variant)?;
+            sheet.write(row, 4, &log.backend)?;
+            if let Some(fill) = &log.parsed {
+                sheet.write_with_format(row, 7, fill.fill_ratio_l, decimal)?;
+                sheet.write_with_format(row, 8, fill.fill_ratio_w, decimal)?;
+                sheet.write_with_format(row, 9, fill.taper_ratio, decimal)?;
+                sheet.write_with_format(row, 10, fill.packing_density, decimal)?;
+            }
+            row += 1;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::{analyze_box_overlay, BoxOverlayConfig};
+    use crate::testing::fixed_backend;
+
+    #[test]
+    fn test_write_xlsx() {
+        let result = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        let bytes = write_xlsx([&result, &result]).unwrap();
+        // .xlsx is a zip archive
+        assert!(bytes.starts_with(b"PK"));
+        assert!(write_xlsx([]).unwrap().starts_with(b"PK"));
+    }
+
+    #[test]
+    fn test_needs_review() {
+        let mut result = analyze_box_overlay(&fixed_backend(), &[], &BoxOverlayConfig::default()).unwrap();
+        assert!(!needs_review(&result));
+        result.verdict = Some(Verdict::Review);
+        assert!(needs_review(&result));
+        assert_eq!(ExportError::Xlsx("x".into()).code(), "XLSX_WRITE");
+    }
+}